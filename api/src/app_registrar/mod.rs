@@ -1,12 +1,12 @@
 use crate::{
     common_types::Events,
+    error::Result,
     titanh::{
         self, app_registrar::events::AppCreated, capsules::calls::types::upload_capsule::App,
         runtime_types::pallet_app_registrar::types::AppSubscriptionStatus,
     },
     TitanhApi,
 };
-use anyhow::Result;
 use sp_core::H256;
 
 pub struct AppRegistrarApi<'a> {
@@ -1,26 +1,49 @@
 use crate::{
-    common_types::{KeyPair, Rpc, SubstrateApi},
-    TitanhApi,
+    common_types::{Rpc, Signer, SubstrateApi},
+    error::Result,
+    rpc::{self, RpcOptions},
+    signer::{CryptoScheme, TxSigner},
+    tx::Outbox,
+    CacheOptions, TitanhApi,
 };
-use anyhow::Result;
-use sp_core::Pair;
-use subxt::{backend::rpc::RpcClient, tx::PairSigner};
+use std::{path::PathBuf, sync::Arc, time::Duration};
 
 pub struct TitanhApiBuilder {
-    /// The rpc url of the substrate node
-    rpc_url: String,
+    /// The rpc urls of the substrate nodes, in order of preference
+    rpc_urls: Vec<String>,
     /// The eventual seed phrase of the user
     seed_phrase: Option<String>,
+    /// The signature scheme of the key pair derived from the seed phrase
+    scheme: CryptoScheme,
+    /// The eventual external signer, used instead of the seed phrase
+    signer: Option<Signer>,
+    /// The connection options of the rpc client
+    rpc_options: RpcOptions,
+    /// The eventual path of the outbox of the pending transactions
+    outbox_path: Option<PathBuf>,
+    /// The eventual options of the client side cache
+    cache: Option<CacheOptions>,
 }
 
 impl TitanhApiBuilder {
     pub fn rpc(url: &str) -> Self {
         TitanhApiBuilder {
-            rpc_url: url.to_string(),
+            rpc_urls: vec![url.to_string()],
             seed_phrase: None,
+            scheme: CryptoScheme::default(),
+            signer: None,
+            rpc_options: RpcOptions::default(),
+            outbox_path: None,
+            cache: None,
         }
     }
 
+    /// Adds a fallback rpc endpoint, used when the previous ones are down
+    pub fn fallback_rpc(mut self, url: &str) -> Self {
+        self.rpc_urls.push(url.to_string());
+        self
+    }
+
     pub fn seed(self, seed: &str) -> Self {
         Self {
             seed_phrase: Some(seed.to_string()),
@@ -28,22 +51,70 @@ impl TitanhApiBuilder {
         }
     }
 
+    /// Sets the signature scheme of the key pair derived from the seed, sr25519 by default
+    pub fn scheme(mut self, scheme: CryptoScheme) -> Self {
+        self.scheme = scheme;
+        self
+    }
+
+    /// Signs the transactions with an external signer, e.g. a hardware wallet or a remote KMS, instead of a seed
+    pub fn signer(mut self, signer: impl TxSigner + 'static) -> Self {
+        self.signer = Some(Arc::new(signer));
+        self
+    }
+
+    /// Sets the timeout of the rpc requests
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.rpc_options.request_timeout = timeout;
+        self
+    }
+
+    /// Sets the timeout of a connection attempt to an rpc endpoint
+    pub fn connection_timeout(mut self, timeout: Duration) -> Self {
+        self.rpc_options.connection_timeout = timeout;
+        self
+    }
+
+    /// Sets the number of reconnection attempts to an rpc endpoint before failing over to the next one
+    pub fn max_reconnects(mut self, max_reconnects: usize) -> Self {
+        self.rpc_options.max_reconnects = max_reconnects;
+        self
+    }
+
+    /// Only accepts TLS (`wss://`) rpc endpoints
+    pub fn secure(mut self) -> Self {
+        self.rpc_options.secure = true;
+        self
+    }
+
+    /// Persists the transactions submitted with `ConsistencyLevel::Eventual` to an outbox file, so that they can be recovered after a restart
+    pub fn outbox(mut self, path: impl Into<PathBuf>) -> Self {
+        self.outbox_path = Some(path.into());
+        self
+    }
+
+    /// Caches the capsules content and metadata on the client side
+    pub fn cache(mut self, options: CacheOptions) -> Self {
+        self.cache = Some(options);
+        self
+    }
+
     pub async fn build(self) -> Result<TitanhApi> {
-        // Derive the key pair from the seed phrase (mnemonic)
-        let signer = if let Some(seed_phrase) = self.seed_phrase {
-            let pair = KeyPair::from_string(&seed_phrase, None)?;
-            let signer = PairSigner::new(pair);
-            Some(signer)
-        } else {
-            None
+        // Derive the key pair from the seed phrase (mnemonic), unless an external signer is given
+        let signer = match (self.signer, self.seed_phrase) {
+            (Some(signer), _) => Some(signer),
+            (None, Some(seed_phrase)) => Some(self.scheme.signer_from_seed(&seed_phrase)?),
+            (None, None) => None,
         };
-        // SECURITY NOTE: We are using an insecure connection assuming that the node is communicating with a trusted local network node: NO NOT RUN THIS CODE IN PRODUCTION
-        let rpc_client = RpcClient::from_insecure_url(self.rpc_url).await?;
+        // SECURITY NOTE: unless the builder is `secure`, plain `ws://` endpoints are accepted, assuming that the node is communicating with a trusted local network node
+        let rpc_client = rpc::connect(&self.rpc_urls, &self.rpc_options).await?;
         let rpc = Rpc::new(rpc_client.clone());
 
         // We can use the same client to drive our full Subxt interface
         let api = SubstrateApi::from_rpc_client(rpc_client.clone()).await?;
 
-        Ok(TitanhApi::new(api, rpc, signer).await?)
+        let outbox = self.outbox_path.map(Outbox::open).transpose()?;
+
+        TitanhApi::new(api, rpc, signer, outbox, self.cache).await
     }
 }
@@ -1,6 +1,7 @@
 use crate::{
-    capsules_types::PutCapsuleOpts,
-    common_types::ConsistencyLevel,
+    capsules_types::{GetManyOpts, PutCapsuleOpts},
+    common_types::{BlockHash, BlockInfo, BlockNumber, ConsistencyLevel},
+    error::{Result, TitanhError},
     titanh::{
         self,
         runtime_types::{
@@ -8,12 +9,13 @@ use crate::{
         },
         utility::calls::types::batch_all::Calls,
     },
+    tx::TxHandle,
 };
 
 use super::ContainerApi;
-use anyhow::Result;
 use codec::{Decode, Encode};
 use sp_core::{Blake2Hasher, Hasher, H256};
+use subxt::{storage::Address, utils::Yes};
 
 pub struct DocumentApi<'a> {
     container_api: &'a ContainerApi<'a>,
@@ -75,14 +77,17 @@ impl Document<'_> {
             .await
     }
 
-    /// Insert a field identified by a key into the document, waiting for the transaction to be included in the transaction pool
-    pub async fn insert_async<Key, Value>(&self, field_key: Key, value: Value) -> Result<H256>
+    /// Insert a field identified by a key into the document, waiting for the transaction to be included in the transaction pool.
+    /// The returned handle tracks whether the transaction is later included, finalized or dropped
+    pub async fn insert_async<Key, Value>(&self, field_key: Key, value: Value) -> Result<TxHandle>
     where
         Key: Encode,
         Value: Encode,
     {
-        self.insert_with_level(field_key, value, ConsistencyLevel::Eventual)
-            .await
+        let tx_hash = self
+            .insert_with_level(field_key, value, ConsistencyLevel::Eventual)
+            .await?;
+        self.api.capsules.titanh.track(tx_hash).await
     }
 
     pub async fn insert_with_level<Key, Value>(
@@ -149,20 +154,122 @@ impl Document<'_> {
         self.read_with_opts(field_key, true).await
     }
 
-    pub async fn read_with_opts<Key, Value>(
+    /// Reads several document entries from the latest block, not yet finalized. A missing entry is returned as `None`
+    pub async fn read_many<Key, Value>(
+        &self,
+        field_keys: impl IntoIterator<Item = Key>,
+    ) -> Result<Vec<Option<Value>>>
+    where
+        Key: Encode,
+        Value: Decode,
+    {
+        self.read_many_with_opts(field_keys, GetManyOpts::default())
+            .await
+    }
+
+    /// Reads several document entries at the same block, given the options. The entries and the capsules metadata are fetched in one request each,
+    /// and then the content is fetched concurrently from IPFS
+    pub async fn read_many_with_opts<Key, Value>(
+        &self,
+        field_keys: impl IntoIterator<Item = Key>,
+        opts: GetManyOpts,
+    ) -> Result<Vec<Option<Value>>>
+    where
+        Key: Encode,
+        Value: Decode,
+    {
+        let block = self
+            .api
+            .capsules
+            .snapshot_block(opts.from_finalized_state)
+            .await?;
+
+        self.read_many_at(field_keys, block.hash, opts.concurrency)
+            .await
+    }
+
+    /// Reads a document entry as it was at a past block. The content must still be available on IPFS
+    pub async fn read_at<Key, Value>(
         &self,
         field_key: Key,
-        from_finalized_state: bool,
+        block_number: BlockNumber,
     ) -> Result<Value>
     where
         Key: Encode,
         Value: Decode,
     {
-        let field_key = field_key.encode();
+        self.snapshot(block_number).await?.read(field_key).await
+    }
+
+    /// Returns a view of the document as it was at a past block.
+    /// The reads of the view fail with `StatePruned` if the node no longer keeps the state of the block
+    pub async fn snapshot(&self, block_number: BlockNumber) -> Result<DocumentSnapshot<'_>> {
+        let block_hash = self.api.capsules.titanh.block_hash(block_number).await?;
+
+        Ok(DocumentSnapshot {
+            document: self,
+            block: BlockInfo::new(block_number, block_hash),
+        })
+    }
+
+    async fn read_many_at<Key, Value>(
+        &self,
+        field_keys: impl IntoIterator<Item = Key>,
+        at: BlockHash,
+        concurrency: usize,
+    ) -> Result<Vec<Option<Value>>>
+    where
+        Key: Encode,
+        Value: Decode,
+    {
+        let capsules = &self.api.capsules;
 
-        let key = BoundedString(BoundedVec(field_key));
-        let query_container_capsule = titanh::storage().capsules().container(self.id, key);
+        let queries: Vec<_> = field_keys
+            .into_iter()
+            .map(|field_key| self.entry_query(field_key))
+            .collect();
+        let capsule_ids = capsules.titanh.query_many(&queries, at).await?;
 
+        capsules.read_capsules(capsule_ids, at, concurrency).await
+    }
+
+    /// Returns the capsule id of a document entry
+    async fn entry_capsule_id<Key: Encode>(
+        &self,
+        field_key: Key,
+        at: Option<BlockHash>,
+    ) -> Result<H256> {
+        let query_container_capsule = self.entry_query(field_key);
+
+        match self
+            .api
+            .capsules
+            .titanh
+            .query(&query_container_capsule, at)
+            .await
+        {
+            Err(TitanhError::NotInStorage) => Err(TitanhError::CapsuleNotFound),
+            res => res,
+        }
+    }
+
+    fn entry_query<Key: Encode>(
+        &self,
+        field_key: Key,
+    ) -> impl Address<Target = H256, IsFetchable = Yes> {
+        let key = BoundedString(BoundedVec(field_key.encode()));
+        titanh::storage().capsules().container(self.id, key)
+    }
+
+    pub async fn read_with_opts<Key, Value>(
+        &self,
+        field_key: Key,
+        from_finalized_state: bool,
+    ) -> Result<Value>
+    where
+        Key: Encode,
+        Value: Decode,
+    {
         let at = if from_finalized_state {
             Some(
                 self.api
@@ -176,12 +283,7 @@ impl Document<'_> {
             None
         };
 
-        let capsule_id = self
-            .api
-            .capsules
-            .titanh
-            .query(&query_container_capsule, at)
-            .await?;
+        let capsule_id = self.entry_capsule_id(field_key, at).await?;
 
         let value = self
             .api
@@ -204,10 +306,13 @@ impl Document<'_> {
             .await
     }
 
-    /// Removes a document entry (without unlinking the underlining capsule) waiting for the transaction to be included in the transaction pool
-    pub async fn remove_async<Key: Encode>(&self, field_key: Key) -> Result<H256> {
-        self.remove_with_level(field_key, ConsistencyLevel::Eventual)
-            .await
+    /// Removes a document entry (without unlinking the underlining capsule) waiting for the transaction to be included in the transaction pool.
+    /// The returned handle tracks whether the transaction is later included, finalized or dropped
+    pub async fn remove_async<Key: Encode>(&self, field_key: Key) -> Result<TxHandle> {
+        let tx_hash = self
+            .remove_with_level(field_key, ConsistencyLevel::Eventual)
+            .await?;
+        self.api.capsules.titanh.track(tx_hash).await
     }
 
     /// Removes a document entry (removing also the underlining capsule)
@@ -254,3 +359,51 @@ impl Document<'_> {
         capsule_id
     }
 }
+
+/// A view of a document as it was at a past block, e.g. to audit its history. The content of the entries must still be available on IPFS
+pub struct DocumentSnapshot<'a> {
+    document: &'a Document<'a>,
+    block: BlockInfo,
+}
+
+impl DocumentSnapshot<'_> {
+    /// Returns the block of the snapshot
+    pub fn block(&self) -> BlockInfo {
+        self.block
+    }
+
+    /// Reads a document entry at the block of the snapshot
+    pub async fn read<Key, Value>(&self, field_key: Key) -> Result<Value>
+    where
+        Key: Encode,
+        Value: Decode,
+    {
+        let capsules = &self.document.api.capsules;
+        let at = Some(self.block.hash);
+
+        let cid = async {
+            let capsule_id = self.document.entry_capsule_id(field_key, at).await?;
+            capsules.capsule_cid_at(capsule_id, at).await
+        }
+        .await
+        .map_err(|e| e.or_pruned(self.block.number))?;
+
+        capsules.read_cid(&cid).await
+    }
+
+    /// Reads several document entries at the block of the snapshot. A missing entry is returned as `None`
+    pub async fn read_many<Key, Value>(
+        &self,
+        field_keys: impl IntoIterator<Item = Key>,
+        concurrency: usize,
+    ) -> Result<Vec<Option<Value>>>
+    where
+        Key: Encode,
+        Value: Decode,
+    {
+        self.document
+            .read_many_at(field_keys, self.block.hash, concurrency)
+            .await
+            .map_err(|e| e.or_pruned(self.block.number))
+    }
+}
@@ -1,6 +1,7 @@
 use super::{CapsulesApi, CapsulesConfig};
 use crate::{
     common_types::{Events, User},
+    error::{Result, TitanhError},
     titanh::{
         self,
         capsules::calls::types::change_container_status::ContainerStatus,
@@ -8,7 +9,6 @@ use crate::{
     },
     DocumentApi,
 };
-use anyhow::Result;
 use codec::Encode;
 use sp_core::{Blake2Hasher, Hasher, H256};
 
@@ -18,12 +18,12 @@ pub struct ContainerApi<'a> {
 }
 
 impl<'a> TryFrom<&'a CapsulesApi<'a>> for ContainerApi<'a> {
-    type Error = anyhow::Error;
-    fn try_from(capsules: &'a CapsulesApi) -> Result<Self, anyhow::Error> {
+    type Error = TitanhError;
+    fn try_from(capsules: &'a CapsulesApi) -> Result<Self> {
         if let Some(config) = &capsules.config {
             Ok(Self { capsules, config })
         } else {
-            Err(anyhow::anyhow!("Capsules API not configured"))
+            Err(TitanhError::NotConfigured("Capsules API"))
         }
     }
 }
@@ -1,29 +1,33 @@
 use crate::{
-    common_types::{BlockNumber, ConsistencyLevel, Events, User},
+    cid_types::parse_cid,
+    common_types::{BlockHash, BlockInfo, BlockNumber, ConsistencyLevel, Events, User},
+    error::{Result, TitanhError},
+    ipfs::read_verified,
+    ipfs_pool::{IpfsEndpoint, IpfsPool, IpfsPoolOptions},
     titanh::{
         self,
         capsules::calls::types::upload_capsule::App,
         runtime_types::{
-            pallet_capsules::{
-                capsule::types::CapsuleUploadData, pallet::Call, types::FollowersStatus,
-            },
+            pallet_capsules::{pallet::Call, types::FollowersStatus},
             titanh_runtime::RuntimeCall,
         },
         utility::calls::types::batch_all::Calls,
     },
+    tx::TxHandle,
     ContainerApi, TitanhApi,
 };
-use anyhow::{Ok, Result};
 use codec::{Decode, Encode};
-use futures::TryStreamExt;
-use ipfs_api_backend_hyper::{request::Add, IpfsApi, IpfsClient, TryFromUri};
+use futures::{stream, StreamExt, TryStreamExt};
+use ipfs_api_backend_hyper::response::AddResponse;
 use sp_core::H256;
-use std::io::Cursor;
-use types::{CapsulesBatch, GetCapsuleOpts, PutCapsuleOpts, UpdateCapsuleOpts};
-use utils::convert_bounded_str;
+use std::sync::Arc;
+use types::{
+    CapsulesBatch, GetCapsuleOpts, GetManyOpts, IpfsAddOpts, PutCapsuleOpts, UpdateCapsuleOpts,
+};
+use utils::{convert_bounded_str, fill_fetched, split_cached};
 
 pub struct CapsulesConfig {
-    ipfs: IpfsClient,
+    ipfs: IpfsPool,
     app: App,
 }
 
@@ -44,7 +48,20 @@ impl<'a> From<&'a TitanhApi> for CapsulesApi<'a> {
 impl<'a> CapsulesApi<'a> {
     /// Provides the IPFS RPC URL and the app id as configuration
     pub fn config(self, ipfs_rpc_url: &str, app: App) -> Result<Self> {
-        let ipfs = IpfsClient::from_str(ipfs_rpc_url)?;
+        let endpoints = vec![IpfsEndpoint::Rpc(ipfs_rpc_url.to_string())];
+
+        self.config_endpoints(endpoints, IpfsPoolOptions::default(), app)
+    }
+
+    /// Provides several IPFS endpoints and the app id as configuration, so that the capsules are still read and written when an endpoint is down.
+    /// The gateways are read-only, and only used for the reads
+    pub fn config_endpoints(
+        self,
+        endpoints: Vec<IpfsEndpoint>,
+        options: IpfsPoolOptions,
+        app: App,
+    ) -> Result<Self> {
+        let ipfs = IpfsPool::new(endpoints, options)?;
         Ok(Self {
             config: Some(CapsulesConfig { ipfs, app }),
             ..self
@@ -64,7 +81,8 @@ impl<'a> CapsulesApi<'a> {
     }
 
     /// Put a new object identified by `id` to IPFS and add the metadata to the chain. The transaction is async by means of not waiting for block inclusion, but just an inclusion in the transaction pool.
-    pub async fn put_async<Id, Value>(&self, id: Id, data: Value) -> Result<H256>
+    /// The returned handle tracks whether the transaction is later included, finalized or dropped
+    pub async fn put_async<Id, Value>(&self, id: Id, data: Value) -> Result<TxHandle>
     where
         Id: Encode,
         Value: Encode,
@@ -73,7 +91,7 @@ impl<'a> CapsulesApi<'a> {
         opts.level = ConsistencyLevel::Eventual;
 
         let tx_hash = self.put_with_options(id, data, opts).await?;
-        Ok(tx_hash)
+        self.titanh.track(tx_hash).await
     }
 
     /// Put a new object identified by `id` to IPFS and add the metadata to the chain, waiting for the transaction to be finalized
@@ -101,37 +119,48 @@ impl<'a> CapsulesApi<'a> {
         Id: Encode,
         Data: Encode,
     {
+        let (cid, size) = self.upload_bytes(data.encode(), &options.ipfs).await?;
+
+        self.submit_capsule(id, cid, size, options).await
+    }
+
+    /// Adds the metadata of a capsule, whose content is already uploaded to IPFS, to the chain
+    async fn submit_capsule<Id: Encode>(
+        &self,
+        id: Id,
+        cid: Vec<u8>,
+        size: u128,
+        options: PutCapsuleOpts,
+    ) -> Result<H256> {
         // Ensure the configuration is set
         let config = self.ensure_config()?;
 
-        let (cid, size) = self.upload_to_ipfs(data).await?;
-        let (retention_blocks, consistency_level) = options.unwrap_fields_or_default();
-        let ending_retention_block =
-            self.titanh.latest_finalized_block().await?.number + retention_blocks;
-
-        // Build the capsule (by default does not allow followers)
-        let capsule = CapsuleUploadData {
-            cid,
-            size,
-            ending_retention_block,
-            followers_status: FollowersStatus::None,
-            encoded_metadata: id.encode(),
+        // The finalized block is not needed for an explicit end of the retention
+        let finalized_block = match options.ending_retention_block {
+            Some(_) => 0,
+            None => self.titanh.latest_finalized_block().await?.number,
         };
+        let capsule = options.upload_data(id.encode(), cid, size, finalized_block);
 
-        let upload_tx = titanh::tx()
-            .capsules()
-            .upload_capsule(config.app, None, capsule);
+        let upload_tx =
+            titanh::tx()
+                .capsules()
+                .upload_capsule(config.app, options.other_owner(), capsule);
 
         let tx_hash = self
             .titanh
-            .sign_and_submit_tx_with_level(&upload_tx, consistency_level)
+            .sign_and_submit_tx_with_level(&upload_tx, options.level)
             .await?;
 
         Ok(tx_hash)
     }
 
-    /// Put a batch of capsules to IPFS and add the metadata to the chain, waiting for transaction pool inclusion
-    pub async fn put_batch_async<Id, Value>(&self, batch: CapsulesBatch<Id, Value>) -> Result<H256>
+    /// Put a batch of capsules to IPFS and add the metadata to the chain, waiting for transaction pool inclusion.
+    /// The returned handle tracks whether the transaction is later included, finalized or dropped
+    pub async fn put_batch_async<Id, Value>(
+        &self,
+        batch: CapsulesBatch<Id, Value>,
+    ) -> Result<TxHandle>
     where
         Id: Encode,
         Value: Encode,
@@ -140,7 +169,7 @@ impl<'a> CapsulesApi<'a> {
         opts.level = ConsistencyLevel::Eventual;
 
         let tx_hash = self.put_batch_with_options(batch, opts).await?;
-        Ok(tx_hash)
+        self.titanh.track(tx_hash).await
     }
 
     /// Put a batch of capsules to IPFS and add the metadata to the chain, waiting for block finalization
@@ -212,12 +241,12 @@ impl<'a> CapsulesApi<'a> {
         Ok(tx_hash)
     }
 
-    /// Removes a capsules. Only waits for transaction pool inclusion
-    pub async fn remove_async<Id: Encode>(&self, id: Id) -> Result<H256> {
+    /// Removes a capsules. Only waits for transaction pool inclusion, the returned handle tracks the transaction
+    pub async fn remove_async<Id: Encode>(&self, id: Id) -> Result<TxHandle> {
         let tx_hash = self
             .remove_with_level(id, ConsistencyLevel::Eventual)
             .await?;
-        Ok(tx_hash)
+        self.titanh.track(tx_hash).await
     }
 
     pub async fn remove_with_level<Id: Encode>(
@@ -228,6 +257,7 @@ impl<'a> CapsulesApi<'a> {
         let config = self.ensure_config()?;
         let capsule_id = self.compute_capsule_id(id, config.app);
 
+        self.invalidate_cache(capsule_id);
         let remove_tx = titanh::tx().capsules().start_destroy_capsule(capsule_id);
 
         let tx_hash = self
@@ -273,6 +303,53 @@ impl<'a> CapsulesApi<'a> {
         Ok(value)
     }
 
+    /// Reads a value as it was at a past block. It fails with `StatePruned` if the node no longer keeps the state of the block,
+    /// and the content must still be available on IPFS
+    pub async fn get_at<Id: Encode, Value: Decode>(
+        &self,
+        id: Id,
+        block_number: BlockNumber,
+    ) -> Result<Value> {
+        let config = self.ensure_config()?;
+
+        let capsule_id = self.compute_capsule_id(id, config.app);
+        let block_hash = self.titanh.block_hash(block_number).await?;
+        let cid = self
+            .capsule_cid_at(capsule_id, Some(block_hash))
+            .await
+            .map_err(|e| e.or_pruned(block_number))?;
+
+        self.read_cid(&cid).await
+    }
+
+    /// Reads several capsules from the latest block, not yet finalized. A missing capsule is returned as `None`
+    pub async fn get_many<Id: Encode, Value: Decode>(
+        &self,
+        ids: impl IntoIterator<Item = Id>,
+    ) -> Result<Vec<Option<Value>>> {
+        self.get_many_with_options(ids, GetManyOpts::default())
+            .await
+    }
+
+    /// Reads several capsules, given the options. The metadata of all the capsules is fetched in a single request at the same block,
+    /// so that the values are a consistent snapshot, and then their content is fetched concurrently from IPFS
+    pub async fn get_many_with_options<Id: Encode, Value: Decode>(
+        &self,
+        ids: impl IntoIterator<Item = Id>,
+        opts: GetManyOpts,
+    ) -> Result<Vec<Option<Value>>> {
+        let config = self.ensure_config()?;
+
+        let capsule_ids = ids
+            .into_iter()
+            .map(|id| Some(self.compute_capsule_id(id, config.app)))
+            .collect();
+        let block = self.snapshot_block(opts.from_finalized_state).await?;
+
+        self.read_capsules(capsule_ids, block.hash, opts.concurrency)
+            .await
+    }
+
     /// Updates the content of a capsule. Waits for block inclusion
     pub async fn update<Id: Encode, Value: Encode>(&self, id: Id, data: Value) -> Result<H256> {
         let tx_hash = self
@@ -281,17 +358,17 @@ impl<'a> CapsulesApi<'a> {
         Ok(tx_hash)
     }
 
-    /// Updates the content of a capsule. Does not wait for block inclusion
+    /// Updates the content of a capsule. Does not wait for block inclusion, the returned handle tracks the transaction
     pub async fn update_async<Id: Encode, Value: Encode>(
         &self,
         id: Id,
         data: Value,
-    ) -> Result<H256> {
+    ) -> Result<TxHandle> {
         let mut opts = UpdateCapsuleOpts::default();
         opts.level = ConsistencyLevel::Eventual;
 
         let tx_hash = self.update_with_options(id, data, opts).await?;
-        Ok(tx_hash)
+        self.titanh.track(tx_hash).await
     }
 
     /// Updates the content of a capsule. Waits for block finalization
@@ -318,13 +395,26 @@ impl<'a> CapsulesApi<'a> {
         let capsule_id = self.compute_capsule_id(id, config.app);
         let (cid, size) = self.upload_to_ipfs(data).await?;
 
+        self.submit_update(capsule_id, cid, size, opts.level).await
+    }
+
+    /// Updates the content of a capsule on chain, with the content already uploaded to IPFS
+    async fn submit_update(
+        &self,
+        capsule_id: H256,
+        cid: Vec<u8>,
+        size: u128,
+        level: ConsistencyLevel,
+    ) -> Result<H256> {
+        self.invalidate_cache(capsule_id);
+
         let update_tx = titanh::tx()
             .capsules()
             .update_capsule_content(capsule_id, cid, size);
 
         let tx_hash = self
             .titanh
-            .sign_and_submit_tx_with_level(&update_tx, opts.level)
+            .sign_and_submit_tx_with_level(&update_tx, level)
             .await?;
 
         Ok(tx_hash)
@@ -436,47 +526,31 @@ impl<'a> CapsulesApi<'a> {
     {
         let config = self.ensure_config()?;
 
-        let (cid, size) = self.upload_to_ipfs(data).await?;
-        let (retention_blocks, _) = opts.unwrap_fields_or_default();
-
-        let ending_retention_block = finalized_block + retention_blocks;
-
-        // Build the capsule
-        let capsule = CapsuleUploadData {
-            cid,
-            size,
-            ending_retention_block,
-            followers_status: FollowersStatus::None,
-            encoded_metadata: id.encode(),
-        };
+        let (cid, size) = self.upload_bytes(data.encode(), &opts.ipfs).await?;
+        let capsule = opts.upload_data(id.encode(), cid, size, finalized_block);
 
         let call = RuntimeCall::Capsules(Call::upload_capsule {
             app: config.app,
-            other_owner: None,
-            capsule: capsule,
+            other_owner: opts.other_owner(),
+            capsule,
         });
 
         Ok(call)
     }
 
     async fn upload_to_ipfs<Data: Encode>(&self, data: Data) -> Result<(Vec<u8>, u128)> {
-        let config = self.ensure_config()?;
-
-        let data = Cursor::new(data.encode());
+        self.upload_bytes(data.encode(), &IpfsAddOpts::default())
+            .await
+    }
 
-        // Do not pin the data
-        let mut add_opts = Add::default();
-        add_opts.pin = Some(false);
-        // Add the data to IPFS
-        let ipfs_res = config.ipfs.add_with_options(data, add_opts).await?;
+    /// Adds already encoded data to IPFS with the given options, returning its cid and size
+    async fn upload_bytes(&self, data: Vec<u8>, opts: &IpfsAddOpts) -> Result<(Vec<u8>, u128)> {
+        let config = self.ensure_config()?;
 
-        let cid = ipfs_res.hash.as_bytes().to_vec();
-        let size: u128 = ipfs_res
-            .size
-            .parse()
-            .expect("Content size is expected to be a valid number");
+        // By default the data is not pinned
+        let ipfs_res = config.ipfs.add(data, opts).await?;
 
-        Ok((cid, size))
+        uploaded_content(ipfs_res)
     }
 
     pub async fn read_capsule<Value: Decode>(
@@ -484,9 +558,103 @@ impl<'a> CapsulesApi<'a> {
         capsule_id: H256,
         from_finalized_state: bool,
     ) -> Result<Value> {
+        let cid = self.capsule_cid(capsule_id, from_finalized_state).await?;
+
+        self.read_cid(&cid).await
+    }
+
+    /// Returns the block a batch of reads is served from
+    async fn snapshot_block(&self, from_finalized_state: bool) -> Result<BlockInfo> {
+        if from_finalized_state {
+            self.titanh.latest_finalized_block().await
+        } else {
+            self.titanh.latest_block().await
+        }
+    }
+
+    /// Reads the capsules at a block, fetching their content with the given concurrency. The `None` ids are returned as `None`
+    async fn read_capsules<Value: Decode>(
+        &self,
+        capsule_ids: Vec<Option<H256>>,
+        at: BlockHash,
+        concurrency: usize,
+    ) -> Result<Vec<Option<Value>>> {
+        let cids = self.capsule_cids_at(&capsule_ids, at).await?;
+
+        // The order of the values is kept
+        let values = stream::iter(cids)
+            .map(|cid| async move {
+                match cid {
+                    Some(cid) => self.read_cid(&cid).await.map(Some),
+                    None => Ok(None),
+                }
+            })
+            .buffered(concurrency.max(1))
+            .try_collect()
+            .await?;
+
+        Ok(values)
+    }
+
+    /// Returns the cids of the capsules at a block, fetching the ones not cached in a single request
+    async fn capsule_cids_at(
+        &self,
+        capsule_ids: &[Option<H256>],
+        at: BlockHash,
+    ) -> Result<Vec<Option<String>>> {
+        let cache = self.titanh.cache.as_ref();
+        let generation = cache.map_or(0, |cache| cache.generation());
+        let (mut cids, missing) = split_cached(capsule_ids, |capsule_id| {
+            cache.and_then(|cache| cache.cid(capsule_id, Some(at)))
+        });
+
+        let queries: Vec<_> = missing
+            .iter()
+            .map(|(_, capsule_id)| titanh::storage().capsules().capsules(*capsule_id))
+            .collect();
+        let capsules = self.titanh.query_many(&queries, at).await?;
+
+        let mut fetched = Vec::with_capacity(capsules.len());
+        for ((_, capsule_id), capsule) in missing.iter().zip(capsules) {
+            let cid = capsule
+                .map(|capsule| convert_bounded_str(capsule.cid))
+                .transpose()?;
+            if let (Some(cache), Some(cid)) = (cache, &cid) {
+                cache.insert_cid(*capsule_id, Some(at), cid.clone(), generation);
+            }
+            fetched.push(cid);
+        }
+        fill_fetched(&mut cids, &missing, fetched);
+
+        Ok(cids)
+    }
+
+    /// Reads and decodes the content of a cid
+    async fn read_cid<Value: Decode>(&self, cid: &str) -> Result<Value> {
         let config = self.ensure_config()?;
-        let capsule_query = titanh::storage().capsules().capsules(capsule_id);
 
+        let cache = self.titanh.cache.as_ref();
+        let response = match cache.and_then(|cache| cache.content(cid)) {
+            Some(content) => content,
+            // The content is verified against the cid, so that neither the IPFS endpoint nor the disk cache can return forged data
+            None => match cache {
+                Some(cache) => {
+                    let blocks = cache.blocks(config.ipfs.clone());
+                    let content: Arc<[u8]> = read_verified(&blocks, cid).await?.into();
+                    cache.insert_content(cid, content.clone());
+                    content
+                }
+                None => read_verified(&config.ipfs, cid).await?.into(),
+            },
+        };
+
+        let value = Value::decode(&mut &response[..])?;
+
+        Ok(value)
+    }
+
+    /// Returns the cid of the capsule content
+    async fn capsule_cid(&self, capsule_id: H256, from_finalized_state: bool) -> Result<String> {
         let at = if from_finalized_state {
             Some(self.titanh.latest_finalized_block().await?)
         } else {
@@ -494,20 +662,38 @@ impl<'a> CapsulesApi<'a> {
         }
         .map(|block| block.hash);
 
-        let capsule = self.titanh.query(&capsule_query, at).await?;
-        let cid = convert_bounded_str(capsule.cid)?;
+        self.capsule_cid_at(capsule_id, at).await
+    }
 
-        let response = config
-            .ipfs
-            .cat(&cid)
-            .map_ok(|chunk| chunk.to_vec())
-            .try_concat()
-            .await
-            .map_err(|_| anyhow::anyhow!("error reading full file"))?;
+    /// Returns the cid of the capsule content at a block, or at the latest one
+    async fn capsule_cid_at(&self, capsule_id: H256, at: Option<BlockHash>) -> Result<String> {
+        let cache = self.titanh.cache.as_ref();
+        if let Some(cid) = cache.and_then(|cache| cache.cid(capsule_id, at)) {
+            return Ok(cid);
+        }
+        // Taken before the read, so that a change of the capsule in the meantime is not cached
+        let generation = cache.map_or(0, |cache| cache.generation());
 
-        let value = Value::decode(&mut &response[..])?;
+        let capsule_query = titanh::storage().capsules().capsules(capsule_id);
 
-        Ok(value)
+        let capsule = match self.titanh.query(&capsule_query, at).await {
+            Err(TitanhError::NotInStorage) => Err(TitanhError::CapsuleNotFound),
+            res => res,
+        }?;
+
+        let cid = convert_bounded_str(capsule.cid)?;
+        if let Some(cache) = cache {
+            cache.insert_cid(capsule_id, at, cid.clone(), generation);
+        }
+
+        Ok(cid)
+    }
+
+    /// Invalidates the cached metadata of a capsule written by the client, before the event of the change is received
+    fn invalidate_cache(&self, capsule_id: H256) {
+        if let Some(cache) = &self.titanh.cache {
+            cache.invalidate(capsule_id);
+        }
     }
 
     pub fn rm_capsule_call(&self, capsule_id: H256) -> RuntimeCall {
@@ -520,6 +706,22 @@ impl<'a> CapsulesApi<'a> {
     }
 }
 
+/// Returns the cid and the size of the content added to IPFS. The CID is validated before it is submitted to the chain
+fn uploaded_content(ipfs_res: AddResponse) -> Result<(Vec<u8>, u128)> {
+    let cid = parse_cid(ipfs_res.hash.as_bytes())?
+        .to_string()
+        .into_bytes();
+    let size: u128 = ipfs_res
+        .size
+        .parse()
+        .expect("Content size is expected to be a valid number");
+
+    Ok((cid, size))
+}
+
 pub mod container;
+pub mod session;
+pub mod stream;
+pub mod transaction;
 pub mod types;
 pub mod utils;
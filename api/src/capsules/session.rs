@@ -0,0 +1,390 @@
+use super::CapsulesApi;
+use crate::{
+    capsules_types::PutCapsuleOpts,
+    common_types::{BlockInfo, BlockNumber, ConsistencyLevel},
+    error::{Result, TitanhError},
+    tx::{TxHandle, TxStatus},
+};
+use codec::{Decode, Encode};
+use sp_core::H256;
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// The interval between the checks of the latest block, while waiting for the chain to catch up with the session
+const CATCH_UP_INTERVAL: Duration = Duration::from_millis(500);
+/// The maximum time to wait for the chain to catch up with the session
+const CATCH_UP_TIMEOUT: Duration = Duration::from_secs(60);
+
+impl CapsulesApi<'_> {
+    /// Opens a session with read-your-writes and monotonic reads guarantees
+    pub fn session(&self) -> Session<'_> {
+        Session::new(self)
+    }
+}
+
+/// A session over the capsules api, giving read-your-writes and monotonic reads without waiting for finality.
+/// The writes are submitted with `ConsistencyLevel::Eventual`, and the session records the cid of each of them until it is included in a block.
+/// A read of a capsule with a pending write returns the written value, and the other reads are served from a block not older than
+/// the blocks of the session writes and reads, waiting for the chain to catch up if needed.
+pub struct Session<'a> {
+    capsules: &'a CapsulesApi<'a>,
+    state: Mutex<SessionState>,
+}
+
+#[derive(Default)]
+struct SessionState {
+    /// The writes not yet finalized, by capsule id
+    writes: HashMap<H256, PendingWrite>,
+    /// The lowest block the reads of the session can be served from
+    min_block: BlockNumber,
+    /// The sequence number of the last recorded write
+    last_write: u64,
+}
+
+struct PendingWrite {
+    /// The sequence number of the write, to tell it apart from a later write of the same capsule
+    seq: u64,
+    /// The cid of the written content, `None` if the capsule is removed
+    cid: Option<String>,
+    /// The handle of the transaction, `None` while it is being submitted
+    tx: Option<TxHandle>,
+}
+
+impl<'a> Session<'a> {
+    pub fn new(capsules: &'a CapsulesApi<'a>) -> Self {
+        Self {
+            capsules,
+            state: Mutex::new(SessionState::default()),
+        }
+    }
+
+    /// Puts a new capsule identified by `id`, without waiting for block inclusion
+    pub async fn put<Id, Value>(&self, id: Id, data: Value) -> Result<TxHandle>
+    where
+        Id: Encode,
+        Value: Encode,
+    {
+        self.put_with_options(id, data, PutCapsuleOpts::default())
+            .await
+    }
+
+    /// Puts a new capsule identified by `id`, given the options. The transaction is always submitted with `ConsistencyLevel::Eventual`
+    pub async fn put_with_options<Id, Value>(
+        &self,
+        id: Id,
+        data: Value,
+        mut options: PutCapsuleOpts,
+    ) -> Result<TxHandle>
+    where
+        Id: Encode,
+        Value: Encode,
+    {
+        let config = self.capsules.ensure_config()?;
+        let capsule_id = self.capsules.compute_capsule_id(&id, config.app);
+
+        let (cid, size) = self
+            .capsules
+            .upload_bytes(data.encode(), &options.ipfs)
+            .await?;
+        options.level = ConsistencyLevel::Eventual;
+        let seq = self.begin_write(capsule_id, Some(&cid));
+        let tx_hash = self.capsules.submit_capsule(id, cid, size, options).await;
+
+        self.record(capsule_id, seq, tx_hash).await
+    }
+
+    /// Updates the content of a capsule, without waiting for block inclusion
+    pub async fn update<Id: Encode, Value: Encode>(&self, id: Id, data: Value) -> Result<TxHandle> {
+        let config = self.capsules.ensure_config()?;
+        let capsule_id = self.capsules.compute_capsule_id(id, config.app);
+
+        let (cid, size) = self.capsules.upload_to_ipfs(data).await?;
+        let seq = self.begin_write(capsule_id, Some(&cid));
+        let tx_hash = self
+            .capsules
+            .submit_update(capsule_id, cid, size, ConsistencyLevel::Eventual)
+            .await;
+
+        self.record(capsule_id, seq, tx_hash).await
+    }
+
+    /// Removes a capsule, without waiting for block inclusion
+    pub async fn remove<Id: Encode>(&self, id: Id) -> Result<TxHandle> {
+        let config = self.capsules.ensure_config()?;
+        let capsule_id = self.capsules.compute_capsule_id(&id, config.app);
+
+        let seq = self.begin_write(capsule_id, None);
+        let tx_hash = self
+            .capsules
+            .remove_with_level(id, ConsistencyLevel::Eventual)
+            .await;
+
+        self.record(capsule_id, seq, tx_hash).await
+    }
+
+    /// Reads a capsule. If the session has a pending write of the capsule the written value is returned,
+    /// otherwise the capsule is read from a block not older than the ones already observed by the session
+    pub async fn get<Id: Encode, Value: Decode>(&self, id: Id) -> Result<Value> {
+        let config = self.capsules.ensure_config()?;
+        let capsule_id = self.capsules.compute_capsule_id(id, config.app);
+
+        let local_write = self.state.lock().unwrap().local_write(capsule_id);
+        match local_write {
+            Some(Some(cid)) => return self.capsules.read_cid(&cid).await,
+            Some(None) => return Err(TitanhError::CapsuleNotFound),
+            None => {}
+        }
+
+        let min_block = self.state.lock().unwrap().min_block;
+        let block = self.latest_block_from(min_block).await?;
+        // The next reads are not served from an older block
+        {
+            let mut state = self.state.lock().unwrap();
+            state.min_block = state.min_block.max(block.number);
+        }
+
+        let cid = self
+            .capsules
+            .capsule_cid_at(capsule_id, Some(block.hash))
+            .await?;
+        self.capsules.read_cid(&cid).await
+    }
+
+    /// Waits for all the submitted writes of the session to be included in a block, so that the next reads are served from the chain
+    pub async fn sync(&self) -> Result<()> {
+        let txs: Vec<TxHandle> = self
+            .state
+            .lock()
+            .unwrap()
+            .writes
+            .values()
+            .filter_map(|write| write.tx.clone())
+            .collect();
+
+        for tx in txs {
+            let block = tx.await_in_block().await?;
+            let mut state = self.state.lock().unwrap();
+            state.min_block = state.min_block.max(block.number);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the number of writes of the session that are not yet finalized
+    pub fn pending_writes(&self) -> usize {
+        self.state.lock().unwrap().writes.len()
+    }
+
+    /// Records a write before its transaction is submitted, so that a concurrent read already returns the written value
+    fn begin_write(&self, capsule_id: H256, cid: Option<&[u8]>) -> u64 {
+        // The cid is built from the IPFS response, so it is a valid string
+        let cid = cid.map(|cid| String::from_utf8_lossy(cid).into_owned());
+        self.state.lock().unwrap().begin_write(capsule_id, cid)
+    }
+
+    /// Tracks the transaction of a recorded write, or drops the write if the transaction could not be submitted
+    async fn record(&self, capsule_id: H256, seq: u64, tx_hash: Result<H256>) -> Result<TxHandle> {
+        let tx = match tx_hash {
+            Ok(tx_hash) => self.capsules.titanh.track(tx_hash).await,
+            Err(e) => Err(e),
+        };
+
+        let mut state = self.state.lock().unwrap();
+        match tx {
+            Ok(tx) => {
+                state.submitted(capsule_id, seq, tx.clone());
+                Ok(tx)
+            }
+            Err(e) => {
+                state.abort_write(capsule_id, seq);
+                Err(e)
+            }
+        }
+    }
+
+    /// Returns the latest block, waiting for the chain to reach the given block number
+    async fn latest_block_from(&self, number: BlockNumber) -> Result<BlockInfo> {
+        let start = Instant::now();
+        loop {
+            let block = self.capsules.titanh.latest_block().await?;
+            if block.number >= number {
+                return Ok(block);
+            }
+            if start.elapsed() >= CATCH_UP_TIMEOUT {
+                return Err(TitanhError::CatchUpTimeout(number));
+            }
+
+            tokio::time::sleep(CATCH_UP_INTERVAL).await;
+        }
+    }
+}
+
+impl SessionState {
+    /// Records a write not yet submitted, replacing the previous write of the capsule. Returns the sequence number of the write
+    fn begin_write(&mut self, capsule_id: H256, cid: Option<String>) -> u64 {
+        self.last_write += 1;
+        let write = PendingWrite {
+            seq: self.last_write,
+            cid,
+            tx: None,
+        };
+        self.writes.insert(capsule_id, write);
+
+        self.last_write
+    }
+
+    /// Sets the transaction of a write, unless a later write of the capsule replaced it
+    fn submitted(&mut self, capsule_id: H256, seq: u64, tx: TxHandle) {
+        if let Some(write) = self
+            .writes
+            .get_mut(&capsule_id)
+            .filter(|write| write.seq == seq)
+        {
+            write.tx = Some(tx);
+        }
+    }
+
+    /// Drops a write whose transaction could not be submitted, unless a later write of the capsule replaced it
+    fn abort_write(&mut self, capsule_id: H256, seq: u64) {
+        if self
+            .writes
+            .get(&capsule_id)
+            .is_some_and(|write| write.seq == seq)
+        {
+            self.writes.remove(&capsule_id);
+        }
+    }
+
+    /// Returns the content of the pending write of a capsule not yet in a block: `Some(None)` if the capsule is removed.
+    /// A write in a block raises the lowest block of the reads, and it is no longer tracked once finalized or dropped
+    fn local_write(&mut self, capsule_id: H256) -> Option<Option<String>> {
+        let write = self.writes.get(&capsule_id)?;
+        // A write being submitted is returned as well
+        let status = write
+            .tx
+            .as_ref()
+            .map_or(TxStatus::Pending, TxHandle::status);
+        match status {
+            TxStatus::Pending => return Some(write.cid.clone()),
+            TxStatus::InBlock(block) => self.min_block = self.min_block.max(block.number),
+            TxStatus::Finalized(block) => {
+                self.min_block = self.min_block.max(block.number);
+                self.writes.remove(&capsule_id);
+            }
+            // The write will not be applied, so the chain state is read
+            TxStatus::Dropped => {
+                self.writes.remove(&capsule_id);
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common_types::BlockHash;
+
+    fn capsule(n: u8) -> H256 {
+        H256::repeat_byte(n)
+    }
+
+    fn block(number: BlockNumber) -> BlockInfo {
+        BlockInfo::new(number, BlockHash(H256::repeat_byte(number as u8)))
+    }
+
+    /// A session state with a submitted write of capsule 1, in the given status
+    fn state_with(cid: Option<&str>, status: TxStatus) -> SessionState {
+        let mut state = SessionState::default();
+        let seq = state.begin_write(capsule(1), cid.map(str::to_string));
+        state.submitted(capsule(1), seq, TxHandle::resolved(H256::zero(), status));
+        state
+    }
+
+    #[test]
+    fn write_being_submitted_is_returned_test() {
+        let mut state = SessionState::default();
+        state.begin_write(capsule(1), Some("cid".to_string()));
+
+        assert_eq!(state.local_write(capsule(1)), Some(Some("cid".to_string())));
+        assert_eq!(state.local_write(capsule(2)), None);
+    }
+
+    #[test]
+    fn pending_write_is_returned_test() {
+        let mut state = state_with(Some("cid"), TxStatus::Pending);
+        assert_eq!(state.local_write(capsule(1)), Some(Some("cid".to_string())));
+
+        let mut state = state_with(None, TxStatus::Pending);
+        assert_eq!(state.local_write(capsule(1)), Some(None));
+        assert_eq!(state.min_block, 0);
+    }
+
+    #[test]
+    fn write_in_block_raises_the_min_block_test() {
+        let mut state = state_with(Some("cid"), TxStatus::InBlock(block(7)));
+
+        assert_eq!(state.local_write(capsule(1)), None);
+        assert_eq!(state.min_block, 7);
+        // It is still tracked, as the block could be retracted
+        assert_eq!(state.writes.len(), 1);
+    }
+
+    #[test]
+    fn finalized_write_is_no_longer_tracked_test() {
+        let mut state = state_with(Some("cid"), TxStatus::Finalized(block(9)));
+
+        assert_eq!(state.local_write(capsule(1)), None);
+        assert_eq!(state.min_block, 9);
+        assert!(state.writes.is_empty());
+    }
+
+    #[test]
+    fn dropped_write_is_no_longer_tracked_test() {
+        let mut state = state_with(Some("cid"), TxStatus::Dropped);
+
+        assert_eq!(state.local_write(capsule(1)), None);
+        assert_eq!(state.min_block, 0);
+        assert!(state.writes.is_empty());
+    }
+
+    #[test]
+    fn aborted_write_is_dropped_test() {
+        let mut state = SessionState::default();
+        let seq = state.begin_write(capsule(1), Some("cid".to_string()));
+        state.abort_write(capsule(1), seq);
+
+        assert_eq!(state.local_write(capsule(1)), None);
+        assert!(state.writes.is_empty());
+    }
+
+    #[test]
+    fn later_write_is_kept_test() {
+        let mut state = SessionState::default();
+        let first = state.begin_write(capsule(1), Some("first".to_string()));
+        let second = state.begin_write(capsule(1), Some("second".to_string()));
+
+        // The outcome of the first write does not affect the second one
+        state.submitted(
+            capsule(1),
+            first,
+            TxHandle::resolved(H256::zero(), TxStatus::Dropped),
+        );
+        state.abort_write(capsule(1), first);
+        assert_eq!(
+            state.local_write(capsule(1)),
+            Some(Some("second".to_string()))
+        );
+
+        state.submitted(
+            capsule(1),
+            second,
+            TxHandle::resolved(H256::zero(), TxStatus::Dropped),
+        );
+        assert_eq!(state.local_write(capsule(1)), None);
+    }
+}
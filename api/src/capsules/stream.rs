@@ -0,0 +1,244 @@
+use super::{uploaded_content, CapsulesApi};
+use crate::{
+    capsules_types::{GetCapsuleOpts, PutCapsuleOpts},
+    error::Result,
+    ipfs::verified_reader,
+};
+use codec::Encode;
+use futures::{ready, AsyncRead, AsyncWriteExt};
+use sp_core::H256;
+use std::{
+    io,
+    path::Path,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
+
+/// The chunker used by IPFS to split streamed content into blocks
+const CHUNKER: &str = "size-262144";
+
+/// Streaming of large capsules. The content is not SCALE encoded: it is uploaded to IPFS as a raw file, chunked by IPFS, and read back block by block.
+/// The SCALE based `put` and `get` are meant for small typed values.
+impl CapsulesApi<'_> {
+    /// Streams the content of a new capsule identified by `id` to IPFS and adds the metadata to the chain with default options
+    pub async fn put_stream<Id, R>(&self, id: Id, data: R) -> Result<H256>
+    where
+        Id: Encode,
+        R: AsyncRead + Send + Sync + Unpin + 'static,
+    {
+        self.put_stream_with_options(id, data, PutCapsuleOpts::default())
+            .await
+    }
+
+    /// Streams the content of a new capsule identified by `id` to IPFS and adds the metadata to the chain, given the options
+    pub async fn put_stream_with_options<Id, R>(
+        &self,
+        id: Id,
+        data: R,
+        options: PutCapsuleOpts,
+    ) -> Result<H256>
+    where
+        Id: Encode,
+        R: AsyncRead + Send + Sync + Unpin + 'static,
+    {
+        let config = self.ensure_config()?;
+
+        // The content is chunked with fixed size blocks, unless another chunker is given
+        let mut add_opts = options.ipfs.to_add();
+        add_opts.chunker = add_opts.chunker.or(Some(CHUNKER));
+        let ipfs_res = config.ipfs.add_async(data, add_opts).await?;
+        let (cid, size) = uploaded_content(ipfs_res)?;
+
+        self.submit_capsule(id, cid, size, options).await
+    }
+
+    /// Uploads a file as the content of a new capsule. `on_progress` is called with the number of bytes read from the file so far
+    pub async fn put_file<Id, F>(
+        &self,
+        id: Id,
+        path: impl AsRef<Path>,
+        options: PutCapsuleOpts,
+        on_progress: F,
+    ) -> Result<H256>
+    where
+        Id: Encode,
+        F: FnMut(u64) + Send + Sync + Unpin + 'static,
+    {
+        let file = tokio::fs::File::open(path).await?;
+        let reader = ProgressReader::new(file.compat(), on_progress);
+
+        self.put_stream_with_options(id, reader, options).await
+    }
+
+    /// Streams the content of a capsule from the latest block, not yet finalized
+    pub async fn get_stream<Id: Encode>(&self, id: Id) -> Result<impl AsyncRead + Unpin> {
+        self.get_stream_with_options(id, GetCapsuleOpts::default())
+            .await
+    }
+
+    /// Streams the content of a capsule, verifying each block against the capsule cid.
+    /// A content that does not match the cid fails the read with an `InvalidData` io error holding the `IntegrityError`
+    pub async fn get_stream_with_options<Id: Encode>(
+        &self,
+        id: Id,
+        opts: GetCapsuleOpts,
+    ) -> Result<impl AsyncRead + Unpin> {
+        let config = self.ensure_config()?;
+
+        let capsule_id = self.compute_capsule_id(id, config.app);
+        let cid = self
+            .capsule_cid(capsule_id, opts.from_finalized_state)
+            .await?;
+
+        verified_reader(config.ipfs.clone(), &cid)
+    }
+
+    /// Downloads the content of a capsule to a file, returning its size. `on_progress` is called with the number of bytes written so far.
+    /// If the download fails the partial file is removed
+    pub async fn get_file<Id, F>(
+        &self,
+        id: Id,
+        path: impl AsRef<Path>,
+        opts: GetCapsuleOpts,
+        on_progress: F,
+    ) -> Result<u64>
+    where
+        Id: Encode,
+        F: FnMut(u64) + Unpin,
+    {
+        let path = path.as_ref();
+        let reader =
+            ProgressReader::new(self.get_stream_with_options(id, opts).await?, on_progress);
+
+        let mut file = tokio::fs::File::create(path).await?.compat_write();
+        let res = async {
+            let size = futures::io::copy(reader, &mut file).await?;
+            file.close().await?;
+            Ok::<_, io::Error>(size)
+        }
+        .await;
+
+        match res {
+            Ok(size) => Ok(size),
+            Err(e) => {
+                let _ = tokio::fs::remove_file(path).await;
+                Err(e.into())
+            }
+        }
+    }
+}
+
+/// A reader that reports the number of bytes read so far, e.g. to track the progress of a streamed capsule
+pub struct ProgressReader<R, F> {
+    inner: R,
+    read: u64,
+    on_progress: F,
+}
+
+impl<R, F: FnMut(u64)> ProgressReader<R, F> {
+    pub fn new(inner: R, on_progress: F) -> Self {
+        Self {
+            inner,
+            read: 0,
+            on_progress,
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin, F: FnMut(u64) + Unpin> AsyncRead for ProgressReader<R, F> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let read = ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+        if read > 0 {
+            this.read += read as u64;
+            (this.on_progress)(this.read);
+        }
+
+        Poll::Ready(Ok(read))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{io::Cursor, AsyncReadExt};
+    use std::sync::{Arc, Mutex};
+
+    /// A reader that fails after returning its content
+    struct FailingReader(Cursor<Vec<u8>>);
+
+    impl AsyncRead for FailingReader {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            match ready!(Pin::new(&mut self.0).poll_read(cx, buf))? {
+                0 => Poll::Ready(Err(io::Error::other("connection reset"))),
+                read => Poll::Ready(Ok(read)),
+            }
+        }
+    }
+
+    fn progress_reader<R>(
+        inner: R,
+    ) -> (
+        ProgressReader<R, impl FnMut(u64) + Unpin>,
+        Arc<Mutex<Vec<u64>>>,
+    ) {
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let on_progress = {
+            let reported = reported.clone();
+            move |read: u64| reported.lock().unwrap().push(read)
+        };
+
+        (ProgressReader::new(inner, on_progress), reported)
+    }
+
+    #[tokio::test]
+    async fn progress_is_cumulative_test() {
+        let (mut reader, reported) = progress_reader(Cursor::new(vec![7u8; 10]));
+
+        let mut buf = [0u8; 4];
+        let mut content = Vec::new();
+        loop {
+            let read = reader.read(&mut buf).await.unwrap();
+            if read == 0 {
+                break;
+            }
+            content.extend_from_slice(&buf[..read]);
+        }
+
+        assert_eq!(content, vec![7u8; 10]);
+        // The end of the content is not reported again
+        assert_eq!(*reported.lock().unwrap(), vec![4, 8, 10]);
+    }
+
+    #[tokio::test]
+    async fn empty_content_reports_nothing_test() {
+        let (mut reader, reported) = progress_reader(Cursor::new(Vec::new()));
+
+        let mut content = Vec::new();
+        reader.read_to_end(&mut content).await.unwrap();
+
+        assert!(content.is_empty());
+        assert!(reported.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn read_error_is_forwarded_test() {
+        let inner = FailingReader(Cursor::new(vec![1u8; 3]));
+        let (mut reader, reported) = progress_reader(inner);
+
+        let mut content = Vec::new();
+        let err = reader.read_to_end(&mut content).await.unwrap_err();
+
+        assert_eq!(err.to_string(), "connection reset");
+        assert_eq!(*reported.lock().unwrap(), vec![3]);
+    }
+}
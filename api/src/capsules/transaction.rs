@@ -0,0 +1,457 @@
+use super::{CapsulesApi, CapsulesConfig};
+use crate::{
+    capsules_types::{IpfsAddOpts, PutCapsuleOpts},
+    common_types::{ConsistencyLevel, Extrinsic, User, MAX_NORMAL_BLOCK_SIZE},
+    error::{Result, TitanhError},
+    titanh::{
+        self,
+        runtime_types::{
+            pallet_capsules::{pallet::Call, types::FollowersStatus},
+            titanh_runtime::RuntimeCall,
+        },
+        utility::calls::types::batch_all::Calls,
+    },
+    tx::TxHandle,
+};
+use codec::Encode;
+use futures::future::try_join_all;
+use sp_core::H256;
+use tokio::sync::OnceCell;
+
+/// The cids and sizes of the uploaded contents, by operation
+type Uploads = Vec<Option<(Vec<u8>, u128)>>;
+
+impl CapsulesApi<'_> {
+    /// Returns a builder of a transaction composing several operations, applied all or nothing
+    pub fn tx(&self) -> Result<TitanhTx<'_>> {
+        let config = self.ensure_config()?;
+
+        Ok(TitanhTx {
+            capsules: self,
+            config,
+            batch: Batch::default(),
+            max_fee: None,
+        })
+    }
+}
+
+/// The size and the fee of a transaction built by `TitanhTx`
+#[derive(Clone, Copy, Debug)]
+pub struct TxEstimate {
+    /// The size in bytes of the signed transaction
+    pub size: usize,
+    /// The partial fee of the transaction, without the tip
+    pub fee: u128,
+}
+
+/// A builder of a transaction composing capsule puts, updates and removes, container attaches and detaches, ownership and follower calls
+/// into a single `utility.batch_all`, so that they are applied all or nothing.
+/// On submission the contents are uploaded to IPFS first, then the batch is signed, checked against the maximum block size and its fee is estimated.
+pub struct TitanhTx<'a> {
+    capsules: &'a CapsulesApi<'a>,
+    config: &'a CapsulesConfig,
+    batch: Batch,
+    /// The eventual maximum fee the transaction can be submitted with
+    max_fee: Option<u128>,
+}
+
+enum Operation {
+    /// A new capsule, whose content is uploaded first
+    Put {
+        encoded_metadata: Vec<u8>,
+        data: Vec<u8>,
+        options: PutCapsuleOpts,
+    },
+    /// A new content of a capsule, uploaded first
+    Update {
+        capsule_id: H256,
+        data: Vec<u8>,
+    },
+    Call(RuntimeCall),
+}
+
+/// The operations of a transaction, with the contents uploaded for them
+#[derive(Default)]
+struct Batch {
+    operations: Vec<Operation>,
+    /// The contents uploaded by the first estimate or submission, so that they are not uploaded again
+    uploads: OnceCell<Uploads>,
+}
+
+impl Batch {
+    /// Adds an operation, dropping the contents uploaded for the previous ones as the calls are built again
+    fn push(&mut self, operation: Operation) {
+        self.operations.push(operation);
+        self.uploads = OnceCell::new();
+    }
+
+    fn ensure_not_empty(&self) -> Result<()> {
+        if self.operations.is_empty() {
+            return Err(TitanhError::invalid_input(
+                "The transaction has no operations",
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// The capsules whose content is updated or that are destroyed by the transaction
+    fn written_capsules(&self) -> impl Iterator<Item = H256> + '_ {
+        self.operations
+            .iter()
+            .filter_map(|operation| match operation {
+                Operation::Update { capsule_id, .. } => Some(*capsule_id),
+                Operation::Call(RuntimeCall::Capsules(Call::start_destroy_capsule {
+                    capsule_id,
+                })) => Some(*capsule_id),
+                _ => None,
+            })
+    }
+}
+
+impl<'a> TitanhTx<'a> {
+    /// Puts a new capsule identified by `id` with default options
+    pub fn put<Id: Encode, Value: Encode>(self, id: Id, data: Value) -> Self {
+        self.put_with_options(id, data, PutCapsuleOpts::default())
+    }
+
+    /// Puts a new capsule identified by `id`. The consistency level of the options is ignored, since it is given on submission.
+    /// The other options apply as in `CapsulesApi::put_with_options`
+    pub fn put_with_options<Id: Encode, Value: Encode>(
+        mut self,
+        id: Id,
+        data: Value,
+        options: PutCapsuleOpts,
+    ) -> Self {
+        self.push(Operation::Put {
+            encoded_metadata: id.encode(),
+            data: data.encode(),
+            options,
+        })
+    }
+
+    /// Updates the content of a capsule
+    pub fn update<Id: Encode, Value: Encode>(mut self, id: Id, data: Value) -> Self {
+        let capsule_id = self.capsule_id(id);
+        self.push(Operation::Update {
+            capsule_id,
+            data: data.encode(),
+        })
+    }
+
+    /// Removes a capsule
+    pub fn remove<Id: Encode>(self, id: Id) -> Self {
+        let call = self.capsules.rm_capsule_call(self.capsule_id(id));
+        self.call(call)
+    }
+
+    /// Attaches a capsule to a container under the given key
+    pub fn attach<Key: Encode, Id: Encode>(self, container_id: H256, key: &Key, id: Id) -> Self {
+        let capsule_id = self.capsule_id(id);
+        self.call(RuntimeCall::Capsules(Call::container_put {
+            container_id,
+            key: key.encode(),
+            capsule_id,
+        }))
+    }
+
+    /// Detaches the capsule under the given key from a container
+    pub fn detach<Key: Encode>(self, container_id: H256, key: &Key) -> Self {
+        self.call(RuntimeCall::Capsules(Call::container_remove {
+            container_id,
+            key: key.encode(),
+        }))
+    }
+
+    /// Shares the ownership of a capsule with another user
+    pub fn share_ownership<Id: Encode>(self, id: Id, who: User) -> Self {
+        let capsule_id = self.capsule_id(id);
+        self.call(RuntimeCall::Capsules(Call::share_capsule_ownership {
+            capsule_id,
+            other_owner: who.account(),
+        }))
+    }
+
+    /// Approves the ownership request of a capsule
+    pub fn approve_ownership<Id: Encode>(self, id: Id) -> Self {
+        let capsule_id = self.capsule_id(id);
+        self.call(RuntimeCall::Capsules(Call::approve_capsule_ownership {
+            capsule_id,
+        }))
+    }
+
+    /// Sets the followers status of a capsule
+    pub fn set_followers_status<Id: Encode>(self, id: Id, status: FollowersStatus) -> Self {
+        let capsule_id = self.capsule_id(id);
+        self.call(RuntimeCall::Capsules(Call::set_capsule_followers_status {
+            capsule_id,
+            followers_status: status,
+        }))
+    }
+
+    /// Follows a capsule
+    pub fn follow<Id: Encode>(self, id: Id) -> Self {
+        let capsule_id = self.capsule_id(id);
+        self.call(RuntimeCall::Capsules(Call::follow_capsule { capsule_id }))
+    }
+
+    /// Adds a priviledged follower to a capsule
+    pub fn add_priviledged_follower<Id: Encode>(self, id: Id, follower: User) -> Self {
+        let capsule_id = self.capsule_id(id);
+        self.call(RuntimeCall::Capsules(Call::add_priviledged_follower {
+            capsule_id,
+            follower: follower.account(),
+        }))
+    }
+
+    /// Approves a priviledged follower request
+    pub fn approve_priviledged<Id: Encode>(self, id: Id) -> Self {
+        let capsule_id = self.capsule_id(id);
+        self.call(RuntimeCall::Capsules(Call::approve_privileged_follow {
+            capsule_id,
+        }))
+    }
+
+    /// Adds any runtime call to the transaction
+    pub fn call(self, call: RuntimeCall) -> Self {
+        self.push(Operation::Call(call))
+    }
+
+    /// Fails the submission if the estimated fee is higher than `fee`
+    pub fn max_fee(mut self, fee: u128) -> Self {
+        self.max_fee = Some(fee);
+        self
+    }
+
+    /// Returns the number of operations of the transaction
+    pub fn len(&self) -> usize {
+        self.batch.operations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.batch.operations.is_empty()
+    }
+
+    /// Estimates the size and the fee of the transaction, uploading its contents to IPFS, without submitting it.
+    /// The contents are not uploaded again by a following submission
+    pub async fn estimate(&self) -> Result<TxEstimate> {
+        let (nonce, extrinsic) = self.sign().await?;
+        let estimate = estimate(&extrinsic).await;
+        self.capsules.titanh.release_nonce(nonce).await;
+
+        estimate
+    }
+
+    /// Submits the transaction, waiting for the given level. It returns the hash of the transaction
+    pub async fn submit(self, level: ConsistencyLevel) -> Result<H256> {
+        let (nonce, extrinsic) = self.sign().await?;
+        if let Err(e) = self.check(&extrinsic).await {
+            self.capsules.titanh.release_nonce(nonce).await;
+            return Err(e);
+        }
+
+        let tx_hash = self
+            .capsules
+            .titanh
+            .submit_signed_with_level(Some(nonce), extrinsic, level)
+            .await?;
+        // The cached capsules are only stale once the transaction is accepted
+        for capsule_id in self.batch.written_capsules() {
+            self.capsules.invalidate_cache(capsule_id);
+        }
+
+        Ok(tx_hash)
+    }
+
+    /// Submits the transaction to the transaction pool, returning a handle to track whether it is later included, finalized or dropped
+    pub async fn submit_async(self) -> Result<TxHandle> {
+        let titanh = self.capsules.titanh;
+        let tx_hash = self.submit(ConsistencyLevel::Eventual).await?;
+
+        titanh.track(tx_hash).await
+    }
+
+    /// Uploads the contents to IPFS and signs the batch of the calls
+    async fn sign(&self) -> Result<(u64, Extrinsic)> {
+        self.batch.ensure_not_empty()?;
+
+        let calls = self.calls().await?;
+        let batch_tx = titanh::tx().utility().batch_all(calls);
+
+        self.capsules.titanh.sign(&batch_tx).await
+    }
+
+    /// Checks the size and the fee of the signed transaction
+    async fn check(&self, extrinsic: &Extrinsic) -> Result<()> {
+        check_estimate(estimate(extrinsic).await?, self.max_fee)
+    }
+
+    /// Builds the calls of the operations, once all the contents are uploaded to IPFS
+    async fn calls(&self) -> Result<Calls> {
+        let uploads = self.batch.uploads.get_or_try_init(|| self.upload()).await?;
+
+        let finalized_block = self.capsules.titanh.latest_finalized_block().await?.number;
+        let mut calls = Calls::new();
+        for (operation, upload) in self.batch.operations.iter().zip(uploads.iter().cloned()) {
+            let call = match (operation, upload) {
+                (
+                    Operation::Put {
+                        encoded_metadata,
+                        options,
+                        ..
+                    },
+                    Some((cid, size)),
+                ) => {
+                    let capsule =
+                        options.upload_data(encoded_metadata.clone(), cid, size, finalized_block);
+                    RuntimeCall::Capsules(Call::upload_capsule {
+                        app: self.config.app,
+                        other_owner: options.other_owner(),
+                        capsule,
+                    })
+                }
+                (Operation::Update { capsule_id, .. }, Some((cid, size))) => {
+                    RuntimeCall::Capsules(Call::update_capsule_content {
+                        capsule_id: *capsule_id,
+                        cid,
+                        size,
+                    })
+                }
+                (Operation::Call(call), _) => call.clone(),
+                _ => unreachable!("The contents of the puts and of the updates are uploaded"),
+            };
+            calls.push(call);
+        }
+
+        Ok(calls)
+    }
+
+    /// Uploads the contents of the puts and of the updates to IPFS
+    async fn upload(&self) -> Result<Uploads> {
+        let uploads = self.batch.operations.iter().map(|operation| async move {
+            match operation {
+                Operation::Put { data, options, .. } => self
+                    .capsules
+                    .upload_bytes(data.clone(), &options.ipfs)
+                    .await
+                    .map(Some),
+                Operation::Update { data, .. } => self
+                    .capsules
+                    .upload_bytes(data.clone(), &IpfsAddOpts::default())
+                    .await
+                    .map(Some),
+                Operation::Call(_) => Ok(None),
+            }
+        });
+
+        try_join_all(uploads).await
+    }
+
+    fn push(mut self, operation: Operation) -> Self {
+        self.batch.push(operation);
+        self
+    }
+
+    fn capsule_id<Id: Encode>(&self, id: Id) -> H256 {
+        self.capsules.compute_capsule_id(id, self.config.app)
+    }
+}
+
+async fn estimate(extrinsic: &Extrinsic) -> Result<TxEstimate> {
+    let size = extrinsic.encoded().len();
+    let fee = extrinsic.partial_fee_estimate().await?;
+
+    Ok(TxEstimate { size, fee })
+}
+
+/// Checks the size and the fee of a signed transaction against the limits it is submitted with
+fn check_estimate(TxEstimate { size, fee }: TxEstimate, max_fee: Option<u128>) -> Result<()> {
+    // The transaction is dispatched in the normal class, that can only fill part of the block
+    if size > MAX_NORMAL_BLOCK_SIZE {
+        return Err(TitanhError::TransactionTooLarge {
+            size,
+            max: MAX_NORMAL_BLOCK_SIZE,
+        });
+    }
+    if let Some(max_fee) = max_fee {
+        if fee > max_fee {
+            return Err(TitanhError::FeeTooHigh { fee, max: max_fee });
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(n: u8) -> Operation {
+        Operation::Update {
+            capsule_id: H256::repeat_byte(n),
+            data: vec![n],
+        }
+    }
+
+    #[test]
+    fn empty_batch_is_rejected_test() {
+        let mut batch = Batch::default();
+        assert!(matches!(
+            batch.ensure_not_empty(),
+            Err(TitanhError::InvalidInput(_))
+        ));
+
+        batch.push(update(1));
+        assert!(batch.ensure_not_empty().is_ok());
+    }
+
+    #[test]
+    fn push_drops_the_uploads_test() {
+        let mut batch = Batch::default();
+        batch.push(update(1));
+        batch.uploads.set(vec![Some((b"cid".to_vec(), 1))]).unwrap();
+
+        // The uploads no longer match the operations
+        batch.push(update(2));
+        assert!(batch.uploads.get().is_none());
+        assert_eq!(batch.operations.len(), 2);
+    }
+
+    #[test]
+    fn written_capsules_are_the_updated_and_destroyed_ones_test() {
+        let mut batch = Batch::default();
+        batch.push(update(1));
+        batch.push(Operation::Call(RuntimeCall::Capsules(
+            Call::start_destroy_capsule {
+                capsule_id: H256::repeat_byte(2),
+            },
+        )));
+        batch.push(Operation::Call(RuntimeCall::Capsules(
+            Call::follow_capsule {
+                capsule_id: H256::repeat_byte(3),
+            },
+        )));
+
+        assert_eq!(
+            batch.written_capsules().collect::<Vec<_>>(),
+            vec![H256::repeat_byte(1), H256::repeat_byte(2)]
+        );
+    }
+
+    #[test]
+    fn estimate_over_the_limits_is_rejected_test() {
+        let estimate = |size, fee| TxEstimate { size, fee };
+
+        assert!(check_estimate(estimate(MAX_NORMAL_BLOCK_SIZE, 100), None).is_ok());
+        assert!(check_estimate(estimate(MAX_NORMAL_BLOCK_SIZE, 100), Some(100)).is_ok());
+        assert!(matches!(
+            check_estimate(estimate(MAX_NORMAL_BLOCK_SIZE + 1, 100), None),
+            Err(TitanhError::TransactionTooLarge { size, max })
+                if size == MAX_NORMAL_BLOCK_SIZE + 1 && max == MAX_NORMAL_BLOCK_SIZE
+        ));
+        assert!(matches!(
+            check_estimate(estimate(10, 101), Some(100)),
+            Err(TitanhError::FeeTooHigh { fee: 101, max: 100 })
+        ));
+    }
+}
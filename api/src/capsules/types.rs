@@ -1,22 +1,44 @@
-use crate::common_types::ConsistencyLevel;
+use crate::{
+    common_types::{BlockNumber, ConsistencyLevel, User},
+    error::TitanhError,
+    titanh::runtime_types::pallet_capsules::{
+        capsule::types::CapsuleUploadData, types::FollowersStatus,
+    },
+};
 use codec::Encode;
+use ipfs_api_backend_hyper::request::Add;
 use sp_core::H256;
+use subxt::utils::AccountId32;
 
 pub type CapsuleKey = H256;
 
 const DEFAULT_CAPSULE_RETENTION_BLOCKS: u32 = 864_000; // 1 month
+const DEFAULT_READ_CONCURRENCY: usize = 16;
+/// The defaults of the IPFS nodes, set explicitly so that all the nodes add a content with the same cid
+const DEFAULT_IPFS_HASH: &str = "sha2-256";
+const DEFAULT_IPFS_CHUNKER: &str = "size-262144";
 
 #[derive(Clone)]
 pub struct PutCapsuleOpts {
     pub retention_blocks: Option<u32>,
+    /// The block at which the retention of the capsule ends, overriding `retention_blocks`
+    pub ending_retention_block: Option<BlockNumber>,
     pub level: ConsistencyLevel,
+    /// The eventual user the ownership of the capsule is shared with, who must approve it
+    pub other_owner: Option<User>,
+    pub followers_status: FollowersStatus,
+    pub ipfs: IpfsAddOpts,
 }
 
 impl Default for PutCapsuleOpts {
     fn default() -> Self {
         Self {
             retention_blocks: Some(DEFAULT_CAPSULE_RETENTION_BLOCKS),
+            ending_retention_block: None,
             level: Default::default(),
+            other_owner: None,
+            followers_status: FollowersStatus::None,
+            ipfs: Default::default(),
         }
     }
 }
@@ -29,6 +51,85 @@ impl PutCapsuleOpts {
             self.level,
         )
     }
+
+    /// Returns the block at which the retention of the capsule ends, given the latest finalized block
+    pub fn ending_retention_block(&self, finalized_block: BlockNumber) -> BlockNumber {
+        let (retention_blocks, _) = self.unwrap_fields_or_default();
+        self.ending_retention_block
+            .unwrap_or(finalized_block + retention_blocks)
+    }
+
+    /// Builds the metadata of a capsule uploaded with these options
+    pub(crate) fn upload_data(
+        &self,
+        encoded_metadata: Vec<u8>,
+        cid: Vec<u8>,
+        size: u128,
+        finalized_block: BlockNumber,
+    ) -> CapsuleUploadData {
+        CapsuleUploadData {
+            cid,
+            size,
+            ending_retention_block: self.ending_retention_block(finalized_block),
+            followers_status: self.followers_status.clone(),
+            encoded_metadata,
+        }
+    }
+
+    pub(crate) fn other_owner(&self) -> Option<AccountId32> {
+        self.other_owner.as_ref().map(User::account)
+    }
+}
+
+/// The options of the IPFS add of the capsule content. The unset ones are set to the IPFS defaults (CIDv0, `sha2-256`, chunks of 256 KiB)
+/// when added through the pool of endpoints, since the nodes may be configured with different ones.
+/// The content is verified against its cid on reads, so the hash function must be supported by the api (e.g. `sha2-256`, `blake2b-256`)
+#[derive(Clone, Debug, Default)]
+pub struct IpfsAddOpts {
+    pub cid_version: Option<u32>,
+    /// The name of the hash function, e.g. `sha2-256`
+    pub hash: Option<String>,
+    pub raw_leaves: Option<bool>,
+    /// The chunker splitting the content in blocks, e.g. `size-262144`
+    pub chunker: Option<String>,
+    /// Whether the content is also pinned by the IPFS node it is uploaded to, besides the pinning nodes
+    pub pin_locally: bool,
+}
+
+impl IpfsAddOpts {
+    /// Returns the options with the unset ones set to the IPFS defaults, so that any node adds the content with the same cid
+    pub(crate) fn resolved(&self) -> Self {
+        let cid_version = self.cid_version.unwrap_or(0);
+        Self {
+            cid_version: Some(cid_version),
+            hash: Some(
+                self.hash
+                    .as_deref()
+                    .unwrap_or(DEFAULT_IPFS_HASH)
+                    .to_string(),
+            ),
+            // The leaves are raw by default with CIDv1
+            raw_leaves: Some(self.raw_leaves.unwrap_or(cid_version > 0)),
+            chunker: Some(
+                self.chunker
+                    .as_deref()
+                    .unwrap_or(DEFAULT_IPFS_CHUNKER)
+                    .to_string(),
+            ),
+            pin_locally: self.pin_locally,
+        }
+    }
+
+    pub(crate) fn to_add(&self) -> Add<'_> {
+        let mut add_opts = Add::default();
+        add_opts.pin = Some(self.pin_locally);
+        add_opts.cid_version = self.cid_version;
+        add_opts.hash = self.hash.as_deref();
+        add_opts.raw_leaves = self.raw_leaves;
+        add_opts.chunker = self.chunker.as_deref();
+
+        add_opts
+    }
 }
 
 #[derive(Default, Clone, Copy, Debug)]
@@ -52,7 +153,7 @@ impl GetCapsuleOpts {
 }
 
 impl TryFrom<ConsistencyLevel> for GetCapsuleOpts {
-    type Error = anyhow::Error;
+    type Error = TitanhError;
 
     fn try_from(level: ConsistencyLevel) -> Result<Self, Self::Error> {
         match level {
@@ -62,7 +163,9 @@ impl TryFrom<ConsistencyLevel> for GetCapsuleOpts {
             ConsistencyLevel::Finalized => Ok(GetCapsuleOpts {
                 from_finalized_state: true,
             }),
-            _ => Err(anyhow::anyhow!("Invalid consistency level")),
+            _ => Err(TitanhError::invalid_input(
+                "Reads support only the committed and finalized levels",
+            )),
         }
     }
 }
@@ -79,6 +182,32 @@ impl From<GetCapsuleOpts> for ConsistencyLevel {
     }
 }
 
+/// The options of the batch reads
+#[derive(Clone, Copy, Debug)]
+pub struct GetManyOpts {
+    pub from_finalized_state: bool,
+    /// The maximum number of contents fetched concurrently from IPFS
+    pub concurrency: usize,
+}
+
+impl Default for GetManyOpts {
+    fn default() -> Self {
+        Self {
+            from_finalized_state: false,
+            concurrency: DEFAULT_READ_CONCURRENCY,
+        }
+    }
+}
+
+impl From<GetCapsuleOpts> for GetManyOpts {
+    fn from(opts: GetCapsuleOpts) -> Self {
+        Self {
+            from_finalized_state: opts.from_finalized_state,
+            ..Default::default()
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct UpdateCapsuleOpts {
     pub level: ConsistencyLevel,
@@ -109,3 +238,115 @@ impl<Id, Value> IntoIterator for CapsulesBatch<Id, Value> {
         self.capsules.into_iter()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_add_options_are_left_to_the_node_test() {
+        let options = IpfsAddOpts::default();
+        let add = options.to_add();
+
+        assert_eq!(add.pin, Some(false));
+        assert_eq!(add.cid_version, None);
+        assert_eq!(add.hash, None);
+        assert_eq!(add.raw_leaves, None);
+        assert_eq!(add.chunker, None);
+    }
+
+    #[test]
+    fn add_options_are_forwarded_test() {
+        let options = IpfsAddOpts {
+            cid_version: Some(1),
+            hash: Some("blake2b-256".to_string()),
+            raw_leaves: Some(true),
+            chunker: Some("size-1024".to_string()),
+            pin_locally: true,
+        };
+        let add = options.to_add();
+
+        assert_eq!(add.pin, Some(true));
+        assert_eq!(add.cid_version, Some(1));
+        assert_eq!(add.hash, Some("blake2b-256"));
+        assert_eq!(add.raw_leaves, Some(true));
+        assert_eq!(add.chunker, Some("size-1024"));
+    }
+
+    #[test]
+    fn resolved_add_options_set_the_defaults_test() {
+        let resolved = IpfsAddOpts::default().resolved();
+        assert_eq!(resolved.cid_version, Some(0));
+        assert_eq!(resolved.hash.as_deref(), Some("sha2-256"));
+        assert_eq!(resolved.raw_leaves, Some(false));
+        assert_eq!(resolved.chunker.as_deref(), Some("size-262144"));
+        assert!(!resolved.pin_locally);
+
+        let options = IpfsAddOpts {
+            cid_version: Some(1),
+            hash: Some("blake2b-256".to_string()),
+            pin_locally: true,
+            ..Default::default()
+        };
+        let resolved = options.resolved();
+        assert_eq!(resolved.cid_version, Some(1));
+        assert_eq!(resolved.hash.as_deref(), Some("blake2b-256"));
+        assert_eq!(resolved.raw_leaves, Some(true));
+        assert_eq!(resolved.chunker.as_deref(), Some("size-262144"));
+        assert!(resolved.pin_locally);
+
+        // The set options are kept
+        let options = IpfsAddOpts {
+            cid_version: Some(1),
+            raw_leaves: Some(false),
+            chunker: Some("size-1024".to_string()),
+            ..Default::default()
+        };
+        let resolved = options.resolved();
+        assert_eq!(resolved.raw_leaves, Some(false));
+        assert_eq!(resolved.chunker.as_deref(), Some("size-1024"));
+    }
+
+    #[test]
+    fn retention_ends_after_the_retention_blocks_test() {
+        let options = PutCapsuleOpts::default();
+        assert_eq!(
+            options.ending_retention_block(100),
+            100 + DEFAULT_CAPSULE_RETENTION_BLOCKS
+        );
+
+        let options = PutCapsuleOpts {
+            retention_blocks: Some(50),
+            ..Default::default()
+        };
+        assert_eq!(options.ending_retention_block(100), 150);
+
+        let options = PutCapsuleOpts {
+            retention_blocks: None,
+            ..Default::default()
+        };
+        assert_eq!(
+            options.ending_retention_block(100),
+            100 + DEFAULT_CAPSULE_RETENTION_BLOCKS
+        );
+    }
+
+    #[test]
+    fn ending_retention_block_overrides_the_retention_blocks_test() {
+        let options = PutCapsuleOpts {
+            retention_blocks: Some(50),
+            ending_retention_block: Some(1_000),
+            ..Default::default()
+        };
+
+        assert_eq!(options.ending_retention_block(100), 1_000);
+        // The finalized block is not read for an explicit end of the retention
+        assert_eq!(options.ending_retention_block(0), 1_000);
+        assert_eq!(
+            options
+                .upload_data(vec![], vec![], 0, 100)
+                .ending_retention_block,
+            1_000
+        );
+    }
+}
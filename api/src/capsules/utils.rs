@@ -1,9 +1,9 @@
 use super::{CapsulesApi, CapsulesConfig};
+use crate::error::{Result, TitanhError};
 use crate::titanh::{
     capsules::calls::types::upload_capsule::App,
     runtime_types::primitives::common_types::BoundedString,
 };
-use anyhow::Result;
 use codec::Encode;
 use sp_core::{Blake2Hasher, Hasher, H256};
 
@@ -14,7 +14,7 @@ impl CapsulesApi<'_> {
     pub fn ensure_config(&self) -> Result<&CapsulesConfig> {
         self.config
             .as_ref()
-            .ok_or_else(|| anyhow::anyhow!("Capsules API configuration is not provided"))
+            .ok_or(TitanhError::NotConfigured("Capsules API"))
     }
 
     pub fn compute_capsule_id<Id: Encode>(&self, id: Id, app: App) -> H256 {
@@ -30,7 +30,96 @@ impl CapsulesApi<'_> {
 
 pub fn convert_bounded_str(bounded_str: BoundedString) -> Result<String> {
     let bounded = bounded_str.0 .0.to_vec();
-    let str = std::str::from_utf8(&bounded)?;
+    let str = std::str::from_utf8(&bounded)
+        .map_err(|_| codec::Error::from("The bounded string is not valid utf8"))?;
 
     Ok(str.to_string())
 }
+
+/// Splits the cids of the capsules into the cached ones, in the order of the ids, and the positions and ids of the ones to fetch.
+/// The `None` ids are neither cached nor fetched
+pub fn split_cached(
+    capsule_ids: &[Option<H256>],
+    cached: impl Fn(H256) -> Option<String>,
+) -> (Vec<Option<String>>, Vec<(usize, H256)>) {
+    let mut cids = Vec::with_capacity(capsule_ids.len());
+    let mut missing = Vec::new();
+    for (i, capsule_id) in capsule_ids.iter().enumerate() {
+        let cid = capsule_id.and_then(&cached);
+        if let (Some(capsule_id), None) = (capsule_id, &cid) {
+            missing.push((i, *capsule_id));
+        }
+        cids.push(cid);
+    }
+
+    (cids, missing)
+}
+
+/// Places the fetched cids, in the order of the missing capsules, at their positions
+pub fn fill_fetched(
+    cids: &mut [Option<String>],
+    missing: &[(usize, H256)],
+    fetched: Vec<Option<String>>,
+) {
+    for ((i, _), cid) in missing.iter().zip(fetched) {
+        cids[*i] = cid;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(n: u8) -> H256 {
+        H256::repeat_byte(n)
+    }
+
+    fn cid(n: u8) -> Option<String> {
+        Some(format!("cid-{}", n))
+    }
+
+    #[test]
+    fn fetched_cids_keep_the_order_with_cache_hits_test() {
+        let ids = [Some(id(1)), Some(id(2)), None, Some(id(3)), Some(id(4))];
+        // Only the capsules 2 and 4 are cached
+        let (mut cids, missing) = split_cached(&ids, |capsule_id| {
+            [id(2), id(4)]
+                .contains(&capsule_id)
+                .then(|| cid(capsule_id[0]).unwrap())
+        });
+        assert_eq!(missing, vec![(0, id(1)), (3, id(3))]);
+
+        fill_fetched(&mut cids, &missing, vec![cid(1), cid(3)]);
+        assert_eq!(cids, vec![cid(1), cid(2), None, cid(3), cid(4)]);
+    }
+
+    #[test]
+    fn capsules_not_found_are_none_test() {
+        let ids = [Some(id(1)), Some(id(2)), Some(id(3))];
+        let (mut cids, missing) = split_cached(&ids, |capsule_id| {
+            (capsule_id == id(3)).then(|| cid(3).unwrap())
+        });
+
+        fill_fetched(&mut cids, &missing, vec![None, cid(2)]);
+        assert_eq!(cids, vec![None, cid(2), cid(3)]);
+    }
+
+    #[test]
+    fn all_cached_fetches_nothing_test() {
+        let ids = [Some(id(1)), None, Some(id(1))];
+        let (cids, missing) = split_cached(&ids, |capsule_id| cid(capsule_id[0]));
+
+        assert!(missing.is_empty());
+        assert_eq!(cids, vec![cid(1), None, cid(1)]);
+    }
+
+    #[test]
+    fn repeated_ids_are_fetched_at_each_position_test() {
+        let ids = [Some(id(1)), Some(id(2)), Some(id(1))];
+        let (mut cids, missing) = split_cached(&ids, |_| None);
+        assert_eq!(missing, vec![(0, id(1)), (1, id(2)), (2, id(1))]);
+
+        fill_fetched(&mut cids, &missing, vec![cid(1), cid(2), cid(1)]);
+        assert_eq!(cids, vec![cid(1), cid(2), cid(1)]);
+    }
+}
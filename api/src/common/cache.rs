@@ -0,0 +1,508 @@
+use super::{
+    cid::Cid,
+    error::Result,
+    ipfs::{verify_block, BlockSource},
+    rpc::is_reconnecting,
+    titanh::capsules::events::{CapsuleContentChanged, CapsuleStartedDestroying},
+    types::{BlockHash, SubstrateApi},
+};
+use futures::{future::BoxFuture, stream, StreamExt};
+use lru::LruCache;
+use sp_core::H256;
+use std::{
+    num::NonZeroUsize,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, Weak,
+    },
+    time::Duration,
+};
+
+/// The delay before subscribing again to the blocks, when the invalidation of the cache fails
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(1);
+
+/// The options of the client side cache
+#[derive(Clone, Debug)]
+pub struct CacheOptions {
+    /// The maximum size in bytes of the content kept in memory
+    pub content_capacity: usize,
+    /// The maximum number of capsule metadata entries kept in memory
+    pub metadata_capacity: usize,
+    /// The eventual directory where the blocks of the content are also stored, surviving restarts
+    pub disk_dir: Option<PathBuf>,
+}
+
+impl Default for CacheOptions {
+    fn default() -> Self {
+        Self {
+            content_capacity: 64 * 1024 * 1024,
+            metadata_capacity: 10_000,
+            disk_dir: None,
+        }
+    }
+}
+
+/// A client side cache of the capsules.
+/// The content is keyed by cid: it is immutable, so it is never invalidated, only evicted. The metadata is keyed by capsule id and block hash,
+/// and the metadata read from the latest state is invalidated by the `CapsuleContentChanged` and `CapsuleStartedDestroying` events.
+/// On disk the raw blocks of the content are kept, so that they are verified against their cids when read again
+pub(crate) struct Cache {
+    content: Mutex<ContentLru>,
+    disk_dir: Option<Arc<PathBuf>>,
+    /// The cids of the capsules, by capsule id and block hash
+    metadata: Mutex<LruCache<(H256, H256), String>>,
+    /// The cids of the capsules in the latest state, by capsule id
+    latest: Mutex<LatestCids>,
+    /// Whether the events are watched, so that the latest metadata can be cached
+    subscribed: AtomicBool,
+}
+
+impl Cache {
+    pub async fn new(options: CacheOptions, api: SubstrateApi) -> Result<Arc<Self>> {
+        if let Some(dir) = &options.disk_dir {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+
+        let metadata_capacity =
+            NonZeroUsize::new(options.metadata_capacity).unwrap_or(NonZeroUsize::MIN);
+        let cache = Arc::new(Self {
+            content: Mutex::new(ContentLru::new(options.content_capacity)),
+            disk_dir: options.disk_dir.map(Arc::new),
+            metadata: Mutex::new(LruCache::new(metadata_capacity)),
+            latest: Mutex::new(LatestCids::new(metadata_capacity)),
+            subscribed: AtomicBool::new(false),
+        });
+        tokio::spawn(invalidate_on_events(Arc::downgrade(&cache), api));
+
+        Ok(cache)
+    }
+
+    /// Returns the cid of a capsule at a block, or in the latest state
+    pub fn cid(&self, capsule_id: H256, at: Option<BlockHash>) -> Option<String> {
+        match at {
+            Some(block_hash) => self
+                .metadata
+                .lock()
+                .unwrap()
+                .get(&(capsule_id, block_hash.0))
+                .cloned(),
+            None => self.latest.lock().unwrap().get(capsule_id),
+        }
+    }
+
+    /// The generation of the latest metadata, to be taken before reading a cid that is then inserted
+    pub fn generation(&self) -> u64 {
+        self.latest.lock().unwrap().generation
+    }
+
+    /// Caches the cid of a capsule, read after taking the `generation`.
+    /// A cid of the latest state is not cached if the metadata has been invalidated since, as the read may predate the change
+    pub fn insert_cid(
+        &self,
+        capsule_id: H256,
+        at: Option<BlockHash>,
+        cid: String,
+        generation: u64,
+    ) {
+        match at {
+            Some(block_hash) => {
+                self.metadata
+                    .lock()
+                    .unwrap()
+                    .put((capsule_id, block_hash.0), cid);
+            }
+            // The latest state is only cached while the invalidation events are received
+            None if self.subscribed.load(Ordering::Acquire) => {
+                self.latest
+                    .lock()
+                    .unwrap()
+                    .insert(capsule_id, cid, generation);
+            }
+            None => {}
+        }
+    }
+
+    /// Invalidates the latest metadata of a capsule, e.g. after it is written by the client
+    pub fn invalidate(&self, capsule_id: H256) {
+        self.latest.lock().unwrap().invalidate(capsule_id);
+    }
+
+    /// Returns the content of a cid kept in memory
+    pub fn content(&self, cid: &str) -> Option<Arc<[u8]>> {
+        self.content.lock().unwrap().get(cid)
+    }
+
+    /// Caches in memory the content of a cid, that must be already verified against it
+    pub fn insert_content(&self, cid: &str, content: Arc<[u8]>) {
+        self.content
+            .lock()
+            .unwrap()
+            .insert(cid.to_string(), content);
+    }
+
+    pub fn clear(&self) {
+        self.content.lock().unwrap().clear();
+        self.metadata.lock().unwrap().clear();
+        self.latest.lock().unwrap().clear();
+    }
+
+    /// Wraps a block source so that its blocks are also read from and stored to the disk cache, if any
+    pub fn blocks<S: BlockSource>(&self, source: S) -> DiskBlocks<S> {
+        DiskBlocks {
+            source,
+            dir: self.disk_dir.clone(),
+        }
+    }
+
+    fn set_subscribed(&self, subscribed: bool) {
+        self.subscribed.store(subscribed, Ordering::Release);
+        // The events may have been missed
+        self.latest.lock().unwrap().clear();
+    }
+}
+
+/// Invalidates the latest metadata of the capsules changed in the new blocks, until the cache is dropped
+async fn invalidate_on_events(cache: Weak<Cache>, api: SubstrateApi) {
+    loop {
+        match watch_events(&cache, &api).await {
+            Ok(()) => return,
+            Err(e) if is_reconnecting(&e) => {
+                log::warn!("Rpc connection lost, the cache subscribes again to the blocks")
+            }
+            Err(e) => log::warn!("The invalidation of the cache failed: {}", e),
+        }
+
+        match cache.upgrade() {
+            Some(cache) => cache.set_subscribed(false),
+            None => return,
+        }
+        tokio::time::sleep(RESUBSCRIBE_DELAY).await;
+    }
+}
+
+/// Watches the events of the best and of the finalized blocks, since the latest state is read from one of them
+async fn watch_events(cache: &Weak<Cache>, api: &SubstrateApi) -> Result<(), subxt::Error> {
+    let best = api.blocks().subscribe_best().await?;
+    let finalized = api.blocks().subscribe_finalized().await?;
+    let mut blocks = stream::select(best, finalized);
+
+    match cache.upgrade() {
+        Some(cache) => cache.set_subscribed(true),
+        None => return Ok(()),
+    }
+
+    while let Some(block) = blocks.next().await {
+        let events = block?.events().await?;
+        let Some(cache) = cache.upgrade() else {
+            return Ok(());
+        };
+
+        for event in events.iter() {
+            let event = event?;
+            if let Some(changed) = event.as_event::<CapsuleContentChanged>()? {
+                cache.invalidate(changed.capsule_id);
+            } else if let Some(destroying) = event.as_event::<CapsuleStartedDestroying>()? {
+                cache.invalidate(destroying.capsule_id);
+            }
+        }
+    }
+
+    Err(subxt::Error::Other(
+        "The blocks subscription has ended".to_string(),
+    ))
+}
+
+/// The cids of the capsules in the latest state, with the generation of the last invalidation
+struct LatestCids {
+    entries: LruCache<H256, String>,
+    /// Increased by every invalidation, so that a cid read before it is not cached
+    generation: u64,
+}
+
+impl LatestCids {
+    fn new(capacity: NonZeroUsize) -> Self {
+        Self {
+            entries: LruCache::new(capacity),
+            generation: 0,
+        }
+    }
+
+    fn get(&mut self, capsule_id: H256) -> Option<String> {
+        self.entries.get(&capsule_id).cloned()
+    }
+
+    fn insert(&mut self, capsule_id: H256, cid: String, generation: u64) {
+        if generation == self.generation {
+            self.entries.put(capsule_id, cid);
+        }
+    }
+
+    fn invalidate(&mut self, capsule_id: H256) {
+        self.entries.pop(&capsule_id);
+        self.generation += 1;
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.generation += 1;
+    }
+}
+
+/// A block source that reads the blocks from the disk cache before fetching them, and stores there the fetched ones.
+/// The blocks on disk are checked against their cids, so that a corrupted or tampered file is fetched again
+#[derive(Clone)]
+pub(crate) struct DiskBlocks<S> {
+    source: S,
+    dir: Option<Arc<PathBuf>>,
+}
+
+impl<S: BlockSource> BlockSource for DiskBlocks<S> {
+    fn get_block<'a>(&'a self, cid: &'a Cid) -> BoxFuture<'a, Result<Vec<u8>>> {
+        Box::pin(async move {
+            let Some(dir) = &self.dir else {
+                return self.source.get_block(cid).await;
+            };
+
+            let path = dir.join(cid.to_string());
+            if let Ok(block) = tokio::fs::read(&path).await {
+                match verify_block(cid, &block) {
+                    Ok(()) => return Ok(block),
+                    Err(e) => log::warn!("Discarding the block cached on disk: {}", e),
+                }
+            }
+
+            let block = self.source.get_block(cid).await?;
+            // A forged block is not stored, the reader rejects it
+            if verify_block(cid, &block).is_ok() {
+                // The file is renamed once written, so that a partial file is never read
+                let tmp_path = path.with_extension("tmp");
+                let res = async {
+                    tokio::fs::write(&tmp_path, &block).await?;
+                    tokio::fs::rename(&tmp_path, &path).await
+                }
+                .await;
+                if let Err(e) = res {
+                    log::warn!("Failed to cache the block {} on disk: {}", cid, e);
+                }
+            }
+
+            Ok(block)
+        })
+    }
+}
+
+/// The content by cid, evicting the least recently used once the size in bytes exceeds the capacity
+struct ContentLru {
+    entries: LruCache<String, Arc<[u8]>>,
+    size: usize,
+    capacity: usize,
+}
+
+impl ContentLru {
+    fn new(capacity: usize) -> Self {
+        Self {
+            entries: LruCache::unbounded(),
+            size: 0,
+            capacity,
+        }
+    }
+
+    fn get(&mut self, cid: &str) -> Option<Arc<[u8]>> {
+        self.entries.get(cid).cloned()
+    }
+
+    fn insert(&mut self, cid: String, content: Arc<[u8]>) {
+        // A content larger than the whole cache is not kept in memory
+        if content.len() > self.capacity {
+            return;
+        }
+
+        self.size += content.len();
+        if let Some(old) = self.entries.put(cid, content) {
+            self.size -= old.len();
+        }
+        while self.size > self.capacity {
+            match self.entries.pop_lru() {
+                Some((_, evicted)) => self.size -= evicted.len(),
+                None => break,
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.size = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::TitanhError;
+    use multihash_codetable::{Code, MultihashDigest};
+    use std::{collections::HashMap, sync::atomic::AtomicUsize};
+
+    fn content(len: usize) -> Arc<[u8]> {
+        vec![0; len].into()
+    }
+
+    fn raw_cid(data: &[u8]) -> Cid {
+        Cid::new_v1(0x55, Code::Sha2_256.digest(data))
+    }
+
+    fn disk_dir(test: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("titanh-cache-{}-{}", std::process::id(), test));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// An in-memory block source counting the fetched blocks
+    #[derive(Clone, Default)]
+    struct Blocks {
+        blocks: Arc<HashMap<Cid, Vec<u8>>>,
+        fetched: Arc<AtomicUsize>,
+    }
+
+    impl BlockSource for Blocks {
+        fn get_block<'a>(&'a self, cid: &'a Cid) -> BoxFuture<'a, Result<Vec<u8>>> {
+            self.fetched.fetch_add(1, Ordering::SeqCst);
+            let block = self.blocks.get(cid).cloned();
+            Box::pin(async move { block.ok_or(TitanhError::Gateway("block not found".into())) })
+        }
+    }
+
+    #[test]
+    fn content_size_is_accounted_test() {
+        let mut lru = ContentLru::new(100);
+        lru.insert("a".to_string(), content(30));
+        lru.insert("b".to_string(), content(40));
+        assert_eq!(lru.size, 70);
+
+        // Replacing an entry accounts for the old size
+        lru.insert("a".to_string(), content(10));
+        assert_eq!(lru.size, 50);
+        assert_eq!(lru.get("a").unwrap().len(), 10);
+
+        lru.clear();
+        assert_eq!(lru.size, 0);
+        assert!(lru.get("b").is_none());
+    }
+
+    #[test]
+    fn least_recently_used_content_is_evicted_test() {
+        let mut lru = ContentLru::new(100);
+        lru.insert("a".to_string(), content(40));
+        lru.insert("b".to_string(), content(40));
+        // "a" becomes the most recently used
+        lru.get("a");
+        lru.insert("c".to_string(), content(40));
+
+        assert!(lru.get("b").is_none());
+        assert!(lru.get("a").is_some());
+        assert!(lru.get("c").is_some());
+        assert_eq!(lru.size, 80);
+    }
+
+    #[test]
+    fn content_larger_than_the_capacity_is_not_kept_test() {
+        let mut lru = ContentLru::new(100);
+        lru.insert("a".to_string(), content(40));
+        lru.insert("b".to_string(), content(101));
+
+        assert!(lru.get("b").is_none());
+        assert!(lru.get("a").is_some());
+        assert_eq!(lru.size, 40);
+    }
+
+    #[test]
+    fn cid_read_before_an_invalidation_is_not_cached_test() {
+        let mut latest = LatestCids::new(NonZeroUsize::new(10).unwrap());
+        let capsule = H256::repeat_byte(1);
+
+        let generation = latest.generation;
+        latest.insert(capsule, "old".to_string(), generation);
+        assert_eq!(latest.get(capsule), Some("old".to_string()));
+
+        // A read started before the invalidation completes after it
+        let generation = latest.generation;
+        latest.invalidate(capsule);
+        latest.insert(capsule, "old".to_string(), generation);
+        assert_eq!(latest.get(capsule), None);
+
+        let generation = latest.generation;
+        latest.insert(capsule, "new".to_string(), generation);
+        assert_eq!(latest.get(capsule), Some("new".to_string()));
+
+        let generation = latest.generation;
+        latest.clear();
+        latest.insert(capsule, "new".to_string(), generation);
+        assert_eq!(latest.get(capsule), None);
+    }
+
+    #[tokio::test]
+    async fn blocks_are_read_again_from_disk_test() {
+        let dir = disk_dir("read-again");
+        let data = b"capsule".to_vec();
+        let cid = raw_cid(&data);
+        let source = Blocks {
+            blocks: Arc::new(HashMap::from([(cid, data.clone())])),
+            ..Default::default()
+        };
+        let blocks = DiskBlocks {
+            source: source.clone(),
+            dir: Some(Arc::new(dir.clone())),
+        };
+
+        assert_eq!(blocks.get_block(&cid).await.unwrap(), data);
+        assert_eq!(blocks.get_block(&cid).await.unwrap(), data);
+        assert_eq!(source.fetched.load(Ordering::SeqCst), 1);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn tampered_block_on_disk_is_fetched_again_test() {
+        let dir = disk_dir("tampered");
+        let data = b"capsule".to_vec();
+        let cid = raw_cid(&data);
+        std::fs::write(dir.join(cid.to_string()), b"forged").unwrap();
+        let source = Blocks {
+            blocks: Arc::new(HashMap::from([(cid, data.clone())])),
+            ..Default::default()
+        };
+        let blocks = DiskBlocks {
+            source: source.clone(),
+            dir: Some(Arc::new(dir.clone())),
+        };
+
+        assert_eq!(blocks.get_block(&cid).await.unwrap(), data);
+        assert_eq!(source.fetched.load(Ordering::SeqCst), 1);
+        // The block on disk has been replaced by the verified one
+        assert_eq!(std::fs::read(dir.join(cid.to_string())).unwrap(), data);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn forged_block_is_not_stored_test() {
+        let dir = disk_dir("forged");
+        let cid = raw_cid(b"capsule");
+        let source = Blocks {
+            blocks: Arc::new(HashMap::from([(cid, b"forged".to_vec())])),
+            ..Default::default()
+        };
+        let blocks = DiskBlocks {
+            source,
+            dir: Some(Arc::new(dir.clone())),
+        };
+
+        // The reader rejects the block, which is not kept on disk
+        assert_eq!(blocks.get_block(&cid).await.unwrap(), b"forged");
+        assert!(!dir.join(cid.to_string()).exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
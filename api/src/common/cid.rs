@@ -0,0 +1,15 @@
+use super::error::{Result, TitanhError};
+pub use ::cid::{multihash::Multihash, Cid};
+
+/// The maximum length of a CID accepted on chain, in its string representation. It fits CIDv1 in base32 with digests up to 512 bits
+pub const MAX_CID_LENGTH: usize = 128;
+
+/// Parses and validates a CID in its string representation, as returned by IPFS and stored on chain. Both CIDv0 and CIDv1 are supported
+pub fn parse_cid(cid: &[u8]) -> Result<Cid> {
+    let cid = std::str::from_utf8(cid).map_err(|_| TitanhError::BadCid)?;
+    if cid.len() > MAX_CID_LENGTH {
+        return Err(TitanhError::BadCid);
+    }
+
+    Cid::try_from(cid).map_err(|_| TitanhError::BadCid)
+}
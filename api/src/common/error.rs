@@ -0,0 +1,374 @@
+use super::{ipfs::IntegrityError, rpc::is_reconnecting, types::BlockNumber};
+use std::{fmt, io};
+use subxt::error::{DispatchError, ModuleError};
+
+/// The error of the node rpc when the state of a block has been discarded by the pruning
+const PRUNED_STATE_ERROR: &str = "State already discarded";
+
+/// The result of the api methods
+pub type Result<T, E = TitanhError> = std::result::Result<T, E>;
+
+/// The errors of the Titanh api. The pallet errors the clients are expected to react to are decoded into typed variants
+#[derive(Debug)]
+pub enum TitanhError {
+    /// The capsule does not exist
+    CapsuleNotFound,
+    /// The container does not exist
+    ContainerNotFound,
+    /// A capsule with the same id already exists
+    CapsuleAlreadyExists,
+    /// The signer has no permission on the app or on the capsule
+    PermissionDenied,
+    /// The CID of the capsule content is malformed
+    BadCid,
+    /// Any other error of a pallet, by name
+    Pallet {
+        pallet: String,
+        error: String,
+    },
+    /// A dispatch error that is not raised by a pallet (e.g. a bad origin or a token error)
+    Dispatch(String),
+    /// The value is not defined in the chain storage
+    NotInStorage,
+    /// There is no block with that number
+    BlockNotFound(BlockNumber),
+    /// The node no longer keeps the state of the block
+    StatePruned(BlockNumber),
+    /// The chain has not reached the block in time
+    CatchUpTimeout(BlockNumber),
+    /// The transaction has not been included in a block
+    TransactionFailed,
+    /// The signed transaction does not fit in the normal class of a block
+    TransactionTooLarge {
+        size: usize,
+        max: usize,
+    },
+    /// The estimated fee of the transaction is higher than the accepted one
+    FeeTooHigh {
+        fee: u128,
+        max: u128,
+    },
+    /// The signer of transactions is not set
+    SignerNotSet,
+    /// The pinning node is not in the pinning ring
+    NodeNotFound,
+    /// The pinning node is already in the pinning ring
+    NodeAlreadyInRing,
+    /// The pinning ring has no nodes
+    EmptyRing,
+    /// The api is missing some configuration (e.g. the IPFS endpoint of the capsules api)
+    NotConfigured(&'static str),
+    /// An invalid argument was provided to the api
+    InvalidInput(String),
+    /// A failure of the chain rpc
+    Rpc(subxt::Error),
+    /// A failure of the IPFS endpoint
+    Ipfs(ipfs_api_backend_hyper::Error),
+    /// A failure of a read-only IPFS gateway
+    Gateway(String),
+    /// The content returned by IPFS does not match its CID
+    Integrity(IntegrityError),
+    /// A value cannot be encoded or decoded
+    Codec(codec::Error),
+    Io(io::Error),
+}
+
+impl TitanhError {
+    pub fn invalid_input(msg: impl ToString) -> Self {
+        TitanhError::InvalidInput(msg.to_string())
+    }
+
+    /// Maps the error of a read at a past block to `StatePruned`, if the node no longer keeps the state of the block
+    pub(crate) fn or_pruned(self, block_number: BlockNumber) -> Self {
+        match &self {
+            TitanhError::Rpc(subxt::Error::Rpc(e))
+                if e.to_string().contains(PRUNED_STATE_ERROR) =>
+            {
+                TitanhError::StatePruned(block_number)
+            }
+            _ => self,
+        }
+    }
+
+    /// Returns whether the error is due to a dropped rpc connection, that is being re-established (see `rpc::is_reconnecting`)
+    pub fn is_reconnecting(&self) -> bool {
+        matches!(self, TitanhError::Rpc(e) if is_reconnecting(e))
+    }
+
+    fn from_module_error(error: &ModuleError) -> Self {
+        let details = match error.details() {
+            Ok(details) => details,
+            Err(_) => return TitanhError::Dispatch(error.details_string()),
+        };
+
+        match (details.pallet.name(), details.variant.name.as_str()) {
+            ("Capsules", "InvalidCapsuleId") => TitanhError::CapsuleNotFound,
+            ("Capsules", "InvalidContainerId") => TitanhError::ContainerNotFound,
+            ("Capsules", "CapsuleIdAlreadyExists") => TitanhError::CapsuleAlreadyExists,
+            ("Capsules", "AppPermissionDenied" | "BadOriginForOwnership")
+            | ("AppRegistrar", "NotOwner" | "NotAllowed") => TitanhError::PermissionDenied,
+            ("Capsules", "BadCid") => TitanhError::BadCid,
+            (pallet, error) => TitanhError::Pallet {
+                pallet: pallet.to_string(),
+                error: error.to_string(),
+            },
+        }
+    }
+}
+
+impl fmt::Display for TitanhError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TitanhError::CapsuleNotFound => write!(f, "Capsule not found"),
+            TitanhError::ContainerNotFound => write!(f, "Container not found"),
+            TitanhError::CapsuleAlreadyExists => write!(f, "Capsule already exists"),
+            TitanhError::PermissionDenied => write!(f, "Permission denied"),
+            TitanhError::BadCid => write!(f, "Invalid CID"),
+            TitanhError::Pallet { pallet, error } => write!(f, "{}::{}", pallet, error),
+            TitanhError::Dispatch(e) => write!(f, "Dispatch error: {}", e),
+            TitanhError::NotInStorage => write!(f, "Value is not defined in storage"),
+            TitanhError::BlockNotFound(number) => {
+                write!(f, "Block hash not found for block number: {}", number)
+            }
+            TitanhError::StatePruned(number) => {
+                write!(f, "The state of block {} has been pruned", number)
+            }
+            TitanhError::CatchUpTimeout(number) => {
+                write!(
+                    f,
+                    "Timed out waiting for the chain to reach block {}",
+                    number
+                )
+            }
+            TitanhError::TransactionFailed => write!(f, "Transaction failed"),
+            TitanhError::TransactionTooLarge { size, max } => write!(
+                f,
+                "The transaction size of {} bytes exceeds the maximum size of {} bytes",
+                size, max
+            ),
+            TitanhError::FeeTooHigh { fee, max } => write!(
+                f,
+                "The estimated fee {} exceeds the maximum fee {}",
+                fee, max
+            ),
+            TitanhError::SignerNotSet => write!(f, "Signer is not set"),
+            TitanhError::NodeNotFound => write!(f, "Node not found"),
+            TitanhError::NodeAlreadyInRing => write!(f, "Node should not already be in the ring"),
+            TitanhError::EmptyRing => write!(f, "Pinning ring is empty"),
+            TitanhError::NotConfigured(what) => write!(f, "{} is not configured", what),
+            TitanhError::InvalidInput(e) => write!(f, "Invalid input: {}", e),
+            TitanhError::Rpc(e) => write!(f, "Rpc error: {}", e),
+            TitanhError::Ipfs(e) => write!(f, "IPFS error: {}", e),
+            TitanhError::Gateway(e) => write!(f, "IPFS gateway error: {}", e),
+            TitanhError::Integrity(e) => write!(f, "{}", e),
+            TitanhError::Codec(e) => write!(f, "Codec error: {}", e),
+            TitanhError::Io(e) => write!(f, "Io error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for TitanhError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TitanhError::Rpc(e) => Some(e),
+            TitanhError::Ipfs(e) => Some(e),
+            TitanhError::Integrity(e) => Some(e),
+            TitanhError::Codec(e) => Some(e),
+            TitanhError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<subxt::Error> for TitanhError {
+    fn from(error: subxt::Error) -> Self {
+        match error {
+            subxt::Error::Runtime(DispatchError::Module(e)) => TitanhError::from_module_error(&e),
+            subxt::Error::Runtime(e) => TitanhError::Dispatch(e.to_string()),
+            subxt::Error::Codec(e) => TitanhError::Codec(e),
+            e => TitanhError::Rpc(e),
+        }
+    }
+}
+
+impl From<ipfs_api_backend_hyper::Error> for TitanhError {
+    fn from(error: ipfs_api_backend_hyper::Error) -> Self {
+        TitanhError::Ipfs(error)
+    }
+}
+
+impl From<IntegrityError> for TitanhError {
+    fn from(error: IntegrityError) -> Self {
+        TitanhError::Integrity(error)
+    }
+}
+
+impl From<codec::Error> for TitanhError {
+    fn from(error: codec::Error) -> Self {
+        TitanhError::Codec(error)
+    }
+}
+
+// The integrity errors of the streamed reads are carried by io errors, so they are unwrapped
+impl From<io::Error> for TitanhError {
+    fn from(error: io::Error) -> Self {
+        if error
+            .get_ref()
+            .is_some_and(|inner| inner.is::<IntegrityError>())
+        {
+            let inner = error.into_inner().expect("The io error holds an error");
+            let e = inner
+                .downcast::<IntegrityError>()
+                .expect("The io error holds an integrity error");
+            return TitanhError::Integrity(*e);
+        }
+
+        TitanhError::Io(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use codec::Decode;
+    use jsonrpsee::{core::ClientError, types::ErrorObject};
+    use subxt::{error::RpcError, Metadata};
+
+    fn metadata() -> Metadata {
+        let bytes = include_bytes!("../../chain-metadata.scale");
+        Metadata::decode(&mut &bytes[..]).unwrap()
+    }
+
+    /// Decodes the runtime error of a pallet, as returned by a failed extrinsic
+    fn pallet_error(pallet: &str, error: &str) -> TitanhError {
+        let metadata = metadata();
+        let pallet_metadata = metadata.pallet_by_name(pallet).unwrap();
+        let variant = pallet_metadata
+            .error_variants()
+            .unwrap()
+            .iter()
+            .find(|variant| variant.name == error)
+            .unwrap();
+        // The `Module` variant of the runtime `DispatchError`
+        let encoded = [3, pallet_metadata.index(), variant.index, 0, 0, 0];
+
+        let error = DispatchError::decode_from(&encoded[..], metadata.clone()).unwrap();
+        subxt::Error::Runtime(error).into()
+    }
+
+    #[test]
+    fn pallet_errors_are_typed_test() {
+        assert!(matches!(
+            pallet_error("Capsules", "InvalidCapsuleId"),
+            TitanhError::CapsuleNotFound
+        ));
+        assert!(matches!(
+            pallet_error("Capsules", "InvalidContainerId"),
+            TitanhError::ContainerNotFound
+        ));
+        assert!(matches!(
+            pallet_error("Capsules", "CapsuleIdAlreadyExists"),
+            TitanhError::CapsuleAlreadyExists
+        ));
+        assert!(matches!(
+            pallet_error("Capsules", "BadCid"),
+            TitanhError::BadCid
+        ));
+        for (pallet, error) in [
+            ("Capsules", "AppPermissionDenied"),
+            ("Capsules", "BadOriginForOwnership"),
+            ("AppRegistrar", "NotOwner"),
+            ("AppRegistrar", "NotAllowed"),
+        ] {
+            assert!(matches!(
+                pallet_error(pallet, error),
+                TitanhError::PermissionDenied
+            ));
+        }
+    }
+
+    #[test]
+    fn other_pallet_errors_are_named_test() {
+        match pallet_error("Capsules", "TooManyOwners") {
+            TitanhError::Pallet { pallet, error } => {
+                assert_eq!(pallet, "Capsules");
+                assert_eq!(error, "TooManyOwners");
+            }
+            e => panic!("unexpected error {}", e),
+        }
+    }
+
+    #[test]
+    fn non_module_error_is_a_dispatch_error_test() {
+        // The `BadOrigin` variant of the runtime `DispatchError`
+        let error = DispatchError::decode_from(&[2u8][..], metadata()).unwrap();
+
+        let error: TitanhError = subxt::Error::Runtime(error).into();
+        assert!(matches!(error, TitanhError::Dispatch(_)));
+    }
+
+    #[test]
+    fn integrity_error_is_unwrapped_from_io_error_test() {
+        let integrity = IntegrityError::HashMismatch {
+            cid: "bafkqaaa".to_string(),
+        };
+        let error = io::Error::new(io::ErrorKind::InvalidData, integrity.clone());
+
+        match TitanhError::from(error) {
+            TitanhError::Integrity(e) => assert_eq!(e, integrity),
+            e => panic!("unexpected error {}", e),
+        }
+    }
+
+    #[test]
+    fn other_io_errors_are_kept_test() {
+        let error = io::Error::new(io::ErrorKind::InvalidData, "bad data");
+        match TitanhError::from(error) {
+            TitanhError::Io(e) => assert_eq!(e.kind(), io::ErrorKind::InvalidData),
+            e => panic!("unexpected error {}", e),
+        }
+
+        let error = io::Error::from(io::ErrorKind::UnexpectedEof);
+        assert!(matches!(TitanhError::from(error), TitanhError::Io(_)));
+    }
+
+    /// An error returned by the rpc client of the api
+    fn rpc_error(error: ClientError) -> TitanhError {
+        subxt::Error::Rpc(RpcError::ClientError(Box::new(error))).into()
+    }
+
+    #[test]
+    fn pruned_state_error_is_mapped_test() {
+        // The error of a substrate node reading the state of a pruned block
+        let error = rpc_error(ClientError::Call(ErrorObject::owned(
+            4003,
+            "Client error: UnknownBlock: State already discarded for 0x0101010101010101010101010101010101010101010101010101010101010101",
+            None::<()>,
+        )));
+
+        assert!(matches!(error.or_pruned(7), TitanhError::StatePruned(7)));
+    }
+
+    #[test]
+    fn other_rpc_errors_are_not_mapped_test() {
+        let error = rpc_error(ClientError::Call(ErrorObject::owned(
+            -32601,
+            "Method not found",
+            None::<()>,
+        )));
+        assert!(matches!(
+            error.or_pruned(7),
+            TitanhError::Rpc(subxt::Error::Rpc(_))
+        ));
+
+        let error = rpc_error(ClientError::RequestTimeout);
+        assert!(matches!(
+            error.or_pruned(7),
+            TitanhError::Rpc(subxt::Error::Rpc(_))
+        ));
+        assert!(matches!(
+            TitanhError::CapsuleNotFound.or_pruned(7),
+            TitanhError::CapsuleNotFound
+        ));
+    }
+}
@@ -0,0 +1,496 @@
+use super::{
+    cid::{parse_cid, Cid},
+    error::{Result, TitanhError},
+};
+use futures::{future::BoxFuture, stream, AsyncRead, Stream, TryStreamExt};
+use ipfs_api_backend_hyper::{IpfsApi, IpfsClient};
+use multihash_codetable::{Code, MultihashDigest};
+use std::{fmt, io};
+
+/// The multicodec of raw blocks
+const RAW_CODEC: u64 = 0x55;
+/// The multicodec of dag-pb blocks, the default format of the files added to IPFS
+const DAG_PB_CODEC: u64 = 0x70;
+/// The multihash of inlined blocks, whose digest is the block itself
+const IDENTITY_HASH: u64 = 0x00;
+
+/// The content returned by an IPFS endpoint does not match the CID it was requested for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IntegrityError {
+    /// The block does not hash to the digest of its CID
+    HashMismatch { cid: String },
+    /// The CID uses a hash function that cannot be verified locally
+    UnsupportedHash { cid: String, code: u64 },
+    /// The CID uses a codec that is neither raw nor dag-pb
+    UnsupportedCodec { cid: String, codec: u64 },
+    /// The block cannot be decoded as a UnixFS file
+    MalformedBlock { cid: String, reason: &'static str },
+}
+
+impl fmt::Display for IntegrityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntegrityError::HashMismatch { cid } => {
+                write!(
+                    f,
+                    "The content returned for {} does not match its hash",
+                    cid
+                )
+            }
+            IntegrityError::UnsupportedHash { cid, code } => {
+                write!(
+                    f,
+                    "The hash function {:#x} of {} is not supported",
+                    code, cid
+                )
+            }
+            IntegrityError::UnsupportedCodec { cid, codec } => {
+                write!(f, "The codec {:#x} of {} is not supported", codec, cid)
+            }
+            IntegrityError::MalformedBlock { cid, reason } => {
+                write!(f, "The block {} is malformed: {}", cid, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for IntegrityError {}
+
+/// A source of the blocks of the verified reads, e.g. an IPFS node or a pool of IPFS endpoints
+pub trait BlockSource: Clone + Send + Sync + 'static {
+    /// Fetches a raw block, that is then verified against its CID by the reader
+    fn get_block<'a>(&'a self, cid: &'a Cid) -> BoxFuture<'a, Result<Vec<u8>>>;
+}
+
+impl BlockSource for IpfsClient {
+    fn get_block<'a>(&'a self, cid: &'a Cid) -> BoxFuture<'a, Result<Vec<u8>>> {
+        Box::pin(async move {
+            let block = self
+                .block_get(&cid.to_string())
+                .map_ok(|chunk| chunk.to_vec())
+                .try_concat()
+                .await?;
+
+            Ok(block)
+        })
+    }
+}
+
+/// Reads the content of a file from IPFS, verifying it against its CID.
+/// The blocks of the file are fetched one by one and each of them is checked against the hash of its CID, so that an IPFS endpoint cannot return forged content.
+/// On a mismatch it returns an `IntegrityError`.
+pub async fn read_verified<S: BlockSource>(ipfs: &S, cid: &str) -> Result<Vec<u8>> {
+    let chunks: Vec<Vec<u8>> = stream_verified(ipfs.clone(), cid)?.try_collect().await?;
+
+    Ok(chunks.concat())
+}
+
+/// Streams the content of a file from IPFS, verifying each block against its CID as in `read_verified`.
+/// Only one block at a time is kept in memory, so that large files can be read.
+pub fn stream_verified<S: BlockSource>(
+    ipfs: S,
+    cid: &str,
+) -> Result<impl Stream<Item = Result<Vec<u8>>> + 'static> {
+    let root = parse_cid(cid.as_bytes())?;
+
+    // The blocks still to be read, the next one on top
+    let pending = vec![root];
+    let stream = stream::try_unfold((ipfs, pending), |(ipfs, mut pending)| async move {
+        // The intermediate nodes of the file hold no data, so they are skipped
+        while let Some(cid) = pending.pop() {
+            let data = read_block(&ipfs, &cid, &mut pending).await?;
+            if !data.is_empty() {
+                return Ok(Some((data, (ipfs, pending))));
+            }
+        }
+
+        Ok::<_, TitanhError>(None)
+    });
+
+    Ok(stream)
+}
+
+/// Streams the verified content of a file as a reader. The integrity errors are returned as `InvalidData` io errors holding the `IntegrityError`
+pub fn verified_reader<S: BlockSource>(
+    ipfs: S,
+    cid: &str,
+) -> Result<impl AsyncRead + Unpin + 'static> {
+    let stream = stream_verified(ipfs, cid)?.map_err(|e| match e {
+        TitanhError::Integrity(e) => io::Error::new(io::ErrorKind::InvalidData, e),
+        TitanhError::Io(e) => e,
+        e => io::Error::other(e.to_string()),
+    });
+
+    Ok(Box::pin(stream).into_async_read())
+}
+
+/// Fetches and verifies a block, returning its file data and pushing its children to the pending blocks
+async fn read_block<S: BlockSource>(
+    ipfs: &S,
+    cid: &Cid,
+    pending: &mut Vec<Cid>,
+) -> Result<Vec<u8>> {
+    let block = ipfs.get_block(cid).await?;
+    verify_block(cid, &block)?;
+
+    match cid.codec() {
+        RAW_CODEC => Ok(block),
+        DAG_PB_CODEC => {
+            let node = PbNode::decode(&block).map_err(|reason| malformed(cid, reason))?;
+            let data = unixfs_file_data(node.data).map_err(|reason| malformed(cid, reason))?;
+            // The data of a node precedes the content of its children
+            for link in node.links.into_iter().rev() {
+                let link = Cid::try_from(link).map_err(|_| malformed(cid, "invalid link"))?;
+                pending.push(link);
+            }
+
+            Ok(data.to_vec())
+        }
+        codec => Err(IntegrityError::UnsupportedCodec {
+            cid: cid.to_string(),
+            codec,
+        }
+        .into()),
+    }
+}
+
+/// Checks that the block hashes to the digest of its CID
+pub(crate) fn verify_block(cid: &Cid, block: &[u8]) -> Result<(), IntegrityError> {
+    let hash = cid.hash();
+    let matches = if hash.code() == IDENTITY_HASH {
+        hash.digest() == block
+    } else {
+        let code = Code::try_from(hash.code()).map_err(|_| IntegrityError::UnsupportedHash {
+            cid: cid.to_string(),
+            code: hash.code(),
+        })?;
+        code.digest(block) == *hash
+    };
+
+    if !matches {
+        return Err(IntegrityError::HashMismatch {
+            cid: cid.to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+fn malformed(cid: &Cid, reason: &'static str) -> IntegrityError {
+    IntegrityError::MalformedBlock {
+        cid: cid.to_string(),
+        reason,
+    }
+}
+
+/// A dag-pb node: the hashes of its links, in order, and its UnixFS data
+struct PbNode<'a> {
+    links: Vec<&'a [u8]>,
+    data: &'a [u8],
+}
+
+impl<'a> PbNode<'a> {
+    fn decode(block: &'a [u8]) -> Result<Self, &'static str> {
+        let mut links = Vec::new();
+        let mut data: &[u8] = &[];
+
+        let mut reader = ProtoReader(block);
+        while let Some((field, value)) = reader.next_field()? {
+            match (field, value) {
+                (1, ProtoValue::Bytes(bytes)) => data = bytes,
+                (2, ProtoValue::Bytes(link)) => {
+                    let mut link_reader = ProtoReader(link);
+                    let mut hash = None;
+                    while let Some((field, value)) = link_reader.next_field()? {
+                        if let (1, ProtoValue::Bytes(bytes)) = (field, value) {
+                            hash = Some(bytes);
+                        }
+                    }
+                    links.push(hash.ok_or("link without hash")?);
+                }
+                _ => return Err("unexpected field"),
+            }
+        }
+
+        Ok(Self { links, data })
+    }
+}
+
+/// Returns the file content held by a UnixFS node, failing if it is not a file
+fn unixfs_file_data(unixfs: &[u8]) -> Result<&[u8], &'static str> {
+    // The UnixFS types of file content
+    const RAW: u64 = 0;
+    const FILE: u64 = 2;
+
+    let mut data_type = None;
+    let mut data: &[u8] = &[];
+
+    let mut reader = ProtoReader(unixfs);
+    while let Some((field, value)) = reader.next_field()? {
+        match (field, value) {
+            (1, ProtoValue::Varint(value)) => data_type = Some(value),
+            (2, ProtoValue::Bytes(bytes)) => data = bytes,
+            // The sizes and the other metadata are not needed to read the content
+            _ => {}
+        }
+    }
+
+    match data_type {
+        Some(RAW) | Some(FILE) => Ok(data),
+        Some(_) => Err("not a file"),
+        None => Err("missing UnixFS type"),
+    }
+}
+
+enum ProtoValue<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,
+}
+
+/// A minimal protobuf reader, enough to decode dag-pb and UnixFS nodes
+struct ProtoReader<'a>(&'a [u8]);
+
+impl<'a> ProtoReader<'a> {
+    fn next_field(&mut self) -> Result<Option<(u64, ProtoValue<'a>)>, &'static str> {
+        if self.0.is_empty() {
+            return Ok(None);
+        }
+
+        let tag = self.varint()?;
+        let value = match tag & 0x7 {
+            0 => ProtoValue::Varint(self.varint()?),
+            1 => {
+                self.take(8)?;
+                ProtoValue::Fixed
+            }
+            2 => {
+                let len = usize::try_from(self.varint()?).map_err(|_| "invalid length")?;
+                ProtoValue::Bytes(self.take(len)?)
+            }
+            5 => {
+                self.take(4)?;
+                ProtoValue::Fixed
+            }
+            _ => return Err("unsupported wire type"),
+        };
+
+        Ok(Some((tag >> 3, value)))
+    }
+
+    fn varint(&mut self) -> Result<u64, &'static str> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let (byte, rest) = self.0.split_first().ok_or("truncated varint")?;
+            self.0 = rest;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err("varint too long")
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], &'static str> {
+        if len > self.0.len() {
+            return Err("truncated field");
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::cid::Multihash;
+    use std::{collections::HashMap, sync::Arc};
+
+    /// The block of an empty UnixFS directory, as added by go-ipfs, and its CID
+    const EMPTY_DIR_BLOCK: [u8; 4] = [0x0a, 0x02, 0x08, 0x01];
+    const EMPTY_DIR_CID: &str = "QmUNLLsPACCz1vLxQVkXqqLX5R1X345qqfHbsf67hvA3Nn";
+
+    /// An in-memory block store
+    #[derive(Clone, Default)]
+    struct Blocks(Arc<HashMap<Cid, Vec<u8>>>);
+
+    impl Blocks {
+        fn insert(&mut self, cid: Cid, block: Vec<u8>) -> Cid {
+            Arc::get_mut(&mut self.0).unwrap().insert(cid, block);
+            cid
+        }
+
+        fn raw(&mut self, data: &[u8]) -> Cid {
+            let cid = Cid::new_v1(RAW_CODEC, Code::Sha2_256.digest(data));
+            self.insert(cid, data.to_vec())
+        }
+
+        fn dag_pb_v0(&mut self, links: &[Cid], data: &[u8]) -> Cid {
+            let block = dag_pb(links, data);
+            let cid = Cid::new_v0(Code::Sha2_256.digest(&block)).unwrap();
+            self.insert(cid, block)
+        }
+
+        fn dag_pb_v1(&mut self, links: &[Cid], data: &[u8]) -> Cid {
+            let block = dag_pb(links, data);
+            let cid = Cid::new_v1(DAG_PB_CODEC, Code::Sha2_256.digest(&block));
+            self.insert(cid, block)
+        }
+    }
+
+    impl BlockSource for Blocks {
+        fn get_block<'a>(&'a self, cid: &'a Cid) -> BoxFuture<'a, Result<Vec<u8>>> {
+            let block = self.0.get(cid).cloned();
+            Box::pin(async move { block.ok_or(TitanhError::Gateway("block not found".into())) })
+        }
+    }
+
+    fn varint(mut value: u64, out: &mut Vec<u8>) {
+        while value >= 0x80 {
+            out.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    }
+
+    fn bytes_field(field: u64, bytes: &[u8], out: &mut Vec<u8>) {
+        varint((field << 3) | 2, out);
+        varint(bytes.len() as u64, out);
+        out.extend_from_slice(bytes);
+    }
+
+    /// Encodes the UnixFS data of a file node
+    fn unixfs_file(data: &[u8]) -> Vec<u8> {
+        let mut unixfs = vec![0x08, 0x02];
+        if !data.is_empty() {
+            bytes_field(2, data, &mut unixfs);
+        }
+        unixfs.push(0x18);
+        varint(data.len() as u64, &mut unixfs);
+        unixfs
+    }
+
+    /// Encodes a dag-pb node, the links first as in the canonical encoding
+    fn dag_pb(links: &[Cid], data: &[u8]) -> Vec<u8> {
+        let mut block = Vec::new();
+        for link in links {
+            let mut encoded = Vec::new();
+            bytes_field(1, &link.to_bytes(), &mut encoded);
+            bytes_field(2, b"", &mut encoded);
+            bytes_field(2, &encoded, &mut block);
+        }
+        bytes_field(1, &unixfs_file(data), &mut block);
+        block
+    }
+
+    fn integrity_error(res: Result<Vec<u8>>) -> IntegrityError {
+        match res {
+            Err(TitanhError::Integrity(e)) => e,
+            _ => panic!("expected an integrity error"),
+        }
+    }
+
+    #[tokio::test]
+    async fn raw_leaf_is_read_test() {
+        let mut blocks = Blocks::default();
+        let cid = blocks.raw(b"hello world");
+
+        let content = read_verified(&blocks, &cid.to_string()).await.unwrap();
+        assert_eq!(content, b"hello world");
+    }
+
+    #[tokio::test]
+    async fn raw_leaves_file_is_read_in_order_test() {
+        let mut blocks = Blocks::default();
+        let leaves = [blocks.raw(b"hello "), blocks.raw(b"world")];
+        let root = blocks.dag_pb_v1(&leaves, b"");
+
+        let content = read_verified(&blocks, &root.to_string()).await.unwrap();
+        assert_eq!(content, b"hello world");
+    }
+
+    #[tokio::test]
+    async fn multi_block_dag_pb_file_is_read_in_order_test() {
+        let mut blocks = Blocks::default();
+        let first = blocks.dag_pb_v0(&[], b"a");
+        let second = blocks.dag_pb_v0(&[], b"b");
+        let inner = blocks.dag_pb_v0(&[first, second], b"");
+        let last = blocks.dag_pb_v0(&[], b"c");
+        // The data of a node precedes the content of its children
+        let root = blocks.dag_pb_v0(&[inner, last], b"0");
+
+        let content = read_verified(&blocks, &root.to_string()).await.unwrap();
+        assert_eq!(content, b"0abc");
+    }
+
+    #[test]
+    fn identity_cid_is_verified_against_its_digest_test() {
+        let multihash = Multihash::<64>::wrap(IDENTITY_HASH, b"inlined").unwrap();
+        let cid = Cid::new_v1(RAW_CODEC, multihash);
+
+        verify_block(&cid, b"inlined").unwrap();
+        assert_eq!(
+            verify_block(&cid, b"forged"),
+            Err(IntegrityError::HashMismatch {
+                cid: cid.to_string()
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn tampered_block_is_rejected_test() {
+        let mut blocks = Blocks::default();
+        let leaves = [blocks.raw(b"hello "), blocks.raw(b"world")];
+        let root = blocks.dag_pb_v1(&leaves, b"");
+        blocks.insert(leaves[1], b"w0rld".to_vec());
+
+        let err = integrity_error(read_verified(&blocks, &root.to_string()).await);
+        assert_eq!(
+            err,
+            IntegrityError::HashMismatch {
+                cid: leaves[1].to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn truncated_varints_are_rejected_test() {
+        assert_eq!(ProtoReader(&[0x96]).varint(), Err("truncated varint"));
+        assert_eq!(ProtoReader(&[0xff; 10]).varint(), Err("varint too long"));
+        assert_eq!(ProtoReader(&[0x96, 0x01]).varint(), Ok(150));
+
+        // A dag-pb node whose data length is cut
+        assert!(PbNode::decode(&[0x0a, 0x80]).is_err());
+        // A UnixFS node whose type is cut
+        assert_eq!(unixfs_file_data(&[0x08, 0x82]), Err("truncated varint"));
+    }
+
+    #[test]
+    fn truncated_field_is_rejected_test() {
+        assert!(PbNode::decode(&[0x0a, 0x05, 0x08]).is_err());
+    }
+
+    #[tokio::test]
+    async fn non_file_unixfs_node_is_rejected_test() {
+        let cid = Cid::try_from(EMPTY_DIR_CID).unwrap();
+        verify_block(&cid, &EMPTY_DIR_BLOCK).unwrap();
+
+        let node = PbNode::decode(&EMPTY_DIR_BLOCK).unwrap();
+        assert!(node.links.is_empty());
+        assert_eq!(unixfs_file_data(node.data), Err("not a file"));
+
+        let mut blocks = Blocks::default();
+        blocks.insert(cid, EMPTY_DIR_BLOCK.to_vec());
+        let err = integrity_error(read_verified(&blocks, EMPTY_DIR_CID).await);
+        assert_eq!(
+            err,
+            IntegrityError::MalformedBlock {
+                cid: EMPTY_DIR_CID.to_string(),
+                reason: "not a file"
+            }
+        );
+    }
+}
@@ -0,0 +1,628 @@
+use super::{
+    cid::Cid,
+    error::{Result, TitanhError},
+    ipfs::{verify_block, BlockSource},
+};
+use crate::capsules_types::IpfsAddOpts;
+use futures::{future::BoxFuture, stream::FuturesUnordered, AsyncRead, StreamExt};
+use hyper::{client::HttpConnector, header, Body, Client, Request};
+use hyper_rustls::HttpsConnector;
+use ipfs_api_backend_hyper::{
+    request::Add, response::AddResponse, IpfsApi, IpfsClient, TryFromUri,
+};
+use std::{
+    io::Cursor,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Weak,
+    },
+    time::Duration,
+};
+
+/// The CID of the empty content, inlined in the CID itself, requested to check that a gateway is up
+const PROBE_CID: &str = "bafkqaaa";
+
+/// An IPFS endpoint of the capsules api
+#[derive(Clone, Debug)]
+pub enum IpfsEndpoint {
+    /// The rpc api of an IPFS node, used for reads and writes
+    Rpc(String),
+    /// A read-only trustless gateway (e.g. a public one), used for reads only when the rpc endpoints fail
+    Gateway(String),
+}
+
+/// The options of a pool of IPFS endpoints
+#[derive(Clone, Debug)]
+pub struct IpfsPoolOptions {
+    /// The delay after which a pending read is also requested to the next endpoint
+    pub hedge_delay: Duration,
+    /// The interval between the health checks of the endpoints
+    pub health_check_interval: Duration,
+    /// Whether the content written to an rpc endpoint is also added to the other ones in the background
+    pub propagate_writes: bool,
+}
+
+impl Default for IpfsPoolOptions {
+    fn default() -> Self {
+        Self {
+            hedge_delay: Duration::from_millis(300),
+            health_check_interval: Duration::from_secs(10),
+            propagate_writes: true,
+        }
+    }
+}
+
+/// A pool of IPFS endpoints, so that an outage of one of them does not break the reads and the writes.
+/// Reads are sent to the healthy endpoints in order, hedged with the next one when an endpoint is slow, and fall back to the gateways.
+/// Writes go to one healthy rpc endpoint, and are propagated to the other ones in the background.
+/// The endpoints are marked unhealthy when a request fails, and healthy again by the periodic health checks
+#[derive(Clone)]
+pub struct IpfsPool {
+    inner: Arc<PoolInner>,
+}
+
+struct PoolInner {
+    /// The rpc endpoints, in order of preference
+    nodes: Vec<Endpoint<IpfsClient>>,
+    /// The read-only gateways, in order of preference
+    gateways: Vec<Endpoint<String>>,
+    http: Client<HttpsConnector<HttpConnector>>,
+    options: IpfsPoolOptions,
+}
+
+struct Endpoint<C> {
+    url: String,
+    client: C,
+    healthy: AtomicBool,
+}
+
+/// An endpoint a block can be read from
+#[derive(Clone, Copy)]
+enum Source<'a> {
+    Node(&'a Endpoint<IpfsClient>),
+    Gateway(&'a Endpoint<String>),
+}
+
+impl IpfsPool {
+    pub fn new(endpoints: Vec<IpfsEndpoint>, options: IpfsPoolOptions) -> Result<Self> {
+        if endpoints.is_empty() {
+            return Err(TitanhError::invalid_input("No IPFS endpoints"));
+        }
+
+        let mut nodes = Vec::new();
+        let mut gateways = Vec::new();
+        for endpoint in endpoints {
+            match endpoint {
+                IpfsEndpoint::Rpc(url) => {
+                    let client = IpfsClient::from_str(&url).map_err(TitanhError::invalid_input)?;
+                    nodes.push(Endpoint::new(url, client));
+                }
+                IpfsEndpoint::Gateway(url) => {
+                    let base_url = url.trim_end_matches('/').to_string();
+                    gateways.push(Endpoint::new(url, base_url));
+                }
+            }
+        }
+
+        let https = hyper_rustls::HttpsConnectorBuilder::new()
+            .with_native_roots()
+            .https_or_http()
+            .enable_http1()
+            .build();
+        let inner = Arc::new(PoolInner {
+            nodes,
+            gateways,
+            http: Client::builder().build(https),
+            options,
+        });
+        // The endpoints are only checked passively without a runtime
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(check_health(Arc::downgrade(&inner)));
+        }
+
+        Ok(Self { inner })
+    }
+
+    /// Returns the urls of the endpoints that are currently healthy
+    pub fn healthy_endpoints(&self) -> Vec<String> {
+        let nodes = self.inner.nodes.iter().filter(|node| node.is_healthy());
+        let gateways = self.inner.gateways.iter().filter(|gw| gw.is_healthy());
+
+        nodes
+            .map(|node| node.url.clone())
+            .chain(gateways.map(|gateway| gateway.url.clone()))
+            .collect()
+    }
+
+    /// Adds some data to a healthy rpc endpoint, then to the other ones in the background.
+    /// The options left to the node defaults are set explicitly, so that every endpoint adds the data with the same cid
+    pub async fn add(&self, data: Vec<u8>, opts: &IpfsAddOpts) -> Result<AddResponse> {
+        let opts = opts.resolved();
+        let mut last_error = None;
+        for (index, node) in self.write_order() {
+            let res = node
+                .client
+                .add_with_options(Cursor::new(data.clone()), opts.to_add())
+                .await;
+            match res {
+                Ok(res) => {
+                    node.set_healthy(true);
+                    self.propagate(index, data, opts, res.hash.clone());
+                    return Ok(res);
+                }
+                Err(e) => {
+                    log::warn!("Failed to add to the IPFS endpoint {}: {}", node.url, e);
+                    node.set_healthy(false);
+                    last_error = Some(e.into());
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or(TitanhError::NotConfigured("IPFS rpc endpoint")))
+    }
+
+    /// Streams some data to a healthy rpc endpoint. The data is read once, so it is neither retried nor propagated
+    pub async fn add_async<R>(&self, data: R, add_opts: Add<'_>) -> Result<AddResponse>
+    where
+        R: AsyncRead + Send + Sync + Unpin + 'static,
+    {
+        let (_, node) = self
+            .write_order()
+            .next()
+            .ok_or(TitanhError::NotConfigured("IPFS rpc endpoint"))?;
+
+        let res = node.client.add_async_with_options(data, add_opts).await;
+        node.set_healthy(res.is_ok());
+
+        Ok(res?)
+    }
+
+    /// Returns the rpc endpoints with their index, the healthy ones first
+    fn write_order(&self) -> impl Iterator<Item = (usize, &Endpoint<IpfsClient>)> {
+        let nodes = self.inner.nodes.iter().enumerate();
+        let (healthy, unhealthy): (Vec<_>, Vec<_>) = nodes.partition(|(_, node)| node.is_healthy());
+
+        healthy.into_iter().chain(unhealthy)
+    }
+
+    /// Returns the rpc endpoints and the gateways to read from, each with the healthy ones first and the unhealthy ones as a last resort
+    fn read_order(&self) -> (Vec<Source<'_>>, Vec<Source<'_>>) {
+        let nodes = self.inner.nodes.iter().map(Source::Node);
+        let gateways = self.inner.gateways.iter().map(Source::Gateway);
+
+        (healthy_first(nodes), healthy_first(gateways))
+    }
+
+    /// Adds the data to the other rpc endpoints in the background, so that they can serve the reads without fetching it from the network
+    fn propagate(&self, written: usize, data: Vec<u8>, opts: IpfsAddOpts, cid: String) {
+        if !self.inner.options.propagate_writes || self.inner.nodes.len() < 2 {
+            return;
+        }
+
+        let inner = self.inner.clone();
+        tokio::spawn(async move {
+            let others = inner
+                .nodes
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| *i != written);
+            for (_, node) in others.filter(|(_, node)| node.is_healthy()) {
+                // The same explicit options give the same cid
+                let data = Cursor::new(data.clone());
+                match node.client.add_with_options(data, opts.to_add()).await {
+                    Ok(res) if res.hash != cid => log::warn!(
+                        "The IPFS endpoint {} added {} as {}",
+                        node.url,
+                        cid,
+                        res.hash
+                    ),
+                    Ok(_) => {}
+                    Err(e) => {
+                        log::warn!(
+                            "Failed to propagate to the IPFS endpoint {}: {}",
+                            node.url,
+                            e
+                        );
+                        node.set_healthy(false);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Fetches a block from an endpoint, verifying it so that a forged block falls back to the other endpoints
+    async fn fetch_block(&self, source: Source<'_>, cid: &Cid) -> Result<Vec<u8>> {
+        let res = match source {
+            Source::Node(node) => node.client.get_block(cid).await,
+            Source::Gateway(gateway) => self.gateway_block(&gateway.client, cid).await,
+        };
+        let res = res.and_then(|block| {
+            verify_block(cid, &block)?;
+            Ok(block)
+        });
+
+        if let Err(e) = &res {
+            log::warn!("Failed to read {} from {}: {}", cid, source.url(), e);
+        }
+        source.set_healthy(res.is_ok());
+
+        res
+    }
+
+    /// Fetches a raw block from a trustless gateway
+    async fn gateway_block(&self, base_url: &str, cid: &Cid) -> Result<Vec<u8>> {
+        let request = Request::get(format!("{}/ipfs/{}?format=raw", base_url, cid))
+            .header(header::ACCEPT, "application/vnd.ipld.raw")
+            .body(Body::empty())
+            .map_err(|e| TitanhError::Gateway(e.to_string()))?;
+
+        let response = self
+            .inner
+            .http
+            .request(request)
+            .await
+            .map_err(|e| TitanhError::Gateway(e.to_string()))?;
+        if !response.status().is_success() {
+            return Err(TitanhError::Gateway(format!(
+                "{} returned {}",
+                base_url,
+                response.status()
+            )));
+        }
+
+        let block = hyper::body::to_bytes(response.into_body())
+            .await
+            .map_err(|e| TitanhError::Gateway(e.to_string()))?;
+
+        Ok(block.to_vec())
+    }
+
+    /// Reads a block from the sources in order. When a source does not answer within the hedge delay, or fails,
+    /// the block is also requested to the next one, and the first verified block is returned
+    async fn hedged_block(&self, sources: Vec<Source<'_>>, cid: &Cid) -> Result<Vec<u8>> {
+        let mut sources = sources.into_iter();
+        let mut requests = FuturesUnordered::new();
+        let mut last_error = None;
+
+        loop {
+            let next = tokio::time::timeout(self.inner.options.hedge_delay, requests.next());
+            match next.await {
+                Ok(Some(Ok(block))) => return Ok(block),
+                Ok(Some(Err(e))) => last_error = Some(e),
+                // No request in flight, or the hedge delay elapsed
+                Ok(None) | Err(_) => {}
+            }
+
+            match sources.next() {
+                Some(source) => requests.push(self.fetch_block(source, cid)),
+                None if requests.is_empty() => {
+                    return Err(last_error.unwrap_or(TitanhError::NotConfigured("IPFS endpoints")))
+                }
+                None => {}
+            }
+        }
+    }
+}
+
+impl BlockSource for IpfsPool {
+    /// Reads a block from the rpc endpoints, hedged as in `hedged_block`. The gateways are only read once every rpc endpoint has failed
+    fn get_block<'a>(&'a self, cid: &'a Cid) -> BoxFuture<'a, Result<Vec<u8>>> {
+        Box::pin(async move {
+            let (nodes, gateways) = self.read_order();
+            let mut last_error = None;
+            for sources in [nodes, gateways] {
+                if sources.is_empty() {
+                    continue;
+                }
+                match self.hedged_block(sources, cid).await {
+                    Ok(block) => return Ok(block),
+                    Err(e) => last_error = Some(e),
+                }
+            }
+
+            Err(last_error.unwrap_or(TitanhError::NotConfigured("IPFS endpoints")))
+        })
+    }
+}
+
+impl<C> Endpoint<C> {
+    fn new(url: String, client: C) -> Self {
+        Self {
+            url,
+            client,
+            healthy: AtomicBool::new(true),
+        }
+    }
+
+    fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::Relaxed)
+    }
+
+    fn set_healthy(&self, healthy: bool) {
+        self.healthy.store(healthy, Ordering::Relaxed);
+    }
+}
+
+impl Source<'_> {
+    fn url(&self) -> &str {
+        match self {
+            Source::Node(node) => &node.url,
+            Source::Gateway(gateway) => &gateway.url,
+        }
+    }
+
+    fn is_healthy(&self) -> bool {
+        match self {
+            Source::Node(node) => node.is_healthy(),
+            Source::Gateway(gateway) => gateway.is_healthy(),
+        }
+    }
+
+    fn set_healthy(&self, healthy: bool) {
+        match self {
+            Source::Node(node) => node.set_healthy(healthy),
+            Source::Gateway(gateway) => gateway.set_healthy(healthy),
+        }
+    }
+}
+
+/// Orders the sources with the healthy ones first
+fn healthy_first<'a>(sources: impl Iterator<Item = Source<'a>>) -> Vec<Source<'a>> {
+    let (healthy, unhealthy): (Vec<_>, Vec<_>) = sources.partition(Source::is_healthy);
+
+    healthy.into_iter().chain(unhealthy).collect()
+}
+
+/// Checks the endpoints periodically, until the pool is dropped
+async fn check_health(inner: Weak<PoolInner>) {
+    let Some(interval) = inner
+        .upgrade()
+        .map(|inner| inner.options.health_check_interval)
+    else {
+        return;
+    };
+
+    loop {
+        tokio::time::sleep(interval).await;
+        let Some(inner) = inner.upgrade() else {
+            return;
+        };
+        let pool = IpfsPool { inner };
+
+        for node in pool.inner.nodes.iter() {
+            node.set_healthy(node.client.version().await.is_ok());
+        }
+        for gateway in pool.inner.gateways.iter() {
+            let probe = Cid::try_from(PROBE_CID).expect("The probe CID is valid");
+            let res = pool.gateway_block(&gateway.client, &probe).await;
+            gateway.set_healthy(res.is_ok());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use multihash_codetable::{Code, MultihashDigest};
+    use std::sync::atomic::AtomicUsize;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    const BLOCK: &[u8] = b"capsule";
+
+    /// A local http endpoint answering every request with the same response, after a delay
+    struct MockEndpoint {
+        url: String,
+        requests: Arc<AtomicUsize>,
+    }
+
+    /// The response of a mock endpoint
+    #[derive(Clone)]
+    enum Response {
+        Block(Vec<u8>),
+        Failure,
+    }
+
+    impl MockEndpoint {
+        async fn start(response: Response, delay: Duration) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            let requests = Arc::new(AtomicUsize::new(0));
+
+            let counter = requests.clone();
+            tokio::spawn(async move {
+                while let Ok((mut socket, _)) = listener.accept().await {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    let response = response.clone();
+                    tokio::spawn(async move {
+                        read_request(&mut socket).await;
+                        tokio::time::sleep(delay).await;
+                        let (status, body) = match response {
+                            Response::Block(block) => ("200 OK", block),
+                            Response::Failure => (
+                                "500 Internal Server Error",
+                                br#"{"Message":"failure","Code":0,"Type":"error"}"#.to_vec(),
+                            ),
+                        };
+                        let head = format!(
+                            "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                            status,
+                            body.len()
+                        );
+                        let _ = socket.write_all(head.as_bytes()).await;
+                        let _ = socket.write_all(&body).await;
+                    });
+                }
+            });
+
+            Self { url, requests }
+        }
+
+        fn requests(&self) -> usize {
+            self.requests.load(Ordering::SeqCst)
+        }
+    }
+
+    /// Reads the head of a request, the requests of the pool have no body
+    async fn read_request(socket: &mut TcpStream) {
+        let mut request = Vec::new();
+        let mut buf = [0; 1024];
+        while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+            match socket.read(&mut buf).await {
+                Ok(0) | Err(_) => return,
+                Ok(n) => request.extend_from_slice(&buf[..n]),
+            }
+        }
+    }
+
+    fn block_cid() -> Cid {
+        Cid::new_v1(0x55, Code::Sha2_256.digest(BLOCK))
+    }
+
+    fn pool(endpoints: Vec<IpfsEndpoint>, hedge_delay: Duration) -> IpfsPool {
+        let options = IpfsPoolOptions {
+            hedge_delay,
+            // The health checks do not interfere with the tests
+            health_check_interval: Duration::from_secs(3600),
+            propagate_writes: false,
+        };
+        IpfsPool::new(endpoints, options).unwrap()
+    }
+
+    #[tokio::test]
+    async fn slow_endpoint_is_hedged_test() {
+        let slow =
+            MockEndpoint::start(Response::Block(BLOCK.to_vec()), Duration::from_secs(5)).await;
+        let fast = MockEndpoint::start(Response::Block(BLOCK.to_vec()), Duration::ZERO).await;
+        let pool = pool(
+            vec![
+                IpfsEndpoint::Rpc(slow.url.clone()),
+                IpfsEndpoint::Rpc(fast.url.clone()),
+            ],
+            Duration::from_millis(50),
+        );
+
+        let block = tokio::time::timeout(Duration::from_secs(2), pool.get_block(&block_cid()))
+            .await
+            .expect("The read is hedged")
+            .unwrap();
+
+        assert_eq!(block, BLOCK);
+        assert_eq!(slow.requests(), 1);
+        assert_eq!(fast.requests(), 1);
+        // A slow endpoint is not unhealthy
+        assert_eq!(pool.healthy_endpoints().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn failed_endpoint_is_marked_unhealthy_test() {
+        let failing = MockEndpoint::start(Response::Failure, Duration::ZERO).await;
+        let healthy = MockEndpoint::start(Response::Block(BLOCK.to_vec()), Duration::ZERO).await;
+        let pool = pool(
+            vec![
+                IpfsEndpoint::Rpc(failing.url.clone()),
+                IpfsEndpoint::Rpc(healthy.url.clone()),
+            ],
+            Duration::from_secs(5),
+        );
+
+        assert_eq!(pool.get_block(&block_cid()).await.unwrap(), BLOCK);
+        assert_eq!(pool.healthy_endpoints(), vec![healthy.url.clone()]);
+
+        // The unhealthy endpoint is read last
+        assert_eq!(pool.get_block(&block_cid()).await.unwrap(), BLOCK);
+        assert_eq!(failing.requests(), 1);
+        assert_eq!(healthy.requests(), 2);
+    }
+
+    #[tokio::test]
+    async fn forged_block_falls_back_to_the_next_endpoint_test() {
+        let forging =
+            MockEndpoint::start(Response::Block(b"forged".to_vec()), Duration::ZERO).await;
+        let honest = MockEndpoint::start(Response::Block(BLOCK.to_vec()), Duration::ZERO).await;
+        let pool = pool(
+            vec![
+                IpfsEndpoint::Rpc(forging.url.clone()),
+                IpfsEndpoint::Rpc(honest.url.clone()),
+            ],
+            Duration::from_secs(5),
+        );
+
+        assert_eq!(pool.get_block(&block_cid()).await.unwrap(), BLOCK);
+        assert_eq!(pool.healthy_endpoints(), vec![honest.url.clone()]);
+    }
+
+    #[tokio::test]
+    async fn unhealthy_endpoint_is_healthy_again_after_a_read_test() {
+        let node = MockEndpoint::start(Response::Block(BLOCK.to_vec()), Duration::ZERO).await;
+        let pool = pool(
+            vec![IpfsEndpoint::Rpc(node.url.clone())],
+            Duration::from_secs(5),
+        );
+        pool.inner.nodes[0].set_healthy(false);
+
+        // The unhealthy endpoints are still read as a last resort
+        assert_eq!(pool.get_block(&block_cid()).await.unwrap(), BLOCK);
+        assert_eq!(pool.healthy_endpoints(), vec![node.url.clone()]);
+    }
+
+    #[tokio::test]
+    async fn gateway_is_not_a_hedge_candidate_test() {
+        let node =
+            MockEndpoint::start(Response::Block(BLOCK.to_vec()), Duration::from_millis(300)).await;
+        let gateway = MockEndpoint::start(Response::Block(BLOCK.to_vec()), Duration::ZERO).await;
+        let pool = pool(
+            vec![
+                IpfsEndpoint::Rpc(node.url.clone()),
+                IpfsEndpoint::Gateway(gateway.url.clone()),
+            ],
+            Duration::from_millis(50),
+        );
+
+        assert_eq!(pool.get_block(&block_cid()).await.unwrap(), BLOCK);
+        assert_eq!(node.requests(), 1);
+        assert_eq!(gateway.requests(), 0);
+    }
+
+    #[tokio::test]
+    async fn gateway_is_read_once_every_node_failed_test() {
+        let first = MockEndpoint::start(Response::Failure, Duration::ZERO).await;
+        let second = MockEndpoint::start(Response::Failure, Duration::ZERO).await;
+        let gateway = MockEndpoint::start(Response::Block(BLOCK.to_vec()), Duration::ZERO).await;
+        let pool = pool(
+            vec![
+                IpfsEndpoint::Rpc(first.url.clone()),
+                IpfsEndpoint::Rpc(second.url.clone()),
+                IpfsEndpoint::Gateway(gateway.url.clone()),
+            ],
+            Duration::from_millis(50),
+        );
+
+        assert_eq!(pool.get_block(&block_cid()).await.unwrap(), BLOCK);
+        assert_eq!(first.requests(), 1);
+        assert_eq!(second.requests(), 1);
+        assert_eq!(gateway.requests(), 1);
+        assert_eq!(pool.healthy_endpoints(), vec![gateway.url.clone()]);
+    }
+
+    #[tokio::test]
+    async fn failure_of_every_endpoint_is_returned_test() {
+        let node = MockEndpoint::start(Response::Failure, Duration::ZERO).await;
+        let gateway = MockEndpoint::start(Response::Failure, Duration::ZERO).await;
+        let pool = pool(
+            vec![
+                IpfsEndpoint::Rpc(node.url.clone()),
+                IpfsEndpoint::Gateway(gateway.url.clone()),
+            ],
+            Duration::from_millis(50),
+        );
+
+        assert!(matches!(
+            pool.get_block(&block_cid()).await,
+            Err(TitanhError::Gateway(_))
+        ));
+        assert!(pool.healthy_endpoints().is_empty());
+    }
+}
@@ -1,3 +1,12 @@
+pub mod cache;
+pub mod cid;
+pub mod error;
+pub mod ipfs;
+pub mod ipfs_pool;
+pub mod nonce;
+pub mod rpc;
+pub mod signer;
+pub mod tx;
 pub mod types;
 
 /// Module for accessing all blockchain related types. It is based on the encoded metadata provided at `runtime_metadata_path`
@@ -0,0 +1,190 @@
+use super::{error::Result, types::Rpc};
+use std::collections::BTreeSet;
+use subxt::utils::AccountId32;
+use tokio::sync::Mutex;
+
+/// Allocates the nonces of the transactions of a signer locally, so that many transactions can be submitted concurrently
+/// without reading the account nonce from the chain for each of them.
+/// The nonce is read again from the chain after a failed submission, since the local one may be out of sync.
+#[derive(Default)]
+pub struct NonceManager {
+    state: Mutex<NonceState>,
+}
+
+#[derive(Default)]
+struct NonceState {
+    /// The nonce following the highest one ever allocated, `None` until the chain nonce is first read
+    next: Option<u64>,
+    /// Whether the chain nonce must be read again before the next allocation
+    outdated: bool,
+    /// The nonces of the transactions not yet accepted by the transaction pool
+    in_flight: BTreeSet<u64>,
+    /// The nonces of the rejected transactions, allocated again before the next one
+    released: BTreeSet<u64>,
+}
+
+impl NonceManager {
+    /// Allocates the nonce of a new transaction of the account
+    pub async fn allocate(&self, rpc: &Rpc, account: &AccountId32) -> Result<u64> {
+        // The lock is held while syncing, so that the other allocations wait for the chain nonce
+        let mut state = self.state.lock().await;
+        if state.next.is_none() || state.outdated {
+            let chain_next = rpc.system_account_next_index(account).await?;
+            state.sync(chain_next);
+        }
+
+        Ok(state.take())
+    }
+
+    /// Marks the transaction with the nonce as accepted by the transaction pool
+    pub async fn submitted(&self, nonce: u64) {
+        self.state.lock().await.in_flight.remove(&nonce);
+    }
+
+    /// Marks the transaction with the nonce as rejected. Its nonce is allocated again, and the chain nonce is read again
+    pub async fn failed(&self, nonce: u64) {
+        let mut state = self.state.lock().await;
+        state.release(nonce);
+        state.outdated = true;
+    }
+
+    /// Releases the nonce of a transaction that has not been submitted. It is allocated again, and the chain nonce is still in sync
+    pub async fn release(&self, nonce: u64) {
+        self.state.lock().await.release(nonce);
+    }
+
+    /// Reads the chain nonce again on the next allocation, e.g. after the account was used by another client
+    pub async fn resync(&self) {
+        self.state.lock().await.outdated = true;
+    }
+
+    /// Returns the number of transactions not yet accepted by the transaction pool
+    pub async fn in_flight(&self) -> usize {
+        self.state.lock().await.in_flight.len()
+    }
+}
+
+impl NonceState {
+    /// Updates the next nonce with the chain one, that accounts for the transactions already in blocks and in the ready queue of the pool.
+    /// The transactions in the future queue of the pool are not accounted for by the chain, so the nonces allocated locally are never allocated again,
+    /// and only the released ones fill the gaps
+    fn sync(&mut self, chain_next: u64) {
+        let next = self.next.map_or(chain_next, |next| next.max(chain_next));
+        // The released nonces that have been used in the meantime are dropped
+        self.released = self.released.split_off(&chain_next);
+        self.next = Some(next);
+        self.outdated = false;
+    }
+
+    /// Allocates a released nonce if any, otherwise the next one
+    fn take(&mut self) -> u64 {
+        let nonce = match self.released.pop_first() {
+            Some(nonce) => nonce,
+            None => {
+                let next = self.next.expect("The nonce is synced before an allocation");
+                self.next = Some(next + 1);
+                next
+            }
+        };
+        self.in_flight.insert(nonce);
+
+        nonce
+    }
+
+    fn release(&mut self, nonce: u64) {
+        self.in_flight.remove(&nonce);
+        self.released.insert(nonce);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn synced(chain_next: u64) -> NonceState {
+        let mut state = NonceState::default();
+        state.sync(chain_next);
+        state
+    }
+
+    #[test]
+    fn nonces_are_allocated_from_the_chain_nonce_test() {
+        let mut state = synced(5);
+
+        assert_eq!([state.take(), state.take(), state.take()], [5, 6, 7]);
+        assert_eq!(state.in_flight.len(), 3);
+    }
+
+    #[test]
+    fn failed_nonce_does_not_collide_with_future_transactions_test() {
+        let mut state = synced(5);
+        let [first, second, third] = [state.take(), state.take(), state.take()];
+        // 6 and 7 reach the future queue of the pool, 5 is rejected
+        state.in_flight.remove(&second);
+        state.in_flight.remove(&third);
+        state.release(first);
+
+        // The chain does not account for the future transactions
+        state.sync(5);
+
+        assert_eq!(state.take(), 5);
+        assert_eq!(state.take(), 8);
+    }
+
+    #[test]
+    fn chain_ahead_of_the_local_nonce_is_followed_test() {
+        let mut state = synced(5);
+        state.take();
+        state.in_flight.clear();
+
+        // Another client has submitted transactions with the same account
+        state.sync(9);
+
+        assert_eq!(state.take(), 9);
+    }
+
+    #[test]
+    fn released_nonces_used_on_chain_are_dropped_test() {
+        let mut state = synced(5);
+        let [first, second] = [state.take(), state.take()];
+        state.release(first);
+        state.release(second);
+
+        // 5 has been used by another client in the meantime
+        state.sync(6);
+
+        assert_eq!(state.take(), 6);
+        assert_eq!(state.take(), 7);
+    }
+
+    #[test]
+    fn released_nonces_are_allocated_in_order_test() {
+        let mut state = synced(1);
+        let nonces = [state.take(), state.take(), state.take()];
+        state.release(nonces[2]);
+        state.release(nonces[0]);
+
+        assert_eq!([state.take(), state.take(), state.take()], [1, 3, 4]);
+    }
+
+    #[tokio::test]
+    async fn released_nonce_does_not_resync_test() {
+        let manager = NonceManager {
+            state: Mutex::new(synced(5)),
+        };
+        let [first, second] = {
+            let mut state = manager.state.lock().await;
+            [state.take(), state.take()]
+        };
+
+        // An estimated transaction is never submitted, so the local nonce is still in sync
+        manager.release(first).await;
+        assert!(!manager.state.lock().await.outdated);
+
+        manager.failed(second).await;
+        let mut state = manager.state.lock().await;
+        assert!(state.outdated);
+        assert_eq!(state.in_flight.len(), 0);
+        assert_eq!(state.take(), 5);
+    }
+}
@@ -0,0 +1,316 @@
+use super::error::{Result, TitanhError};
+use jsonrpsee::core::ClientError;
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Weak,
+    },
+    time::Duration,
+};
+use subxt::{
+    backend::rpc::{
+        reconnecting_rpc_client::{ExponentialBackoff, RpcClient as ReconnectingRpcClient},
+        RawRpcFuture, RawRpcSubscription, RawValue, RpcClient, RpcClientT,
+    },
+    error::RpcError,
+};
+use tokio::sync::OnceCell;
+
+/// The maximum delay between the connection attempts to an endpoint unreachable at startup
+const MAX_CONNECT_DELAY: Duration = Duration::from_secs(60);
+
+/// The connection options of the chain rpc client
+#[derive(Clone, Debug)]
+pub struct RpcOptions {
+    /// The timeout of a single rpc request
+    pub request_timeout: Duration,
+    /// The timeout of a connection attempt to an endpoint
+    pub connection_timeout: Duration,
+    /// The number of reconnection attempts to an endpoint, with exponential backoff, before it is considered down
+    pub max_reconnects: usize,
+    /// Whether only TLS endpoints (`wss://`) are accepted
+    pub secure: bool,
+}
+
+impl Default for RpcOptions {
+    fn default() -> Self {
+        Self {
+            request_timeout: Duration::from_secs(30),
+            connection_timeout: Duration::from_secs(10),
+            max_reconnects: 10,
+            secure: false,
+        }
+    }
+}
+
+/// Connects to the chain rpc endpoints. The connections are re-established when they drop, and the requests fail over to the next endpoint while the current one is reconnecting
+pub async fn connect(endpoints: &[String], options: &RpcOptions) -> Result<RpcClient> {
+    if endpoints.is_empty() {
+        return Err(TitanhError::invalid_input("No rpc endpoint is provided"));
+    }
+
+    for url in endpoints {
+        check_endpoint(url, options.secure)?;
+    }
+
+    let mut clients = Vec::new();
+    for url in endpoints {
+        let endpoint = Arc::new(Endpoint {
+            url: url.clone(),
+            client: OnceCell::new(),
+        });
+        match build_client(url, options).await {
+            Ok(client) => {
+                let _ = endpoint.client.set(client);
+            }
+            // An unreachable endpoint is kept and connected in the background, as long as another one is available
+            Err(e) => {
+                log::warn!("Failed to connect to the rpc endpoint {}: {}", url, e);
+                tokio::spawn(connect_later(Arc::downgrade(&endpoint), options.clone()));
+            }
+        }
+        clients.push(endpoint);
+    }
+
+    if clients
+        .iter()
+        .all(|endpoint| !endpoint.client.initialized())
+    {
+        return Err(TitanhError::Rpc(subxt::Error::Other(format!(
+            "None of the rpc endpoints {:?} is reachable",
+            endpoints
+        ))));
+    }
+
+    Ok(RpcClient::new(FailoverRpcClient {
+        clients,
+        active: AtomicUsize::new(0),
+    }))
+}
+
+async fn build_client(
+    url: &str,
+    options: &RpcOptions,
+) -> std::result::Result<ReconnectingRpcClient, impl std::fmt::Display> {
+    let retry_policy = ExponentialBackoff::from_millis(100)
+        .max_delay(Duration::from_secs(10))
+        .take(options.max_reconnects);
+
+    ReconnectingRpcClient::builder()
+        .retry_policy(retry_policy)
+        .request_timeout(options.request_timeout)
+        .connection_timeout(options.connection_timeout)
+        .build(url.to_string())
+        .await
+}
+
+/// Connects to an endpoint that was unreachable at startup, until it succeeds or the client is dropped
+async fn connect_later(endpoint: Weak<Endpoint>, options: RpcOptions) {
+    let mut delay = options.connection_timeout;
+    loop {
+        tokio::time::sleep(delay).await;
+        let Some(endpoint) = endpoint.upgrade() else {
+            return;
+        };
+
+        match build_client(&endpoint.url, &options).await {
+            Ok(client) => {
+                log::info!("Connected to the rpc endpoint {}", endpoint.url);
+                let _ = endpoint.client.set(client);
+                return;
+            }
+            Err(e) => log::debug!(
+                "Failed to connect to the rpc endpoint {}: {}",
+                endpoint.url,
+                e
+            ),
+        }
+        delay = (delay * 2).min(MAX_CONNECT_DELAY);
+    }
+}
+
+/// Returns whether the error is due to a dropped connection, that is being re-established.
+/// The subscriptions end with such an error, so they must be opened again (and the blocks in between fetched by number).
+pub fn is_reconnecting(error: &subxt::Error) -> bool {
+    matches!(
+        error,
+        subxt::Error::Rpc(RpcError::DisconnectedWillReconnect(_))
+    )
+}
+
+/// Returns whether the request failed because of the endpoint rather than because of the request: its connection dropped or is closed
+/// after the reconnection attempts, or it did not answer in time. The JSON-RPC errors returned by the node are answers, not failures
+fn is_endpoint_down(error: &RpcError) -> bool {
+    match error {
+        RpcError::DisconnectedWillReconnect(_) => true,
+        RpcError::ClientError(e) => match e.downcast_ref::<ClientError>() {
+            Some(
+                ClientError::RequestTimeout
+                | ClientError::RestartNeeded(_)
+                | ClientError::Transport(_),
+            ) => true,
+            Some(_) => false,
+            // The reconnecting client itself is closed
+            None => true,
+        },
+        _ => false,
+    }
+}
+
+fn check_endpoint(url: &str, secure: bool) -> Result<()> {
+    let scheme = url.split("://").next().unwrap_or_default();
+    match scheme {
+        "wss" => Ok(()),
+        "ws" if !secure => Ok(()),
+        "ws" => Err(TitanhError::invalid_input(format!(
+            "The rpc endpoint {} is not secure, use wss://",
+            url
+        ))),
+        _ => Err(TitanhError::invalid_input(format!(
+            "The rpc endpoint {} is not a WebSocket url",
+            url
+        ))),
+    }
+}
+
+/// An rpc endpoint, with its client once connected
+struct Endpoint {
+    url: String,
+    client: OnceCell<ReconnectingRpcClient>,
+}
+
+/// An rpc client over several endpoints. The requests go to the active endpoint, and move to the next one when it is down
+struct FailoverRpcClient {
+    /// The endpoints, in order of preference
+    clients: Vec<Arc<Endpoint>>,
+    /// The index of the endpoint currently in use
+    active: AtomicUsize,
+}
+
+impl FailoverRpcClient {
+    /// The connected endpoints in the order they are tried, starting from the active one
+    fn endpoints(&self) -> impl Iterator<Item = (usize, &ReconnectingRpcClient)> {
+        let active = self.active.load(Ordering::Relaxed);
+        (0..self.clients.len()).filter_map(move |i| {
+            let idx = (active + i) % self.clients.len();
+            self.clients[idx].client.get().map(|client| (idx, client))
+        })
+    }
+
+    fn failover(&self, from: usize, error: &RpcError) {
+        if self.clients.len() > 1 {
+            log::warn!(
+                "Rpc endpoint {} is down ({}), failing over to the next one",
+                self.clients[from].url,
+                error
+            );
+        }
+    }
+}
+
+impl RpcClientT for FailoverRpcClient {
+    fn request_raw<'a>(
+        &'a self,
+        method: &'a str,
+        params: Option<Box<RawValue>>,
+    ) -> RawRpcFuture<'a, Box<RawValue>> {
+        Box::pin(async move {
+            let mut last_error = None;
+            for (idx, client) in self.endpoints() {
+                match client.request_raw(method, params.clone()).await {
+                    Err(e) if is_endpoint_down(&e) => {
+                        self.failover(idx, &e);
+                        last_error = Some(e);
+                    }
+                    res => {
+                        self.active.store(idx, Ordering::Relaxed);
+                        return res;
+                    }
+                }
+            }
+
+            // An endpoint connected at startup is always tried
+            Err(last_error.expect("There is at least one connected endpoint"))
+        })
+    }
+
+    fn subscribe_raw<'a>(
+        &'a self,
+        sub: &'a str,
+        params: Option<Box<RawValue>>,
+        unsub: &'a str,
+    ) -> RawRpcFuture<'a, RawRpcSubscription> {
+        Box::pin(async move {
+            let mut last_error = None;
+            for (idx, client) in self.endpoints() {
+                match client.subscribe_raw(sub, params.clone(), unsub).await {
+                    Err(e) if is_endpoint_down(&e) => {
+                        self.failover(idx, &e);
+                        last_error = Some(e);
+                    }
+                    res => {
+                        self.active.store(idx, Ordering::Relaxed);
+                        return res;
+                    }
+                }
+            }
+
+            // An endpoint connected at startup is always tried
+            Err(last_error.expect("There is at least one connected endpoint"))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonrpsee::types::ErrorObject;
+    use std::io;
+
+    fn client_error(error: ClientError) -> RpcError {
+        RpcError::ClientError(Box::new(error))
+    }
+
+    #[test]
+    fn insecure_endpoints_are_rejected_test() {
+        assert!(check_endpoint("wss://rpc.titanh.io", true).is_ok());
+        assert!(check_endpoint("wss://rpc.titanh.io", false).is_ok());
+        assert!(check_endpoint("ws://127.0.0.1:9944", false).is_ok());
+        assert!(matches!(
+            check_endpoint("ws://127.0.0.1:9944", true),
+            Err(TitanhError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn non_websocket_endpoints_are_rejected_test() {
+        for url in [
+            "http://127.0.0.1:9933",
+            "https://rpc.titanh.io",
+            "127.0.0.1:9944",
+        ] {
+            assert!(matches!(
+                check_endpoint(url, false),
+                Err(TitanhError::InvalidInput(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn endpoint_is_down_when_it_does_not_answer_test() {
+        assert!(is_endpoint_down(&client_error(ClientError::RequestTimeout)));
+        assert!(is_endpoint_down(&client_error(ClientError::Transport(
+            io::Error::other("connection reset").into()
+        ))));
+        assert!(is_endpoint_down(&RpcError::DisconnectedWillReconnect(
+            "connection reset".to_string()
+        )));
+    }
+
+    #[test]
+    fn json_rpc_errors_are_answers_test() {
+        let error = ClientError::Call(ErrorObject::owned(-32601, "Method not found", None::<()>));
+
+        assert!(!is_endpoint_down(&client_error(error)));
+    }
+}
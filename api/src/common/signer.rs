@@ -0,0 +1,241 @@
+use super::{
+    error::{Result, TitanhError},
+    types::{KeyPair, Signer},
+};
+use codec::{Decode, Encode};
+use futures::future::{self, BoxFuture};
+use sp_core::{ecdsa, ed25519, sr25519, Pair};
+use sp_crypto_hashing::blake2_256;
+use std::sync::Arc;
+use subxt::{
+    tx::Payload,
+    utils::{AccountId32, MultiSignature},
+    Metadata,
+};
+
+/// A signer of the api transactions, e.g. a local key pair, a hardware wallet or a remote KMS.
+/// The signature is asynchronous, so that it can be requested to an external device or service
+pub trait TxSigner: Send + Sync {
+    /// The account paying for the transactions
+    fn account_id(&self) -> AccountId32;
+
+    /// Signs the payload of a transaction. The payload is already hashed by the api when longer than 256 bytes
+    fn sign<'a>(&'a self, payload: &'a [u8]) -> BoxFuture<'a, Result<MultiSignature>>;
+
+    /// Returns the local sr25519 key pair of the signer, if any, e.g. to sign off-chain messages
+    fn key_pair(&self) -> Option<&KeyPair> {
+        None
+    }
+}
+
+impl TxSigner for sr25519::Pair {
+    fn account_id(&self) -> AccountId32 {
+        AccountId32(self.public().into())
+    }
+
+    fn sign<'a>(&'a self, payload: &'a [u8]) -> BoxFuture<'a, Result<MultiSignature>> {
+        let signature = Pair::sign(self, payload);
+        Box::pin(future::ready(Ok(MultiSignature::Sr25519(signature.into()))))
+    }
+
+    fn key_pair(&self) -> Option<&KeyPair> {
+        Some(self)
+    }
+}
+
+impl TxSigner for ed25519::Pair {
+    fn account_id(&self) -> AccountId32 {
+        AccountId32(self.public().into())
+    }
+
+    fn sign<'a>(&'a self, payload: &'a [u8]) -> BoxFuture<'a, Result<MultiSignature>> {
+        let signature = Pair::sign(self, payload);
+        Box::pin(future::ready(Ok(MultiSignature::Ed25519(signature.into()))))
+    }
+}
+
+impl TxSigner for ecdsa::Pair {
+    /// The account of an ECDSA key is the hash of its compressed public key, as in the runtime `MultiSigner`
+    fn account_id(&self) -> AccountId32 {
+        AccountId32(blake2_256(self.public().as_ref()))
+    }
+
+    fn sign<'a>(&'a self, payload: &'a [u8]) -> BoxFuture<'a, Result<MultiSignature>> {
+        // The payload is hashed with blake2 before signing, as the runtime expects
+        let signature = Pair::sign(self, payload);
+        Box::pin(future::ready(Ok(MultiSignature::Ecdsa(signature.into()))))
+    }
+}
+
+/// The signature schemes of the key pairs derived from a seed, all accepted by the runtime `MultiSignature`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CryptoScheme {
+    #[default]
+    Sr25519,
+    Ed25519,
+    Ecdsa,
+}
+
+impl CryptoScheme {
+    /// Derives the signer of the scheme from a seed phrase (mnemonic) or a secret uri
+    pub fn signer_from_seed(self, seed: &str) -> Result<Signer> {
+        let invalid_seed = |_| TitanhError::invalid_input("Invalid seed phrase");
+        let signer: Signer = match self {
+            CryptoScheme::Sr25519 => {
+                Arc::new(sr25519::Pair::from_string(seed, None).map_err(invalid_seed)?)
+            }
+            CryptoScheme::Ed25519 => {
+                Arc::new(ed25519::Pair::from_string(seed, None).map_err(invalid_seed)?)
+            }
+            CryptoScheme::Ecdsa => {
+                Arc::new(ecdsa::Pair::from_string(seed, None).map_err(invalid_seed)?)
+            }
+        };
+
+        Ok(signer)
+    }
+}
+
+/// A transaction built by the api and signed elsewhere, e.g. on an air-gapped device.
+/// It is exported as SCALE encoded hex, so that it can be moved as text or as a QR code,
+/// then the signature of its payload is given back to the api to submit it
+#[derive(Clone, Debug, Encode, Decode)]
+pub struct OfflineTx {
+    /// The account that must sign the transaction
+    pub account: AccountId32,
+    /// The nonce of the transaction
+    pub nonce: u64,
+    /// The SCALE encoded call
+    pub call_data: Vec<u8>,
+    /// The payload to sign
+    pub payload: Vec<u8>,
+}
+
+impl OfflineTx {
+    /// Returns the SCALE encoded transaction as hex
+    pub fn to_hex(&self) -> String {
+        format!("0x{}", hex::encode(self.encode()))
+    }
+
+    /// Decodes a transaction exported by `to_hex`
+    pub fn from_hex(encoded: &str) -> Result<Self> {
+        let encoded = encoded.strip_prefix("0x").unwrap_or(encoded);
+        let bytes = hex::decode(encoded).map_err(TitanhError::invalid_input)?;
+
+        Ok(Self::decode(&mut &bytes[..])?)
+    }
+
+    /// Returns the payload to sign as hex
+    pub fn payload_hex(&self) -> String {
+        format!("0x{}", hex::encode(&self.payload))
+    }
+}
+
+/// An already encoded call, e.g. the call of an offline transaction
+pub(crate) struct RawCall<'a>(pub &'a [u8]);
+
+impl Payload for RawCall<'_> {
+    fn encode_call_data_to(
+        &self,
+        _metadata: &Metadata,
+        out: &mut Vec<u8>,
+    ) -> Result<(), subxt::Error> {
+        out.extend_from_slice(self.0);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offline_tx() -> OfflineTx {
+        OfflineTx {
+            account: AccountId32([7; 32]),
+            nonce: 42,
+            call_data: vec![1, 2, 3],
+            payload: vec![4, 5, 6, 7],
+        }
+    }
+
+    #[test]
+    fn offline_tx_hex_round_trip_test() {
+        let tx = offline_tx();
+        let encoded = tx.to_hex();
+        assert!(encoded.starts_with("0x"));
+
+        let decoded = OfflineTx::from_hex(&encoded).unwrap();
+        assert_eq!(decoded.encode(), tx.encode());
+        // The prefix is optional
+        let decoded = OfflineTx::from_hex(&encoded[2..]).unwrap();
+        assert_eq!(decoded.encode(), tx.encode());
+
+        assert_eq!(tx.payload_hex(), "0x04050607");
+    }
+
+    #[test]
+    fn malformed_offline_tx_is_rejected_test() {
+        assert!(matches!(
+            OfflineTx::from_hex("0xnothex"),
+            Err(TitanhError::InvalidInput(_))
+        ));
+
+        let encoded = offline_tx().to_hex();
+        assert!(matches!(
+            OfflineTx::from_hex(&encoded[..encoded.len() - 2]),
+            Err(TitanhError::Codec(_))
+        ));
+    }
+
+    #[test]
+    fn ecdsa_account_is_the_hash_of_the_compressed_public_key_test() {
+        let pair = ecdsa::Pair::from_string("//Alice", None).unwrap();
+        let public = pair.public();
+        assert_eq!(public.as_ref().len(), 33);
+
+        assert_eq!(
+            TxSigner::account_id(&pair),
+            AccountId32(blake2_256(public.as_ref()))
+        );
+    }
+
+    #[tokio::test]
+    async fn ecdsa_signature_verifies_against_the_account_test() {
+        let pair = ecdsa::Pair::from_string("//Alice", None).unwrap();
+        let payload = b"payload";
+
+        let MultiSignature::Ecdsa(signature) = TxSigner::sign(&pair, payload).await.unwrap() else {
+            panic!("The signature is not an ecdsa one");
+        };
+        let signature = ecdsa::Signature::from_raw(signature);
+        assert!(ecdsa::Pair::verify(&signature, payload, &pair.public()));
+
+        // The runtime recovers the public key from the signature and compares its hash with the account
+        let recovered = signature.recover(payload).unwrap();
+        assert_eq!(
+            AccountId32(blake2_256(recovered.as_ref())),
+            TxSigner::account_id(&pair)
+        );
+    }
+
+    #[tokio::test]
+    async fn sr25519_and_ed25519_signatures_verify_test() {
+        let payload = b"payload";
+
+        let pair = sr25519::Pair::from_string("//Alice", None).unwrap();
+        let MultiSignature::Sr25519(signature) = TxSigner::sign(&pair, payload).await.unwrap()
+        else {
+            panic!("The signature is not an sr25519 one");
+        };
+        let signature = sr25519::Signature::from_raw(signature);
+        assert!(sr25519::Pair::verify(&signature, payload, &pair.public()));
+
+        let pair = ed25519::Pair::from_string("//Alice", None).unwrap();
+        let MultiSignature::Ed25519(signature) = TxSigner::sign(&pair, payload).await.unwrap()
+        else {
+            panic!("The signature is not an ed25519 one");
+        };
+        let signature = ed25519::Signature::from_raw(signature);
+        assert!(ed25519::Pair::verify(&signature, payload, &pair.public()));
+    }
+}
@@ -0,0 +1,652 @@
+use super::{
+    error::{Result, TitanhError},
+    nonce::NonceManager,
+    rpc::is_reconnecting,
+    types::{BlockHash, BlockInfo, BlockNumber, Rpc, SubstrateApi},
+};
+use futures::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use sp_core::H256;
+use sp_crypto_hashing::blake2_256;
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::watch;
+
+/// The number of finalized blocks a transaction can be missing from the transaction pool before it is considered dropped
+const DROP_CHECKS: u8 = 2;
+/// The delay before subscribing again to the blocks, when the watcher fails
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(1);
+
+/// The status of a submitted transaction
+#[derive(Clone, Copy)]
+pub enum TxStatus {
+    /// The transaction is in the transaction pool
+    Pending,
+    /// The transaction is included in a block, not yet finalized
+    InBlock(BlockInfo),
+    /// The transaction is included in a finalized block
+    Finalized(BlockInfo),
+    /// The transaction has been dropped from the transaction pool, and it will not be included in a block
+    Dropped,
+}
+
+/// A handle to a transaction submitted with `ConsistencyLevel::Eventual`, to learn whether it is later included, finalized or dropped
+#[derive(Clone)]
+pub struct TxHandle {
+    tx_hash: H256,
+    status: watch::Receiver<TxStatus>,
+}
+
+impl TxHandle {
+    /// Returns the hash of the transaction
+    pub fn hash(&self) -> H256 {
+        self.tx_hash
+    }
+
+    /// Returns the latest known status of the transaction
+    pub fn status(&self) -> TxStatus {
+        *self.status.borrow()
+    }
+
+    /// Waits for the transaction to be included in a block, returning the block.
+    /// It fails with `TransactionFailed` if the transaction is dropped
+    pub async fn await_in_block(&self) -> Result<BlockInfo> {
+        let status = self
+            .wait_for(|status| !matches!(status, TxStatus::Pending))
+            .await?;
+
+        match status {
+            TxStatus::InBlock(block) | TxStatus::Finalized(block) => Ok(block),
+            _ => Err(TitanhError::TransactionFailed),
+        }
+    }
+
+    /// Waits for the transaction to be included in a finalized block, returning the block.
+    /// It fails with `TransactionFailed` if the transaction is dropped
+    pub async fn await_finalized(&self) -> Result<BlockInfo> {
+        let status = self
+            .wait_for(|status| matches!(status, TxStatus::Finalized(_) | TxStatus::Dropped))
+            .await?;
+
+        match status {
+            TxStatus::Finalized(block) => Ok(block),
+            _ => Err(TitanhError::TransactionFailed),
+        }
+    }
+
+    async fn wait_for(&self, f: impl FnMut(&TxStatus) -> bool) -> Result<TxStatus> {
+        let mut status = self.status.clone();
+        // The status is no longer updated once the transaction is finalized or dropped
+        let status = status
+            .wait_for(f)
+            .await
+            .map_err(|_| TitanhError::TransactionFailed)?;
+
+        Ok(*status)
+    }
+
+    /// A handle of a transaction whose status is already known
+    pub(crate) fn resolved(tx_hash: H256, status: TxStatus) -> Self {
+        let (_, status) = watch::channel(status);
+        Self { tx_hash, status }
+    }
+}
+
+/// Watches the blocks for the tracked transactions. A single background task serves all the tracked transactions, and it stops once none is left
+pub(crate) struct TxWatcher {
+    api: SubstrateApi,
+    rpc: Rpc,
+    /// The nonces of the signer, read again from the chain when a transaction is dropped
+    nonces: Arc<NonceManager>,
+    outbox: Option<Outbox>,
+    state: Mutex<WatcherState>,
+}
+
+#[derive(Default)]
+struct WatcherState {
+    /// The tracked transactions, by hash
+    txs: HashMap<H256, TrackedTx>,
+    /// Whether the background task is running
+    running: bool,
+    /// The latest finalized block processed
+    last_finalized: Option<BlockNumber>,
+}
+
+struct TrackedTx {
+    status: watch::Sender<TxStatus>,
+    /// The latest finalized block when the transaction was submitted
+    since: BlockNumber,
+    /// The number of consecutive finalized blocks the transaction was missing from the pool
+    missing: u8,
+}
+
+impl TxWatcher {
+    pub fn new(
+        api: SubstrateApi,
+        rpc: Rpc,
+        nonces: Arc<NonceManager>,
+        outbox: Option<Outbox>,
+    ) -> Self {
+        Self {
+            api,
+            rpc,
+            nonces,
+            outbox,
+            state: Mutex::new(WatcherState::default()),
+        }
+    }
+
+    /// Tracks a transaction submitted to the pool, recording it in the outbox if any
+    pub async fn track(self: &Arc<Self>, tx_hash: H256) -> Result<TxHandle> {
+        let last_finalized = self.state.lock().unwrap().last_finalized;
+        let since = match last_finalized {
+            Some(number) => number,
+            None => self.latest_finalized_number().await?,
+        };
+        if let Some(outbox) = &self.outbox {
+            outbox.pending(tx_hash, since)?;
+        }
+
+        Ok(self.watch(tx_hash, since))
+    }
+
+    /// Recovers the transactions of the outbox that were pending when the app stopped.
+    /// The blocks finalized in the meantime are scanned for them, and the others are tracked again
+    pub async fn recover(self: &Arc<Self>) -> Result<Vec<TxHandle>> {
+        let outbox = self
+            .outbox
+            .as_ref()
+            .ok_or(TitanhError::NotConfigured("The transactions outbox"))?;
+        let mut pending = outbox.pending_txs();
+        let Some(since) = pending.values().min().copied() else {
+            return Ok(Vec::new());
+        };
+
+        let mut handles = Vec::new();
+        let latest = self.latest_finalized_number().await?;
+        for number in since..=latest {
+            let block_hash = self
+                .rpc
+                .chain_get_block_hash(Some(number.into()))
+                .await?
+                .ok_or(TitanhError::BlockNotFound(number))?;
+            let extrinsics = self.block_extrinsics(block_hash).await?;
+            let block = BlockInfo::new(number, BlockHash(block_hash));
+
+            for tx_hash in extrinsics {
+                if pending.remove(&tx_hash).is_some() {
+                    outbox.done(tx_hash)?;
+                    handles.push(TxHandle::resolved(tx_hash, TxStatus::Finalized(block)));
+                }
+            }
+        }
+
+        // The transactions still pending are either in the pool or dropped
+        handles.extend(
+            pending
+                .into_iter()
+                .map(|(tx_hash, since)| self.watch(tx_hash, since)),
+        );
+
+        Ok(handles)
+    }
+
+    fn watch(self: &Arc<Self>, tx_hash: H256, since: BlockNumber) -> TxHandle {
+        let mut state = self.state.lock().unwrap();
+        let status = match state.txs.get(&tx_hash) {
+            Some(tracked) => tracked.status.subscribe(),
+            None => {
+                let (sender, status) = watch::channel(TxStatus::Pending);
+                let tracked = TrackedTx {
+                    status: sender,
+                    since,
+                    missing: 0,
+                };
+                state.txs.insert(tx_hash, tracked);
+                status
+            }
+        };
+
+        if !state.running {
+            state.running = true;
+            tokio::spawn(self.clone().run());
+        }
+
+        TxHandle { tx_hash, status }
+    }
+
+    async fn run(self: Arc<Self>) {
+        loop {
+            match self.watch_blocks().await {
+                Ok(true) => return,
+                Ok(false) => {
+                    log::warn!("The blocks subscription of the transaction watcher has ended")
+                }
+                Err(e) if is_reconnecting(&e) => {
+                    log::warn!("Rpc connection lost, the transaction watcher subscribes again to the blocks")
+                }
+                Err(e) => log::warn!("The transaction watcher failed: {}", e),
+            }
+
+            if self.stop_if_idle() {
+                return;
+            }
+            tokio::time::sleep(RESUBSCRIBE_DELAY).await;
+        }
+    }
+
+    /// Processes the new blocks, returning `true` once there are no transactions left to track
+    async fn watch_blocks(&self) -> Result<bool, subxt::Error> {
+        let best = self.api.blocks().subscribe_best().await?;
+        let finalized = self.api.blocks().subscribe_finalized().await?;
+        let mut blocks = stream::select(best.map(|b| (b, false)), finalized.map(|b| (b, true)));
+
+        while let Some((block, is_finalized)) = blocks.next().await {
+            let block = block?;
+            let info = BlockInfo::new(block.number(), BlockHash(block.hash()));
+            let extrinsics = self.block_extrinsics(block.hash()).await?;
+
+            self.process_block(info, &extrinsics, is_finalized);
+            if is_finalized {
+                self.check_dropped().await?;
+            }
+
+            if self.stop_if_idle() {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    fn process_block(&self, block: BlockInfo, extrinsics: &HashSet<H256>, is_finalized: bool) {
+        let mut state = self.state.lock().unwrap();
+        if is_finalized {
+            state.last_finalized = Some(block.number);
+        }
+
+        for (tx_hash, tracked) in state.txs.iter_mut() {
+            let status = *tracked.status.borrow();
+            if extrinsics.contains(tx_hash) {
+                let status = if is_finalized {
+                    TxStatus::Finalized(block)
+                } else {
+                    TxStatus::InBlock(block)
+                };
+                tracked.status.send_replace(status);
+                tracked.missing = 0;
+            } else if let TxStatus::InBlock(in_block) = status {
+                // The block of the transaction has not been finalized, so the transaction is back in the pool
+                if is_finalized && in_block.number <= block.number {
+                    tracked.status.send_replace(TxStatus::Pending);
+                }
+            }
+        }
+
+        if is_finalized {
+            self.remove_if(&mut state, |status| {
+                matches!(status, TxStatus::Finalized(_))
+            });
+        }
+    }
+
+    /// Marks as dropped the pending transactions that are missing from the transaction pool
+    async fn check_dropped(&self) -> Result<(), subxt::Error> {
+        let any_pending = self
+            .state
+            .lock()
+            .unwrap()
+            .txs
+            .values()
+            .any(|tracked| matches!(*tracked.status.borrow(), TxStatus::Pending));
+        if !any_pending {
+            return Ok(());
+        }
+
+        let pool: HashSet<H256> = self
+            .rpc
+            .author_pending_extrinsics()
+            .await?
+            .iter()
+            .map(|ext| H256(blake2_256(&ext.0)))
+            .collect();
+
+        // The transactions missing from the pool for long enough, with the latest finalized block at their submission
+        let missing: HashMap<H256, BlockNumber> = {
+            let mut state = self.state.lock().unwrap();
+            state
+                .txs
+                .iter_mut()
+                .filter(|(_, tracked)| matches!(*tracked.status.borrow(), TxStatus::Pending))
+                .filter_map(|(tx_hash, tracked)| {
+                    if pool.contains(tx_hash) {
+                        tracked.missing = 0;
+                        return None;
+                    }
+                    // The transaction may have left the pool for a block not yet processed, so it is checked again
+                    tracked.missing += 1;
+                    (tracked.missing >= DROP_CHECKS).then_some((*tx_hash, tracked.since))
+                })
+                .collect()
+        };
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        // The transactions may have been included in a block produced before the watcher subscribed to the blocks,
+        // so they are looked up in the blocks since their submission before being considered dropped
+        let included = self.find_included(&missing).await?;
+
+        let dropped = {
+            let mut state = self.state.lock().unwrap();
+            let last_finalized = state.last_finalized;
+            let mut dropped = false;
+            for tx_hash in missing.keys() {
+                let Some(tracked) = state.txs.get_mut(tx_hash) else {
+                    continue;
+                };
+                let status = match included.get(tx_hash) {
+                    Some(block) if last_finalized.is_some_and(|number| block.number <= number) => {
+                        TxStatus::Finalized(*block)
+                    }
+                    Some(block) => TxStatus::InBlock(*block),
+                    None => {
+                        log::warn!("Transaction {:?} has been dropped", tx_hash);
+                        dropped = true;
+                        TxStatus::Dropped
+                    }
+                };
+                tracked.status.send_replace(status);
+                tracked.missing = 0;
+            }
+            self.remove_if(&mut state, |status| {
+                matches!(status, TxStatus::Finalized(_) | TxStatus::Dropped)
+            });
+            dropped
+        };
+
+        // The nonce of a dropped transaction is unused
+        if dropped {
+            self.nonces.resync().await;
+        }
+
+        Ok(())
+    }
+
+    /// Stops tracking the transactions in a final status
+    fn remove_if(&self, state: &mut WatcherState, f: impl Fn(&TxStatus) -> bool) {
+        state.txs.retain(|tx_hash, tracked| {
+            if !f(&tracked.status.borrow()) {
+                return true;
+            }
+            if let Some(outbox) = &self.outbox {
+                if let Err(e) = outbox.done(*tx_hash) {
+                    log::warn!("Failed to update the transactions outbox: {}", e);
+                }
+            }
+            false
+        });
+    }
+
+    fn stop_if_idle(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.txs.is_empty() {
+            state.running = false;
+        }
+
+        !state.running
+    }
+
+    /// Looks up the transactions in the blocks of the best chain since their submission, returning the block of the included ones
+    async fn find_included(
+        &self,
+        txs: &HashMap<H256, BlockNumber>,
+    ) -> Result<HashMap<H256, BlockInfo>, subxt::Error> {
+        let mut included = HashMap::new();
+        let Some(since) = txs.values().min().copied() else {
+            return Ok(included);
+        };
+        let best = match self.rpc.chain_get_header(None).await? {
+            Some(header) => header.number,
+            None => return Ok(included),
+        };
+
+        for number in since..=best {
+            let Some(block_hash) = self.rpc.chain_get_block_hash(Some(number.into())).await? else {
+                break;
+            };
+            let block = BlockInfo::new(number, BlockHash(block_hash));
+            for tx_hash in self.block_extrinsics(block_hash).await? {
+                if txs.get(&tx_hash).is_some_and(|since| *since <= number) {
+                    included.insert(tx_hash, block);
+                }
+            }
+
+            if included.len() == txs.len() {
+                break;
+            }
+        }
+
+        Ok(included)
+    }
+
+    /// Returns the hashes of the extrinsics of a block
+    async fn block_extrinsics(&self, block_hash: H256) -> Result<HashSet<H256>, subxt::Error> {
+        let extrinsics = self
+            .rpc
+            .chain_get_block(Some(block_hash))
+            .await?
+            .map(|details| details.block.extrinsics)
+            .unwrap_or_default()
+            .iter()
+            .map(|ext| H256(blake2_256(&ext.0)))
+            .collect();
+
+        Ok(extrinsics)
+    }
+
+    async fn latest_finalized_number(&self) -> Result<BlockNumber> {
+        let finalized_head = self.rpc.chain_get_finalized_head().await?;
+        let header = self
+            .rpc
+            .chain_get_header(Some(finalized_head))
+            .await?
+            .ok_or(TitanhError::NotInStorage)?;
+
+        Ok(header.number)
+    }
+}
+
+/// A persisted log of the pending transactions, so that the app can recover them after a restart.
+/// Each line is a JSON record, and the file is compacted when it is opened.
+pub struct Outbox {
+    path: PathBuf,
+    state: Mutex<OutboxState>,
+}
+
+struct OutboxState {
+    file: File,
+    /// The pending transactions, with the latest finalized block at submission
+    pending: HashMap<H256, BlockNumber>,
+}
+
+#[derive(Serialize, Deserialize)]
+enum OutboxRecord {
+    Pending { tx_hash: H256, since: BlockNumber },
+    Done { tx_hash: H256 },
+}
+
+impl Outbox {
+    /// Opens the outbox at the given path, creating it if it does not exist
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+
+        let mut pending = HashMap::new();
+        if path.exists() {
+            for line in BufReader::new(File::open(&path)?).lines() {
+                let line = line?;
+                // A partially written last record is skipped
+                match serde_json::from_str(&line) {
+                    Ok(OutboxRecord::Pending { tx_hash, since }) => {
+                        pending.insert(tx_hash, since);
+                    }
+                    Ok(OutboxRecord::Done { tx_hash }) => {
+                        pending.remove(&tx_hash);
+                    }
+                    Err(e) => log::warn!("Skipping a malformed outbox record: {}", e),
+                }
+            }
+        }
+
+        // Only the pending transactions are kept
+        let compacted = path.with_extension("tmp");
+        let mut file = File::create(&compacted)?;
+        for (tx_hash, since) in &pending {
+            write_record(
+                &mut file,
+                &OutboxRecord::Pending {
+                    tx_hash: *tx_hash,
+                    since: *since,
+                },
+            )?;
+        }
+        file.sync_all()?;
+        fs::rename(&compacted, &path)?;
+
+        let file = OpenOptions::new().append(true).open(&path)?;
+        Ok(Self {
+            path,
+            state: Mutex::new(OutboxState { file, pending }),
+        })
+    }
+
+    /// Returns the path of the outbox file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn pending_txs(&self) -> HashMap<H256, BlockNumber> {
+        self.state.lock().unwrap().pending.clone()
+    }
+
+    /// Records a pending transaction. The record is synced to the disk, so that the transaction is recovered after a crash
+    fn pending(&self, tx_hash: H256, since: BlockNumber) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        write_record(&mut state.file, &OutboxRecord::Pending { tx_hash, since })?;
+        state.file.sync_data()?;
+        state.pending.insert(tx_hash, since);
+
+        Ok(())
+    }
+
+    fn done(&self, tx_hash: H256) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.pending.remove(&tx_hash).is_some() {
+            write_record(&mut state.file, &OutboxRecord::Done { tx_hash })?;
+        }
+
+        Ok(())
+    }
+}
+
+fn write_record(file: &mut File, record: &OutboxRecord) -> Result<()> {
+    let mut line = serde_json::to_vec(record).map_err(std::io::Error::from)?;
+    line.push(b'\n');
+    file.write_all(&line)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the path of the outbox of a test, in a fresh directory of its own
+    fn outbox_path(test: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("titanh-outbox-{}-{}", std::process::id(), test));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        dir.join("outbox.jsonl")
+    }
+
+    fn tx(n: u8) -> H256 {
+        H256::repeat_byte(n)
+    }
+
+    #[test]
+    fn outbox_is_compacted_on_open_test() {
+        let path = outbox_path("compaction");
+        let outbox = Outbox::open(&path).unwrap();
+        outbox.pending(tx(1), 10).unwrap();
+        outbox.pending(tx(2), 11).unwrap();
+        outbox.done(tx(1)).unwrap();
+        drop(outbox);
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 3);
+
+        let outbox = Outbox::open(&path).unwrap();
+        assert_eq!(outbox.pending_txs(), HashMap::from([(tx(2), 11)]));
+        // Only the pending transaction is left in the file
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 1);
+    }
+
+    #[test]
+    fn torn_last_record_is_skipped_test() {
+        let path = outbox_path("torn-line");
+        let outbox = Outbox::open(&path).unwrap();
+        outbox.pending(tx(1), 10).unwrap();
+        drop(outbox);
+
+        // The app crashed while appending a record
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"Pending":{"tx_hash":"0x02"#).unwrap();
+        drop(file);
+
+        let outbox = Outbox::open(&path).unwrap();
+        assert_eq!(outbox.pending_txs(), HashMap::from([(tx(1), 10)]));
+
+        // The next records are not appended to the torn one
+        outbox.pending(tx(3), 12).unwrap();
+        drop(outbox);
+        let outbox = Outbox::open(&path).unwrap();
+        assert_eq!(
+            outbox.pending_txs(),
+            HashMap::from([(tx(1), 10), (tx(3), 12)])
+        );
+    }
+
+    #[test]
+    fn done_of_an_unknown_transaction_is_not_recorded_test() {
+        let path = outbox_path("unknown-done");
+        let outbox = Outbox::open(&path).unwrap();
+        outbox.done(tx(1)).unwrap();
+
+        assert!(fs::read_to_string(&path).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn resolved_handles_test() {
+        let block = BlockInfo::new(5, BlockHash(H256::repeat_byte(5)));
+
+        let finalized = TxHandle::resolved(tx(1), TxStatus::Finalized(block));
+        assert_eq!(finalized.await_in_block().await.unwrap().number, 5);
+        assert_eq!(finalized.await_finalized().await.unwrap().number, 5);
+
+        let dropped = TxHandle::resolved(tx(2), TxStatus::Dropped);
+        assert!(matches!(
+            dropped.await_in_block().await,
+            Err(TitanhError::TransactionFailed)
+        ));
+        assert!(matches!(
+            dropped.await_finalized().await,
+            Err(TitanhError::TransactionFailed)
+        ));
+    }
+}
@@ -1,12 +1,19 @@
+use super::error::{Result, TitanhError};
+use super::signer::TxSigner;
 use super::titanh;
-use anyhow::Result;
 use codec::{Decode, Encode};
 use serde::{Deserialize, Serialize};
 use sp_core::H256;
+use std::sync::Arc;
 use subxt::backend::legacy::LegacyRpcMethods;
 use subxt::blocks::ExtrinsicEvents;
 use subxt::utils::AccountId32;
-use subxt::{blocks::BlockRef, tx::PairSigner, OnlineClient, SubstrateConfig};
+use subxt::{blocks::BlockRef, OnlineClient, SubstrateConfig};
+
+/// The maximum size in bytes of a block, as defined by the runtime `primitives`
+pub const MAX_BLOCK_SIZE: usize = 5 * 1024 * 1024;
+/// The maximum size in bytes of the normal transactions of a block. The runtime keeps 25% of the block for the operational ones
+pub const MAX_NORMAL_BLOCK_SIZE: usize = MAX_BLOCK_SIZE / 4 * 3;
 
 #[derive(Copy, Clone, Encode, Decode)]
 pub struct BlockInfo {
@@ -26,11 +33,17 @@ pub type KeyPair = sp_core::sr25519::Pair;
 /// The substrate api
 pub type SubstrateApi = OnlineClient<SubstrateConfig>;
 /// Signer used in the api transactions
-pub type Signer = PairSigner<SubstrateConfig, KeyPair>;
+pub type Signer = Arc<dyn TxSigner>;
 /// Chain's Rpc methods
 pub type Rpc = LegacyRpcMethods<SubstrateConfig>;
 /// The events of the chain to be used in the api
 pub type Events = ExtrinsicEvents<SubstrateConfig>;
+/// All the events of a block
+pub type BlockEvents = subxt::events::Events<SubstrateConfig>;
+/// A signed transaction, ready to be submitted
+pub type Extrinsic = subxt::tx::SubmittableExtrinsic<SubstrateConfig, SubstrateApi>;
+/// A transaction waiting for its signature
+pub type PartialTx = subxt::tx::PartialExtrinsic<SubstrateConfig, SubstrateApi>;
 
 #[derive(Clone, Encode, Decode, Copy)]
 pub struct BlockHash(pub H256);
@@ -47,6 +60,7 @@ impl From<H256> for BlockHash {
     }
 }
 
+#[derive(Clone)]
 pub struct User(AccountId32);
 
 impl User {
@@ -54,15 +68,17 @@ impl User {
         let mut account = [0u8; 32];
         if pubkey.starts_with("0x") {
             let pubkey = &pubkey[2..];
-            let pubkey = hex::decode(pubkey)?;
+            let pubkey = hex::decode(pubkey).map_err(TitanhError::invalid_input)?;
             if pubkey.len() != 32 {
-                return Err(anyhow::anyhow!("Invalid user public key"));
+                return Err(TitanhError::invalid_input("Invalid user public key"));
             }
             account.copy_from_slice(&pubkey);
 
             Ok(User(AccountId32::from(account)))
         } else {
-            Err(anyhow::anyhow!("User public key is not in hex format"))
+            Err(TitanhError::invalid_input(
+                "User public key is not in hex format",
+            ))
         }
     }
 
@@ -1,18 +1,28 @@
-use anyhow::Result;
 use app_registrar::AppRegistrarApi;
+use codec::Decode;
 use common::{
+    cache::{Cache, CacheOptions},
+    error::{Result, TitanhError},
+    nonce::NonceManager,
+    signer::{OfflineTx, RawCall, TxSigner},
     titanh::{
         runtime_types::{frame_system::EventRecord, titanh_runtime::RuntimeEvent},
         utility::calls::types::batch_all::Calls as RuntimeCalls,
     },
+    tx::{Outbox, TxHandle, TxWatcher},
     types::{
-        BlockHash, BlockInfo, BlockNumber, ConsistencyLevel, Events, Rpc, Signer, SubstrateApi,
+        BlockHash, BlockInfo, BlockNumber, ConsistencyLevel, Events, Extrinsic, PartialTx, Rpc,
+        Signer, SubstrateApi,
     },
 };
 use pinning_committee::PinningCommitteeApi;
 use sp_core::H256;
+use std::{collections::HashMap, sync::Arc};
 use subxt::{
-    config::DefaultExtrinsicParamsBuilder as Params, storage::Address, tx::Payload, utils::Yes,
+    config::DefaultExtrinsicParamsBuilder as Params,
+    storage::Address,
+    tx::Payload,
+    utils::{AccountId32, MultiSignature, Yes},
     SubstrateConfig,
 };
 
@@ -25,13 +35,20 @@ mod pinning_committee;
 // Export
 pub use builder::TitanhApiBuilder;
 pub use capsules::container::{
-    document::{Document, DocumentApi},
+    document::{Document, DocumentApi, DocumentSnapshot},
     ContainerApi,
 };
+pub use capsules::session::Session;
+pub use capsules::stream::ProgressReader;
+pub use capsules::transaction::{TitanhTx, TxEstimate};
 pub use capsules::types as capsules_types;
 pub use capsules::types::CapsulesBatch;
 pub use capsules::CapsulesApi;
-pub use common::{titanh, types as common_types};
+pub use common::{
+    cache::CacheOptions, cid as cid_types, error, ipfs, ipfs_pool, rpc, signer, titanh, tx,
+    types as common_types,
+};
+pub use error::TitanhError;
 pub use pinning_committee::types as pinning_committee_types;
 
 /// Titanh api
@@ -41,8 +58,14 @@ pub struct TitanhApi {
     pub substrate_api: SubstrateApi,
     /// The chain rpc methods
     pub rpc: Rpc,
-    /// The singer of transactions
+    /// The singer of transactions, a local key pair or an external signer
     pub signer: Option<Signer>,
+    /// The nonces of the signer transactions, shared by the clones of the api
+    nonces: Arc<NonceManager>,
+    /// The watcher of the transactions submitted with `ConsistencyLevel::Eventual`
+    watcher: Arc<TxWatcher>,
+    /// The eventual client side cache of the capsules
+    cache: Option<Arc<Cache>>,
 }
 
 impl TitanhApi {
@@ -50,14 +73,34 @@ impl TitanhApi {
         substrate_api: SubstrateApi,
         rpc: Rpc,
         signer: Option<Signer>,
+        outbox: Option<Outbox>,
+        cache: Option<CacheOptions>,
     ) -> Result<Self> {
+        let nonces = Arc::new(NonceManager::default());
+        let watcher = TxWatcher::new(substrate_api.clone(), rpc.clone(), nonces.clone(), outbox);
+
+        let cache = match cache {
+            Some(options) => Some(Cache::new(options, substrate_api.clone()).await?),
+            None => None,
+        };
+
         Ok(TitanhApi {
             substrate_api,
             rpc,
             signer,
+            nonces,
+            watcher: Arc::new(watcher),
+            cache,
         })
     }
 
+    /// Empties the client side cache, if any
+    pub fn clear_cache(&self) {
+        if let Some(cache) = &self.cache {
+            cache.clear();
+        }
+    }
+
     /// Returns the app registrar api
     pub fn app_registrar(&self) -> AppRegistrarApi<'_> {
         AppRegistrarApi::from(self)
@@ -95,10 +138,49 @@ impl TitanhApi {
         let result = storage
             .fetch(address)
             .await?
-            .ok_or_else(|| anyhow::anyhow!("Value is not defined in storage"))?;
+            .ok_or(TitanhError::NotInStorage)?;
         Ok(result)
     }
 
+    /// Queries several entries of the chain's storage in a single request, at the same block. A missing entry is returned as `None`
+    pub async fn query_many<Addr>(
+        &self,
+        addresses: &[Addr],
+        at: BlockHash,
+    ) -> Result<Vec<Option<<Addr as Address>::Target>>>
+    where
+        Addr: Address<IsFetchable = Yes>,
+        <Addr as Address>::Target: Decode,
+    {
+        if addresses.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let storage_client = self.substrate_api.storage();
+        let keys = addresses
+            .iter()
+            .map(|address| storage_client.address_bytes(address))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let change_sets = self
+            .rpc
+            .state_query_storage_at(keys.iter().map(|key| &key[..]), Some(at.0))
+            .await?;
+        let mut values = HashMap::new();
+        for (key, value) in change_sets.into_iter().flat_map(|set| set.changes) {
+            if let Some(value) = value {
+                values.insert(key.0, value.0);
+            }
+        }
+
+        keys.iter()
+            .map(|key| match values.get(key) {
+                Some(value) => Ok(Some(Decode::decode(&mut &value[..])?)),
+                None => Ok(None),
+            })
+            .collect()
+    }
+
     pub async fn runtime_events(
         &self,
         at: Option<BlockHash>,
@@ -118,13 +200,26 @@ impl TitanhApi {
         if let Some(hash) = block_hash {
             Ok(hash.into())
         } else {
-            Err(anyhow::anyhow!(
-                "Block hash not found for block number: {}",
-                block_number
-            ))
+            Err(TitanhError::BlockNotFound(block_number))
         }
     }
 
+    /// Returns the latest block, not yet finalized
+    pub async fn latest_block(&self) -> Result<BlockInfo> {
+        let best_hash = self
+            .rpc
+            .chain_get_block_hash(None)
+            .await?
+            .ok_or(TitanhError::NotInStorage)?;
+        let block_num_query = titanh::storage().system().number();
+        let number = self.query(&block_num_query, Some(best_hash.into())).await?;
+
+        Ok(BlockInfo {
+            number,
+            hash: best_hash.into(),
+        })
+    }
+
     pub async fn latest_finalized_block(&self) -> Result<BlockInfo> {
         let finalized_head = self.rpc.chain_get_finalized_head().await?;
         let block_num_query = titanh::storage().system().number();
@@ -139,42 +234,197 @@ impl TitanhApi {
     }
 
     fn ensure_signer(&self) -> Result<&Signer> {
-        let signer = self
-            .signer
-            .as_ref()
-            .ok_or_else(|| anyhow::anyhow!("Signer is not set"))?;
+        let signer = self.signer.as_ref().ok_or(TitanhError::SignerNotSet)?;
 
         Ok(signer)
     }
 
-    /// Signs and submits a transaction. If it succeeds, it means the transaction is included in the transaction pool, not in a block.
-    pub async fn sign_and_submit<Call: Payload>(&self, tx: &Call) -> Result<H256> {
+    /// Returns the number of transactions of the signer that are not yet accepted by the transaction pool
+    pub async fn in_flight_transactions(&self) -> usize {
+        self.nonces.in_flight().await
+    }
+
+    /// Reads the nonce of the signer again from the chain before the next transaction, e.g. after the account was used by another client
+    pub async fn resync_nonce(&self) {
+        self.nonces.resync().await
+    }
+
+    /// Tracks the status of a transaction submitted to the transaction pool. It is recorded in the outbox, if any, until it is finalized or dropped
+    pub async fn track(&self, tx_hash: H256) -> Result<TxHandle> {
+        self.watcher.track(tx_hash).await
+    }
+
+    /// Returns the handles of the transactions that were pending in the outbox when the app stopped
+    pub async fn recover_pending_writes(&self) -> Result<Vec<TxHandle>> {
+        self.watcher.recover().await
+    }
+
+    /// Allocates the nonce of the next transaction of the signer
+    async fn next_nonce(&self, signer: &Signer) -> Result<u64> {
+        self.nonces.allocate(&self.rpc, &signer.account_id()).await
+    }
+
+    /// Tracks the nonce of a submitted transaction, depending on whether it has been accepted by the transaction pool
+    /// The nonce is `None` for the transactions signed offline, that are not allocated locally
+    async fn track_submission<T>(
+        &self,
+        nonce: Option<u64>,
+        res: Result<T, subxt::Error>,
+    ) -> Result<T> {
+        match (res, nonce) {
+            (Ok(value), Some(nonce)) => {
+                self.nonces.submitted(nonce).await;
+                Ok(value)
+            }
+            (Err(e), Some(nonce)) => {
+                self.nonces.failed(nonce).await;
+                Err(e.into())
+            }
+            (res, None) => res.map_err(Into::into),
+        }
+    }
+
+    /// Releases the nonce of a transaction that may not have been included, e.g. dropped from the pool.
+    /// It is allocated again only if the chain nonce has not moved past it
+    async fn nonce_maybe_unused(&self, nonce: Option<u64>) {
+        match nonce {
+            Some(nonce) => self.nonces.failed(nonce).await,
+            None => self.nonces.resync().await,
+        }
+    }
+
+    /// Signs a transaction with the next nonce of the signer, without submitting it.
+    /// The nonce must then be either submitted with the transaction, or released if the transaction is discarded
+    pub(crate) async fn sign<Call: Payload>(&self, tx: &Call) -> Result<(u64, Extrinsic)> {
         let signer = self.ensure_signer()?;
+        let nonce = self.next_nonce(signer).await?;
+
+        match self.sign_with_nonce(tx, signer.as_ref(), nonce).await {
+            Ok(extrinsic) => Ok((nonce, extrinsic)),
+            Err(e) => {
+                self.nonces.failed(nonce).await;
+                Err(e)
+            }
+        }
+    }
 
-        let tx_hash = self
+    /// Signs a transaction with the given nonce. The payload is signed by the signer, that may be an external device or service
+    async fn sign_with_nonce<Call: Payload>(
+        &self,
+        tx: &Call,
+        signer: &dyn TxSigner,
+        nonce: u64,
+    ) -> Result<Extrinsic> {
+        let partial_tx = self.partial_tx(tx, nonce)?;
+        let signature = signer.sign(&partial_tx.signer_payload()).await?;
+
+        Ok(partial_tx.sign_with_address_and_signature(&signer.account_id().into(), &signature))
+    }
+
+    /// Builds an immortal transaction with the given nonce, waiting for its signature
+    fn partial_tx<Call: Payload>(&self, tx: &Call, nonce: u64) -> Result<PartialTx> {
+        let ext_params = Params::<SubstrateConfig>::new().nonce(nonce).build();
+        let partial_tx = self
             .substrate_api
             .tx()
-            .sign_and_submit_default(tx, signer)
-            .await?;
+            .create_partial_signed_offline(tx, ext_params)?;
 
-        Ok(tx_hash)
+        Ok(partial_tx)
+    }
+
+    /// Builds a transaction of `account` to be signed offline, with the next nonce of the account.
+    /// The transaction is immortal, so it can be signed at any time as long as the runtime is not upgraded
+    pub async fn offline_tx<Call: Payload>(
+        &self,
+        tx: &Call,
+        account: AccountId32,
+    ) -> Result<OfflineTx> {
+        let nonce = self.rpc.system_account_next_index(&account).await?;
+        let call_data = self.substrate_api.tx().call_data(tx)?;
+        let payload = self
+            .partial_tx(&RawCall(&call_data), nonce)?
+            .signer_payload();
+
+        Ok(OfflineTx {
+            account,
+            nonce,
+            call_data,
+            payload,
+        })
+    }
+
+    /// Submits a transaction signed offline, waiting for the given level
+    pub async fn submit_offline(
+        &self,
+        tx: &OfflineTx,
+        signature: MultiSignature,
+        level: ConsistencyLevel,
+    ) -> Result<H256> {
+        let partial_tx = self.partial_tx(&RawCall(&tx.call_data), tx.nonce)?;
+        if partial_tx.signer_payload() != tx.payload {
+            return Err(TitanhError::invalid_input(
+                "The offline transaction does not match the current runtime",
+            ));
+        }
+
+        let extrinsic =
+            partial_tx.sign_with_address_and_signature(&tx.account.clone().into(), &signature);
+        let res = self.submit_signed_with_level(None, extrinsic, level).await;
+
+        // The nonces allocated locally do not account for the transaction
+        if self
+            .signer
+            .as_ref()
+            .is_some_and(|signer| signer.account_id() == tx.account)
+        {
+            self.nonces.resync().await;
+        }
+
+        res
+    }
+
+    /// Releases the nonce of a signed transaction that is not submitted, so that it is allocated again
+    pub(crate) async fn release_nonce(&self, nonce: u64) {
+        self.nonces.release(nonce).await
+    }
+
+    /// Signs and submits a transaction. If it succeeds, it means the transaction is included in the transaction pool, not in a block.
+    /// The nonce is allocated locally, so that many transactions can be submitted concurrently
+    pub async fn sign_and_submit<Call: Payload>(&self, tx: &Call) -> Result<H256> {
+        let (nonce, extrinsic) = self.sign(tx).await?;
+        self.submit_signed(Some(nonce), extrinsic).await
     }
 
     /// Signs and submits a transaction. It waits for the transaction to be included in a block
     pub async fn sign_and_submit_wait_in_block<Call: Payload>(&self, tx: &Call) -> Result<Events> {
-        let signer = self.ensure_signer()?;
-        let current_nonce = self
-            .rpc
-            .system_account_next_index(&signer.account_id())
-            .await?;
-        let ext_params = Params::<SubstrateConfig>::new()
-            .nonce(current_nonce)
-            .build();
-        let mut tx_progress = self
-            .substrate_api
-            .tx()
-            .sign_and_submit_then_watch(tx, signer, ext_params)
-            .await?;
+        let (nonce, extrinsic) = self.sign(tx).await?;
+        self.submit_signed_wait_in_block(Some(nonce), extrinsic)
+            .await
+    }
+
+    /// Signs and submits a transaction. It waits until the transaction is finalized.
+    pub async fn sign_and_submit_wait_finalized<Call: Payload>(&self, tx: &Call) -> Result<Events> {
+        let (nonce, extrinsic) = self.sign(tx).await?;
+        self.submit_signed_wait_finalized(Some(nonce), extrinsic)
+            .await
+    }
+
+    /// Submits a signed transaction to the transaction pool
+    async fn submit_signed(&self, nonce: Option<u64>, extrinsic: Extrinsic) -> Result<H256> {
+        let res = extrinsic.submit().await;
+        let tx_hash = self.track_submission(nonce, res).await?;
+
+        Ok(tx_hash)
+    }
+
+    /// Submits a signed transaction, waiting for it to be included in a block
+    async fn submit_signed_wait_in_block(
+        &self,
+        nonce: Option<u64>,
+        extrinsic: Extrinsic,
+    ) -> Result<Events> {
+        let res = extrinsic.submit_and_watch().await;
+        let mut tx_progress = self.track_submission(nonce, res).await?;
 
         while let Some(block_status) = tx_progress.next().await {
             let status = block_status?;
@@ -184,42 +434,50 @@ impl TitanhApi {
             }
         }
 
-        Err(anyhow::anyhow!("Transaction failed"))
+        // The transaction has been dropped from the pool, so its nonce may be unused
+        self.nonce_maybe_unused(nonce).await;
+        Err(TitanhError::TransactionFailed)
     }
 
-    /// Signs and submits a transaction. It waits until the transaction is finalized.
-    pub async fn sign_and_submit_wait_finalized<Call: Payload>(&self, tx: &Call) -> Result<Events> {
-        let signer = self.ensure_signer()?;
+    /// Submits a signed transaction, waiting for it to be finalized
+    async fn submit_signed_wait_finalized(
+        &self,
+        nonce: Option<u64>,
+        extrinsic: Extrinsic,
+    ) -> Result<Events> {
+        let res = extrinsic.submit_and_watch().await;
+        let tx_progress = self.track_submission(nonce, res).await?;
 
-        // Submit the extrinisc, and wait for it to be successful and in a finalized block.
+        // Wait for the extrinisc to be successful and in a finalized block.
         // We get back the extrinsic events if all is well.
-        let events = self
-            .substrate_api
-            .tx()
-            .sign_and_submit_then_watch_default(tx, signer)
-            .await?
-            .wait_for_finalized_success()
-            .await?;
-
-        Ok(events)
+        match tx_progress.wait_for_finalized_success().await {
+            Ok(events) => Ok(events),
+            Err(e) => {
+                // The transaction may have been dropped from the pool, so its nonce may be unused
+                self.nonce_maybe_unused(nonce).await;
+                Err(e.into())
+            }
+        }
     }
 
-    pub async fn sign_and_submit_tx_with_level<Call: Payload>(
+    /// Submits a signed transaction, waiting for the given level
+    pub(crate) async fn submit_signed_with_level(
         &self,
-        tx: &Call,
+        nonce: Option<u64>,
+        extrinsic: Extrinsic,
         level: ConsistencyLevel,
     ) -> Result<H256> {
         let tx_hash = match level {
             // Just include the transaction in the transaction pool
-            ConsistencyLevel::Eventual => self.sign_and_submit(tx).await?,
+            ConsistencyLevel::Eventual => self.submit_signed(nonce, extrinsic).await?,
             // Wait for block inclusion
             ConsistencyLevel::Committed => {
-                let events = self.sign_and_submit_wait_in_block(tx).await?;
+                let events = self.submit_signed_wait_in_block(nonce, extrinsic).await?;
                 events.extrinsic_hash()
             }
             // Wait for block finalization
             ConsistencyLevel::Finalized => {
-                let events = self.sign_and_submit_wait_finalized(tx).await?;
+                let events = self.submit_signed_wait_finalized(nonce, extrinsic).await?;
                 events.extrinsic_hash()
             }
         };
@@ -227,7 +485,17 @@ impl TitanhApi {
         Ok(tx_hash)
     }
 
-    /// Signs and submits a batch of transactions (all or nothing). It waits until the transaction is finalized.
+    pub async fn sign_and_submit_tx_with_level<Call: Payload>(
+        &self,
+        tx: &Call,
+        level: ConsistencyLevel,
+    ) -> Result<H256> {
+        let (nonce, extrinsic) = self.sign(tx).await?;
+        self.submit_signed_with_level(Some(nonce), extrinsic, level)
+            .await
+    }
+
+    /// Signs and submits a batch of transactions (all or nothing), waiting for the given level
     pub async fn sign_and_submit_batch(
         &self,
         calls: RuntimeCalls,
@@ -235,18 +503,6 @@ impl TitanhApi {
     ) -> Result<H256> {
         let batch_tx = titanh::tx().utility().batch_all(calls);
 
-        let tx_hash = match level {
-            ConsistencyLevel::Eventual => self.sign_and_submit(&batch_tx).await?,
-            ConsistencyLevel::Committed => self
-                .sign_and_submit_wait_in_block(&batch_tx)
-                .await?
-                .extrinsic_hash(),
-            ConsistencyLevel::Finalized => self
-                .sign_and_submit_wait_finalized(&batch_tx)
-                .await?
-                .extrinsic_hash(),
-        };
-
-        Ok(tx_hash)
+        self.sign_and_submit_tx_with_level(&batch_tx, level).await
     }
 }
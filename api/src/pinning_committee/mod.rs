@@ -1,5 +1,6 @@
 use crate::{
     common_types::{BlockInfo, BlockNumber, ConsistencyLevel},
+    error::{Result, TitanhError},
     titanh::{
         self,
         runtime_types::{
@@ -9,11 +10,10 @@ use crate::{
     },
     TitanhApi,
 };
-use anyhow::Result;
-use codec::Decode;
 use crypto::IpfsPair;
 use sp_core::{Blake2Hasher, Hasher, H256};
-use types::PinningRing;
+use subxt::utils::AccountId32;
+use types::{Handoff, NodeId, PinningRing};
 
 pub struct PinningCommitteeApi<'a> {
     titanh: &'a TitanhApi,
@@ -34,7 +34,8 @@ impl PinningCommitteeApi<'_> {
     pub fn ipfs_seeds(self, ipfs_peers_seed: Vec<Vec<u8>>) -> Result<Self> {
         let mut ipfs_peers = Vec::new();
         for seed in ipfs_peers_seed {
-            let pair = IpfsPair::from_seed(&seed).map_err(|_| anyhow::anyhow!("Invalid seed"))?;
+            let pair = IpfsPair::from_seed(&seed)
+                .map_err(|_| TitanhError::invalid_input("Invalid IPFS seed"))?;
             ipfs_peers.push(pair);
         }
 
@@ -61,6 +62,55 @@ impl PinningCommitteeApi<'_> {
         Ok(pinning_ring)
     }
 
+    /// Returns the pinning nodes controlled by a validator at a given block
+    pub async fn validator_pinning_nodes_at(
+        &self,
+        validator: [u8; 32],
+        block: BlockInfo,
+    ) -> Result<Vec<NodeId>> {
+        let nodes_query = titanh::storage()
+            .pinning_committee()
+            .validator_pinning_nodes(AccountId32::from(validator));
+        let nodes = self
+            .titanh
+            .substrate_api
+            .storage()
+            .at(block.hash)
+            .fetch_or_default(&nodes_query)
+            .await?;
+
+        Ok(nodes)
+    }
+
+    /// Returns the key transfer of a leaving node that still has to be acknowledged by its successors (if any)
+    pub async fn pending_handoff_at(
+        &self,
+        leaving_node: NodeId,
+        block: BlockInfo,
+    ) -> Result<Option<Handoff>> {
+        let handoff_query = titanh::storage()
+            .pinning_committee()
+            .leaving_nodes(leaving_node);
+        let handoff = self
+            .titanh
+            .substrate_api
+            .storage()
+            .at(block.hash)
+            .fetch(&handoff_query)
+            .await?;
+
+        Ok(handoff)
+    }
+
+    /// Acknowledges, on behalf of `pinning_node`, that the rows transferred by a leaving node have been fetched and their content pinned. The transaction is only included in the transaction pool.
+    pub async fn ack_handoff(&self, pinning_node: NodeId, leaving_node: NodeId) -> Result<H256> {
+        let ack_tx = titanh::tx()
+            .pinning_committee()
+            .ack_handoff(pinning_node, leaving_node);
+
+        self.titanh.sign_and_submit(&ack_tx).await
+    }
+
     pub async fn set_committe_config(
         &self,
         rep_factor: u32,
@@ -92,7 +142,7 @@ impl PinningCommitteeApi<'_> {
             .titanh
             .signer
             .as_ref()
-            .ok_or_else(|| anyhow::anyhow!("Signer is not set"))?;
+            .ok_or(TitanhError::SignerNotSet)?;
         if let Some(ipfs_peers) = &self.ipfs_peers {
             for ipfs_pair in ipfs_peers {
                 let registration_call =
@@ -107,7 +157,7 @@ impl PinningCommitteeApi<'_> {
 
             Ok(tx_hash)
         } else {
-            Err(anyhow::anyhow!("IPFS peers keys are not set"))
+            Err(TitanhError::NotConfigured("IPFS peers keys"))
         }
     }
 
@@ -118,6 +168,17 @@ impl PinningCommitteeApi<'_> {
         block_num: BlockNumber,
     ) -> Result<H256> {
         let pinning_node = self.compute_pinning_node_id()?;
+        self.remove_pinning_node(pinning_node, cids, block_num)
+            .await
+    }
+
+    /// Removes a pinning node of the validator from the committee, waiting for finality. Useful when the IPFS seeds of the node are not available (e.g. from the pinning node itself).
+    pub async fn remove_pinning_node(
+        &self,
+        pinning_node: NodeId,
+        cids: Vec<Vec<u8>>,
+        block_num: BlockNumber,
+    ) -> Result<H256> {
         let leave_tx = titanh::tx()
             .pinning_committee()
             .rm_pinning_node(pinning_node, KeyTableAt { block_num, cids });
@@ -140,7 +201,7 @@ impl PinningCommitteeApi<'_> {
 
             Ok(Blake2Hasher::hash(&ids[..]))
         } else {
-            Err(anyhow::anyhow!("IPFS peers keys are not set"))
+            Err(TitanhError::NotConfigured("IPFS peers keys"))
         }
     }
 }
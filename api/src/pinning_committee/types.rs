@@ -1,13 +1,16 @@
 use crate::{
     capsules_types::CapsuleKey,
     common_types::{BlockHash, BlockInfo},
+    error::{Result, TitanhError},
 };
-use anyhow::Result;
 use sp_core::H256;
 
 /// A pinning node's identifier in the ring
 pub type NodeId = H256;
 
+/// The key transfer of a leaving node, that its successors must acknowledge before the deadline
+pub use crate::titanh::runtime_types::pallet_pinning_committee::types::Handoff;
+
 /// The pinning ring
 pub struct PinningRing {
     ring: Vec<NodeId>,
@@ -52,7 +55,7 @@ impl PinningRing {
 
             Ok(idx)
         } else {
-            Err(anyhow::anyhow!("Node should not already be in the ring"))
+            Err(TitanhError::NodeAlreadyInRing)
         }
     }
 
@@ -69,7 +72,7 @@ impl PinningRing {
         let lookup = self.node_lookup(node);
         match lookup {
             NodeLookup::Found(idx) => Ok(idx),
-            NodeLookup::NotFound(_) => Err(anyhow::anyhow!("Node not found")),
+            NodeLookup::NotFound(_) => Err(TitanhError::NodeNotFound),
         }
     }
 
@@ -101,7 +104,7 @@ impl PinningRing {
     /// Looks for the closest node in the ring given a `target_key`
     fn binary_search_closest_node(&self, target_key: CapsuleKey) -> Result<usize> {
         if self.ring.is_empty() {
-            return Err(anyhow::anyhow!("Pinning ring is empty"));
+            return Err(TitanhError::EmptyRing);
         }
 
         let mut low = 0;
@@ -1,7 +1,9 @@
 use base64::engine::general_purpose;
 use base64::Engine;
 use clap::{Parser, Subcommand};
+use keystore::{KeyType, Keystore};
 use libp2p::identity::{ed25519::Keypair, Keypair as IdKeypair};
+use std::path::PathBuf;
 
 #[derive(Parser)]
 #[command(name = "ipfs-key")]
@@ -15,8 +17,19 @@ struct Cli {
 
 #[derive(Subcommand)]
 enum Commands {
-    /// Generate ipfs peer infos: seed, privkey_protobuf, pubkey, peer_id
-    Generate {},
+    /// Generate ipfs peer infos: seed, privkey_protobuf, pubkey, peer_id.
+    /// With a keystore, the seed is stored encrypted in the keystore instead of being printed
+    Generate {
+        /// The path of the keystore directory
+        #[arg(long, requires = "name")]
+        keystore: Option<PathBuf>,
+        /// The name of the key in the keystore
+        #[arg(long, requires = "keystore")]
+        name: Option<String>,
+        /// The path of a file containing the keystore password. If not set, the password is read from the `TITANH_KEYSTORE_PASSWORD` environment variable or from the terminal
+        #[arg(long, requires = "keystore")]
+        password_file: Option<PathBuf>,
+    },
 }
 
 fn generate_peer_info() -> Result<PeerInfo, String> {
@@ -59,10 +72,28 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
 
     match &cli.command {
-        Commands::Generate {} => {
+        Commands::Generate {
+            keystore,
+            name,
+            password_file,
+        } => {
             let peer_info = generate_peer_info()?;
 
-            println!("Seed: 0x{}", hex::encode(peer_info.seed));
+            match (keystore, name) {
+                (Some(keystore), Some(name)) => {
+                    let keystore = Keystore::open(keystore)?;
+                    let password = keystore::read_password(password_file.as_deref())?;
+                    let path = keystore.insert(
+                        &name,
+                        KeyType::Ed25519,
+                        &peer_info.seed,
+                        &peer_info.pubkey,
+                        &password,
+                    )?;
+                    println!("Seed stored in the keystore at: {}", path.display());
+                }
+                _ => println!("Seed: 0x{}", hex::encode(&peer_info.seed)),
+            }
             println!("Privkey_protobuf: {}", peer_info.privkey_protobuf);
             println!("Pubkey: 0x{}", hex::encode(peer_info.pubkey));
             println!("Peer_id: {}", peer_info.peer_id);
@@ -0,0 +1,103 @@
+use anyhow::Result;
+use clap::Args;
+use keystore::{KeyType, Keystore};
+use sp_core::{ed25519, sr25519, Pair};
+use std::io::BufRead;
+use std::path::PathBuf;
+use zeroize::Zeroizing;
+
+/// The keystore holding the secrets used by the CLI
+#[derive(Args)]
+pub struct KeystoreArgs {
+    /// The path of the keystore directory
+    #[arg(long)]
+    pub keystore: PathBuf,
+    /// The path of a file containing the keystore password. If not set, the password is read from the `TITANH_KEYSTORE_PASSWORD` environment variable or from the terminal
+    #[arg(long)]
+    pub password_file: Option<PathBuf>,
+}
+
+/// A keystore opened with its password, so that several keys are loaded with a single password prompt
+pub struct UnlockedKeystore {
+    keystore: Keystore,
+    password: Zeroizing<String>,
+}
+
+impl UnlockedKeystore {
+    /// Loads the secret URI of an account (sr25519) key
+    pub fn load_suri(&self, name: &str) -> Result<Zeroizing<String>> {
+        self.keystore
+            .load(name, KeyType::Sr25519, &self.password)?
+            .suri()
+    }
+
+    /// Loads the seeds of IPFS (ed25519) keys
+    pub fn load_ipfs_seeds(&self, names: &[String]) -> Result<Vec<Vec<u8>>> {
+        let mut seeds = Vec::new();
+        for name in names {
+            let seed = self
+                .keystore
+                .load(name, KeyType::Ed25519, &self.password)?
+                .seed()?;
+            seeds.push(seed.to_vec());
+        }
+
+        Ok(seeds)
+    }
+}
+
+impl KeystoreArgs {
+    /// Opens the keystore and reads its password
+    pub fn unlock(&self) -> Result<UnlockedKeystore> {
+        let keystore = Keystore::open(&self.keystore)?;
+        let password = keystore::read_password(self.password_file.as_deref())?;
+
+        Ok(UnlockedKeystore { keystore, password })
+    }
+
+    /// Imports an account (sr25519) key, reading its secret URI from the standard input
+    pub fn import_account_key(&self, name: &str) -> Result<()> {
+        let mut suri = Zeroizing::new(String::new());
+        std::io::stdin().lock().read_line(&mut suri)?;
+        let suri = suri.trim();
+
+        let pair = sr25519::Pair::from_string(suri, None)
+            .map_err(|e| anyhow::anyhow!("Invalid secret URI: {:?}", e))?;
+
+        let UnlockedKeystore { keystore, password } = self.unlock()?;
+        let path = keystore.insert(
+            name,
+            KeyType::Sr25519,
+            suri.as_bytes(),
+            pair.public().as_ref(),
+            &password,
+        )?;
+        println!("Imported sr25519 key {} into {}", name, path.display());
+
+        Ok(())
+    }
+
+    /// Imports IPFS (ed25519) keys from their seeds, naming them `<prefix>-<n>`
+    pub fn import_ipfs_seeds(&self, seeds: Vec<Vec<u8>>, prefix: &str) -> Result<Vec<String>> {
+        let UnlockedKeystore { keystore, password } = self.unlock()?;
+
+        let mut names = Vec::new();
+        for (idx, seed) in seeds.into_iter().enumerate() {
+            let seed = Zeroizing::new(seed);
+            let pair = ed25519::Pair::from_seed_slice(&seed)
+                .map_err(|_| anyhow::anyhow!("Invalid ed25519 seed at position {}", idx + 1))?;
+
+            let name = format!("{}-{}", prefix, idx + 1);
+            keystore.insert(
+                &name,
+                KeyType::Ed25519,
+                &seed,
+                pair.public().as_ref(),
+                &password,
+            )?;
+            names.push(name);
+        }
+
+        Ok(names)
+    }
+}
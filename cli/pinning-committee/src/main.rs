@@ -1,5 +1,7 @@
 use api::TitanhApiBuilder;
 use clap::{Parser, Subcommand};
+use keys::KeystoreArgs;
+use pinning::DbBackend;
 use std::fs;
 use std::io::BufRead;
 use std::path::PathBuf;
@@ -16,9 +18,11 @@ struct Cli {
 enum Commands {
     /// Set the pinning committee configuration
     CommitteeConfig {
-        /// The seed phrase of the sudo account
-        #[arg(short, long)]
-        seed_phrase: String,
+        #[command(flatten)]
+        keystore: KeystoreArgs,
+        /// The name of the sudo account key in the keystore
+        #[arg(long)]
+        key: String,
         /// The chain rpc endpoint
         #[arg(short, long)]
         rpc: String,
@@ -34,24 +38,28 @@ enum Commands {
     },
     /// Register a new pinning node
     RegisterPinningNode {
-        /// The seed phrase of the validator account
-        #[arg(short, long)]
-        seed_phrase: String,
+        #[command(flatten)]
+        keystore: KeystoreArgs,
+        /// The name of the validator account key in the keystore
+        #[arg(long)]
+        key: String,
         /// The chain rpc endpoint
         #[arg(short, long)]
         rpc: String,
-        /// The path to the file containing hex-encoded IPFS seeds, one per line
-        #[arg(short, long)]
-        seeds_file: String,
+        /// The name of an IPFS key of the node in the keystore
+        #[arg(long = "ipfs-key", required = true)]
+        ipfs_keys: Vec<String>,
     },
-    /// Leave the pinning committee
+    /// Leave the pinning committee on behalf of a stopped pinning node, reading its database. A running node should leave with `pinning-node leave` instead
     LeavePinningCommittee {
-        /// The seed phrase of the validator account
-        #[arg(short, long)]
-        seed_phrase: String,
-        /// The path to the file containing hex-encoded IPFS seeds, one per line
-        #[arg(short, long)]
-        seeds_file: String,
+        #[command(flatten)]
+        keystore: KeystoreArgs,
+        /// The name of the validator account key in the keystore
+        #[arg(long)]
+        key: String,
+        /// The name of an IPFS key of the node in the keystore
+        #[arg(long = "ipfs-key", required = true)]
+        ipfs_keys: Vec<String>,
         /// The chain rpc endpoint
         #[arg(short, long)]
         chain_rpc: String,
@@ -61,6 +69,31 @@ enum Commands {
         /// The keytable rows to upload to ipfs
         #[arg(short, long)]
         table_rows: u32,
+        /// The storage root of the node data, as configured by `data_dir` in the node config file. Defaults to the home directory
+        #[arg(long)]
+        data_dir: Option<String>,
+        /// The storage engine of the node checkpointing db, as configured by `db_backend` in the node config file
+        #[arg(long, default_value = "sled")]
+        db_backend: DbBackend,
+    },
+    /// Import an account (sr25519) key into the keystore, reading its secret URI (e.g. the seed phrase) from the standard input
+    ImportAccountKey {
+        #[command(flatten)]
+        keystore: KeystoreArgs,
+        /// The name of the key in the keystore
+        #[arg(long)]
+        name: String,
+    },
+    /// Import the IPFS (ed25519) keys of a node into the keystore, from a file of hex-encoded seeds. The keys are named `<name-prefix>-<n>`, and the seeds file can be deleted afterwards
+    ImportIpfsSeeds {
+        #[command(flatten)]
+        keystore: KeystoreArgs,
+        /// The path to the file containing hex-encoded IPFS seeds, one per line
+        #[arg(short, long)]
+        seeds_file: String,
+        /// The prefix of the names of the keys in the keystore
+        #[arg(long)]
+        name_prefix: String,
     },
 }
 
@@ -111,13 +144,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     match cli.command {
         Commands::CommitteeConfig {
-            seed_phrase,
+            keystore,
+            key,
             rpc,
             rep_factor,
             ipfs_replicas,
             pinning_nodes,
         } => {
-            let api = TitanhApiBuilder::rpc(&rpc).seed(&seed_phrase).build().await?;
+            let suri = keystore.unlock()?.load_suri(&key)?;
+            let api = TitanhApiBuilder::rpc(&rpc).seed(&suri).build().await?;
 
             let tx_hash = api
                 .pinning_committee()
@@ -129,14 +164,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             );
         }
         Commands::RegisterPinningNode {
-            seed_phrase,
+            keystore,
+            key,
             rpc,
-            seeds_file,
+            ipfs_keys,
         } => {
-            let seeds_path = PathBuf::from(seeds_file);
-            let ipfs_seeds = get_seeds_from_hex_file(seeds_path)?;
+            let keystore = keystore.unlock()?;
+            let ipfs_seeds = keystore.load_ipfs_seeds(&ipfs_keys)?;
+            let suri = keystore.load_suri(&key)?;
 
-            let api = TitanhApiBuilder::rpc(&rpc).seed(&seed_phrase).build().await?;
+            let api = TitanhApiBuilder::rpc(&rpc).seed(&suri).build().await?;
 
             let tx_hash = api
                 .pinning_committee()
@@ -149,24 +186,29 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             );
         }
         Commands::LeavePinningCommittee {
-            seed_phrase,
-            seeds_file,
+            keystore,
+            key,
+            ipfs_keys,
             chain_rpc,
             ipfs_rpc,
             table_rows,
+            data_dir,
+            db_backend,
         } => {
-            let seeds_path = PathBuf::from(seeds_file);
-            let ipfs_seeds = get_seeds_from_hex_file(seeds_path)?;
+            let keystore = keystore.unlock()?;
+            let ipfs_seeds = keystore.load_ipfs_seeds(&ipfs_keys)?;
+            let suri = keystore.load_suri(&key)?;
 
             let api = TitanhApiBuilder::rpc(&chain_rpc)
-                .seed(&seed_phrase)
+                .seed(&suri)
                 .build()
                 .await?;
             let committee_api = api.pinning_committee().ipfs_seeds(ipfs_seeds)?;
 
             let node_id = committee_api.compute_pinning_node_id()?;
-            let node_checkpoint =
-                node_leave::read_node_checkpoint_from_db(table_rows, node_id)?;
+            let node_checkpoint = node_leave::read_node_checkpoint_from_db(
+                table_rows, node_id, data_dir, db_backend,
+            )?;
 
             let block_num = node_checkpoint.height();
             let keytable = node_checkpoint.keytable();
@@ -180,9 +222,24 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 tx_hash
             );
         }
+        Commands::ImportAccountKey { keystore, name } => {
+            keystore.import_account_key(&name)?;
+        }
+        Commands::ImportIpfsSeeds {
+            keystore,
+            seeds_file,
+            name_prefix,
+        } => {
+            let seeds_path = PathBuf::from(seeds_file);
+            let ipfs_seeds = get_seeds_from_hex_file(seeds_path)?;
+
+            let names = keystore.import_ipfs_seeds(ipfs_seeds, &name_prefix)?;
+            println!("Imported IPFS keys: {}", names.join(" "));
+        }
     }
 
     Ok(())
 }
 
+mod keys;
 mod node_leave;
@@ -2,7 +2,7 @@ use anyhow::Result;
 use ipfs_api_backend_hyper::{request::Add, IpfsApi, IpfsClient, TryFromUri};
 use pinning::{
     checkpointing::{Checkpoint, DbCheckpoint},
-    FaultTolerantKeyTable,
+    DbBackend, FaultTolerantKeyTable,
 };
 use sp_core::H256;
 use std::io::Cursor;
@@ -10,8 +10,15 @@ use std::io::Cursor;
 pub fn read_node_checkpoint_from_db(
     rep_factor: u32,
     node_id: H256,
+    data_dir: Option<String>,
+    db_backend: DbBackend,
 ) -> Result<Checkpoint> {
-    let db = DbCheckpoint::from_values(rep_factor, node_id, false);
+    let data_dir = match data_dir {
+        Some(data_dir) => data_dir,
+        None => pinning::default_data_dir()?,
+    };
+    let node_dir = pinning::node_dir(&data_dir, node_id);
+    let db = DbCheckpoint::from_values(rep_factor, node_dir, false, db_backend)?;
     let checkpoint = db.get_checkpoint()?;
     Ok(checkpoint)
 }
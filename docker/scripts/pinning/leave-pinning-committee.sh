@@ -19,33 +19,25 @@ fi
 
 NODE_IDX="$1"
 
-# List of required environment variables
-REQUIRED_VARS=("VALIDATOR_SEED" "CHAIN_RPC" "IPFS_RPC" "REPLICATION_FACTOR")
-
-# Check if each required environment variable is set
-for var in "${REQUIRED_VARS[@]}"; do
-    if [[ -z "${!var}" ]]; then
-        error_exit "$var environment variable is not set."
-    fi
-done
-
 # Define paths
-CLI_PATH="$HOME/cli/pinning-committee/target/release/pinning-committee"
+PINNING_NODE_PATH="$HOME/pinning-node/target/release/pinning_node"
 
-# Check if the CLI program exists
-if [[ ! -x "$CLI_PATH" ]]; then
-    error_exit "CLI program not found or not executable at $CLI_PATH"
+# Check if the Pinning Node program exists
+if [[ ! -x "$PINNING_NODE_PATH" ]]; then
+    error_exit "Pinning Node program not found or not executable at $PINNING_NODE_PATH"
 fi
 
-IPFS_SEEDS_PATH="$HOME/config/node-$NODE_IDX/ipfs_seeds"
+NODE_CONFIG_PATH="$HOME/pinning-node-$NODE_IDX.toml"
+
+# Check if the node config file, written when the node has been started, exists
+if [[ ! -f "$NODE_CONFIG_PATH" ]]; then
+    error_exit "Node config file not found at $NODE_CONFIG_PATH"
+fi
 
 echo "===================================================================="
-echo "Uploading node keytable to IPFS and sending the leave tx on chain..."
+echo "Requesting the running pinning node $NODE_IDX to leave the committee..."
 echo "===================================================================="
 
-"$CLI_PATH" leave-pinning-committee \
-    --seed-phrase "$VALIDATOR_SEED" \
-    --seeds-file "$IPFS_SEEDS_PATH" \
-    --chain-rpc "$CHAIN_RPC" \
-    --ipfs-rpc "$IPFS_RPC" \
-    --table-rows "$REPLICATION_FACTOR"
+# The running node uploads its keytable, sends the leave tx on chain and unpins its content
+"$PINNING_NODE_PATH" leave \
+    --config "$NODE_CONFIG_PATH"
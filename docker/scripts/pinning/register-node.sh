@@ -28,7 +28,7 @@ fi
 NODE_IDX="$1"
 
 # List of required environment variables
-REQUIRED_VARS=("VALIDATOR_SEED" "CHAIN_RPC" "FAILURE_RETRY")
+REQUIRED_VARS=("VALIDATOR_SEED" "CHAIN_RPC" "FAILURE_RETRY" "TITANH_KEYSTORE_PASSWORD")
 
 # Check if each required environment variable is set
 for var in "${REQUIRED_VARS[@]}"; do
@@ -46,6 +46,26 @@ if [[ ! -x "$CLI_PATH" ]]; then
 fi
 
 IPFS_SEEDS_PATH="$HOME/config/node-$NODE_IDX/ipfs_seeds"
+KEYSTORE_PATH="$HOME/keystore"
+IPFS_KEY_PREFIX="node-$NODE_IDX-ipfs"
+
+# Import the validator key and the IPFS keys of the node into the keystore (the password is read from TITANH_KEYSTORE_PASSWORD)
+if [[ ! -f "$KEYSTORE_PATH/validator.json" ]]; then
+    echo "$VALIDATOR_SEED" | "$CLI_PATH" import-account-key \
+        --keystore "$KEYSTORE_PATH" \
+        --name validator
+fi
+if [[ ! -f "$KEYSTORE_PATH/$IPFS_KEY_PREFIX-1.json" ]]; then
+    "$CLI_PATH" import-ipfs-seeds \
+        --keystore "$KEYSTORE_PATH" \
+        --seeds-file "$IPFS_SEEDS_PATH" \
+        --name-prefix "$IPFS_KEY_PREFIX"
+fi
+
+IPFS_KEY_ARGS=()
+for IPFS_KEY_FILE in "$KEYSTORE_PATH/$IPFS_KEY_PREFIX"-*.json; do
+    IPFS_KEY_ARGS+=(--ipfs-key "$(basename "$IPFS_KEY_FILE" .json)")
+done
 
 # Display header
 echo -e "${YELLOW}==============================================================="
@@ -53,9 +73,8 @@ echo -e "${GREEN}${BOLD}Registering${RESET} for Pinning Node ${BOLD}#${NODE_IDX}
 echo -e "${YELLOW}===============================================================${RESET}"
 
 # Show registration details
-echo -e "${BOLD}Validator Seed: ${RESET}${VALIDATOR_SEED}"
 echo -e "${BOLD}Chain RPC: ${RESET}${CHAIN_RPC}"
-echo -e "${BOLD}IPFS Seeds File: ${RESET}${IPFS_SEEDS_PATH}"
+echo -e "${BOLD}Keystore: ${RESET}${KEYSTORE_PATH}"
 echo ""
 
 # Simulate progress
@@ -64,9 +83,10 @@ sleep 1
 
 # Execute the CLI command
 "$CLI_PATH" register-pinning-node \
-    --seed-phrase "$VALIDATOR_SEED" \
+    --keystore "$KEYSTORE_PATH" \
+    --key validator \
     --rpc "$CHAIN_RPC" \
-    --seeds-file "$IPFS_SEEDS_PATH"
+    "${IPFS_KEY_ARGS[@]}"
 
 # On success
 echo -e "${GREEN}[SUCCESS]${RESET} Registration transaction for virtual node ${BOLD}#${NODE_IDX}${RESET} has been successfully sent!"
@@ -47,19 +47,27 @@ for NODE_IDX in "${NODE_ARGS[@]}"; do
     fi
  
     IPFS_PUBKEYS_PATH="$HOME/config/node-$NODE_IDX/ipfs-pubkeys.json"
+    NODE_CONFIG_PATH="$HOME/pinning-node-$NODE_IDX.toml"
     echo "=============================================="
     echo "Starting pinning node $NODE_IDX with log level $LOG_LEVEL"
     echo "=============================================="
+
+    # Write the node config file. The validator key is read from the keystore filled at registration
+    cat > "$NODE_CONFIG_PATH" <<EOF
+keystore = "$HOME/keystore"
+validator_key = "validator"
+chain_node_endpoint = "$CHAIN_RPC"
+ipfs_peers_file = "$IPFS_PUBKEYS_PATH"
+failure_retry = $FAILURE_RETRY
+rep_factor = $REPLICATION_FACTOR
+keytable_log = true
+latency = true
+data_dir = "$HOME/data"
+EOF
  
     # Start the pinning node
     RUST_LOG="$LOG_LEVEL" "$PINNING_NODE_PATH" start \
-        --seed "$VALIDATOR_SEED" \
-        --rpc "$CHAIN_RPC" \
-        --retries "$FAILURE_RETRY" \
-        --ipfs-peers-config "$IPFS_PUBKEYS_PATH" \
-        --rep-factor "$REPLICATION_FACTOR" \
-        --keytable-log \
-        --latency  > "$HOME/pinning_$NODE_IDX.log" 2>&1 &
+        --config "$NODE_CONFIG_PATH" > "$HOME/pinning_$NODE_IDX.log" 2>&1 &
  
     echo "PID $!" > "$HOME/pid_node_$NODE_IDX"
 
@@ -78,12 +78,12 @@ async fn write_document() -> Result<()> {
     // Insert student certificate into the document
     let student_name = config.certificate.student_name;
 
-    let tx_hash = doc.insert_async(student_name, certificate).await?;
+    let tx = doc.insert_async(student_name, certificate).await?;
 
     // Print results
     println!(
         "Certificate uploaded with tx hash: 0x{}",
-        hex::encode(tx_hash.as_bytes())
+        hex::encode(tx.hash().as_bytes())
     );
     println!("Certificate encryption key: 0x{}", hex::encode(key));
 
@@ -1,57 +1,136 @@
 use anyhow::Result;
 use sp_core::H256;
+use std::time::Duration;
 use titan_api::{
+    common_types::{BlockEvents, BlockNumber},
+    rpc::is_reconnecting,
     titanh::{
         self,
         capsules::events::{CapsuleItemsDeleted, CapsuleStartedDestroying},
     },
-    TitanhApi, TitanhApiBuilder,
+    TitanhApi, TitanhApiBuilder, TitanhError,
 };
 
+/// The delay before subscribing again to the finalized blocks, when the rpc connection is down
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(1);
+/// The delay before submitting again a garbage collection transaction, when the rpc connection is down
+const RESUBMIT_DELAY: Duration = Duration::from_secs(1);
+
 pub struct GarbageCollectorConsumer {
     /// The seed phrase of the garbage collector node
     collector_seed: String,
-    /// The rpc url of the substrate node
-    rpc_url: String,
+    /// The rpc urls of the substrate nodes, in order of preference
+    rpc_urls: Vec<String>,
     /// Key range of the garbage collector
     key_range: Option<(H256, H256)>,
 }
 
 impl GarbageCollectorConsumer {
-    pub fn new(collector_seed: String, rpc_url: String, key_range: Option<(H256, H256)>) -> Self {
+    pub fn new(
+        collector_seed: String,
+        rpc_urls: Vec<String>,
+        key_range: Option<(H256, H256)>,
+    ) -> Self {
         GarbageCollectorConsumer {
             collector_seed,
-            rpc_url,
+            rpc_urls,
             key_range,
         }
     }
 
     pub async fn start(self) -> Result<()> {
-        let api = TitanhApiBuilder::rpc(&self.rpc_url)
-            .seed(&self.collector_seed)
-            .build()
-            .await?;
+        let (rpc_url, fallback_urls) = self
+            .rpc_urls
+            .split_first()
+            .ok_or(anyhow::anyhow!("No rpc url is provided"))?;
+        let mut builder = TitanhApiBuilder::rpc(rpc_url).seed(&self.collector_seed);
+        for url in fallback_urls {
+            builder = builder.fallback_rpc(url);
+        }
+        let api = builder.build().await?;
 
-        let mut blocks_sub = api.substrate_api.blocks().subscribe_finalized().await?;
+        // The last processed block
+        let mut last_block: Option<BlockNumber> = None;
+        loop {
+            // The subscription ends when the rpc connection drops, so it is opened again
+            let mut blocks_sub = match api.substrate_api.blocks().subscribe_finalized().await {
+                Ok(blocks_sub) => blocks_sub,
+                Err(e) if is_reconnecting(&e) => {
+                    log::warn!("Rpc connection lost, subscribing again to finalized blocks");
+                    tokio::time::sleep(RESUBSCRIBE_DELAY).await;
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
 
-        while let Some(block) = blocks_sub.next().await {
-            let block = block?;
-            log::info!("Processing block: {:?}", block.number());
-            let events = block.events().await?;
+            while let Some(block) = blocks_sub.next().await {
+                let block = match block {
+                    Ok(block) => block,
+                    Err(e) if is_reconnecting(&e) => {
+                        log::warn!(
+                            "Rpc connection lost, the finalized blocks subscription has ended"
+                        );
+                        break;
+                    }
+                    Err(e) => return Err(e.into()),
+                };
 
-            for event in events.iter() {
-                let event = event?;
+                // The reads of the block fail as well when the rpc connection drops. The block is then processed again, with the missed ones,
+                // once subscribed again, since `last_block` is only moved forward once a block is processed
+                let res: Result<()> = async {
+                    // The blocks finalized while the subscription was down
+                    if let Some(last) = last_block {
+                        for block_num in last + 1..block.number() {
+                            log::info!("Processing missed block: {:?}", block_num);
+                            let block_hash = api.block_hash(block_num).await?;
+                            let events = api
+                                .substrate_api
+                                .events()
+                                .at(block_hash)
+                                .await
+                                .map_err(TitanhError::from)?;
+                            self.process_events(&api, events).await?;
+                            last_block = Some(block_num);
+                        }
+                    }
 
-                let maybe_destroying_event = event.as_event::<CapsuleStartedDestroying>()?;
+                    log::info!("Processing block: {:?}", block.number());
+                    let events = block.events().await.map_err(TitanhError::from)?;
+                    self.process_events(&api, events).await?;
+                    last_block = Some(block.number());
 
-                if let Some(destroying_event) = maybe_destroying_event {
-                    log::info!("Recieved destroying event: {:?}", destroying_event);
+                    Ok(())
+                }
+                .await;
 
-                    let key = destroying_event.capsule_id;
-                    if self.is_key_in_range(&key) {
-                        log::info!("Destroying capsule with key: {:?}", key);
-                        destroy_capsule(&api, key).await?;
+                match res {
+                    Ok(()) => {}
+                    Err(e) if is_disconnected(&e) => {
+                        log::warn!(
+                            "Rpc connection lost while processing block {}, subscribing again",
+                            block.number()
+                        );
+                        break;
                     }
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+    }
+
+    async fn process_events(&self, api: &TitanhApi, events: BlockEvents) -> Result<()> {
+        for event in events.iter() {
+            let event = event?;
+
+            let maybe_destroying_event = event.as_event::<CapsuleStartedDestroying>()?;
+
+            if let Some(destroying_event) = maybe_destroying_event {
+                log::info!("Recieved destroying event: {:?}", destroying_event);
+
+                let key = destroying_event.capsule_id;
+                if self.is_key_in_range(&key) {
+                    log::info!("Destroying capsule with key: {:?}", key);
+                    destroy_capsule(api, key).await?;
                 }
             }
         }
@@ -84,6 +163,10 @@ pub async fn destroy_capsule(api: &TitanhApi, capsule_id: H256) -> Result<()> {
                 let res = api
                     .sign_and_submit_wait_finalized(&ownership_deletion)
                     .await;
+                if submit_interrupted(&res) {
+                    tokio::time::sleep(RESUBMIT_DELAY).await;
+                    continue;
+                }
 
                 if let Ok(events) = res {
                     let completition_event = events.find_first::<CapsuleItemsDeleted>()?.unwrap();
@@ -102,6 +185,10 @@ pub async fn destroy_capsule(api: &TitanhApi, capsule_id: H256) -> Result<()> {
                 let res = api
                     .sign_and_submit_wait_finalized(&followers_deletion)
                     .await;
+                if submit_interrupted(&res) {
+                    tokio::time::sleep(RESUBMIT_DELAY).await;
+                    continue;
+                }
 
                 if let Ok(events) = res {
                     let completition_event = events.find_first::<CapsuleItemsDeleted>()?.unwrap();
@@ -121,6 +208,10 @@ pub async fn destroy_capsule(api: &TitanhApi, capsule_id: H256) -> Result<()> {
                 let res = api
                     .sign_and_submit_wait_finalized(&container_keys_deletion)
                     .await;
+                if submit_interrupted(&res) {
+                    tokio::time::sleep(RESUBMIT_DELAY).await;
+                    continue;
+                }
 
                 if let Ok(events) = res {
                     let completition_event = events.find_first::<CapsuleItemsDeleted>()?.unwrap();
@@ -136,7 +227,11 @@ pub async fn destroy_capsule(api: &TitanhApi, capsule_id: H256) -> Result<()> {
             }
             GarbageCollectionPhase::FinishDestroy => {
                 let finish_destroy = capsules_tx.finish_destroy_capsule(capsule_id);
-                let _ = api.sign_and_submit_wait_finalized(&finish_destroy).await;
+                let res = api.sign_and_submit_wait_finalized(&finish_destroy).await;
+                if submit_interrupted(&res) {
+                    tokio::time::sleep(RESUBMIT_DELAY).await;
+                    continue;
+                }
 
                 garbage_phase = GarbageCollectionPhase::Exit;
             }
@@ -147,6 +242,19 @@ pub async fn destroy_capsule(api: &TitanhApi, capsule_id: H256) -> Result<()> {
     Ok(())
 }
 
+/// Returns whether the error is due to a dropped rpc connection, that is being re-established
+fn is_disconnected(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<TitanhError>()
+        .is_some_and(TitanhError::is_reconnecting)
+}
+
+/// Returns whether a garbage collection transaction failed because the rpc connection dropped, rather than on chain.
+/// It is then submitted again, instead of assuming that someone else has already garbage collected
+fn submit_interrupted<T>(res: &Result<T, TitanhError>) -> bool {
+    res.as_ref().is_err_and(TitanhError::is_reconnecting)
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum GarbageCollectionPhase {
     OwnershipApprovals,
@@ -8,10 +8,10 @@ mod utils;
 async fn main() -> Result<()> {
     env_logger::init();
 
-    let (collector_seed, rpc_url, key_range) = utils::load_env()?;
+    let (collector_seed, rpc_urls, key_range) = utils::load_env()?;
 
     // Create a new garbage collector consumer that consumes destroying events from the chain
-    let garbage_collector = GarbageCollectorConsumer::new(collector_seed, rpc_url, key_range);
+    let garbage_collector = GarbageCollectorConsumer::new(collector_seed, rpc_urls, key_range);
 
     // Start the garbage collector
     garbage_collector.start().await
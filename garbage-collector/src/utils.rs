@@ -1,11 +1,16 @@
-use std::str::FromStr;
+use std::{path::PathBuf, str::FromStr};
 
 use anyhow::Result;
+use keystore::{KeyType, Keystore};
 use sp_core::H256;
 
-pub fn load_env() -> Result<(String, String, Option<(H256, H256)>)> {
-    let collector_seed = std::env::var("COLLECTOR_SEED")?;
-    let rpc_url = std::env::var("RPC_URL")?;
+/// Loads the collector seed, the rpc urls and the key range. `RPC_URL` may list several comma separated urls, the next ones used as fallbacks
+pub fn load_env() -> Result<(String, Vec<String>, Option<(H256, H256)>)> {
+    let collector_seed = load_collector_suri()?;
+    let rpc_urls = std::env::var("RPC_URL")?
+        .split(',')
+        .map(|url| url.trim().to_string())
+        .collect();
     let key_range = std::env::var("KEY_RANGE")?;
 
     let key_range = if key_range.is_empty() {
@@ -17,5 +22,22 @@ pub fn load_env() -> Result<(String, String, Option<(H256, H256)>)> {
         Some((start, end))
     };
 
-    Ok((collector_seed, rpc_url, key_range))
+    Ok((collector_seed, rpc_urls, key_range))
+}
+
+/// Loads the secret URI of the collector account from the keystore (`COLLECTOR_KEYSTORE`), under the key name `COLLECTOR_KEY`.
+/// The keystore password is read from `COLLECTOR_PASSWORD_FILE` if set, otherwise from `TITANH_KEYSTORE_PASSWORD`
+fn load_collector_suri() -> Result<String> {
+    let keystore = Keystore::open(std::env::var("COLLECTOR_KEYSTORE")?)?;
+    let key_name = std::env::var("COLLECTOR_KEY")?;
+    let password_file = std::env::var("COLLECTOR_PASSWORD_FILE")
+        .ok()
+        .map(PathBuf::from);
+    let password = keystore::read_password(password_file.as_deref())?;
+
+    let suri = keystore
+        .load(&key_name, KeyType::Sr25519, &password)?
+        .suri()?;
+
+    Ok(suri.to_string())
 }
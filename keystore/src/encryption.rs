@@ -0,0 +1,172 @@
+use aes_gcm::{
+    aead::{Aead, KeyInit, Payload},
+    Aes256Gcm, Nonce,
+};
+use anyhow::Result;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+/// scrypt cost parameters of the new key files (N = 2^15, r = 8, p = 1)
+const SCRYPT_LOG_N: u8 = 15;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+/// The bounds of the scrypt parameters accepted from a key file, so that a crafted file cannot make the key
+/// derivation use gigabytes of memory or run for hours, nor weaken it below the usual interactive costs
+const SCRYPT_LOG_N_RANGE: std::ops::RangeInclusive<u8> = 10..=20;
+const SCRYPT_MAX_R: u32 = 32;
+const SCRYPT_MAX_P: u32 = 16;
+const SALT_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+const KEY_LEN: usize = 32;
+
+/// A secret encrypted with AES-256-GCM, under a key derived from a password with scrypt
+#[derive(Serialize, Deserialize, Default)]
+pub struct EncryptedSecret {
+    kdf: ScryptParams,
+    /// The hex-encoded nonce of the cipher
+    nonce: String,
+    /// The hex-encoded ciphertext, including the authentication tag
+    ciphertext: String,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct ScryptParams {
+    log_n: u8,
+    r: u32,
+    p: u32,
+    /// The hex-encoded salt
+    salt: String,
+}
+
+impl EncryptedSecret {
+    pub fn encrypt(secret: &[u8], password: &str, associated_data: &[u8]) -> Result<Self> {
+        let mut salt = [0u8; SALT_LEN];
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut salt);
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let kdf = ScryptParams {
+            log_n: SCRYPT_LOG_N,
+            r: SCRYPT_R,
+            p: SCRYPT_P,
+            salt: hex::encode(salt),
+        };
+        let cipher = kdf.cipher(password)?;
+        let payload = Payload {
+            msg: secret,
+            aad: associated_data,
+        };
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), payload)
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt the secret"))?;
+
+        Ok(Self {
+            kdf,
+            nonce: hex::encode(nonce),
+            ciphertext: hex::encode(ciphertext),
+        })
+    }
+
+    /// Decrypts the secret. It fails if the password is wrong or if the encrypted data or the associated data have been tampered with
+    pub fn decrypt(&self, password: &str, associated_data: &[u8]) -> Result<Zeroizing<Vec<u8>>> {
+        let nonce = hex::decode(&self.nonce)?;
+        if nonce.len() != NONCE_LEN {
+            return Err(anyhow::anyhow!("Invalid nonce length"));
+        }
+        let ciphertext = hex::decode(&self.ciphertext)?;
+
+        let cipher = self.kdf.cipher(password)?;
+        let payload = Payload {
+            msg: &ciphertext,
+            aad: associated_data,
+        };
+        let secret = cipher
+            .decrypt(Nonce::from_slice(&nonce), payload)
+            .map_err(|_| anyhow::anyhow!("Failed to decrypt the secret"))?;
+
+        Ok(Zeroizing::new(secret))
+    }
+}
+
+impl ScryptParams {
+    /// Derives the cipher key from the password
+    fn cipher(&self, password: &str) -> Result<Aes256Gcm> {
+        self.check()?;
+        let salt = hex::decode(&self.salt)?;
+        let params = scrypt::Params::new(self.log_n, self.r, self.p, KEY_LEN)
+            .map_err(|e| anyhow::anyhow!("Invalid scrypt parameters: {}", e))?;
+
+        let mut key = Zeroizing::new([0u8; KEY_LEN]);
+        scrypt::scrypt(password.as_bytes(), &salt, &params, key.as_mut())
+            .map_err(|e| anyhow::anyhow!("Failed to derive the key: {}", e))?;
+
+        Aes256Gcm::new_from_slice(key.as_ref())
+            .map_err(|_| anyhow::anyhow!("Invalid cipher key length"))
+    }
+
+    /// Checks that the parameters read from a key file are within the accepted bounds
+    fn check(&self) -> Result<()> {
+        if !SCRYPT_LOG_N_RANGE.contains(&self.log_n)
+            || !(1..=SCRYPT_MAX_R).contains(&self.r)
+            || !(1..=SCRYPT_MAX_P).contains(&self.p)
+        {
+            return Err(anyhow::anyhow!(
+                "Unsupported scrypt parameters log_n = {}, r = {}, p = {}",
+                self.log_n,
+                self.r,
+                self.p
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AAD: &[u8] = b"1:alice:sr25519:00";
+
+    #[test]
+    fn secret_round_trip_test() {
+        let encrypted = EncryptedSecret::encrypt(b"secret", "password", AAD).unwrap();
+
+        let secret = encrypted.decrypt("password", AAD).unwrap();
+        assert_eq!(secret.as_slice(), b"secret");
+    }
+
+    #[test]
+    fn wrong_password_fails_test() {
+        let encrypted = EncryptedSecret::encrypt(b"secret", "password", AAD).unwrap();
+
+        assert!(encrypted.decrypt("wrong", AAD).is_err());
+    }
+
+    #[test]
+    fn tampered_associated_data_fails_test() {
+        let encrypted = EncryptedSecret::encrypt(b"secret", "password", AAD).unwrap();
+
+        assert!(encrypted
+            .decrypt("password", b"1:alice:ed25519:00")
+            .is_err());
+    }
+
+    #[test]
+    fn out_of_bounds_scrypt_params_are_rejected_test() {
+        let mut encrypted = EncryptedSecret::encrypt(b"secret", "password", AAD).unwrap();
+        for (log_n, r, p) in [
+            (30, SCRYPT_R, SCRYPT_P),
+            (SCRYPT_LOG_N, 0, SCRYPT_P),
+            (SCRYPT_LOG_N, SCRYPT_R, 64),
+        ] {
+            encrypted.kdf.log_n = log_n;
+            encrypted.kdf.r = r;
+            encrypted.kdf.p = p;
+
+            let err = encrypted.decrypt("password", AAD).unwrap_err();
+            assert!(err.to_string().contains("Unsupported scrypt parameters"));
+        }
+    }
+}
@@ -0,0 +1,383 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use zeroize::Zeroizing;
+
+mod encryption;
+
+use encryption::EncryptedSecret;
+
+/// The environment variable from which the keystore password is read, when no password file is given
+pub const PASSWORD_ENV: &str = "TITANH_KEYSTORE_PASSWORD";
+/// The version of the key files written by the keystore
+const KEY_FILE_VERSION: u8 = 1;
+
+/// The type of a key stored in the keystore
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyType {
+    /// Validator (account) keys. The secret is the secret URI of the key (e.g. a seed phrase, with an optional derivation path)
+    Sr25519,
+    /// IPFS peer keys. The secret is the 32 bytes seed of the key
+    Ed25519,
+}
+
+impl fmt::Display for KeyType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyType::Sr25519 => write!(f, "sr25519"),
+            KeyType::Ed25519 => write!(f, "ed25519"),
+        }
+    }
+}
+
+impl FromStr for KeyType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sr25519" => Ok(KeyType::Sr25519),
+            "ed25519" => Ok(KeyType::Ed25519),
+            _ => Err(anyhow::anyhow!(
+                "Unknown key type {}, expected sr25519 or ed25519",
+                s
+            )),
+        }
+    }
+}
+
+/// A key file of the keystore. Only the secret is encrypted, the public key is readable to identify the key
+#[derive(Serialize, Deserialize)]
+struct KeyFile {
+    version: u8,
+    name: String,
+    key_type: KeyType,
+    /// The hex-encoded public key
+    public: String,
+    crypto: EncryptedSecret,
+}
+
+impl KeyFile {
+    /// The data authenticated along with the secret, so that the readable fields cannot be tampered with
+    fn associated_data(&self) -> Vec<u8> {
+        format!(
+            "{}:{}:{}:{}",
+            self.version, self.name, self.key_type, self.public
+        )
+        .into_bytes()
+    }
+}
+
+/// A key decrypted from the keystore. The secret is wiped from memory when dropped
+pub struct StoredKey {
+    pub name: String,
+    pub key_type: KeyType,
+    pub public: Vec<u8>,
+    secret: Zeroizing<Vec<u8>>,
+}
+
+impl StoredKey {
+    pub fn secret(&self) -> &[u8] {
+        &self.secret
+    }
+
+    /// Returns the secret URI of an sr25519 key
+    pub fn suri(&self) -> Result<Zeroizing<String>> {
+        if self.key_type != KeyType::Sr25519 {
+            return Err(anyhow::anyhow!("Key {} is not an sr25519 key", self.name));
+        }
+        let suri = std::str::from_utf8(&self.secret)
+            .map_err(|_| anyhow::anyhow!("Key {} has an invalid secret URI", self.name))?;
+
+        Ok(Zeroizing::new(suri.to_string()))
+    }
+
+    /// Returns the seed of an ed25519 key
+    pub fn seed(&self) -> Result<Zeroizing<Vec<u8>>> {
+        if self.key_type != KeyType::Ed25519 {
+            return Err(anyhow::anyhow!("Key {} is not an ed25519 key", self.name));
+        }
+
+        Ok(self.secret.clone())
+    }
+}
+
+/// The public information of a key of the keystore
+pub struct KeyInfo {
+    pub name: String,
+    pub key_type: KeyType,
+    pub public: Vec<u8>,
+}
+
+/// A directory of password-encrypted JSON key files (`<name>.json`).
+/// Secrets are encrypted with AES-256-GCM, using a key derived from the password with scrypt.
+pub struct Keystore {
+    dir: PathBuf,
+}
+
+impl Keystore {
+    /// Opens the keystore at `dir`, creating the directory if needed
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).map_err(|e| {
+            anyhow::anyhow!("Failed to create the keystore at {}: {}", dir.display(), e)
+        })?;
+        restrict_permissions(&dir, 0o700)?;
+
+        Ok(Self { dir })
+    }
+
+    /// Encrypts a key with the password and writes it to the keystore. An existing key with the same name is not overwritten
+    pub fn insert(
+        &self,
+        name: &str,
+        key_type: KeyType,
+        secret: &[u8],
+        public: &[u8],
+        password: &str,
+    ) -> Result<PathBuf> {
+        let path = self.key_path(name)?;
+        if path.exists() {
+            return Err(anyhow::anyhow!(
+                "Key {} already exists in the keystore",
+                name
+            ));
+        }
+
+        let mut key_file = KeyFile {
+            version: KEY_FILE_VERSION,
+            name: name.to_string(),
+            key_type,
+            public: hex::encode(public),
+            crypto: EncryptedSecret::default(),
+        };
+        key_file.crypto = EncryptedSecret::encrypt(secret, password, &key_file.associated_data())?;
+
+        write_key_file(&path, &serde_json::to_vec_pretty(&key_file)?)?;
+
+        Ok(path)
+    }
+
+    /// Reads and decrypts a key of the keystore, checking its type
+    pub fn load(&self, name: &str, key_type: KeyType, password: &str) -> Result<StoredKey> {
+        let key_file = self.read_key_file(name)?;
+        if key_file.key_type != key_type {
+            return Err(anyhow::anyhow!(
+                "Key {} is an {} key, expected an {} key",
+                name,
+                key_file.key_type,
+                key_type
+            ));
+        }
+
+        let secret = key_file
+            .crypto
+            .decrypt(password, &key_file.associated_data())
+            .map_err(|_| anyhow::anyhow!("Failed to decrypt key {}: wrong password", name))?;
+
+        Ok(StoredKey {
+            name: key_file.name,
+            key_type: key_file.key_type,
+            public: hex::decode(&key_file.public)?,
+            secret,
+        })
+    }
+
+    /// Lists the keys of the keystore, without decrypting them
+    pub fn list(&self) -> Result<Vec<KeyInfo>> {
+        let mut keys = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+            let key_file: KeyFile = serde_json::from_slice(&fs::read(&path)?)
+                .map_err(|e| anyhow::anyhow!("Invalid key file {}: {}", path.display(), e))?;
+
+            keys.push(KeyInfo {
+                name: key_file.name,
+                key_type: key_file.key_type,
+                public: hex::decode(&key_file.public)?,
+            });
+        }
+        keys.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(keys)
+    }
+
+    fn read_key_file(&self, name: &str) -> Result<KeyFile> {
+        let path = self.key_path(name)?;
+        let content = fs::read(&path).map_err(|e| {
+            anyhow::anyhow!("Failed to read key {} at {}: {}", name, path.display(), e)
+        })?;
+        let key_file: KeyFile = serde_json::from_slice(&content)
+            .map_err(|e| anyhow::anyhow!("Invalid key file {}: {}", path.display(), e))?;
+
+        if key_file.version != KEY_FILE_VERSION {
+            return Err(anyhow::anyhow!(
+                "Unsupported version {} of key file {}",
+                key_file.version,
+                path.display()
+            ));
+        }
+
+        Ok(key_file)
+    }
+
+    fn key_path(&self, name: &str) -> Result<PathBuf> {
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(anyhow::anyhow!(
+                "Invalid key name {}, only alphanumeric characters, '-' and '_' are allowed",
+                name
+            ));
+        }
+
+        Ok(self.dir.join(format!("{}.json", name)))
+    }
+}
+
+/// Reads the keystore password from `password_file` if given, otherwise from the `PASSWORD_ENV` environment variable, otherwise from the terminal
+pub fn read_password(password_file: Option<&Path>) -> Result<Zeroizing<String>> {
+    if let Some(path) = password_file {
+        let password = fs::read_to_string(path).map_err(|e| {
+            anyhow::anyhow!("Failed to read the password file {}: {}", path.display(), e)
+        })?;
+        return Ok(Zeroizing::new(
+            password.trim_end_matches(['\n', '\r']).to_string(),
+        ));
+    }
+
+    if let Ok(password) = std::env::var(PASSWORD_ENV) {
+        return Ok(Zeroizing::new(password));
+    }
+
+    let password = rpassword::prompt_password("Keystore password: ")
+        .map_err(|e| anyhow::anyhow!("Failed to read the keystore password: {}", e))?;
+
+    Ok(Zeroizing::new(password))
+}
+
+/// Writes a key file atomically: the content is written to a temporary file, readable only by the owner from its creation,
+/// then renamed, so that a crash never leaves a partial key file nor exposes the secret to other users
+fn write_key_file(path: &Path, content: &[u8]) -> Result<()> {
+    let tmp_path = path.with_extension("json.tmp");
+    // A file left by an interrupted write may have other permissions, so it is never reused
+    let _ = fs::remove_file(&tmp_path);
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let written = options.open(&tmp_path).and_then(|mut file| {
+        file.write_all(content)?;
+        file.sync_all()
+    });
+    if let Err(e) = written.and_then(|_| fs::rename(&tmp_path, path)) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(anyhow::anyhow!(
+            "Failed to write the key file {}: {}",
+            path.display(),
+            e
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn restrict_permissions(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn restrict_permissions(_path: &Path, _mode: u32) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Opens a keystore in a fresh directory of its own for each test
+    fn keystore(test: &str) -> Keystore {
+        let dir =
+            std::env::temp_dir().join(format!("titanh-keystore-{}-{}", std::process::id(), test));
+        let _ = fs::remove_dir_all(&dir);
+
+        Keystore::open(dir).unwrap()
+    }
+
+    #[test]
+    fn key_round_trip_test() {
+        let keystore = keystore("round-trip");
+        keystore
+            .insert("alice", KeyType::Sr25519, b"//Alice", &[1; 32], "password")
+            .unwrap();
+
+        let key = keystore
+            .load("alice", KeyType::Sr25519, "password")
+            .unwrap();
+        assert_eq!(key.suri().unwrap().as_str(), "//Alice");
+        assert_eq!(key.public, vec![1; 32]);
+    }
+
+    #[test]
+    fn wrong_password_fails_test() {
+        let keystore = keystore("wrong-password");
+        keystore
+            .insert("alice", KeyType::Sr25519, b"//Alice", &[1; 32], "password")
+            .unwrap();
+
+        let err = keystore
+            .load("alice", KeyType::Sr25519, "wrong")
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("wrong password"));
+    }
+
+    #[test]
+    fn existing_key_is_not_overwritten_test() {
+        let keystore = keystore("overwrite");
+        keystore
+            .insert("alice", KeyType::Sr25519, b"//Alice", &[1; 32], "password")
+            .unwrap();
+
+        assert!(keystore
+            .insert("alice", KeyType::Sr25519, b"//Bob", &[2; 32], "password")
+            .is_err());
+        let key = keystore
+            .load("alice", KeyType::Sr25519, "password")
+            .unwrap();
+        assert_eq!(key.suri().unwrap().as_str(), "//Alice");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn key_file_is_only_readable_by_the_owner_test() {
+        use std::os::unix::fs::PermissionsExt;
+        let keystore = keystore("permissions");
+        let path = keystore
+            .insert("ipfs-1", KeyType::Ed25519, &[3; 32], &[4; 32], "password")
+            .unwrap();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert!(!path.with_extension("json.tmp").exists());
+        assert_eq!(keystore.list().unwrap().len(), 1);
+    }
+}
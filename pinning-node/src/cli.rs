@@ -1,41 +1,48 @@
+use anyhow::Result;
 use clap::{Parser, Subcommand};
 
-use crate::utils::config::{Config, PeersConfig};
+use crate::utils::config_file::ConfigFile;
 
 #[derive(Parser)]
 #[command(name = "pinning-node")]
-#[command(about = "CLI to start a pinning node")]
+#[command(about = "CLI to start and control a pinning node")]
 pub struct Cli {
     #[command(subcommand)]
     command: Commands,
 }
 
+/// The command to execute, parsed from the CLI
+pub enum NodeCommand {
+    /// Runs the pinning node, eventually bootstrapping it from keytable snapshots
+    Run {
+        config: ConfigFile,
+        bootstrap_snapshots: Vec<String>,
+    },
+    /// Requests the running pinning node, whose local data is in the given node directory, to gracefully leave the committee
+    Leave(String),
+}
+
 impl Cli {
-    pub fn parse_config() -> Config {
+    pub fn parse_command() -> Result<NodeCommand> {
         let cli = Self::parse();
-        match cli.command {
-            Commands::Start {
-                seed,
-                rpc,
-                retries,
-                ipfs_peers_config,
-                rep_factor,
-                keytable_log,
-                latency,
-            } => {
-                let peers_config = PeersConfig::from_json(&ipfs_peers_config);
-
-                Config::new(
-                    seed,
-                    rpc,
-                    retries,
-                    peers_config.ipfs_peers,
-                    rep_factor,
-                    keytable_log,
-                    latency,
-                )
+        let command = match cli.command {
+            Commands::Start { config } => NodeCommand::Run {
+                config: ConfigFile::load(&config)?,
+                bootstrap_snapshots: Vec::new(),
+            },
+            Commands::Bootstrap {
+                from_snapshot,
+                config,
+            } => NodeCommand::Run {
+                config: ConfigFile::load(&config)?,
+                bootstrap_snapshots: from_snapshot,
+            },
+            Commands::Leave { config } => {
+                NodeCommand::Leave(ConfigFile::load(&config)?.node_dir()?)
             }
-        }
+        };
+
+        Ok(command)
     }
 }
 
@@ -43,26 +50,23 @@ impl Cli {
 enum Commands {
     /// Starts the pinning node
     Start {
-        #[arg(short, long)]
-        /// The seed phrase of the validator associated to the pinning node
-        seed: String,
-        #[arg(short, long)]
-        /// The endpoint of the chain rpc node
-        rpc: String,
-        /// The number of retries for a failed pinning operation
-        #[arg(short, long)]
-        retries: u8,
-        /// The path of the json file containing the ipfs peers bounded to the pinning node
-        #[arg(short, long)]
-        ipfs_peers_config: String,
-        /// The ring replication factor
-        #[arg(short, long)]
-        rep_factor: u32,
-        /// The optional path to the file where the node keytable will be logged
-        #[arg(short, long)]
-        keytable_log: bool,
-        /// Whether to track latency
-        #[arg(short, long)]
-        latency: bool,
+        /// The path of the node config file (TOML or JSON). Its values can be overridden by `PINNING_NODE_*` environment variables
+        #[arg(long)]
+        config: String,
+    },
+    /// Bootstraps a fresh pinning node from the keytable snapshots published by other nodes, then starts it
+    Bootstrap {
+        /// The CID of a signed keytable snapshot. The snapshots of the predecessor and of the successor of the node cover all of its keys
+        #[arg(long = "from-snapshot", required = true)]
+        from_snapshot: Vec<String>,
+        /// The path of the node config file (TOML or JSON)
+        #[arg(long)]
+        config: String,
+    },
+    /// Requests the running pinning node to gracefully leave the committee, through its local control endpoint
+    Leave {
+        /// The path of the node config file (TOML or JSON)
+        #[arg(long)]
+        config: String,
     },
 }
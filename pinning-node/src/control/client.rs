@@ -0,0 +1,40 @@
+use super::{control_socket, LEAVE_COMMAND, LEAVE_COMPLETED, LEAVE_ERROR_PREFIX};
+use anyhow::Result;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::UnixStream,
+};
+
+/// Requests a running node to gracefully leave the committee through its control endpoint, printing the progress of the procedure.
+/// The node does all the work, so its database is never accessed from here.
+pub async fn request_leave(node_dir: &str) -> Result<()> {
+    let socket_path = control_socket(node_dir);
+    let stream = UnixStream::connect(&socket_path).await.map_err(|e| {
+        anyhow::anyhow!(
+            "Failed to connect to the node control endpoint at {}: {}",
+            socket_path,
+            e
+        )
+    })?;
+
+    let (reader, mut writer) = stream.into_split();
+    writer
+        .write_all(format!("{}\n", LEAVE_COMMAND).as_bytes())
+        .await?;
+
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(status) = lines.next_line().await? {
+        if let Some(err) = status.strip_prefix(LEAVE_ERROR_PREFIX) {
+            return Err(anyhow::anyhow!("The node failed to leave: {}", err));
+        }
+        println!("{}", status);
+
+        if status == LEAVE_COMPLETED {
+            return Ok(());
+        }
+    }
+
+    Err(anyhow::anyhow!(
+        "The node closed the control connection before completing the leave procedure"
+    ))
+}
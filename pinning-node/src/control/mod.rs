@@ -0,0 +1,49 @@
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+
+/// The command sent to the control endpoint to request a graceful leave
+pub const LEAVE_COMMAND: &str = "leave";
+/// The last status reported by a successful leave procedure
+pub const LEAVE_COMPLETED: &str = "The node has left the pinning committee";
+/// The prefix of a status line reporting the failure of the leave procedure
+pub const LEAVE_ERROR_PREFIX: &str = "error: ";
+
+/// A request to gracefully leave the pinning committee
+pub struct LeaveRequest {
+    /// The channel where the progress of the leave procedure is reported to the requester (if any)
+    progress: Option<UnboundedSender<String>>,
+}
+
+impl LeaveRequest {
+    pub fn new(progress: Option<UnboundedSender<String>>) -> Self {
+        Self { progress }
+    }
+
+    /// Reports a status of the leave procedure
+    pub fn report(&self, status: &str) {
+        log::info!("{}", status);
+        if let Some(progress) = &self.progress {
+            // The requester may have disconnected, the procedure goes on anyway
+            let _ = progress.send(status.to_string());
+        }
+    }
+
+    /// Reports the failure of the leave procedure
+    pub fn report_error(&self, err: &anyhow::Error) {
+        log::error!("Leave procedure failed: {}", err);
+        if let Some(progress) = &self.progress {
+            let _ = progress.send(format!("{}{}", LEAVE_ERROR_PREFIX, err));
+        }
+    }
+}
+
+pub type LeaveRequests = UnboundedReceiver<LeaveRequest>;
+
+/// The path of the unix socket used as local control endpoint of the node
+pub fn control_socket(node_dir: &str) -> String {
+    format!("{}/control.sock", node_dir)
+}
+
+pub mod client;
+pub mod server;
+#[cfg(test)]
+mod tests;
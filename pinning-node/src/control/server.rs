@@ -0,0 +1,89 @@
+use super::{LeaveRequest, LeaveRequests, LEAVE_COMMAND, LEAVE_ERROR_PREFIX};
+use anyhow::Result;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    signal::unix::{signal, SignalKind},
+    sync::mpsc::{unbounded_channel, UnboundedSender},
+};
+
+/// Local control endpoint of the node.
+/// A leave request is received either through the unix socket or through the SIGUSR1 signal.
+pub struct ControlServer {
+    socket_path: String,
+}
+
+impl ControlServer {
+    pub fn new(socket_path: String) -> Self {
+        Self { socket_path }
+    }
+
+    /// Spawns the tasks that listen for control requests. Returns the receiving end of the leave requests
+    pub fn listen(self) -> Result<LeaveRequests> {
+        let (requests_tx, requests_rx) = unbounded_channel();
+
+        // Remove the socket left by a previous execution (if any)
+        let _ = std::fs::remove_file(&self.socket_path);
+        let listener = UnixListener::bind(&self.socket_path)?;
+        log::info!("Control endpoint listening at {}", self.socket_path);
+
+        let socket_requests = requests_tx.clone();
+        tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        let requests = socket_requests.clone();
+                        tokio::spawn(async move {
+                            if let Err(e) = handle_connection(stream, requests).await {
+                                log::error!("Control connection failed: {}", e);
+                            }
+                        });
+                    }
+                    Err(e) => log::error!("Failed to accept control connection: {}", e),
+                }
+            }
+        });
+
+        let mut leave_signal = signal(SignalKind::user_defined1())?;
+        tokio::spawn(async move {
+            while leave_signal.recv().await.is_some() {
+                log::info!("Received leave signal");
+                if requests_tx.send(LeaveRequest::new(None)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(requests_rx)
+    }
+}
+
+async fn handle_connection(
+    stream: UnixStream,
+    requests: UnboundedSender<LeaveRequest>,
+) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let command = BufReader::new(reader)
+        .lines()
+        .next_line()
+        .await?
+        .unwrap_or_default();
+
+    if command.trim() == LEAVE_COMMAND {
+        let (progress_tx, mut progress_rx) = unbounded_channel();
+        requests
+            .send(LeaveRequest::new(Some(progress_tx)))
+            .map_err(|_| anyhow::anyhow!("The node is not accepting leave requests"))?;
+
+        // Stream the progress until the leave procedure terminates
+        while let Some(status) = progress_rx.recv().await {
+            writer.write_all(format!("{}\n", status).as_bytes()).await?;
+        }
+    } else {
+        writer
+            .write_all(format!("{}Unknown command: {}\n", LEAVE_ERROR_PREFIX, command).as_bytes())
+            .await?;
+    }
+
+    Ok(())
+}
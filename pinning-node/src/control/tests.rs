@@ -0,0 +1,71 @@
+use super::{
+    client::request_leave, control_socket, server::ControlServer, LeaveRequests, LEAVE_COMPLETED,
+    LEAVE_ERROR_PREFIX,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::UnixStream,
+};
+
+/// Starts a control server in a fresh directory of its own, returning the directory and the leave requests it receives
+fn listen(test: &str) -> (String, LeaveRequests) {
+    let dir = std::env::temp_dir().join(format!("titanh-control-{}-{}", std::process::id(), test));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let node_dir = dir.to_str().unwrap().to_string();
+
+    let requests = ControlServer::new(control_socket(&node_dir))
+        .listen()
+        .unwrap();
+
+    (node_dir, requests)
+}
+
+#[tokio::test]
+async fn leave_request_reaches_the_node_test() {
+    let (node_dir, mut requests) = listen("leave");
+    let client = tokio::spawn(async move { request_leave(&node_dir).await });
+
+    // The node reports the progress of the procedure to the client
+    let request = requests.recv().await.unwrap();
+    request.report("Handing off the pinned contents");
+    request.report(LEAVE_COMPLETED);
+    drop(request);
+
+    assert!(client.await.unwrap().is_ok());
+}
+
+#[tokio::test]
+async fn failed_leave_is_reported_to_the_client_test() {
+    let (node_dir, mut requests) = listen("failed-leave");
+    let client = tokio::spawn(async move { request_leave(&node_dir).await });
+
+    let request = requests.recv().await.unwrap();
+    request.report_error(&anyhow::anyhow!("The node is not registered"));
+    drop(request);
+
+    let err = client.await.unwrap().unwrap_err();
+    assert!(err.to_string().contains("The node is not registered"));
+}
+
+#[tokio::test]
+async fn unknown_command_is_rejected_test() {
+    let (node_dir, mut requests) = listen("unknown-command");
+
+    let mut stream = UnixStream::connect(control_socket(&node_dir))
+        .await
+        .unwrap();
+    stream.write_all(b"join\n").await.unwrap();
+    let reply = BufReader::new(stream)
+        .lines()
+        .next_line()
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(
+        reply,
+        format!("{}Unknown command: join", LEAVE_ERROR_PREFIX)
+    );
+    assert!(requests.try_recv().is_err());
+}
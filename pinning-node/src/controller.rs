@@ -1,12 +1,20 @@
 use crate::{
-    cli::Cli,
-    db::checkpointing::DbCheckpoint,
-    events::{consumer::NodeConsumer, dispatcher::NodeEventDispatcher, producer::NodeProducer},
+    control::{self, server::ControlServer, LeaveRequests},
+    db::checkpointing::{DbCheckpoint, NodeState},
+    events::{
+        consumer::{ConsumerExit, NodeConsumer},
+        dispatcher::NodeEventDispatcher,
+        producer::NodeProducer,
+    },
+    http::{metrics::NodeMetrics, server::HttpServer},
     ipfs::client_builder::IpfsClientBuilder,
+    snapshot::{importer, publisher::SnapshotPublisher},
     substrate::client_builder::SubstrateClientBuilder,
     types::events_pool::NodeEventsPool,
+    utils::config::{Config, RetirementPolicy},
 };
 use anyhow::Result;
+use tokio::sync::mpsc;
 
 pub struct PinningNodeController {
     /// Node event producer.
@@ -14,16 +22,47 @@ pub struct PinningNodeController {
     producer: NodeProducer,
     /// Node event consumer. It consumes and dispatches events from the events pool (that abstracts away a channel).
     consumer: NodeConsumer,
+    /// Leave requests received by the local control endpoint
+    leave_requests: LeaveRequests,
+    /// What the node does once it has been removed from the ring
+    retirement: RetirementPolicy,
+    /// Whether the node has been removed from the ring and must complete its retirement
+    retiring: bool,
 }
 
 impl PinningNodeController {
-    pub async fn bootstrap() -> Result<Self> {
-        let config = Cli::parse_config();
+    pub async fn bootstrap(config: Config) -> Result<Self> {
+        // Node metrics, exposed by the optional HTTP server
+        let metrics = NodeMetrics::new()?.arc();
+        let (admin_tx, admin_requests) = mpsc::unbounded_channel();
+        if let Some(http_addr) = config.http_addr {
+            HttpServer::new(http_addr, metrics.clone(), admin_tx)
+                .spawn()
+                .await?;
+        }
+
         // Node checkpointing db
-        let db = DbCheckpoint::from_config(&config);
+        let db = DbCheckpoint::from_config(&config)?;
+        if !config.bootstrap_snapshots.is_empty() {
+            // Fast bootstrap from the keytable snapshots of other nodes
+            let height = importer::import_snapshots(&config, &db, metrics.clone()).await?;
+            log::info!(
+                "Node bootstrapped from snapshots at block number: {}",
+                height
+            );
+        }
         let checkpoint = db.get_checkpoint()?;
         // Block number until which the node has processed events and has an up to date keytable.
         log::info!("Checkpoint is at block number: {}", checkpoint.height());
+        // A node that has been removed from the ring can only complete its retirement
+        let node_state = db.read_node_state()?;
+        let retiring = node_state != NodeState::Active;
+        if retiring {
+            log::info!(
+                "Resuming the retirement of the node, in state {:?}",
+                node_state
+            );
+        }
 
         // Build the substrate client to read the blockchain related data
         let sub_client = SubstrateClientBuilder::from_config(&config, &db)
@@ -35,6 +74,12 @@ impl PinningNodeController {
             hex::encode(sub_client.node_id())
         );
         let ring = sub_client.ring().await;
+        if !retiring && ring.node(&sub_client.node_id()).is_err() {
+            return Err(anyhow::anyhow!(
+                "The node is not part of the pinning ring at block {}. If it has been removed, archive its checkpointing db and register it again",
+                ring.height()
+            ));
+        }
 
         let sub_client = sub_client.arc();
         let events_pool = NodeEventsPool::new().mutable_ref();
@@ -46,38 +91,81 @@ impl PinningNodeController {
             start_block_recovering,
             ring.height(),
             config.latency,
+            metrics.clone(),
         );
 
         // Build the IPFS client for ipfs related operations (e.g. pinning, unpinning, reading files)
-        let ipfs_client = IpfsClientBuilder::from_config(&config, checkpoint.pin_counts())
-            .build()
-            .await?;
+        let ipfs_client =
+            IpfsClientBuilder::from_config(&config, checkpoint.pin_counts(), metrics.clone())
+                .build()
+                .await?;
         log::info!(
             "IPFS client initialized successfully using replicas: {:?}",
             config.ipfs_peers
         );
+        // Optional publisher of keytable snapshots
+        let publisher = config
+            .snapshot_interval
+            .map(|interval| SnapshotPublisher::new(sub_client.clone(), interval));
         // Event dispatcher
         let dispatcher = NodeEventDispatcher::from_config(
             db,
             ipfs_client,
             sub_client,
+            publisher,
             ring,
             checkpoint.height(),
             checkpoint.keytable(),
+            metrics,
         );
-        let consumer = NodeConsumer::new(events_pool, dispatcher);
+        let consumer = NodeConsumer::new(events_pool, dispatcher, admin_requests);
+
+        // Local control endpoint, to request a graceful leave
+        let leave_requests =
+            ControlServer::new(control::control_socket(&config.node_dir())).listen()?;
 
-        Ok(Self { producer, consumer })
+        Ok(Self {
+            producer,
+            consumer,
+            leave_requests,
+            retirement: config.retirement,
+            retiring,
+        })
     }
 
     pub async fn execute(mut self) -> Result<()> {
+        if self.retiring {
+            return self.consumer.retire(&self.retirement).await;
+        }
+
         // Spawn the producer thread
         let producer_handle = self.producer.produce_events();
 
-        // Run the consumer task concurrently
-        self.consumer.consume_events().await?;
+        // Run the consumer task concurrently, until a leave request is received or the node is removed from the ring
+        let exit = self
+            .consumer
+            .consume_events(&mut self.leave_requests)
+            .await?;
+
+        match exit {
+            ConsumerExit::Leave(request) => {
+                // Stop producing events and drain the pool, so that the keytable is checkpointed at the last fully produced block
+                producer_handle.abort();
+                let _ = producer_handle.await;
+                self.consumer.drain_events().await?;
 
-        // Wait for the producer task to complete
-        producer_handle.await?
+                let res = self.consumer.leave(&request, &self.retirement).await;
+                if let Err(e) = &res {
+                    request.report_error(e);
+                }
+                res
+            }
+            ConsumerExit::Retired => {
+                producer_handle.abort();
+                self.consumer.retire(&self.retirement).await
+            }
+            // Wait for the producer task to complete
+            ConsumerExit::Closed => producer_handle.await?,
+        }
     }
 }
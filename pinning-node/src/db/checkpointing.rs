@@ -1,14 +1,28 @@
+use super::{
+    delta::{CheckpointDelta, FoldedDeltas},
+    migrations,
+    store::{CheckpointStore, DbBackend, StoreBatch},
+};
 use crate::{
     types::{
         cid::Cid,
-        keytable::{FaultTolerantKeyTable, TableRow},
+        keytable::{ColumnKey, FaultTolerantKeyTable, RowUpdate, TableRow},
     },
     utils::config::Config,
 };
 use anyhow::Result;
-use api::{common_types::BlockNumber, pinning_committee_types::NodeId};
+use api::common_types::BlockNumber;
 use codec::{Decode, Encode};
-use sled::{Batch as DbBatch, Db};
+use fs2::FileExt;
+use std::{
+    cell::Cell,
+    collections::{BTreeMap, HashMap},
+    fs::{self, File, OpenOptions},
+    path::Path,
+};
+
+/// The default number of checkpoints appended to the delta log before it is compacted
+pub const DEFAULT_COMPACTION_INTERVAL: u32 = 100;
 
 #[derive(Encode, Decode, Clone)]
 pub struct Checkpoint {
@@ -46,48 +60,146 @@ impl Checkpoint {
     }
 }
 
+/// The lifecycle state of the node, persisted so that a retirement is resumed after a crash
+#[derive(Encode, Decode, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeState {
+    /// The node is part of the ring and handles its keys
+    Active,
+    /// The node has been removed from the ring at block `at`, it no longer handles keys
+    Retired { at: BlockNumber },
+    /// The node has been removed from the ring at block `at` and its content has been unpinned
+    Unpinned { at: BlockNumber },
+}
+
 pub struct DbCheckpoint {
-    db: Db,
+    store: Box<dyn CheckpointStore>,
+    backend: DbBackend,
     rep_factor: u32,
     keytable_log: bool,
-    node_id: NodeId,
+    /// The directory where the node stores its local data
+    node_dir: String,
+    /// The number of checkpoints appended to the delta log before it is compacted into the rows
+    compaction_interval: u32,
+    /// The sequence number of the last checkpoint of the delta log
+    last_seq: Cell<u64>,
+    /// The number of checkpoints in the delta log
+    log_len: Cell<u32>,
+    /// The lock file of the db, exclusively locked as long as the db is open
+    _lock: Option<File>,
 }
 
 impl DbCheckpoint {
-    pub fn from_config(config: &Config) -> Self {
-        // Open database
-        let db = Self::open_db_from_node(config.node_id());
-        Self {
-            db,
-            rep_factor: config.rep_factor,
-            keytable_log: config.keytable_log,
-            node_id: config.node_id(),
-        }
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let db = Self::from_values(
+            config.rep_factor,
+            config.node_dir(),
+            config.keytable_log,
+            config.db_backend,
+        )?;
+
+        Ok(db.with_compaction_interval(config.compaction_interval))
     }
 
-    pub fn from_values(rep_factor: u32, node_id: NodeId, keytable_log: bool) -> Self {
+    /// Opens the db of the node whose local data is in `node_dir`, creating the directory if needed.
+    /// It fails if the db is already opened by another process.
+    pub fn from_values(
+        rep_factor: u32,
+        node_dir: String,
+        keytable_log: bool,
+        backend: DbBackend,
+    ) -> Result<Self> {
+        fs::create_dir_all(&node_dir).map_err(|e| {
+            anyhow::anyhow!("Failed to create the node directory {}: {}", node_dir, e)
+        })?;
+        let lock = Self::lock_db(&node_dir)?;
+
         // Open database
-        let db = Self::open_db_from_node(node_id);
-        Self {
-            db,
+        let store = backend.open(&node_dir)?;
+
+        Self::with_store(
+            store,
+            backend,
             rep_factor,
+            node_dir,
             keytable_log,
-            node_id,
-        }
+            Some(lock),
+        )
+    }
+
+    /// Opens the db in an in-memory store, without a node directory
+    #[cfg(test)]
+    pub(super) fn from_store(store: Box<dyn CheckpointStore>, rep_factor: u32) -> Result<Self> {
+        Self::with_store(
+            store,
+            DbBackend::Sled,
+            rep_factor,
+            String::new(),
+            false,
+            None,
+        )
+    }
+
+    fn with_store(
+        store: Box<dyn CheckpointStore>,
+        backend: DbBackend,
+        rep_factor: u32,
+        node_dir: String,
+        keytable_log: bool,
+        lock: Option<File>,
+    ) -> Result<Self> {
+        migrations::migrate(store.as_ref())?;
+
+        // Find the tail of the delta log
+        let mut last_seq = 0;
+        let mut log_len = 0;
+        store.scan_prefix(DELTA_PREFIX, &mut |key, _| {
+            last_seq = delta_seq(key)?;
+            log_len += 1;
+            Ok(())
+        })?;
+
+        Ok(Self {
+            store,
+            backend,
+            rep_factor,
+            keytable_log,
+            node_dir,
+            compaction_interval: DEFAULT_COMPACTION_INTERVAL,
+            last_seq: Cell::new(last_seq),
+            log_len: Cell::new(log_len),
+            _lock: lock,
+        })
     }
 
-    fn open_db_from_node(node_id: NodeId) -> Db {
-        let home = std::env::var("HOME").unwrap();
-        let node_id = hex::encode(&node_id.encode()[..=8]);
-        let db_name = format!("{}/node_{}/db", home, node_id);
-        sled::open(db_name).unwrap()
+    pub fn with_compaction_interval(mut self, compaction_interval: u32) -> Self {
+        self.compaction_interval = compaction_interval;
+        self
+    }
+
+    /// Takes the exclusive lock of the db. The lock is released when the file is closed, even if the process crashes
+    fn lock_db(node_dir: &str) -> Result<File> {
+        let lock_path = format!("{}/db.lock", node_dir);
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .map_err(|e| anyhow::anyhow!("Failed to open the db lock file {}: {}", lock_path, e))?;
+        lock.try_lock_exclusive().map_err(|_| {
+            anyhow::anyhow!(
+                "The checkpointing db in {} is used by another process",
+                node_dir
+            )
+        })?;
+
+        Ok(lock)
     }
 
     /// Retrieves some checkpoint value from the database.
-    fn read_checkpoint_value<D: Decode>(&self, key: &str) -> Result<Option<D>> {
+    fn read_checkpoint_value<D: Decode>(&self, key: impl AsRef<[u8]>) -> Result<Option<D>> {
         let checkpoint = self
-            .db
-            .get(key.as_bytes())
+            .store
+            .get(key.as_ref())
             .map_err(|_| anyhow::anyhow!("Failed to read checkpoint from db"))?;
 
         if let Some(checkpoint) = checkpoint {
@@ -100,25 +212,38 @@ impl DbCheckpoint {
         }
     }
 
-    /// Retrieves the checkpoint.
+    /// Retrieves the checkpoint, replaying the delta log on top of the compacted rows.
     pub fn get_checkpoint(&self) -> Result<Checkpoint> {
         // Build the keytable
-        let mut keytable =
-            FaultTolerantKeyTable::new(self.rep_factor, self.node_id, self.keytable_log);
-        let mut pin_counts = Vec::new();
-        for idx in 0..self.rep_factor {
-            let key = format!("partition_{}", idx);
-            let row = self.read_checkpoint_value::<TableRow>(&key)?;
-
-            if let Some(row) = row {
-                keytable.mutable_table().add_row(row.clone());
+        let out_file = self
+            .keytable_log
+            .then(|| format!("{}/keytable.log", self.node_dir));
+        let mut keytable = FaultTolerantKeyTable::new(self.rep_factor, out_file);
+        for idx in 0..self.rep_factor as usize {
+            let row = self.read_row(idx)?;
+            keytable.mutable_table().insert_row_at(idx, row)?;
+        }
 
-                // Read the pin counts
-                for cid in row.values() {
-                    let pin_count = self.read_cid_pin_count(cid)?;
-                    pin_counts.push((cid.clone(), pin_count));
-                }
+        // Replay the delta log
+        let mut logged_pins = HashMap::new();
+        self.store.scan_prefix(DELTA_PREFIX, &mut |_, value| {
+            let delta = CheckpointDelta::decode_from(value)?;
+            for row_delta in delta.rows {
+                row_delta.apply(keytable.mutable_table())?;
             }
+            logged_pins.extend(delta.pin_counts);
+
+            Ok(())
+        })?;
+
+        // Read the pin counts
+        let mut pin_counts = Vec::new();
+        for cid in keytable.values() {
+            let pin_count = match logged_pins.get(cid) {
+                Some(pin_count) => *pin_count,
+                None => self.read_cid_pin_count(cid)?,
+            };
+            pin_counts.push((cid.clone(), pin_count));
         }
 
         let block_num = self.read_blocknumber()?.unwrap_or_default();
@@ -126,21 +251,103 @@ impl DbCheckpoint {
         Ok(Checkpoint::new(block_num, keytable, pin_counts))
     }
 
-    /// Commits to storage the block number that the node has processed in terms of events and the affected rows in the keytable.
+    /// Reads a keytable row, stored as one entry per column key
+    fn read_row(&self, idx: usize) -> Result<TableRow> {
+        let prefix = row_prefix(idx);
+        let mut columns = BTreeMap::new();
+        self.store.scan_prefix(&prefix, &mut |key, value| {
+            let column_key = ColumnKey::decode(&mut &key[prefix.len()..])
+                .map_err(|_| anyhow::anyhow!("Failed to decode the column key of row {}", idx))?;
+            let cid = Cid::decode(&mut &value[..])
+                .map_err(|_| anyhow::anyhow!("Failed to decode the cid of row {}", idx))?;
+            columns.insert(column_key, cid);
+
+            Ok(())
+        })?;
+
+        columns
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Row {} exceeds the maximum number of columns", idx))
+    }
+
+    /// Commits to storage the block number that the node has processed in terms of events and the keys changed in the keytable.
     pub fn commit_checkpoint(
         &self,
         block_num: BlockNumber,
-        rows: Vec<&TableRow>,
+        rows: Vec<RowUpdate>,
+        pin_counts: Vec<(Cid, u32)>,
+    ) -> Result<()> {
+        self.append_delta(block_num, rows, pin_counts, None)
+    }
+
+    /// Commits to storage a checkpoint, together with a new state of the node.
+    pub fn commit_checkpoint_with_state(
+        &self,
+        block_num: BlockNumber,
+        rows: Vec<RowUpdate>,
         pin_counts: Vec<(Cid, u32)>,
+        state: NodeState,
     ) -> Result<()> {
-        let mut batch = DbBatch::default();
+        self.append_delta(block_num, rows, pin_counts, Some(state))
+    }
+
+    /// Appends the changes of a checkpoint to the delta log, compacting the log when it is due
+    fn append_delta(
+        &self,
+        block_num: BlockNumber,
+        rows: Vec<RowUpdate>,
+        pin_counts: Vec<(Cid, u32)>,
+        state: Option<NodeState>,
+    ) -> Result<()> {
+        let seq = self.last_seq.get() + 1;
+        let delta = CheckpointDelta::new(block_num, rows, pin_counts);
+
+        let mut batch = StoreBatch::default();
+        batch.insert(delta_key(seq), delta.encode());
         batch.insert(BLOCK_NUM_KEY, block_num.encode());
-        for (idx, row) in rows.iter().enumerate() {
-            let key = format!("partition_{}", idx);
-            batch.insert(key.as_bytes(), row.encode());
+        if let Some(state) = state {
+            batch.insert(NODE_STATE_KEY, state.encode());
+        }
+        self.store.apply_batch(batch)?;
+        self.last_seq.set(seq);
+        self.log_len.set(self.log_len.get() + 1);
+
+        if self.log_len.get() >= self.compaction_interval {
+            self.compact()?;
+        }
+
+        Ok(())
+    }
+
+    /// Folds the delta log into the rows and the pin counts, and truncates it.
+    /// Both happen in a single batch, so that a crash leaves either the whole log or the compacted rows.
+    pub fn compact(&self) -> Result<()> {
+        let mut folded = FoldedDeltas::default();
+        let mut batch = StoreBatch::default();
+        self.store.scan_prefix(DELTA_PREFIX, &mut |key, value| {
+            folded.fold(CheckpointDelta::decode_from(value)?);
+            batch.remove(key);
+            Ok(())
+        })?;
+
+        for (idx, row) in folded.rows {
+            let idx = idx as usize;
+            if row.replaced {
+                // The stored columns are replaced, the last write of a key wins within the batch
+                self.store.scan_prefix(&row_prefix(idx), &mut |key, _| {
+                    batch.remove(key);
+                    Ok(())
+                })?;
+            }
+            for (key, cid) in row.columns {
+                match cid {
+                    Some(cid) => batch.insert(row_key(idx, &key), cid.encode()),
+                    None => batch.remove(row_key(idx, &key)),
+                }
+            }
         }
 
-        for (cid, pin_count) in pin_counts {
+        for (cid, pin_count) in folded.pin_counts {
             if pin_count == 0 {
                 batch.remove(cid.as_ref());
             } else {
@@ -148,8 +355,36 @@ impl DbCheckpoint {
             }
         }
 
-        // Commit the batch
-        self.db.apply_batch(batch)?;
+        self.store.apply_batch(batch)?;
+        self.log_len.set(0);
+        log::info!("Checkpointing delta log compacted");
+
+        Ok(())
+    }
+
+    pub fn read_node_state(&self) -> Result<NodeState> {
+        let state = self.read_checkpoint_value::<NodeState>(NODE_STATE_KEY)?;
+
+        Ok(state.unwrap_or(NodeState::Active))
+    }
+
+    /// Archives the db of a retired node, or wipes it if `wipe` is set. A fresh db is then used if the node is started again.
+    pub fn archive(&self, at: BlockNumber, wipe: bool) -> Result<()> {
+        self.store.flush()?;
+
+        let db_path = self.backend.db_path(&self.node_dir);
+        if wipe {
+            if Path::new(&db_path).is_dir() {
+                fs::remove_dir_all(&db_path)?;
+            } else {
+                fs::remove_file(&db_path)?;
+            }
+            log::info!("Checkpointing db wiped");
+        } else {
+            let archive_path = format!("{}.retired-{}", db_path, at);
+            fs::rename(&db_path, &archive_path)?;
+            log::info!("Checkpointing db archived at {}", archive_path);
+        }
 
         Ok(())
     }
@@ -172,4 +407,36 @@ impl DbCheckpoint {
     }
 }
 
+/// The prefix of the columns of the row `idx`
+pub(super) fn row_prefix(idx: usize) -> Vec<u8> {
+    let mut prefix = ROW_PREFIX.to_vec();
+    prefix.extend_from_slice(&(idx as u32).to_be_bytes());
+    prefix
+}
+
+/// The key of a column of the row `idx`. The column keys of a row are ordered as in the row
+pub(super) fn row_key(idx: usize, column_key: &ColumnKey) -> Vec<u8> {
+    let mut key = row_prefix(idx);
+    key.extend_from_slice(&column_key.encode());
+    key
+}
+
+/// The key of the checkpoint `seq` of the delta log. The checkpoints are ordered by sequence number
+fn delta_key(seq: u64) -> Vec<u8> {
+    let mut key = DELTA_PREFIX.to_vec();
+    key.extend_from_slice(&seq.to_be_bytes());
+    key
+}
+
+fn delta_seq(key: &[u8]) -> Result<u64> {
+    let seq = key[DELTA_PREFIX.len()..]
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Invalid delta log key {:?}", key))?;
+
+    Ok(u64::from_be_bytes(seq))
+}
+
 const BLOCK_NUM_KEY: &str = "block_num";
+const NODE_STATE_KEY: &str = "node_state";
+const ROW_PREFIX: &[u8] = b"row/";
+const DELTA_PREFIX: &[u8] = b"delta/";
@@ -0,0 +1,144 @@
+use crate::types::{
+    cid::Cid,
+    keytable::{ColumnKey, KeyTable, RowUpdate, TableRow, MAX_COLUMNS},
+};
+use anyhow::Result;
+use api::common_types::BlockNumber;
+use codec::{Decode, Encode};
+use std::collections::{BTreeMap, HashMap};
+
+/// The changes of a keytable row at a checkpoint
+#[derive(Encode, Decode)]
+pub enum RowDelta {
+    /// The keys inserted into and removed from the row
+    Keys {
+        idx: u32,
+        inserted: Vec<(ColumnKey, Cid)>,
+        removed: Vec<ColumnKey>,
+    },
+    /// The whole row, replacing the previous one (e.g. after the rows have been shifted)
+    Full { idx: u32, row: TableRow },
+}
+
+impl RowDelta {
+    pub fn from_update(update: RowUpdate) -> Self {
+        let idx = update.idx as u32;
+        match update.keys {
+            Some(keys) => {
+                let mut inserted = Vec::new();
+                let mut removed = Vec::new();
+                for key in keys {
+                    match update.row.get(&key) {
+                        Some(cid) => inserted.push((key, cid.clone())),
+                        None => removed.push(key),
+                    }
+                }
+
+                RowDelta::Keys {
+                    idx,
+                    inserted,
+                    removed,
+                }
+            }
+            None => RowDelta::Full {
+                idx,
+                row: update.row.clone(),
+            },
+        }
+    }
+
+    /// Replays the changes on the table
+    pub fn apply(self, table: &mut KeyTable<ColumnKey, Cid, MAX_COLUMNS>) -> Result<()> {
+        match self {
+            RowDelta::Keys {
+                idx,
+                inserted,
+                removed,
+            } => {
+                for (key, cid) in inserted {
+                    table.insert(idx as usize, key, cid)?;
+                }
+                for key in removed.iter() {
+                    table.remove(idx as usize, key)?;
+                }
+            }
+            RowDelta::Full { idx, row } => table.insert_row_at(idx as usize, row)?,
+        }
+
+        Ok(())
+    }
+}
+
+/// A checkpoint appended to the delta log: the changes of the keytable and of the pin counts up to a block
+#[derive(Encode, Decode)]
+pub struct CheckpointDelta {
+    pub block_num: BlockNumber,
+    pub rows: Vec<RowDelta>,
+    pub pin_counts: Vec<(Cid, u32)>,
+}
+
+impl CheckpointDelta {
+    pub fn new(block_num: BlockNumber, rows: Vec<RowUpdate>, pin_counts: Vec<(Cid, u32)>) -> Self {
+        Self {
+            block_num,
+            rows: rows.into_iter().map(RowDelta::from_update).collect(),
+            pin_counts,
+        }
+    }
+
+    pub fn decode_from(value: &[u8]) -> Result<Self> {
+        Self::decode(&mut &value[..])
+            .map_err(|_| anyhow::anyhow!("Failed to decode a checkpoint of the delta log"))
+    }
+}
+
+/// The changes of a row accumulated over several checkpoints
+#[derive(Default)]
+pub struct FoldedRow {
+    /// Whether the stored row is replaced, rather than updated
+    pub replaced: bool,
+    /// The final value of each changed column, `None` if the column has been removed
+    pub columns: BTreeMap<ColumnKey, Option<Cid>>,
+}
+
+/// The checkpoints of the delta log folded together, so that each column and pin count is written once at compaction
+#[derive(Default)]
+pub struct FoldedDeltas {
+    pub rows: BTreeMap<u32, FoldedRow>,
+    pub pin_counts: HashMap<Cid, u32>,
+}
+
+impl FoldedDeltas {
+    /// Folds the next checkpoint of the log
+    pub fn fold(&mut self, delta: CheckpointDelta) {
+        for row_delta in delta.rows {
+            match row_delta {
+                RowDelta::Keys {
+                    idx,
+                    inserted,
+                    removed,
+                } => {
+                    let row = self.rows.entry(idx).or_default();
+                    for (key, cid) in inserted {
+                        row.columns.insert(key, Some(cid));
+                    }
+                    for key in removed {
+                        row.columns.insert(key, None);
+                    }
+                }
+                RowDelta::Full { idx, row } => {
+                    let columns = row.iter().map(|(key, cid)| (*key, Some(cid.clone())));
+                    self.rows.insert(
+                        idx,
+                        FoldedRow {
+                            replaced: true,
+                            columns: columns.collect(),
+                        },
+                    );
+                }
+            }
+        }
+
+        self.pin_counts.extend(delta.pin_counts);
+    }
+}
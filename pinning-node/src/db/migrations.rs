@@ -0,0 +1,116 @@
+use super::{
+    checkpointing::row_key,
+    store::{CheckpointStore, StoreBatch},
+};
+use crate::types::keytable::TableRow;
+use anyhow::Result;
+use codec::{Decode, Encode};
+
+/// The version of the checkpoint format written by the node
+pub const SCHEMA_VERSION: u32 = 3;
+
+/// A migration of the checkpoint format. It returns the batch that upgrades the db to the next version
+type Migration = fn(&dyn CheckpointStore) -> Result<StoreBatch>;
+
+/// The migrations of the checkpoint format, in order: `MIGRATIONS[n]` upgrades a db from version `n` to version `n + 1`.
+/// A change of the checkpoint format bumps `SCHEMA_VERSION` and appends its migration.
+const MIGRATIONS: [Migration; SCHEMA_VERSION as usize] = [unversioned_to_v1, v1_to_v2, v2_to_v3];
+
+/// Upgrades the db to the current checkpoint format. A fresh db is marked with the current version.
+pub fn migrate(store: &dyn CheckpointStore) -> Result<()> {
+    let mut version = match read_schema_version(store)? {
+        Some(version) => version,
+        // A db written before the schema versioning
+        None if !store.is_empty()? => 0,
+        None => {
+            let mut batch = StoreBatch::default();
+            batch.insert(SCHEMA_VERSION_KEY, SCHEMA_VERSION.encode());
+            store.apply_batch(batch)?;
+            store.flush()?;
+            return Ok(());
+        }
+    };
+
+    if version > SCHEMA_VERSION {
+        return Err(anyhow::anyhow!(
+            "The checkpointing db has schema version {}, but this node only supports up to version {}",
+            version,
+            SCHEMA_VERSION
+        ));
+    }
+
+    while version < SCHEMA_VERSION {
+        let migration = MIGRATIONS[version as usize];
+        let mut batch = migration(store)?;
+        // The version is bumped atomically with the migrated data, so that an interrupted migration is run again
+        batch.insert(SCHEMA_VERSION_KEY, (version + 1).encode());
+        store.apply_batch(batch)?;
+        store.flush()?;
+
+        version += 1;
+        log::info!("Checkpointing db migrated to schema version {}", version);
+    }
+
+    Ok(())
+}
+
+fn read_schema_version(store: &dyn CheckpointStore) -> Result<Option<u32>> {
+    let version = store
+        .get(SCHEMA_VERSION_KEY.as_bytes())
+        .map_err(|_| anyhow::anyhow!("Failed to read the schema version from db"))?;
+
+    version
+        .map(|version| {
+            u32::decode(&mut version.as_ref())
+                .map_err(|_| anyhow::anyhow!("Failed to decode the schema version"))
+        })
+        .transpose()
+}
+
+/// The db written before the schema versioning already uses the version 1 format, it only needs to be marked
+fn unversioned_to_v1(_store: &dyn CheckpointStore) -> Result<StoreBatch> {
+    Ok(StoreBatch::default())
+}
+
+/// Splits the rows, stored as a single blob under `partition_<n>`, into one entry per column key.
+/// The version 1 format loaded the non-empty partitions in order into consecutive rows, so they are migrated the same way.
+fn v1_to_v2(store: &dyn CheckpointStore) -> Result<StoreBatch> {
+    let mut partitions = Vec::new();
+    store.scan_prefix(LEGACY_PARTITION_PREFIX.as_bytes(), &mut |key, value| {
+        let suffix = &key[LEGACY_PARTITION_PREFIX.len()..];
+        let partition = std::str::from_utf8(suffix)
+            .ok()
+            .and_then(|partition| partition.parse::<u32>().ok())
+            .ok_or(anyhow::anyhow!("Invalid legacy partition key {:?}", key))?;
+        let row = TableRow::decode(&mut &value[..])
+            .map_err(|_| anyhow::anyhow!("Failed to decode legacy partition {}", partition))?;
+        partitions.push((partition, key.to_vec(), row));
+
+        Ok(())
+    })?;
+    // The keys are ordered bytewise, e.g. `partition_10` before `partition_2`
+    partitions.sort_by_key(|(partition, _, _)| *partition);
+
+    let mut batch = StoreBatch::default();
+    let mut row_idx = 0;
+    for (_, key, row) in partitions {
+        batch.remove(key);
+        if row.is_empty() {
+            continue;
+        }
+        for (column_key, cid) in row.iter() {
+            batch.insert(row_key(row_idx, column_key), cid.encode());
+        }
+        row_idx += 1;
+    }
+
+    Ok(batch)
+}
+
+/// The version 3 format adds the delta log of the checkpoints. A db without a log is already compacted
+fn v2_to_v3(_store: &dyn CheckpointStore) -> Result<StoreBatch> {
+    Ok(StoreBatch::default())
+}
+
+const SCHEMA_VERSION_KEY: &str = "schema_version";
+const LEGACY_PARTITION_PREFIX: &str = "partition_";
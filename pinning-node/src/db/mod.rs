@@ -1 +1,7 @@
 pub mod checkpointing;
+mod delta;
+mod migrations;
+pub mod store;
+
+#[cfg(test)]
+mod tests;
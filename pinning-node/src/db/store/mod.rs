@@ -0,0 +1,113 @@
+use anyhow::Result;
+use serde::Deserialize;
+use std::{collections::BTreeMap, fmt, path::Path, str::FromStr};
+
+pub mod redb_store;
+pub mod sled_store;
+
+/// The key-value storage of the checkpointing db.
+/// Keys are ordered bytewise, so that the entries sharing a prefix (e.g. the columns of a keytable row) can be scanned in order.
+pub trait CheckpointStore: Send + Sync {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>>;
+
+    /// Visits the entries whose key starts with `prefix`, ordered by key
+    fn scan_prefix(
+        &self,
+        prefix: &[u8],
+        visit: &mut dyn FnMut(&[u8], &[u8]) -> Result<()>,
+    ) -> Result<()>;
+
+    /// Applies all the writes of the batch atomically
+    fn apply_batch(&self, batch: StoreBatch) -> Result<()>;
+
+    fn is_empty(&self) -> Result<bool>;
+
+    /// Makes the applied batches durable
+    fn flush(&self) -> Result<()>;
+}
+
+/// A set of writes applied atomically to the store. When the same key is written more than once, the last write wins
+#[derive(Default)]
+pub struct StoreBatch(BTreeMap<Vec<u8>, Option<Vec<u8>>>);
+
+impl StoreBatch {
+    pub fn insert(&mut self, key: impl AsRef<[u8]>, value: Vec<u8>) {
+        self.0.insert(key.as_ref().to_vec(), Some(value));
+    }
+
+    pub fn remove(&mut self, key: impl AsRef<[u8]>) {
+        self.0.insert(key.as_ref().to_vec(), None);
+    }
+
+    /// Returns the writes of the batch, where a `None` value is a removal
+    pub fn into_writes(self) -> impl Iterator<Item = (Vec<u8>, Option<Vec<u8>>)> {
+        self.0.into_iter()
+    }
+}
+
+/// The storage engine of the checkpointing db
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DbBackend {
+    #[default]
+    Sled,
+    Redb,
+}
+
+impl DbBackend {
+    /// The path of the db in the node directory
+    pub fn db_path(&self, node_dir: &str) -> String {
+        match self {
+            DbBackend::Sled => format!("{}/db", node_dir),
+            DbBackend::Redb => format!("{}/db.redb", node_dir),
+        }
+    }
+
+    /// Opens the store in the node directory, creating it if needed.
+    /// It refuses to create a store when the node directory already holds a db of another backend, which would be silently ignored.
+    pub fn open(&self, node_dir: &str) -> Result<Box<dyn CheckpointStore>> {
+        let db_path = self.db_path(node_dir);
+        for other in [DbBackend::Sled, DbBackend::Redb] {
+            let other_path = other.db_path(node_dir);
+            if other != *self && !Path::new(&db_path).exists() && Path::new(&other_path).exists() {
+                return Err(anyhow::anyhow!(
+                    "The node directory {} holds a {} checkpointing db, but the {} backend is configured",
+                    node_dir,
+                    other,
+                    self
+                ));
+            }
+        }
+
+        let store: Box<dyn CheckpointStore> = match self {
+            DbBackend::Sled => Box::new(sled_store::SledStore::open(&db_path)?),
+            DbBackend::Redb => Box::new(redb_store::RedbStore::open(&db_path)?),
+        };
+
+        Ok(store)
+    }
+}
+
+impl fmt::Display for DbBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbBackend::Sled => write!(f, "sled"),
+            DbBackend::Redb => write!(f, "redb"),
+        }
+    }
+}
+
+impl FromStr for DbBackend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sled" => Ok(DbBackend::Sled),
+            "redb" => Ok(DbBackend::Redb),
+            _ => Err(anyhow::anyhow!(
+                "Unknown db backend {}, expected sled or redb",
+                s
+            )),
+        }
+    }
+}
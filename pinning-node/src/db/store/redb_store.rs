@@ -0,0 +1,86 @@
+use super::{CheckpointStore, StoreBatch};
+use anyhow::Result;
+use redb::{Database, ReadableTable, TableDefinition};
+
+/// The single table holding the checkpoint entries
+const CHECKPOINT_TABLE: TableDefinition<&[u8], &[u8]> = TableDefinition::new("checkpoint");
+
+/// A checkpoint store backed by redb. Every batch is a write transaction, durable once committed
+pub struct RedbStore {
+    db: Database,
+}
+
+impl RedbStore {
+    pub fn open(path: &str) -> Result<Self> {
+        let db = Database::create(path).map_err(|e| {
+            anyhow::anyhow!("Failed to open the checkpointing db at {}: {}", path, e)
+        })?;
+
+        // Create the table, so that it can always be opened by read transactions
+        let tx = db.begin_write()?;
+        tx.open_table(CHECKPOINT_TABLE)?;
+        tx.commit()?;
+
+        Ok(Self { db })
+    }
+}
+
+impl CheckpointStore for RedbStore {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let tx = self.db.begin_read()?;
+        let table = tx.open_table(CHECKPOINT_TABLE)?;
+        let value = table.get(key)?;
+
+        Ok(value.map(|value| value.value().to_vec()))
+    }
+
+    fn scan_prefix(
+        &self,
+        prefix: &[u8],
+        visit: &mut dyn FnMut(&[u8], &[u8]) -> Result<()>,
+    ) -> Result<()> {
+        let tx = self.db.begin_read()?;
+        let table = tx.open_table(CHECKPOINT_TABLE)?;
+        for entry in table.range(prefix..)? {
+            let (key, value) = entry?;
+            if !key.value().starts_with(prefix) {
+                break;
+            }
+            visit(key.value(), value.value())?;
+        }
+
+        Ok(())
+    }
+
+    fn apply_batch(&self, batch: StoreBatch) -> Result<()> {
+        let tx = self.db.begin_write()?;
+        {
+            let mut table = tx.open_table(CHECKPOINT_TABLE)?;
+            for (key, value) in batch.into_writes() {
+                match value {
+                    Some(value) => {
+                        table.insert(key.as_slice(), value.as_slice())?;
+                    }
+                    None => {
+                        table.remove(key.as_slice())?;
+                    }
+                }
+            }
+        }
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    fn is_empty(&self) -> Result<bool> {
+        let tx = self.db.begin_read()?;
+        let table = tx.open_table(CHECKPOINT_TABLE)?;
+
+        Ok(table.first()?.is_none())
+    }
+
+    fn flush(&self) -> Result<()> {
+        // Committed transactions are already durable
+        Ok(())
+    }
+}
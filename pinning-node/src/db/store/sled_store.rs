@@ -0,0 +1,62 @@
+use super::{CheckpointStore, StoreBatch};
+use anyhow::Result;
+use sled::{Batch as DbBatch, Db};
+
+/// A checkpoint store backed by sled
+pub struct SledStore {
+    db: Db,
+}
+
+impl SledStore {
+    pub fn open(path: &str) -> Result<Self> {
+        let db = sled::open(path).map_err(|e| {
+            anyhow::anyhow!("Failed to open the checkpointing db at {}: {}", path, e)
+        })?;
+
+        Ok(Self { db })
+    }
+}
+
+impl CheckpointStore for SledStore {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let value = self.db.get(key)?;
+
+        Ok(value.map(|value| value.to_vec()))
+    }
+
+    fn scan_prefix(
+        &self,
+        prefix: &[u8],
+        visit: &mut dyn FnMut(&[u8], &[u8]) -> Result<()>,
+    ) -> Result<()> {
+        for entry in self.db.scan_prefix(prefix) {
+            let (key, value) = entry?;
+            visit(&key, &value)?;
+        }
+
+        Ok(())
+    }
+
+    fn apply_batch(&self, batch: StoreBatch) -> Result<()> {
+        let mut db_batch = DbBatch::default();
+        for (key, value) in batch.into_writes() {
+            match value {
+                Some(value) => db_batch.insert(key, value),
+                None => db_batch.remove(key),
+            }
+        }
+        self.db.apply_batch(db_batch)?;
+
+        Ok(())
+    }
+
+    fn is_empty(&self) -> Result<bool> {
+        Ok(self.db.is_empty())
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.db.flush()?;
+
+        Ok(())
+    }
+}
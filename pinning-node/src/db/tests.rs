@@ -0,0 +1,297 @@
+use super::{
+    checkpointing::{row_key, DbCheckpoint},
+    migrations::{migrate, SCHEMA_VERSION},
+    store::{CheckpointStore, StoreBatch},
+};
+use crate::types::{
+    cid::Cid,
+    keytable::{ColumnKey, FaultTolerantKeyTable, TableRow},
+};
+use anyhow::Result;
+use api::cid_types::{self, Multihash};
+use codec::{Decode, Encode};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+const REP_FACTOR: u32 = 3;
+
+/// An in-memory store that survives the db, so that the db can be reopened after a simulated crash
+#[derive(Clone, Default)]
+struct MemoryStore {
+    entries: Arc<Mutex<BTreeMap<Vec<u8>, Vec<u8>>>>,
+    /// The number of batches applied before the node crashes, if any. The batches after the crash are lost
+    crash_after: Arc<Mutex<Option<usize>>>,
+}
+
+impl MemoryStore {
+    fn crash_after(&self, batches: usize) {
+        *self.crash_after.lock().unwrap() = Some(batches);
+    }
+
+    /// The store as found by the restarted node, as the crash left it
+    fn restart(&self) -> Self {
+        *self.crash_after.lock().unwrap() = None;
+        self.clone()
+    }
+}
+
+impl CheckpointStore for MemoryStore {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self.entries.lock().unwrap().get(key).cloned())
+    }
+
+    fn scan_prefix(
+        &self,
+        prefix: &[u8],
+        visit: &mut dyn FnMut(&[u8], &[u8]) -> Result<()>,
+    ) -> Result<()> {
+        let entries = self.entries.lock().unwrap();
+        for (key, value) in entries.range(prefix.to_vec()..) {
+            if !key.starts_with(prefix) {
+                break;
+            }
+            visit(key, value)?;
+        }
+
+        Ok(())
+    }
+
+    fn apply_batch(&self, batch: StoreBatch) -> Result<()> {
+        let mut crash_after = self.crash_after.lock().unwrap();
+        match *crash_after {
+            Some(0) => return Err(anyhow::anyhow!("The node has crashed")),
+            Some(batches) => *crash_after = Some(batches - 1),
+            None => {}
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        for (key, value) in batch.into_writes() {
+            match value {
+                Some(value) => entries.insert(key, value),
+                None => entries.remove(&key),
+            };
+        }
+
+        Ok(())
+    }
+
+    fn is_empty(&self) -> Result<bool> {
+        Ok(self.entries.lock().unwrap().is_empty())
+    }
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+fn open_db(store: &MemoryStore, compaction_interval: u32) -> DbCheckpoint {
+    DbCheckpoint::from_store(Box::new(store.restart()), REP_FACTOR)
+        .unwrap()
+        .with_compaction_interval(compaction_interval)
+}
+
+fn key(n: u8) -> ColumnKey {
+    ColumnKey::repeat_byte(n)
+}
+
+fn cid(n: u8) -> Cid {
+    // A CIDv1 of raw content, with a sha2-256 digest
+    let multihash = Multihash::<64>::wrap(0x12, &[n; 32]).unwrap();
+    let cid = cid_types::Cid::new_v1(0x55, multihash).to_string();
+    Cid::parse(cid.as_bytes()).unwrap()
+}
+
+fn rows(keytable: &FaultTolerantKeyTable) -> Vec<Vec<(ColumnKey, Cid)>> {
+    keytable
+        .rows()
+        .map(|row| row.iter().map(|(key, cid)| (*key, cid.clone())).collect())
+        .collect()
+}
+
+#[test]
+fn rows_are_checkpointed_at_their_index_test() {
+    let store = MemoryStore::default();
+    let db = open_db(&store, 1);
+    let mut keytable = db.get_checkpoint().unwrap().keytable();
+
+    // Only the last row changes, so it is the only flushed row
+    keytable.insert(2, key(1), cid(1)).unwrap();
+    db.commit_checkpoint(1, keytable.flush(), vec![(cid(1), 1)])
+        .unwrap();
+    drop(db);
+
+    let checkpoint = open_db(&store, 1).get_checkpoint().unwrap();
+    assert_eq!(
+        rows(&checkpoint.keytable()),
+        vec![vec![], vec![], vec![(key(1), cid(1))]]
+    );
+}
+
+#[test]
+fn recovery_replays_the_delta_log_test() {
+    let store = MemoryStore::default();
+    let db = open_db(&store, 100);
+    let mut keytable = db.get_checkpoint().unwrap().keytable();
+
+    keytable.insert(0, key(1), cid(1)).unwrap();
+    keytable.insert(1, key(2), cid(2)).unwrap();
+    db.commit_checkpoint(1, keytable.flush(), vec![(cid(1), 1), (cid(2), 1)])
+        .unwrap();
+    keytable.remove(0, &key(1)).unwrap();
+    keytable.insert(0, key(3), cid(2)).unwrap();
+    db.commit_checkpoint(2, keytable.flush(), vec![(cid(1), 0), (cid(2), 2)])
+        .unwrap();
+    drop(db);
+
+    let checkpoint = open_db(&store, 100).get_checkpoint().unwrap();
+    assert_eq!(checkpoint.height(), 2);
+    assert_eq!(checkpoint.pin_counts(), vec![(cid(2), 2), (cid(2), 2)]);
+    assert_eq!(rows(&checkpoint.keytable()), rows(&keytable));
+}
+
+#[test]
+fn compaction_preserves_the_checkpoint_test() {
+    let store = MemoryStore::default();
+    let db = open_db(&store, 2);
+    let mut keytable = db.get_checkpoint().unwrap().keytable();
+
+    for n in 1..=5 {
+        keytable.insert(0, key(n), cid(n)).unwrap();
+        if n > 1 {
+            keytable.remove(0, &key(n - 1)).unwrap();
+        }
+        db.commit_checkpoint(n.into(), keytable.flush(), vec![(cid(n), 1)])
+            .unwrap();
+    }
+    // The whole rows are rewritten when they are shifted
+    keytable.partition_row(0, &key(5)).unwrap();
+    db.commit_checkpoint(6, keytable.flush(), Vec::new())
+        .unwrap();
+    drop(db);
+
+    let checkpoint = open_db(&store, 2).get_checkpoint().unwrap();
+    assert_eq!(checkpoint.height(), 6);
+    assert_eq!(rows(&checkpoint.keytable()), rows(&keytable));
+}
+
+#[test]
+fn crash_before_the_barrier_recovers_the_last_checkpoint_test() {
+    let store = MemoryStore::default();
+    let db = open_db(&store, 100);
+    let mut keytable = db.get_checkpoint().unwrap().keytable();
+
+    keytable.insert(0, key(1), cid(1)).unwrap();
+    db.commit_checkpoint(1, keytable.flush(), vec![(cid(1), 1)])
+        .unwrap();
+    let checkpointed_rows = rows(&keytable);
+
+    // The events of block 2 are dispatched, but the node crashes while committing them
+    keytable.remove(0, &key(1)).unwrap();
+    keytable.insert(1, key(2), cid(2)).unwrap();
+    store.crash_after(0);
+    assert!(db
+        .commit_checkpoint(2, keytable.flush(), vec![(cid(1), 0), (cid(2), 1)])
+        .is_err());
+    drop(db);
+
+    let checkpoint = open_db(&store, 100).get_checkpoint().unwrap();
+    assert_eq!(checkpoint.height(), 1);
+    assert_eq!(checkpoint.pin_counts(), vec![(cid(1), 1)]);
+    assert_eq!(rows(&checkpoint.keytable()), checkpointed_rows);
+}
+
+#[test]
+fn crash_during_compaction_keeps_the_delta_log_test() {
+    let store = MemoryStore::default();
+    let db = open_db(&store, 2);
+    let mut keytable = db.get_checkpoint().unwrap().keytable();
+
+    keytable.insert(0, key(1), cid(1)).unwrap();
+    db.commit_checkpoint(1, keytable.flush(), vec![(cid(1), 1)])
+        .unwrap();
+
+    // The checkpoint of block 2 is appended, but the node crashes while compacting the log
+    keytable.insert(0, key(2), cid(2)).unwrap();
+    store.crash_after(1);
+    assert!(db
+        .commit_checkpoint(2, keytable.flush(), vec![(cid(2), 1)])
+        .is_err());
+    drop(db);
+
+    // The log is compacted at the next checkpoint
+    let db = open_db(&store, 2);
+    let mut recovered = db.get_checkpoint().unwrap();
+    assert_eq!(recovered.height(), 2);
+    assert_eq!(rows(&recovered.clone().keytable()), rows(&keytable));
+
+    keytable.remove(0, &key(1)).unwrap();
+    db.commit_checkpoint(3, keytable.flush(), vec![(cid(1), 0)])
+        .unwrap();
+    drop(db);
+
+    recovered = open_db(&store, 2).get_checkpoint().unwrap();
+    assert_eq!(recovered.height(), 3);
+    assert_eq!(recovered.pin_counts(), vec![(cid(2), 1)]);
+    assert_eq!(rows(&recovered.keytable()), rows(&keytable));
+}
+
+/// A row of the version 1 format, stored as a single blob
+fn legacy_row(entries: &[(u8, u8)]) -> Vec<u8> {
+    let row: BTreeMap<ColumnKey, Cid> = entries.iter().map(|(k, c)| (key(*k), cid(*c))).collect();
+    TableRow::try_from(row).unwrap().encode()
+}
+
+#[test]
+fn legacy_partitions_are_migrated_test() {
+    let store = MemoryStore::default();
+    let mut batch = StoreBatch::default();
+    batch.insert("partition_0", legacy_row(&[(1, 1)]));
+    batch.insert("partition_2", legacy_row(&[]));
+    batch.insert("partition_10", legacy_row(&[(2, 2), (3, 3)]));
+    store.apply_batch(batch).unwrap();
+
+    migrate(&store).unwrap();
+
+    // The empty partition is dropped and `partition_10` is loaded after `partition_0`
+    let entries = store.entries.lock().unwrap().clone();
+    let expected: BTreeMap<Vec<u8>, Vec<u8>> = [
+        (row_key(0, &key(1)), cid(1).encode()),
+        (row_key(1, &key(2)), cid(2).encode()),
+        (row_key(1, &key(3)), cid(3).encode()),
+        (b"schema_version".to_vec(), SCHEMA_VERSION.encode()),
+    ]
+    .into_iter()
+    .collect();
+    assert_eq!(entries, expected);
+    assert_eq!(
+        u32::decode(&mut &entries[b"schema_version".as_slice()][..]).unwrap(),
+        3
+    );
+
+    // The migrated rows are loaded by the db
+    let db = open_db(&store, 2);
+    assert_eq!(
+        rows(&db.get_checkpoint().unwrap().keytable())[..2],
+        vec![
+            vec![(key(1), cid(1))],
+            vec![(key(2), cid(2)), (key(3), cid(3))]
+        ]
+    );
+}
+
+#[test]
+fn newer_schema_version_is_refused_test() {
+    let store = MemoryStore::default();
+    let mut batch = StoreBatch::default();
+    batch.insert("schema_version", (SCHEMA_VERSION + 1).encode());
+    batch.insert("partition_0", legacy_row(&[(1, 1)]));
+    store.apply_batch(batch).unwrap();
+    let before = store.entries.lock().unwrap().clone();
+
+    assert!(migrate(&store).is_err());
+    assert!(DbCheckpoint::from_store(Box::new(store.clone()), REP_FACTOR).is_err());
+    // The db is left untouched
+    assert_eq!(*store.entries.lock().unwrap(), before);
+}
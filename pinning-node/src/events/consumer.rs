@@ -1,7 +1,9 @@
 use super::dispatcher::{traits::AsyncMutableDispatcher, NodeEventDispatcher};
 use crate::{
-    types::{batch::Batch, events_pool::NodeEventsPool},
-    utils::ref_builder::MutableRef,
+    control::{LeaveRequest, LeaveRequests},
+    http::admin::AdminRequests,
+    types::{batch::Batch, events::NodeEvent, events_pool::NodeEventsPool},
+    utils::{config::RetirementPolicy, ref_builder::MutableRef},
 };
 use anyhow::Result;
 use std::mem;
@@ -11,38 +13,117 @@ pub struct NodeConsumer {
     events_pool: MutableRef<NodeEventsPool>,
     /// Event dispatcher
     dispatcher: NodeEventDispatcher,
+    /// The batch of events of the block being consumed
+    consuming_batch: Batch<NodeEvent>,
+    /// Admin requests received by the HTTP server
+    admin_requests: AdminRequests,
+}
+
+/// The reason why the consumer has stopped consuming events
+pub enum ConsumerExit {
+    /// The events channel has been closed
+    Closed,
+    /// A leave request has been received
+    Leave(LeaveRequest),
+    /// The node has seen its own removal from the ring
+    Retired,
 }
 
 impl NodeConsumer {
-    pub fn new(events_pool: MutableRef<NodeEventsPool>, dispatcher: NodeEventDispatcher) -> Self {
+    pub fn new(
+        events_pool: MutableRef<NodeEventsPool>,
+        dispatcher: NodeEventDispatcher,
+        admin_requests: AdminRequests,
+    ) -> Self {
         Self {
             events_pool,
             dispatcher,
+            consuming_batch: Batch::default(),
+            admin_requests,
         }
     }
 
-    /// Consumes recieving events, first from the events `Vec` and then from the channel for new finalized events
-    pub async fn consume_events(mut self) -> Result<()> {
-        let mut events_pool = self.events_pool.borrow_mut();
+    /// Consumes recieving events, first from the events `Vec` and then from the channel for new finalized events.
+    /// Consuming stops when a leave request is received or when the node is removed from the ring. Admin requests are answered between two events.
+    pub async fn consume_events(
+        &mut self,
+        leave_requests: &mut LeaveRequests,
+    ) -> Result<ConsumerExit> {
+        let events_pool = self.events_pool.clone();
+        let mut events_pool = events_pool.borrow_mut();
 
         // Consume and dispatch upcoming events from the pool (aka channel), grouping them into batches.
-        let mut consuming_batch = Batch::default();
-        while let Some(event) = events_pool.read_handle().receive_events().await {
-            consuming_batch.insert(event.clone());
-            log::info!("Consuming event {:?}, added to current batch", event);
-
-            if let Some(block_num) = event.block_barrier_event() {
-                log::info!("Consuming batch identified by block number: {}", block_num);
-                // Dispatch the batch
-                let dispatchable_batch = mem::take(&mut consuming_batch);
-                self.dispatcher.async_dispatch(dispatchable_batch).await?;
-                log::info!(
-                    "Dispatching of batch with size: {} completed",
-                    consuming_batch.size()
-                );
+        loop {
+            let event = tokio::select! {
+                event = events_pool.read_handle().receive_events() => event,
+                Some(request) = leave_requests.recv() => return Ok(ConsumerExit::Leave(request)),
+                Some(request) = self.admin_requests.recv() => {
+                    self.dispatcher.admin(request);
+                    continue;
+                }
+            };
+
+            match event {
+                Some(event) => self.consume_event(event).await?,
+                None => return Ok(ConsumerExit::Closed),
+            }
+
+            if self.dispatcher.retired_at().is_some() {
+                return Ok(ConsumerExit::Retired);
+            }
+        }
+    }
+
+    /// Consumes the events left in the pool, once the producer has been stopped.
+    /// Events that are not followed by a block barrier are discarded, since their block cannot be checkpointed.
+    pub async fn drain_events(&mut self) -> Result<()> {
+        let events_pool = self.events_pool.clone();
+        let mut events_pool = events_pool.borrow_mut();
+
+        while let Some(event) = events_pool.read_handle().try_receive_event() {
+            self.consume_event(event).await?;
+
+            if self.dispatcher.retired_at().is_some() {
+                return Err(anyhow::anyhow!(
+                    "The node has been removed from the ring while draining its events"
+                ));
             }
         }
 
+        let discarded = mem::take(&mut self.consuming_batch);
+        if discarded.size() > 0 {
+            log::info!(
+                "Discarded {} events of a block that has not been fully produced",
+                discarded.size()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Gracefully leaves the pinning committee. The events pool must be drained first.
+    pub async fn leave(&mut self, request: &LeaveRequest, policy: &RetirementPolicy) -> Result<()> {
+        self.dispatcher.leave_committee(request, policy).await
+    }
+
+    /// Completes the retirement of a node removed from the ring
+    pub async fn retire(&mut self, policy: &RetirementPolicy) -> Result<()> {
+        self.dispatcher.retire(policy).await
+    }
+
+    async fn consume_event(&mut self, event: NodeEvent) -> Result<()> {
+        self.consuming_batch.insert(event.clone());
+        log::info!("Consuming event {:?}, added to current batch", event);
+
+        if let Some(block_num) = event.block_barrier_event() {
+            log::info!("Consuming batch identified by block number: {}", block_num);
+            // Dispatch the batch
+            let dispatchable_batch = mem::take(&mut self.consuming_batch);
+            let batch_size = dispatchable_batch.size();
+            self.dispatcher.async_dispatch(dispatchable_batch).await?;
+            log::info!("Dispatching of batch with size: {} completed", batch_size);
+        }
+
         Ok(())
     }
 }
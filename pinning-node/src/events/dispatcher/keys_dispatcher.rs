@@ -86,15 +86,20 @@ impl MutableDispatcher<JoinNodeEvent, Option<TableRow>> for KeysDispatcher {
     }
 }
 
-/// Dispatcher for processing a node leave event. It returns the IPFS cid that the node must fetch in order to get the row to update the keytable (if any).
+/// The effect of a node leave on the current node
+pub enum LeaveOutcome {
+    /// The node must fetch the row located by the IPFS cid, and dispatch the pinning events of the keys not transferred by the leaving node
+    FetchRow(Cid, Batch<PinningEvent>),
+    /// The leaving node is the current node
+    Retired,
+    /// The node is not affected by the leave
+    Unaffected,
+}
+
+/// Dispatcher for processing a node leave event. It returns how the node is affected by the leave, e.g. the IPFS cid that the node must fetch in order to get the row to update the keytable.
 #[async_trait(?Send)]
-impl<'a> AsyncMutableDispatcher<LeaveNodeEventAt, Option<(Cid, Batch<PinningEvent>)>>
-    for KeysDispatcher
-{
-    async fn async_dispatch(
-        &mut self,
-        leave_event_at: LeaveNodeEventAt,
-    ) -> Result<Option<(Cid, Batch<PinningEvent>)>> {
+impl<'a> AsyncMutableDispatcher<LeaveNodeEventAt, LeaveOutcome> for KeysDispatcher {
+    async fn async_dispatch(&mut self, leave_event_at: LeaveNodeEventAt) -> Result<LeaveOutcome> {
         let (leave_event, event_block_num, event_idx) = leave_event_at;
 
         let left_node = leave_event.node();
@@ -104,7 +109,8 @@ impl<'a> AsyncMutableDispatcher<LeaveNodeEventAt, Option<(Cid, Batch<PinningEven
         self.ring.remove_node(&left_node)?;
 
         if dist == 0 {
-            panic!("The current node is not expected to read a leave event of itself, remove the node checkpointing db and restart the node");
+            // The node has been removed from the ring, its keys are now handled by the successors
+            return Ok(LeaveOutcome::Retired);
         }
 
         if dist <= self.ring.replication() {
@@ -134,10 +140,10 @@ impl<'a> AsyncMutableDispatcher<LeaveNodeEventAt, Option<(Cid, Batch<PinningEven
             let pin_events: Vec<PinningEvent> = replay_batch.into_iter().map(|e| e.pin).collect();
             let pin_batch = Batch::from(pin_events);
 
-            return Ok(Some((cid, pin_batch)));
+            return Ok(LeaveOutcome::FetchRow(cid, pin_batch));
         }
 
-        Ok(None)
+        Ok(LeaveOutcome::Unaffected)
     }
 }
 
@@ -203,6 +209,10 @@ impl KeysDispatcher {
         &mut self.keytable
     }
 
+    pub fn ring(&self) -> &PinningRing {
+        &self.ring
+    }
+
     pub fn keytable(&self) -> &FaultTolerantKeyTable {
         &self.keytable
     }
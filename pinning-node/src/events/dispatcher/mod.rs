@@ -1,20 +1,29 @@
 use std::time::SystemTime;
 
 use crate::{
-    db::checkpointing::DbCheckpoint as DbDispatcher,
+    control::{LeaveRequest, LEAVE_COMPLETED},
+    db::checkpointing::{DbCheckpoint as DbDispatcher, NodeState},
+    http::{
+        admin::{AdminRequest, KeyEntry, KeyTableView, PinStatusView, RingView},
+        metrics::NodeMetrics,
+    },
     ipfs::client::IpfsClient as PinDispatcher,
+    snapshot::publisher::SnapshotPublisher,
     substrate::client::SubstrateClient,
     types::{
         batch::Batch,
         events::{CheckpointEvent, NodeEvent},
         keytable::FaultTolerantKeyTable,
     },
-    utils::ref_builder::AtomicRef,
+    utils::{config::RetirementPolicy, ref_builder::AtomicRef},
 };
 use anyhow::Result;
-use api::{common_types::BlockNumber, pinning_committee_types::PinningRing};
+use api::{
+    common_types::{BlockInfo, BlockNumber},
+    pinning_committee_types::{NodeId, PinningRing},
+};
 use async_trait::async_trait;
-use keys_dispatcher::KeysDispatcher;
+use keys_dispatcher::{KeysDispatcher, LeaveOutcome};
 use traits::{AsyncMutableDispatcher, Dispatcher, MutableDispatcher};
 
 /// Event dispatcher
@@ -25,10 +34,20 @@ pub struct NodeEventDispatcher {
     pinning: PinDispatcher,
     /// Dispatcher for capsule keys operations
     keys: KeysDispatcher,
+    /// Substrate client
+    client: AtomicRef<SubstrateClient>,
+    /// The optional publisher of keytable snapshots
+    publisher: Option<SnapshotPublisher>,
     /// The block number until which the node has checkpointed the processed events.
     block_num: BlockNumber,
     /// The entrance time of the current processing batch (optional)
     batch_entrance_time: Option<SystemTime>,
+    /// The block number at which the node has been removed from the ring (if any)
+    retired_at: Option<BlockNumber>,
+    /// The leaving nodes whose transferred rows are acknowledged once they are checkpointed
+    pending_acks: Vec<NodeId>,
+    /// Node metrics
+    metrics: AtomicRef<NodeMetrics>,
 }
 
 impl NodeEventDispatcher {
@@ -36,26 +55,273 @@ impl NodeEventDispatcher {
         db: DbDispatcher,
         pin: PinDispatcher,
         sub_client: AtomicRef<SubstrateClient>,
+        publisher: Option<SnapshotPublisher>,
         ring: PinningRing,
         block_num: BlockNumber,
         keytable: FaultTolerantKeyTable,
+        metrics: AtomicRef<NodeMetrics>,
     ) -> Self {
-        let keys: KeysDispatcher = KeysDispatcher::new(sub_client, ring, keytable);
+        let keys: KeysDispatcher = KeysDispatcher::new(sub_client.clone(), ring, keytable);
 
         Self {
             db,
             pinning: pin,
             keys,
+            client: sub_client,
+            publisher,
             block_num,
             batch_entrance_time: None,
+            retired_at: None,
+            pending_acks: Vec::new(),
+            metrics,
+        }
+    }
+
+    /// Acknowledges the handoffs of the rows checkpointed so far, so that the leaving nodes can drop their data.
+    /// A failed ack (e.g. when replaying an old removal) does not affect the node
+    async fn ack_handoffs(&mut self) {
+        for left_node in std::mem::take(&mut self.pending_acks) {
+            let ack = self
+                .client
+                .api()
+                .pinning_committee()
+                .ack_handoff(self.client.node_id(), left_node)
+                .await;
+            if let Err(e) = ack {
+                log::error!("Failed to acknowledge the handoff: {}", e);
+            }
+        }
+    }
+
+    /// Answers a read-only admin request with the current state of the node
+    pub fn admin(&self, request: AdminRequest) {
+        // A request whose requester has gone away is ignored
+        match request {
+            AdminRequest::KeyTable(reply) => {
+                let rows = self
+                    .keys
+                    .keytable()
+                    .rows()
+                    .map(|row| {
+                        row.iter()
+                            .map(|(key, cid)| KeyEntry {
+                                key: hex::encode(key),
+                                cid: cid.as_ref().to_string(),
+                            })
+                            .collect()
+                    })
+                    .collect();
+                let _ = reply.send(KeyTableView {
+                    block_num: self.block_num,
+                    rows,
+                });
+            }
+            AdminRequest::PinStatus(cid, reply) => {
+                let keys = self
+                    .keys
+                    .keytable()
+                    .rows()
+                    .flat_map(|row| row.iter())
+                    .filter(|(_, key_cid)| **key_cid == cid)
+                    .map(|(key, _)| hex::encode(key))
+                    .collect();
+                let _ = reply.send(PinStatusView {
+                    cid: cid.as_ref().to_string(),
+                    pin_count: self.pinning.pin_count(&cid),
+                    keys,
+                });
+            }
+            AdminRequest::Ring(reply) => {
+                let ring = self.keys.ring();
+                let node_id = self.client.node_id();
+                let nodes = (0..ring.len())
+                    .map(|idx| hex::encode(ring.get(idx)))
+                    .collect();
+                let _ = reply.send(RingView {
+                    node: hex::encode(node_id),
+                    position: ring.node(&node_id).ok(),
+                    replication_factor: ring.replication(),
+                    nodes,
+                });
+            }
         }
     }
+
+    /// The block number at which the node has seen its own removal from the ring (if any). A retired node does not dispatch events anymore.
+    pub fn retired_at(&self) -> Option<BlockNumber> {
+        self.retired_at
+    }
+
+    /// Completes the retirement of the node, after its removal from the ring. It resumes from the persisted node state, so that a crash in the middle of the retirement is recovered on restart.
+    pub async fn retire(&mut self, policy: &RetirementPolicy) -> Result<()> {
+        let at = match self.db.read_node_state()? {
+            NodeState::Retired { at } => {
+                if let Some(unpin_after) = policy.unpin_after {
+                    log::info!(
+                        "Waiting until block {} to unpin the content of the retired node",
+                        at + unpin_after
+                    );
+                    self.wait_finalized_block(at + unpin_after).await?;
+                    self.unpin_content(at).await?;
+                }
+                at
+            }
+            NodeState::Unpinned { at } => at,
+            NodeState::Active => {
+                return Err(anyhow::anyhow!(
+                    "Only a node removed from the ring can retire"
+                ));
+            }
+        };
+
+        self.db.archive(at, policy.wipe_db)?;
+        log::info!("The node has retired from the pinning committee");
+
+        Ok(())
+    }
+
+    /// Unpins the content of the keytable, committing the unpinned state of the node
+    async fn unpin_content(&mut self, at: BlockNumber) -> Result<()> {
+        for cid in self.keys.keytable().values() {
+            self.pinning.pin_remove(cid).await?;
+        }
+
+        self.db.commit_checkpoint_with_state(
+            self.block_num,
+            Vec::new(),
+            self.pinning.flush_pins(),
+            NodeState::Unpinned { at },
+        )?;
+
+        Ok(())
+    }
+
+    /// Waits until the block `block_num` is finalized
+    async fn wait_finalized_block(&self, block_num: BlockNumber) -> Result<()> {
+        let mut blocks_sub = self
+            .client
+            .api()
+            .substrate_api
+            .blocks()
+            .subscribe_finalized()
+            .await?;
+
+        while let Some(block) = blocks_sub.next().await {
+            if block?.number() >= block_num {
+                return Ok(());
+            }
+        }
+
+        Err(anyhow::anyhow!("Finalized blocks subscription has ended"))
+    }
+
+    /// Gracefully leaves the pinning committee, transferring the keytable checkpointed at `block_num`.
+    /// The content of the node is unpinned only after the successors have acknowledged the transfer, or the handoff has expired. Then, the db is archived as for a retired node.
+    pub async fn leave_committee(
+        &mut self,
+        request: &LeaveRequest,
+        policy: &RetirementPolicy,
+    ) -> Result<()> {
+        let block_num = self.block_num;
+
+        // The rows stay pinned until the successors have fetched them
+        request.report(&format!(
+            "Uploading the keytable rows checkpointed at block {}",
+            block_num
+        ));
+        let mut row_cids = Vec::new();
+        for row in self.keys.keytable().encoded_rows() {
+            row_cids.push(self.pinning.add(row).await?);
+        }
+
+        request.report("Submitting the leave transaction and waiting for finality");
+        let cids = row_cids
+            .iter()
+            .map(|cid| cid.as_ref().as_bytes().to_vec())
+            .collect();
+        let tx_hash = self
+            .client
+            .api()
+            .pinning_committee()
+            .remove_pinning_node(self.client.node_id(), cids, block_num)
+            .await?;
+        request.report(&format!("Leave transaction finalized: {:?}", tx_hash));
+
+        request.report("Waiting for the successors to acknowledge the transferred rows");
+        self.wait_handoff(request).await?;
+
+        request.report("Unpinning the content of the node");
+        for cid in row_cids.iter() {
+            self.pinning.remove(cid).await?;
+        }
+        self.unpin_content(block_num).await?;
+        self.db.archive(block_num, policy.wipe_db)?;
+
+        request.report(LEAVE_COMPLETED);
+
+        Ok(())
+    }
+
+    /// Waits, on finalized blocks, until the handoff of the node is no longer pending: either all the successors have acknowledged it, or it has expired
+    async fn wait_handoff(&self, request: &LeaveRequest) -> Result<()> {
+        let node_id = self.client.node_id();
+        let mut blocks_sub = self
+            .client
+            .api()
+            .substrate_api
+            .blocks()
+            .subscribe_finalized()
+            .await?;
+
+        // The last known pending handoff
+        let mut pending_handoff = None;
+        while let Some(block) = blocks_sub.next().await {
+            let block = block?;
+            let block = BlockInfo::new(block.number(), block.hash().into());
+
+            let handoff = self
+                .client
+                .api()
+                .pinning_committee()
+                .pending_handoff_at(node_id, block)
+                .await?;
+
+            match handoff {
+                Some(handoff) => {
+                    request.report(&format!(
+                        "Pending acknowledgements: {}, handoff deadline at block {}",
+                        handoff.pending.0.len(),
+                        handoff.deadline
+                    ));
+                    pending_handoff = Some(handoff);
+                }
+                None => {
+                    match pending_handoff {
+                        Some(handoff) if block.number >= handoff.deadline => {
+                            request.report(&format!(
+                                "Handoff expired without the acknowledgements of: {:?}",
+                                handoff.pending.0
+                            ));
+                        }
+                        _ => request.report("The successors have acknowledged the handoff"),
+                    }
+                    return Ok(());
+                }
+            }
+        }
+
+        Err(anyhow::anyhow!("Finalized blocks subscription has ended"))
+    }
 }
 
 #[async_trait(?Send)]
 impl AsyncMutableDispatcher<Batch<NodeEvent>, ()> for NodeEventDispatcher {
     async fn async_dispatch(&mut self, batch: Batch<NodeEvent>) -> Result<()> {
         for (idx, event) in batch.into_iter().enumerate() {
+            self.metrics
+                .events_processed
+                .with_label_values(&[event.kind()])
+                .inc();
             // Handle event
             match event {
                 // Pinning event
@@ -86,17 +352,43 @@ impl AsyncMutableDispatcher<Batch<NodeEvent>, ()> for NodeEventDispatcher {
                 // Node removal event
                 NodeEvent::NodeRemoval(leave_event) => {
                     log::info!("Dispatching node removal event {:?}", leave_event);
+                    let left_node = leave_event.node();
                     // (event, event_block_num, event_idx)
                     let leaved_event_at = (leave_event, self.block_num + 1, idx);
                     // Dispatch the leave event and get the CID that locates the row to be transferred
-                    let res = self.keys.async_dispatch(leaved_event_at).await?;
-                    if let Some((cid, batch)) = res {
+                    let outcome = self.keys.async_dispatch(leaved_event_at).await?;
+                    if let LeaveOutcome::Retired = outcome {
+                        // The node has been removed from the ring: it checkpoints the events dispatched so far in the block and stops handling keys
+                        let retired_at = self.block_num + 1;
+                        log::info!(
+                            "The node has been removed from the ring at block {}",
+                            retired_at
+                        );
+                        let rows = self.keys.mutable_keytable().flush();
+                        let pins = self.pinning.flush_pins();
+                        self.db.commit_checkpoint_with_state(
+                            retired_at,
+                            rows,
+                            pins,
+                            NodeState::Retired { at: retired_at },
+                        )?;
+                        self.block_num = retired_at;
+                        self.retired_at = Some(retired_at);
+                        self.ack_handoffs().await;
+
+                        return Ok(());
+                    }
+
+                    if let LeaveOutcome::FetchRow(cid, batch) = outcome {
                         println!("CID of row to recover: {:?}", cid);
                         let mut transferred_row = self.pinning.async_dispatch((cid, batch)).await?;
                         // Update the table with the row fetched from IPFS
                         self.keys
                             .mutable_keytable()
                             .extend_last_row(&mut transferred_row)?;
+
+                        // The transfer is acknowledged once the extended row is checkpointed: a node crashing before the checkpoint replays the removal
+                        self.pending_acks.push(left_node);
                     }
                     log::info!("Node removal event dispatched successfully");
                 }
@@ -111,17 +403,43 @@ impl AsyncMutableDispatcher<Batch<NodeEvent>, ()> for NodeEventDispatcher {
                     let checkpoint_event =
                         CheckpointEvent::new(block_num, flushing_rows, flushing_pins);
                     self.db.dispatch(checkpoint_event)?;
+                    self.ack_handoffs().await;
 
                     if let Some(batch_entrance_time) = self.batch_entrance_time {
                         // Log the latency of the batch (from entrance to exit)
                         let latency = batch_entrance_time.elapsed()?;
                         log::info!("Batch latency: {:?}", latency);
+                        self.metrics.batch_latency.observe(latency.as_secs_f64());
                     }
                     // update the block number
                     self.block_num = block_num;
+                    self.metrics.checkpoint_height.set(block_num as i64);
+                    for (row_idx, row) in self.keys.keytable().rows().enumerate() {
+                        self.metrics
+                            .keytable_row_size
+                            .with_label_values(&[&row_idx.to_string()])
+                            .set(row.len() as i64);
+                    }
 
                     // Log the keytable if needed
                     self.keys.keytable().log(block_num)?;
+
+                    // Publish a keytable snapshot if needed. A failed publication does not affect the node
+                    if let Some(publisher) = self.publisher.as_mut() {
+                        if publisher.should_publish(block_num) {
+                            let res = publisher
+                                .publish(&mut self.pinning, self.keys.keytable(), block_num)
+                                .await;
+                            match res {
+                                Ok(cid) => log::info!(
+                                    "Keytable snapshot at block {} published with CID: {:?}",
+                                    block_num,
+                                    cid
+                                ),
+                                Err(e) => log::error!("Failed to publish keytable snapshot: {}", e),
+                            }
+                        }
+                    }
                 }
                 NodeEvent::LatencyTracker(system_time) => {
                     self.batch_entrance_time = Some(system_time);
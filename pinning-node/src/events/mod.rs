@@ -1,3 +1,5 @@
 pub mod consumer;
 pub mod dispatcher;
 pub mod producer;
+#[cfg(test)]
+mod tests;
@@ -1,6 +1,7 @@
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 use crate::{
+    http::metrics::NodeMetrics,
     substrate::client::SubstrateClient,
     types::{channels::PoolWritingHandle, events::NodeEvent, events_pool::NodeEventsPool},
     utils::{
@@ -11,10 +12,15 @@ use crate::{
 use anyhow::Result;
 use api::{
     common_types::{BlockInfo, BlockNumber},
+    rpc::is_reconnecting,
     titanh::{self, runtime_types::pallet_capsules::capsule::types::Status},
+    TitanhError,
 };
 use tokio::task::JoinHandle;
 
+/// The delay before subscribing again to the finalized blocks, when the rpc connection is down
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(1);
+
 pub struct NodeProducer {
     /// Substrate client
     client: AtomicRef<SubstrateClient>,
@@ -26,6 +32,8 @@ pub struct NodeProducer {
     ring_height: BlockNumber,
     /// Wheter to track latency of events processing
     track_latency: bool,
+    /// Node metrics
+    metrics: AtomicRef<NodeMetrics>,
 }
 
 impl NodeProducer {
@@ -35,6 +43,7 @@ impl NodeProducer {
         start_block_recovering: BlockNumber,
         ring_height: BlockNumber,
         track_latency: bool,
+        metrics: AtomicRef<NodeMetrics>,
     ) -> Self {
         Self {
             client,
@@ -42,6 +51,7 @@ impl NodeProducer {
             start_block_recovering,
             ring_height,
             track_latency,
+            metrics,
         }
     }
 
@@ -57,60 +67,122 @@ impl NodeProducer {
         let ring_height = self.ring_height;
 
         let track_latency = self.track_latency;
+        let metrics = self.metrics.clone();
         // Spawn a new task
         tokio::spawn(async move {
-            // Subscribe to finalized blocks to get events in real-time
-            let mut blocks_sub = client
-                .api()
-                .substrate_api
-                .blocks()
-                .subscribe_finalized()
-                .await?;
-
             let mut has_recovered = false;
-            while let Some(block) = blocks_sub.next().await {
-                let block = block?;
-                let block_num = block.number();
-
-                if !has_recovered {
-                    // Before processing new events of finalized blocks, we must recover the events.
-
-                    log::info!(
-                        "Starting to recover events from {} to {}",
-                        start_block_recovering,
-                        block_num.saturating_sub(1)
-                    );
-                    produce_recover_events(
-                        &client,
-                        &mut pool_write_handle,
-                        start_block_recovering,
-                        block_num,
-                        ring_height,
-                    )
-                    .await?;
-                    log::info!("Recover events produced successfully");
-
-                    has_recovered = true;
-                }
+            // The last finalized block whose events have been produced
+            let mut last_block: Option<BlockNumber> = None;
 
-                let block = BlockInfo::new(block_num, block.hash().into());
-                let events = client.events_at(block).await?;
-                if track_latency {
-                    let now = SystemTime::now();
-                    pool_write_handle.send_event(NodeEvent::LatencyTracker(now))?;
-                }
-                for event in events {
-                    // Send the new events to the channel for processing.
-                    pool_write_handle.send_event(event.clone())?;
-                    log::info!("Produced new event: {:?}", event);
+            loop {
+                // Subscribe to finalized blocks to get events in real-time. The subscription ends when the rpc connection drops, so it is opened again
+                let mut blocks_sub = match client
+                    .api()
+                    .substrate_api
+                    .blocks()
+                    .subscribe_finalized()
+                    .await
+                {
+                    Ok(blocks_sub) => blocks_sub,
+                    Err(e) if is_reconnecting(&e) => {
+                        log::warn!("Rpc connection lost, subscribing again to finalized blocks");
+                        tokio::time::sleep(RESUBSCRIBE_DELAY).await;
+                        continue;
+                    }
+                    Err(e) => return Err(e.into()),
+                };
+
+                while let Some(block) = blocks_sub.next().await {
+                    let block = match block {
+                        Ok(block) => block,
+                        Err(e) if is_reconnecting(&e) => {
+                            log::warn!(
+                                "Rpc connection lost, the finalized blocks subscription has ended"
+                            );
+                            break;
+                        }
+                        Err(e) => return Err(e.into()),
+                    };
+                    let block_num = block.number();
+                    metrics.finalized_height.set(block_num as i64);
+
+                    // The reads of the block fail as well when the rpc connection drops. The block is then read again, with the missed ones,
+                    // once subscribed again, since `last_block` is only moved forward once its events are produced
+                    let res: Result<()> = async {
+                        if !has_recovered {
+                            // Before processing new events of finalized blocks, we must recover the events.
+
+                            log::info!(
+                                "Starting to recover events from {} to {}",
+                                start_block_recovering,
+                                block_num.saturating_sub(1)
+                            );
+                            produce_recover_events(
+                                &client,
+                                &mut pool_write_handle,
+                                start_block_recovering,
+                                block_num,
+                                ring_height,
+                            )
+                            .await?;
+                            log::info!("Recover events produced successfully");
+
+                            has_recovered = true;
+                            last_block = Some(block_num.saturating_sub(1));
+                        } else if let Some(last) = last_block {
+                            // The blocks finalized while the subscription was down
+                            if block_num > last + 1 {
+                                let missed_events =
+                                    client.events_in_range(last + 1, block_num - 1).await?;
+                                for event in missed_events {
+                                    pool_write_handle.send_event(event.clone())?;
+                                    log::info!("Produced a missed event: {:?}", event);
+                                }
+                                last_block = Some(block_num - 1);
+                            }
+                        }
+
+                        let block = BlockInfo::new(block_num, block.hash().into());
+                        let events = client.events_at(block).await?;
+                        if track_latency {
+                            let now = SystemTime::now();
+                            pool_write_handle.send_event(NodeEvent::LatencyTracker(now))?;
+                        }
+                        for event in events {
+                            // Send the new events to the channel for processing.
+                            pool_write_handle.send_event(event.clone())?;
+                            log::info!("Produced new event: {:?}", event);
+                        }
+                        last_block = Some(block_num);
+
+                        Ok(())
+                    }
+                    .await;
+
+                    match res {
+                        Ok(()) => {}
+                        Err(e) if is_disconnected(&e) => {
+                            log::warn!(
+                                "Rpc connection lost while reading block {}, subscribing again",
+                                block_num
+                            );
+                            break;
+                        }
+                        Err(e) => return Err(e),
+                    }
                 }
             }
-
-            unreachable!("Unexpected chain behavior: block finalization has stopped. The chain is expected to continuously finalize blocks.");
         })
     }
 }
 
+/// Returns whether the error is due to a dropped rpc connection, that is being re-established
+fn is_disconnected(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<TitanhError>()
+        .is_some_and(TitanhError::is_reconnecting)
+}
+
 /// Produce events to recover.
 // Events can be recovered due to 2 possible scenarios:
 // 1. The node has just started and must recover all capsules for producing pinning events.
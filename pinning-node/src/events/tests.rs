@@ -0,0 +1,187 @@
+use super::{
+    consumer::{ConsumerExit, NodeConsumer},
+    dispatcher::NodeEventDispatcher,
+};
+use crate::{
+    db::{
+        checkpointing::{DbCheckpoint, NodeState},
+        store::DbBackend,
+    },
+    http::metrics::NodeMetrics,
+    ipfs::client::IpfsClient,
+    substrate::client::SubstrateClient,
+    types::{
+        events::{try_event_from_runtime, NodeEvent},
+        events_pool::NodeEventsPool,
+        keytable::FaultTolerantKeyTable,
+    },
+    utils::config::RetirementPolicy,
+};
+use api::{
+    common_types::{BlockHash, BlockInfo, BlockNumber, Rpc, SubstrateApi},
+    pinning_committee_types::{NodeId, PinningRing},
+    titanh::{
+        pinning_committee::Event as PinningCommitteeEvent,
+        runtime_types::{
+            pallet_pinning_committee::types::KeyTableAt, titanh_runtime::RuntimeEvent,
+        },
+    },
+    TitanhApi,
+};
+use codec::Decode;
+use sp_core::H256;
+use std::path::Path;
+use subxt::{
+    backend::rpc::{RawRpcFuture, RawRpcSubscription, RawValue, RpcClient, RpcClientT},
+    client::RuntimeVersion,
+    error::RpcError,
+    utils::AccountId32,
+    Metadata,
+};
+use tokio::sync::mpsc::unbounded_channel;
+
+const REP_FACTOR: u32 = 3;
+
+/// An rpc client without a chain node behind it: the requests of the node under test are rejected
+struct NoNode;
+
+impl RpcClientT for NoNode {
+    fn request_raw<'a>(
+        &'a self,
+        method: &'a str,
+        _params: Option<Box<RawValue>>,
+    ) -> RawRpcFuture<'a, Box<RawValue>> {
+        Box::pin(async move {
+            Err(RpcError::RequestRejected(format!(
+                "No node to answer {}",
+                method
+            )))
+        })
+    }
+
+    fn subscribe_raw<'a>(
+        &'a self,
+        sub: &'a str,
+        _params: Option<Box<RawValue>>,
+        _unsub: &'a str,
+    ) -> RawRpcFuture<'a, RawRpcSubscription> {
+        Box::pin(async move {
+            Err(RpcError::RequestRejected(format!(
+                "No node to answer {}",
+                sub
+            )))
+        })
+    }
+}
+
+fn node(n: u8) -> NodeId {
+    H256::repeat_byte(n)
+}
+
+fn block(number: BlockNumber) -> BlockInfo {
+    BlockInfo::new(number, BlockHash(H256::repeat_byte(number as u8)))
+}
+
+/// A client of the node `node_id`, that is not connected to a chain node
+async fn client(node_id: NodeId) -> SubstrateClient {
+    let bytes = include_bytes!("../../../api/chain-metadata.scale");
+    let metadata = Metadata::decode(&mut &bytes[..]).unwrap();
+    let rpc_client = RpcClient::new(NoNode);
+    let runtime_version = RuntimeVersion {
+        spec_version: 100,
+        transaction_version: 1,
+    };
+    let substrate_api = SubstrateApi::from_rpc_client_with(
+        H256::zero(),
+        runtime_version,
+        metadata,
+        rpc_client.clone(),
+    )
+    .unwrap();
+    let api = TitanhApi::new(substrate_api, Rpc::new(rpc_client), None, None, None)
+        .await
+        .unwrap();
+
+    SubstrateClient::new(api, node_id, block(0))
+}
+
+/// Opens the db of a node in a fresh directory of its own
+fn open_db(test: &str) -> (String, DbCheckpoint) {
+    let node_dir = std::env::temp_dir()
+        .join(format!("titanh-events-{}-{}", std::process::id(), test))
+        .to_str()
+        .unwrap()
+        .to_string();
+    let _ = std::fs::remove_dir_all(&node_dir);
+    let db =
+        DbCheckpoint::from_values(REP_FACTOR, node_dir.clone(), false, DbBackend::Sled).unwrap();
+
+    (node_dir, db)
+}
+
+/// The event of the removal of `pinning_node` from the ring, as deposited by the pinning committee pallet
+fn removal(pinning_node: NodeId, block_num: BlockNumber) -> NodeEvent {
+    let event = RuntimeEvent::PinningCommittee(PinningCommitteeEvent::PinningNodeRemoval {
+        validator: AccountId32([1; 32]),
+        pinning_node,
+        key_table: KeyTableAt {
+            block_num,
+            cids: Vec::new(),
+        },
+    });
+
+    try_event_from_runtime(event).unwrap()
+}
+
+#[tokio::test]
+async fn own_removal_retires_the_node_test() {
+    let own_id = node(2);
+    let (node_dir, db) = open_db("own-removal");
+    let metrics = NodeMetrics::new().unwrap().arc();
+    let ring = PinningRing::new(
+        vec![node(1), own_id, node(3), node(4)],
+        REP_FACTOR,
+        block(0),
+    );
+    let dispatcher = NodeEventDispatcher::from_config(
+        db,
+        IpfsClient::new(Vec::new(), Vec::new(), 3, Vec::new(), metrics.clone()),
+        client(own_id).await.arc(),
+        None,
+        ring,
+        4,
+        FaultTolerantKeyTable::new(REP_FACTOR, None),
+        metrics,
+    );
+
+    let events_pool = NodeEventsPool::new();
+    let mut events = events_pool.write_handle();
+    let (_admin_tx, admin_requests) = unbounded_channel();
+    let mut consumer = NodeConsumer::new(events_pool.mutable_ref(), dispatcher, admin_requests);
+
+    // The node sees its own removal in block 5
+    events.send_event(removal(own_id, 4)).unwrap();
+    events.send_event(NodeEvent::BlockBarrier(5)).unwrap();
+    let (_leave_tx, mut leave_requests) = unbounded_channel();
+    let exit = consumer.consume_events(&mut leave_requests).await.unwrap();
+    assert!(matches!(exit, ConsumerExit::Retired));
+
+    // The content stays pinned and the db is archived
+    let policy = RetirementPolicy {
+        unpin_after: None,
+        wipe_db: false,
+    };
+    consumer.retire(&policy).await.unwrap();
+    let db_path = DbBackend::Sled.db_path(&node_dir);
+    assert!(!Path::new(&db_path).exists());
+    assert!(Path::new(&format!("{}.retired-5", db_path)).is_dir());
+
+    // The archived db records the retirement
+    drop(consumer);
+    std::fs::rename(format!("{}.retired-5", db_path), &db_path).unwrap();
+    let db = DbCheckpoint::from_values(REP_FACTOR, node_dir, false, DbBackend::Sled).unwrap();
+    assert!(matches!(
+        db.read_node_state().unwrap(),
+        NodeState::Retired { at: 5 }
+    ));
+}
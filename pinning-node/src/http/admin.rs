@@ -0,0 +1,43 @@
+use crate::types::cid::Cid;
+use serde::Serialize;
+use tokio::sync::{mpsc::UnboundedReceiver, oneshot::Sender};
+
+/// A read-only request about the state of the node. It is answered by the events consumer, between the dispatch of two events.
+pub enum AdminRequest {
+    KeyTable(Sender<KeyTableView>),
+    PinStatus(Cid, Sender<PinStatusView>),
+    Ring(Sender<RingView>),
+}
+
+pub type AdminRequests = UnboundedReceiver<AdminRequest>;
+
+#[derive(Serialize)]
+pub struct KeyTableView {
+    /// The block number at which the keytable has been checkpointed
+    pub block_num: u32,
+    pub rows: Vec<Vec<KeyEntry>>,
+}
+
+#[derive(Serialize)]
+pub struct KeyEntry {
+    pub key: String,
+    pub cid: String,
+}
+
+#[derive(Serialize)]
+pub struct PinStatusView {
+    pub cid: String,
+    /// The number of keys of the node that pin the CID (none if it is not pinned)
+    pub pin_count: Option<u32>,
+    /// The keys of the keytable that point to the CID
+    pub keys: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct RingView {
+    pub node: String,
+    /// The position of the node in the ring (none if the node is not part of the ring)
+    pub position: Option<usize>,
+    pub replication_factor: u32,
+    pub nodes: Vec<String>,
+}
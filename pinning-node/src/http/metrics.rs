@@ -0,0 +1,115 @@
+use crate::utils::ref_builder::{self, AtomicRef};
+use anyhow::Result;
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+
+/// Prometheus metrics of the pinning node
+pub struct NodeMetrics {
+    registry: Registry,
+    /// The number of events processed, by kind
+    pub events_processed: IntCounterVec,
+    /// The latency of a batch, from its entrance in the events pool to its checkpoint
+    pub batch_latency: Histogram,
+    /// The number of pinning operations, by replica, operation and result
+    pub pins: IntCounterVec,
+    /// The number of pins whose first attempt failed and that are still being retried
+    pub pending_retries: IntGauge,
+    /// The number of keys in each row of the keytable
+    pub keytable_row_size: IntGaugeVec,
+    /// The block number at which the node has checkpointed
+    pub checkpoint_height: IntGauge,
+    /// The latest finalized block number seen by the node
+    pub finalized_height: IntGauge,
+}
+
+impl NodeMetrics {
+    pub fn new() -> Result<Self> {
+        let registry = Registry::new_custom(Some("titanh_pinning".to_string()), None)?;
+
+        let events_processed = IntCounterVec::new(
+            Opts::new("events_processed_total", "Number of events processed"),
+            &["kind"],
+        )?;
+        let batch_latency = Histogram::with_opts(HistogramOpts::new(
+            "batch_latency_seconds",
+            "Latency of a batch of events, from the entrance to the checkpoint",
+        ))?;
+        let pins = IntCounterVec::new(
+            Opts::new(
+                "pins_total",
+                "Number of pinning operations on IPFS replicas",
+            ),
+            &["replica", "op", "result"],
+        )?;
+        let pending_retries = IntGauge::new(
+            "pending_pin_retries",
+            "Number of pins being retried after a failed attempt",
+        )?;
+        let keytable_row_size = IntGaugeVec::new(
+            Opts::new("keytable_row_size", "Number of keys in a keytable row"),
+            &["row"],
+        )?;
+        let checkpoint_height = IntGauge::new(
+            "checkpoint_height",
+            "Block number at which the node has checkpointed",
+        )?;
+        let finalized_height = IntGauge::new(
+            "finalized_height",
+            "Latest finalized block number seen by the node",
+        )?;
+
+        registry.register(Box::new(events_processed.clone()))?;
+        registry.register(Box::new(batch_latency.clone()))?;
+        registry.register(Box::new(pins.clone()))?;
+        registry.register(Box::new(pending_retries.clone()))?;
+        registry.register(Box::new(keytable_row_size.clone()))?;
+        registry.register(Box::new(checkpoint_height.clone()))?;
+        registry.register(Box::new(finalized_height.clone()))?;
+
+        Ok(Self {
+            registry,
+            events_processed,
+            batch_latency,
+            pins,
+            pending_retries,
+            keytable_row_size,
+            checkpoint_height,
+            finalized_height,
+        })
+    }
+
+    pub fn arc(self) -> AtomicRef<Self> {
+        ref_builder::create_atomic_ref(self)
+    }
+
+    /// Records the result of a pinning operation on a replica
+    pub fn record_pin(&self, replica: &str, op: &str, ok: bool) {
+        let result = if ok { "ok" } else { "failed" };
+        self.pins.with_label_values(&[replica, op, result]).inc();
+    }
+
+    /// Counts a pin as pending a retry until the returned guard is dropped
+    pub fn pending_retry(&self) -> PendingRetry {
+        self.pending_retries.inc();
+        PendingRetry(self.pending_retries.clone())
+    }
+
+    /// Encodes the metrics in the Prometheus text format
+    pub fn encode(&self) -> Result<String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+
+        Ok(String::from_utf8(buffer)?)
+    }
+}
+
+/// A pin being retried. The pending retries gauge is decremented when it is dropped, on every exit path of the retries
+pub struct PendingRetry(IntGauge);
+
+impl Drop for PendingRetry {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
@@ -0,0 +1,6 @@
+pub mod admin;
+pub mod metrics;
+pub mod server;
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,109 @@
+use super::{
+    admin::{AdminRequest, KeyTableView, PinStatusView, RingView},
+    metrics::NodeMetrics,
+};
+use crate::{types::cid::Cid, utils::ref_builder::AtomicRef};
+use anyhow::Result;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::get,
+    Json, Router,
+};
+use std::{net::SocketAddr, time::Duration};
+use tokio::sync::{mpsc::UnboundedSender, oneshot};
+
+/// How long an admin request waits for the node to answer
+const ADMIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Embedded HTTP server of the node, exposing the Prometheus metrics and the admin endpoints
+pub struct HttpServer {
+    addr: SocketAddr,
+    state: ServerState,
+}
+
+#[derive(Clone)]
+struct ServerState {
+    metrics: AtomicRef<NodeMetrics>,
+    admin: UnboundedSender<AdminRequest>,
+}
+
+impl HttpServer {
+    pub fn new(
+        addr: SocketAddr,
+        metrics: AtomicRef<NodeMetrics>,
+        admin: UnboundedSender<AdminRequest>,
+    ) -> Self {
+        Self {
+            addr,
+            state: ServerState { metrics, admin },
+        }
+    }
+
+    /// Spawns the server task, returning the address it listens at (e.g. when bound to the port 0)
+    pub async fn spawn(self) -> Result<SocketAddr> {
+        let app = Router::new()
+            .route("/health", get(health))
+            .route("/metrics", get(metrics))
+            .route("/admin/keytable", get(keytable))
+            .route("/admin/pins/:cid", get(pin_status))
+            .route("/admin/ring", get(ring))
+            .with_state(self.state);
+
+        let listener = tokio::net::TcpListener::bind(self.addr).await?;
+        let addr = listener.local_addr()?;
+        log::info!("HTTP server listening at {}", addr);
+
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, app).await {
+                log::error!("HTTP server failed: {}", e);
+            }
+        });
+
+        Ok(addr)
+    }
+}
+
+async fn health() -> &'static str {
+    "ok"
+}
+
+async fn metrics(State(state): State<ServerState>) -> Result<String, StatusCode> {
+    state
+        .metrics
+        .encode()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+async fn keytable(State(state): State<ServerState>) -> Result<Json<KeyTableView>, StatusCode> {
+    admin_query(&state, AdminRequest::KeyTable).await
+}
+
+async fn pin_status(
+    State(state): State<ServerState>,
+    Path(cid): Path<String>,
+) -> Result<Json<PinStatusView>, StatusCode> {
+    let cid = Cid::try_from(cid.into_bytes()).map_err(|_| StatusCode::BAD_REQUEST)?;
+    admin_query(&state, |reply| AdminRequest::PinStatus(cid, reply)).await
+}
+
+async fn ring(State(state): State<ServerState>) -> Result<Json<RingView>, StatusCode> {
+    admin_query(&state, AdminRequest::Ring).await
+}
+
+/// Sends an admin request to the node and waits for the reply. The node does not answer while it is not consuming events (e.g. while leaving)
+async fn admin_query<T>(
+    state: &ServerState,
+    request: impl FnOnce(oneshot::Sender<T>) -> AdminRequest,
+) -> Result<Json<T>, StatusCode> {
+    let (reply_tx, reply_rx) = oneshot::channel();
+    state
+        .admin
+        .send(request(reply_tx))
+        .map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?;
+
+    match tokio::time::timeout(ADMIN_TIMEOUT, reply_rx).await {
+        Ok(Ok(reply)) => Ok(Json(reply)),
+        _ => Err(StatusCode::SERVICE_UNAVAILABLE),
+    }
+}
@@ -0,0 +1,142 @@
+use super::{
+    admin::{AdminRequest, KeyEntry, KeyTableView, PinStatusView, RingView},
+    metrics::NodeMetrics,
+    server::HttpServer,
+};
+use crate::utils::ref_builder::AtomicRef;
+use std::net::SocketAddr;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    sync::mpsc::{self, UnboundedReceiver},
+};
+
+const CID: &str = "QmUNLLsPACCz1vLxQVkXqqLX5R1X345qqfHbsf67hvA3Nn";
+
+/// Starts a server on a free port, returning its address and the admin requests it forwards
+async fn start(metrics: AtomicRef<NodeMetrics>) -> (SocketAddr, UnboundedReceiver<AdminRequest>) {
+    let (admin_tx, admin_rx) = mpsc::unbounded_channel();
+    let addr = HttpServer::new(([127, 0, 0, 1], 0).into(), metrics, admin_tx)
+        .spawn()
+        .await
+        .unwrap();
+
+    (addr, admin_rx)
+}
+
+/// Sends a GET request, returning the status code and the body of the response
+async fn get(addr: SocketAddr, path: &str) -> (u16, String) {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        path, addr
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+
+    (status, body.to_string())
+}
+
+/// Answers the admin requests as a node with a single key and a ring of two nodes
+async fn answer(mut requests: UnboundedReceiver<AdminRequest>) {
+    while let Some(request) = requests.recv().await {
+        match request {
+            AdminRequest::KeyTable(reply) => {
+                let _ = reply.send(KeyTableView {
+                    block_num: 7,
+                    rows: vec![vec![KeyEntry {
+                        key: "0x01".to_string(),
+                        cid: CID.to_string(),
+                    }]],
+                });
+            }
+            AdminRequest::PinStatus(cid, reply) => {
+                let _ = reply.send(PinStatusView {
+                    cid: cid.as_ref().to_string(),
+                    pin_count: Some(1),
+                    keys: vec!["0x01".to_string()],
+                });
+            }
+            AdminRequest::Ring(reply) => {
+                let _ = reply.send(RingView {
+                    node: "0xaa".to_string(),
+                    position: Some(0),
+                    replication_factor: 2,
+                    nodes: vec!["0xaa".to_string(), "0xbb".to_string()],
+                });
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn metrics_are_rendered_test() {
+    let metrics = NodeMetrics::new().unwrap().arc();
+    let (addr, _admin) = start(metrics.clone()).await;
+
+    metrics.record_pin("replica", "add", false);
+    metrics.checkpoint_height.set(42);
+    let pending = metrics.pending_retry();
+
+    let (status, body) = get(addr, "/metrics").await;
+    assert_eq!(status, 200);
+    assert!(body
+        .lines()
+        .any(|line| line.starts_with("titanh_pinning_pins_total{")
+            && line.contains(r#"replica="replica""#)
+            && line.contains(r#"result="failed""#)
+            && line.ends_with(" 1")));
+    assert!(body.contains("titanh_pinning_checkpoint_height 42"));
+    assert!(body.contains("titanh_pinning_pending_pin_retries 1"));
+
+    // The retry is no longer pending once it exits
+    drop(pending);
+    let (_, body) = get(addr, "/metrics").await;
+    assert!(body.contains("titanh_pinning_pending_pin_retries 0"));
+}
+
+#[tokio::test]
+async fn admin_requests_are_answered_by_the_node_test() {
+    let (addr, admin) = start(NodeMetrics::new().unwrap().arc()).await;
+    tokio::spawn(answer(admin));
+
+    let (status, body) = get(addr, "/admin/keytable").await;
+    assert_eq!(status, 200);
+    let keytable: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(keytable["block_num"], 7);
+    assert_eq!(keytable["rows"][0][0]["cid"], CID);
+
+    let (status, body) = get(addr, &format!("/admin/pins/{}", CID)).await;
+    assert_eq!(status, 200);
+    let pin: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(pin["cid"], CID);
+    assert_eq!(pin["pin_count"], 1);
+
+    let (status, body) = get(addr, "/admin/ring").await;
+    assert_eq!(status, 200);
+    let ring: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(ring["position"], 0);
+    assert_eq!(ring["nodes"].as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn malformed_cid_is_rejected_test() {
+    let (addr, _admin) = start(NodeMetrics::new().unwrap().arc()).await;
+
+    let (status, _) = get(addr, "/admin/pins/not-a-cid").await;
+    assert_eq!(status, 400);
+}
+
+#[tokio::test]
+async fn admin_requests_fail_when_the_node_does_not_answer_test() {
+    let (addr, admin) = start(NodeMetrics::new().unwrap().arc()).await;
+    // The node has stopped consuming the events
+    drop(admin);
+
+    let (status, _) = get(addr, "/admin/ring").await;
+    assert_eq!(status, 503);
+}
@@ -1,29 +1,38 @@
+use crate::http::metrics::NodeMetrics;
 use crate::types::cid::Cid;
+use crate::utils::ref_builder::AtomicRef;
 use anyhow::Result;
-use futures::TryStreamExt;
+use api::ipfs::read_verified;
 use ipfs_api_backend_hyper::Error as IpfsError;
-use ipfs_api_backend_hyper::{IpfsApi, IpfsClient as ApiIpfsClient};
+use ipfs_api_backend_hyper::{request::Add, IpfsApi, IpfsClient as ApiIpfsClient};
 use rand::rngs::SmallRng as Randomness;
 use rand::{Rng, SeedableRng};
 use std::collections::HashMap;
 use std::future::Future;
+use std::io::Cursor;
 
 pub struct IpfsClient {
     /// The IPFS clients
     clients: Vec<ApiIpfsClient>,
+    /// The rpc urls of the IPFS replicas, in the same order of the clients
+    replicas: Vec<String>,
     /// The number of retries for pinning operations
     failure_retry: u8,
     /// The random number generator used for selecting a random client
     rng: Randomness,
     /// Pinning metadata of the client
     pinning_metadata: PinMetadata,
+    /// Node metrics
+    metrics: AtomicRef<NodeMetrics>,
 }
 
 impl IpfsClient {
     pub fn new(
         ipfs_clients: Vec<ApiIpfsClient>,
+        replicas: Vec<String>,
         failure_retry: u8,
         pin_counts: Vec<(Cid, u32)>,
+        metrics: AtomicRef<NodeMetrics>,
     ) -> Self {
         let rng = Randomness::from_entropy();
 
@@ -35,22 +44,41 @@ impl IpfsClient {
 
         Self {
             clients: ipfs_clients,
+            replicas,
             failure_retry,
             rng,
             pinning_metadata,
+            metrics,
         }
     }
 
+    /// Reads some content from IPFS, verifying it against its cid
     pub async fn get(&mut self, cid: Cid) -> Result<Vec<u8>> {
         let client = self.select_client();
-        let response = client
-            .cat(cid.as_ref())
-            .map_ok(|chunk| chunk.to_vec())
-            .try_concat()
-            .await
-            .map_err(|_| anyhow::anyhow!("error reading full file"))?;
+        let content = read_verified(client, cid.as_ref()).await?;
 
-        Ok(response)
+        Ok(content)
+    }
+
+    /// Adds some content to IPFS, pinning it. The content is not tracked by the pin counts, since it does not belong to the keytable.
+    pub async fn add(&mut self, data: Vec<u8>) -> Result<Cid> {
+        let client = self.select_client();
+
+        let mut add_opts = Add::default();
+        add_opts.pin = Some(true);
+        let ipfs_res = client.add_with_options(Cursor::new(data), add_opts).await?;
+
+        Cid::try_from(ipfs_res.hash.into_bytes())
+    }
+
+    /// Removes a pin of some content that is not tracked by the pin counts (e.g. added with `add`)
+    pub async fn remove(&mut self, cid: &Cid) -> Result<()> {
+        for client in self.clients.iter() {
+            // The content is pinned only by the replica that added it, so failures of other replicas are ignored
+            let _ = Self::handle_pin_op(|| client.pin_rm(cid.as_ref(), true)).await;
+        }
+
+        Ok(())
     }
 
     // Add a pin
@@ -65,11 +93,16 @@ impl IpfsClient {
 
     // Select a random ipfs client from the available nodes.
     fn select_client(&mut self) -> &ApiIpfsClient {
-        let idx = self.rng.gen_range(0..self.clients.len());
+        let idx = self.select_replica();
         let node = &self.clients[idx];
         node
     }
 
+    // Select the index of a random replica
+    fn select_replica(&mut self) -> usize {
+        self.rng.gen_range(0..self.clients.len())
+    }
+
     async fn handle_pin_op<F, Fut, R>(op: F) -> Result<()>
     where
         // HRTB: The closure must work for any lifetime 'a
@@ -104,23 +137,30 @@ impl IpfsClient {
                     return Ok(());
                 }
 
+                // The failed attempts are counted by the pins metric, and the pin is pending a retry after the first one
+                let mut pending_retry = None;
                 for _ in 0..self.failure_retry {
-                    let client = self.select_client();
+                    let idx = self.select_replica();
+                    let client = &self.clients[idx];
                     let res = Self::handle_pin_op(|| client.pin_add(cid.as_ref(), true)).await;
+                    self.metrics
+                        .record_pin(&self.replicas[idx], "add", res.is_ok());
 
                     if res.is_ok() {
                         self.pinning_metadata.insert_cid_pinning_ref(cid.clone());
                         break;
                     }
+                    pending_retry.get_or_insert_with(|| self.metrics.pending_retry());
                 }
             }
             PinOp::Remove => {
                 let remaining_pins = self.pinning_metadata.decrement_cid_pinning_ref(cid)?;
 
                 if remaining_pins == 0 {
-                    for client in self.clients.iter() {
+                    for (client, replica) in self.clients.iter().zip(self.replicas.iter()) {
                         // If the client is offline and is not able to remove the pin, ignore the error
                         let res = Self::handle_pin_op(|| client.pin_rm(cid.as_ref(), true)).await;
+                        self.metrics.record_pin(replica, "remove", res.is_ok());
                         if res.is_ok() {
                             update_cid_pins_to_flush(
                                 &mut self.pinning_metadata.pins_to_flush,
@@ -143,6 +183,23 @@ impl IpfsClient {
     pub fn flush_pins(&mut self) -> Vec<(Cid, u32)> {
         self.pinning_metadata.flush_pins()
     }
+
+    /// Returns the pin count of a CID, if it is pinned
+    pub fn pin_count(&self, cid: &Cid) -> Option<u32> {
+        self.pinning_metadata
+            .pin_counts
+            .get(cid)
+            .map(|(count, _)| *count)
+    }
+
+    /// Returns the current pin counts of all the pinned cids
+    pub fn pin_counts(&self) -> Vec<(Cid, u32)> {
+        self.pinning_metadata
+            .pin_counts
+            .iter()
+            .map(|(cid, (count, _))| (cid.clone(), *count))
+            .collect()
+    }
 }
 
 #[derive(PartialEq, Eq)]
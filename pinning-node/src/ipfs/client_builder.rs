@@ -1,5 +1,9 @@
 use super::client::IpfsClient;
-use crate::{types::cid::Cid, utils::config::Config};
+use crate::{
+    http::metrics::NodeMetrics,
+    types::cid::Cid,
+    utils::{config::Config, ref_builder::AtomicRef},
+};
 use anyhow::Result;
 use ipfs_api_backend_hyper::{IpfsClient as ApiIpfsClient, TryFromUri};
 
@@ -20,21 +24,30 @@ impl<'a> From<&'a Config> for IpfsConfig<'a> {
 pub struct IpfsClientBuilder<'a> {
     config: IpfsConfig<'a>,
     cid_pins: Vec<(Cid, u32)>,
+    metrics: AtomicRef<NodeMetrics>,
 }
 
 const MAX_REPLICAS: usize = 10;
 
 impl<'a> IpfsClientBuilder<'a> {
-    pub fn from_config(config: &'a Config, cid_pins: Vec<(Cid, u32)>) -> Self {
+    pub fn from_config(
+        config: &'a Config,
+        cid_pins: Vec<(Cid, u32)>,
+        metrics: AtomicRef<NodeMetrics>,
+    ) -> Self {
         let config = IpfsConfig::from(config);
-        Self { config, cid_pins }
+        Self {
+            config,
+            cid_pins,
+            metrics,
+        }
     }
 
     pub async fn build(self) -> Result<IpfsClient> {
         let replicas: Result<Vec<ApiIpfsClient>, _> = self
             .config
             .rpc_replicas
-            .into_iter()
+            .iter()
             .map(|url| ApiIpfsClient::from_str(url))
             .collect();
 
@@ -49,10 +62,19 @@ impl<'a> IpfsClientBuilder<'a> {
                 MAX_REPLICAS
             ));
         }
+        let replica_urls = self
+            .config
+            .rpc_replicas
+            .iter()
+            .map(|url| url.to_string())
+            .collect();
+
         Ok(IpfsClient::new(
             replicas,
+            replica_urls,
             self.config.failure_retry,
             self.cid_pins,
+            self.metrics,
         ))
     }
 }
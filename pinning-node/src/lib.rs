@@ -1,14 +1,22 @@
 mod cli;
+mod control;
 mod controller;
 mod db;
 mod events;
+mod http;
 mod ipfs;
+mod snapshot;
 mod substrate;
 mod types;
 mod utils;
 
-// Export the node controller
+// Export the node controller and the CLI
+pub use cli::{Cli, NodeCommand};
 pub use controller::PinningNodeController;
+// Export the client of the node control endpoint
+pub use control::client::request_leave;
 // Export the checkpointing db and keytable
-pub use db::checkpointing;
+pub use db::{checkpointing, store::DbBackend};
 pub use types::keytable::FaultTolerantKeyTable;
+// Export the location of the node local data
+pub use utils::config::{default_data_dir, node_dir};
@@ -1,13 +1,28 @@
 use anyhow::Result;
-use pinning::PinningNodeController;
+use pinning::{request_leave, Cli, NodeCommand, PinningNodeController};
 
-#[tokio::main]
-async fn main() -> Result<()> {
+fn main() -> Result<()> {
     // Initialize the logger
     env_logger::init();
 
-    // Bootstrap the node
-    let node = PinningNodeController::bootstrap().await?;
-    // Execute the node
-    node.execute().await
+    match Cli::parse_command()? {
+        NodeCommand::Run {
+            config,
+            bootstrap_snapshots,
+        } => {
+            // The runtime is sized by the config file
+            config.runtime()?.block_on(async move {
+                // Validate the config against the chain state
+                let config = config.into_config(bootstrap_snapshots).await?;
+                // Bootstrap the node
+                let node = PinningNodeController::bootstrap(config).await?;
+                // Execute the node
+                node.execute().await
+            })
+        }
+        // Ask the running node to leave the committee
+        NodeCommand::Leave(node_dir) => {
+            tokio::runtime::Runtime::new()?.block_on(request_leave(&node_dir))
+        }
+    }
 }
@@ -0,0 +1,176 @@
+use super::SignedSnapshot;
+use crate::{
+    db::checkpointing::DbCheckpoint,
+    http::metrics::NodeMetrics,
+    ipfs::{client::IpfsClient, client_builder::IpfsClientBuilder},
+    substrate::{client::SubstrateClient, client_builder::SubstrateClientBuilder},
+    types::{cid::Cid, keytable::TableRow},
+    utils::{config::Config, ref_builder::AtomicRef},
+};
+use anyhow::Result;
+use api::{
+    capsules_types::CapsuleKey,
+    common_types::{BlockInfo, BlockNumber},
+    pinning_committee_types::{NodeId, PinningRing},
+};
+use codec::Decode;
+use std::collections::HashMap;
+
+/// Imports the keytable snapshots specified in the config into the (empty) checkpointing db of the node.
+/// The snapshots of the predecessor and of the successor of the node in the ring cover all the keys handled by the node. The keys that do not belong to the node are discarded.
+/// Returns the block number at which the node has been checkpointed, from which the node replays the chain events as usual.
+pub async fn import_snapshots(
+    config: &Config,
+    db: &DbCheckpoint,
+    metrics: AtomicRef<NodeMetrics>,
+) -> Result<BlockNumber> {
+    if db.read_blocknumber()?.is_some() {
+        return Err(anyhow::anyhow!(
+            "The node already has a checkpoint, snapshots can only be imported by a fresh node"
+        ));
+    }
+
+    let client = SubstrateClientBuilder::from_config(config, db)
+        .build()
+        .await?;
+    // The client does not track any pin, the imported pins are flushed to the db at the end
+    let mut ipfs = IpfsClientBuilder::from_config(config, Vec::new(), metrics)
+        .build()
+        .await?;
+
+    let mut snapshots = Vec::new();
+    for snapshot_cid in config.bootstrap_snapshots.iter() {
+        let snapshot_cid = Cid::try_from(snapshot_cid.clone().into_bytes())?;
+        let encoded_snapshot = ipfs.get(snapshot_cid).await?;
+        snapshots.push(decode_snapshot(&encoded_snapshot)?);
+    }
+
+    let height = snapshots_height(&snapshots)?;
+    let block = BlockInfo::new(height, client.api().block_hash(height).await?);
+    let ring = client
+        .api()
+        .pinning_committee()
+        .pinning_ring_at(block)
+        .await?;
+
+    let node_id = client.node_id();
+    ring.node(&node_id).map_err(|_| {
+        anyhow::anyhow!(
+            "The node is not part of the ring at block {}, a more recent snapshot is required",
+            height
+        )
+    })?;
+
+    let mut keytable = db.get_checkpoint()?.keytable();
+    for snapshot in snapshots.iter() {
+        ensure_producer(&client, &ring, snapshot, block).await?;
+
+        // The keys of the snapshot that belong to the node
+        let mut entries: Vec<(usize, CapsuleKey, Cid)> = Vec::new();
+        // The number of keys that point to each CID
+        let mut refs: HashMap<Cid, u32> = HashMap::new();
+
+        for row_cid in snapshot.snapshot.row_cids()? {
+            let row = fetch_row(&mut ipfs, row_cid).await?;
+            for (key, cid) in row.iter() {
+                *refs.entry(cid.clone()).or_default() += 1;
+
+                if let Some(row_idx) = ring.key_node_partition(*key, node_id)? {
+                    entries.push((row_idx, *key, cid.clone()));
+                }
+            }
+        }
+        snapshot.snapshot.ensure_pin_counts(&refs)?;
+
+        for (row_idx, key, cid) in entries {
+            // The same key can be part of more than one snapshot
+            if keytable.insert(row_idx, key, cid.clone())?.is_none() {
+                ipfs.pin_add(&cid).await;
+            }
+        }
+        log::info!(
+            "Imported snapshot of node {} at block number {}",
+            hex::encode(snapshot.snapshot.node),
+            height
+        );
+    }
+
+    // Commit the imported keys at the snapshot height
+    db.commit_checkpoint(height, keytable.flush(), ipfs.flush_pins())?;
+
+    Ok(height)
+}
+
+/// Decodes a signed snapshot, rejecting it if the signature does not match its content
+pub(super) fn decode_snapshot(encoded_snapshot: &[u8]) -> Result<SignedSnapshot> {
+    let snapshot = SignedSnapshot::decode(&mut &encoded_snapshot[..])
+        .map_err(|_| anyhow::anyhow!("Failed to decode snapshot"))?;
+    snapshot.verify()?;
+
+    Ok(snapshot)
+}
+
+/// Every snapshot must be taken at the same height, so that the imported keytable is consistent
+fn snapshots_height(snapshots: &[SignedSnapshot]) -> Result<BlockNumber> {
+    let height = snapshots
+        .first()
+        .ok_or(anyhow::anyhow!("No snapshot provided"))?
+        .snapshot
+        .height();
+
+    if snapshots.iter().any(|s| s.snapshot.height() != height) {
+        return Err(anyhow::anyhow!(
+            "Snapshots must be taken at the same block number"
+        ));
+    }
+
+    Ok(height)
+}
+
+/// Ensures that the snapshot has been produced by a node of the ring, controlled by the validator that signed it
+async fn ensure_producer(
+    client: &SubstrateClient,
+    ring: &PinningRing,
+    snapshot: &SignedSnapshot,
+    block: BlockInfo,
+) -> Result<()> {
+    let validator_nodes = client
+        .api()
+        .pinning_committee()
+        .validator_pinning_nodes_at(snapshot.signer_account(), block)
+        .await?;
+
+    check_producer(ring, snapshot, &validator_nodes)
+}
+
+/// Checks the producer of a snapshot against the ring and the nodes controlled by the signer
+pub(super) fn check_producer(
+    ring: &PinningRing,
+    snapshot: &SignedSnapshot,
+    validator_nodes: &[NodeId],
+) -> Result<()> {
+    let producer = snapshot.snapshot.node;
+    ring.node(&producer).map_err(|_| {
+        anyhow::anyhow!(
+            "The snapshot producer {} is not part of the ring",
+            hex::encode(producer)
+        )
+    })?;
+
+    if !validator_nodes.contains(&producer) {
+        return Err(anyhow::anyhow!(
+            "The snapshot signer does not control the producer node {}",
+            hex::encode(producer)
+        ));
+    }
+
+    Ok(())
+}
+
+async fn fetch_row(ipfs: &mut IpfsClient, cid: Cid) -> Result<TableRow> {
+    let encoded_row = ipfs.get(cid).await?;
+    let row = TableRow::decode(&mut &encoded_row[..])
+        .map_err(|_| anyhow::anyhow!("Failed to decode snapshot row"))?;
+
+    Ok(row)
+}
@@ -0,0 +1,101 @@
+use crate::types::cid::Cid;
+use anyhow::Result;
+use api::{
+    common_types::{BlockNumber, KeyPair},
+    pinning_committee_types::NodeId,
+    titanh::runtime_types::pallet_pinning_committee::types::KeyTableAt,
+};
+use codec::{Decode, Encode};
+use sp_core::{sr25519, Pair};
+use std::collections::HashMap;
+
+/// A snapshot of the keytable of a pinning node.
+/// The rows are uploaded to IPFS and referenced by their cids, with the same encoding of the keytable transferred by a leaving node.
+#[derive(Encode, Decode)]
+pub struct KeyTableSnapshot {
+    /// The pinning node that produced the snapshot
+    pub node: NodeId,
+    /// The cids of the encoded keytable rows, up to date with the block number `block_num`
+    pub key_table: KeyTableAt<BlockNumber>,
+    /// The pin counts of the producing node for each CID
+    pub pin_counts: Vec<(Cid, u32)>,
+}
+
+impl KeyTableSnapshot {
+    pub fn height(&self) -> BlockNumber {
+        self.key_table.block_num
+    }
+
+    pub fn row_cids(&self) -> Result<Vec<Cid>> {
+        self.key_table
+            .cids
+            .iter()
+            .map(|cid| Cid::try_from(cid.clone()))
+            .collect()
+    }
+
+    /// Ensures that every CID referenced by the rows is accounted for in the pin counts of the producer. `refs` are the number of keys that point to each CID.
+    pub fn ensure_pin_counts(&self, refs: &HashMap<Cid, u32>) -> Result<()> {
+        let pin_counts: HashMap<&Cid, u32> = self
+            .pin_counts
+            .iter()
+            .map(|(cid, count)| (cid, *count))
+            .collect();
+
+        for (cid, count) in refs {
+            let pin_count = pin_counts.get(cid).copied().unwrap_or_default();
+            if pin_count < *count {
+                return Err(anyhow::anyhow!(
+                    "Inconsistent snapshot: cid {:?} is referenced {} times, but its pin count is {}",
+                    cid,
+                    count,
+                    pin_count
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A keytable snapshot signed by the validator that controls the producing node
+#[derive(Encode, Decode)]
+pub struct SignedSnapshot {
+    pub snapshot: KeyTableSnapshot,
+    /// The validator account
+    pub signer: sr25519::Public,
+    pub signature: sr25519::Signature,
+}
+
+impl SignedSnapshot {
+    pub fn sign(snapshot: KeyTableSnapshot, pair: &KeyPair) -> Self {
+        let signature = pair.sign(&snapshot.encode());
+        Self {
+            snapshot,
+            signer: pair.public(),
+            signature,
+        }
+    }
+
+    pub fn verify(&self) -> Result<()> {
+        if KeyPair::verify(&self.signature, self.snapshot.encode(), &self.signer) {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("Invalid snapshot signature"))
+        }
+    }
+
+    /// The account of the validator that signed the snapshot
+    pub fn signer_account(&self) -> [u8; 32] {
+        let mut account = [0u8; 32];
+        account.copy_from_slice(self.signer.as_ref());
+
+        account
+    }
+}
+
+pub mod importer;
+pub mod publisher;
+
+#[cfg(test)]
+mod tests;
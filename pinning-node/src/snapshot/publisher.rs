@@ -0,0 +1,92 @@
+use super::{KeyTableSnapshot, SignedSnapshot};
+use crate::{
+    ipfs::client::IpfsClient,
+    substrate::client::SubstrateClient,
+    types::{cid::Cid, keytable::FaultTolerantKeyTable},
+    utils::ref_builder::AtomicRef,
+};
+use anyhow::Result;
+use api::{
+    common_types::BlockNumber, titanh::runtime_types::pallet_pinning_committee::types::KeyTableAt,
+};
+use codec::Encode;
+use std::collections::HashSet;
+
+/// Periodically publishes a signed snapshot of the node keytable to IPFS
+pub struct SnapshotPublisher {
+    client: AtomicRef<SubstrateClient>,
+    /// The interval, in blocks, at which snapshots are published
+    interval: BlockNumber,
+    /// The cids pinned for the last published snapshot. They are unpinned once a new snapshot is published
+    last_published: Vec<Cid>,
+}
+
+impl SnapshotPublisher {
+    pub fn new(client: AtomicRef<SubstrateClient>, interval: BlockNumber) -> Self {
+        Self {
+            client,
+            interval,
+            last_published: Vec::new(),
+        }
+    }
+
+    pub fn should_publish(&self, block_num: BlockNumber) -> bool {
+        block_num % self.interval == 0
+    }
+
+    /// Uploads the keytable rows and the signed snapshot that references them. Returns the CID of the snapshot.
+    pub async fn publish(
+        &mut self,
+        ipfs: &mut IpfsClient,
+        keytable: &FaultTolerantKeyTable,
+        block_num: BlockNumber,
+    ) -> Result<Cid> {
+        let mut published = Vec::new();
+        let mut row_cids = Vec::new();
+        for row in keytable.encoded_rows() {
+            let cid = ipfs.add(row).await?;
+            row_cids.push(cid.as_ref().as_bytes().to_vec());
+            published.push(cid);
+        }
+
+        let snapshot = KeyTableSnapshot {
+            node: self.client.node_id(),
+            key_table: KeyTableAt {
+                block_num,
+                cids: row_cids,
+            },
+            pin_counts: ipfs.pin_counts(),
+        };
+        let pair = self
+            .client
+            .api()
+            .signer
+            .as_ref()
+            .ok_or(anyhow::anyhow!("Signer not set"))?
+            .key_pair()
+            .ok_or(anyhow::anyhow!("The signer has no sr25519 key pair"))?;
+        let signed_snapshot = SignedSnapshot::sign(snapshot, pair);
+
+        let snapshot_cid = ipfs.add(signed_snapshot.encode()).await?;
+        published.push(snapshot_cid.clone());
+
+        // The previous snapshot is superseded by the new one
+        let previous = std::mem::replace(&mut self.last_published, published);
+        for cid in superseded(previous, &self.last_published) {
+            ipfs.remove(&cid).await?;
+        }
+
+        Ok(snapshot_cid)
+    }
+}
+
+/// Returns the cids of the previous snapshot that are not part of the new one.
+/// The unchanged rows have the same cid in both snapshots, so they must stay pinned
+pub(super) fn superseded(previous: Vec<Cid>, published: &[Cid]) -> Vec<Cid> {
+    let published: HashSet<&Cid> = published.iter().collect();
+
+    previous
+        .into_iter()
+        .filter(|cid| !published.contains(cid))
+        .collect()
+}
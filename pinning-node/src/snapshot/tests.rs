@@ -0,0 +1,113 @@
+use super::{
+    importer::{check_producer, decode_snapshot},
+    publisher::superseded,
+    KeyTableSnapshot, SignedSnapshot,
+};
+use crate::types::cid::Cid;
+use api::{
+    cid_types::{self, Multihash},
+    common_types::{BlockInfo, KeyPair},
+    pinning_committee_types::{NodeId, PinningRing},
+    titanh::runtime_types::pallet_pinning_committee::types::KeyTableAt,
+};
+use codec::Encode;
+use sp_core::{Pair, H256};
+
+fn cid(n: u8) -> Cid {
+    // A CIDv1 of raw content, with a sha2-256 digest
+    let multihash = Multihash::<64>::wrap(0x12, &[n; 32]).unwrap();
+    let cid = cid_types::Cid::new_v1(0x55, multihash).to_string();
+    Cid::parse(cid.as_bytes()).unwrap()
+}
+
+fn node(n: u8) -> NodeId {
+    H256::repeat_byte(n)
+}
+
+fn ring(nodes: &[u8]) -> PinningRing {
+    let ring = nodes.iter().map(|n| node(*n)).collect();
+    PinningRing::new(ring, 2, BlockInfo::new(1, H256::zero()))
+}
+
+fn signed_snapshot(producer: NodeId, pair: &KeyPair) -> SignedSnapshot {
+    let snapshot = KeyTableSnapshot {
+        node: producer,
+        key_table: KeyTableAt {
+            block_num: 10,
+            cids: vec![cid(1).as_ref().as_bytes().to_vec()],
+        },
+        pin_counts: vec![(cid(2), 1)],
+    };
+    SignedSnapshot::sign(snapshot, pair)
+}
+
+#[test]
+fn signed_snapshot_is_decoded_test() {
+    let pair = KeyPair::from_seed(&[1; 32]);
+    let encoded = signed_snapshot(node(1), &pair).encode();
+
+    let snapshot = decode_snapshot(&encoded).unwrap();
+    assert_eq!(snapshot.snapshot.height(), 10);
+    assert_eq!(snapshot.snapshot.row_cids().unwrap(), vec![cid(1)]);
+}
+
+#[test]
+fn tampered_snapshot_is_rejected_test() {
+    let pair = KeyPair::from_seed(&[1; 32]);
+    let mut snapshot = signed_snapshot(node(1), &pair);
+    // The snapshot claims a pin count the signer never signed
+    snapshot.snapshot.pin_counts = vec![(cid(2), 5)];
+
+    assert!(decode_snapshot(&snapshot.encode()).is_err());
+}
+
+#[test]
+fn snapshot_signed_by_another_key_is_rejected_test() {
+    let pair = KeyPair::from_seed(&[1; 32]);
+    let other = KeyPair::from_seed(&[2; 32]);
+    let mut snapshot = signed_snapshot(node(1), &pair);
+    snapshot.signer = other.public();
+
+    assert!(decode_snapshot(&snapshot.encode()).is_err());
+}
+
+#[test]
+fn snapshot_of_a_controlled_producer_is_accepted_test() {
+    let pair = KeyPair::from_seed(&[1; 32]);
+    let snapshot = signed_snapshot(node(1), &pair);
+
+    check_producer(&ring(&[1, 2, 3]), &snapshot, &[node(1)]).unwrap();
+}
+
+#[test]
+fn snapshot_of_a_node_not_controlled_by_the_signer_is_rejected_test() {
+    let pair = KeyPair::from_seed(&[1; 32]);
+    // The signer controls another node of the ring
+    let snapshot = signed_snapshot(node(1), &pair);
+
+    assert!(check_producer(&ring(&[1, 2, 3]), &snapshot, &[node(2)]).is_err());
+}
+
+#[test]
+fn snapshot_of_a_node_out_of_the_ring_is_rejected_test() {
+    let pair = KeyPair::from_seed(&[1; 32]);
+    let snapshot = signed_snapshot(node(4), &pair);
+
+    assert!(check_producer(&ring(&[1, 2, 3]), &snapshot, &[node(4)]).is_err());
+}
+
+#[test]
+fn unchanged_rows_stay_pinned_test() {
+    // The rows 1 and 2 are unchanged, the row 3 is replaced by the row 4 and the snapshot 5 by the snapshot 6
+    let previous = vec![cid(1), cid(2), cid(3), cid(5)];
+    let published = vec![cid(1), cid(2), cid(4), cid(6)];
+
+    assert_eq!(superseded(previous, &published), vec![cid(3), cid(5)]);
+}
+
+#[test]
+fn nothing_is_unpinned_by_an_identical_snapshot_test() {
+    let previous = vec![cid(1), cid(2)];
+
+    assert!(superseded(previous.clone(), &previous).is_empty());
+}
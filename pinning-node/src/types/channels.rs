@@ -31,4 +31,9 @@ impl PoolReadingHandle {
     pub async fn receive_events(&mut self) -> Option<NodeEvent> {
         self.rx_events.recv().await
     }
+
+    /// Receives an event already in the channel, without waiting for new ones
+    pub fn try_receive_event(&mut self) -> Option<NodeEvent> {
+        self.rx_events.try_recv().ok()
+    }
 }
@@ -1,14 +1,31 @@
-use codec::{Decode, Encode};
+use api::cid_types::parse_cid;
+use codec::{Decode, Encode, Input};
 
-#[derive(Clone, Encode, Decode, Debug, PartialEq, Eq, Hash)]
+/// A validated CID, kept in its canonical string representation
+#[derive(Clone, Encode, Debug, PartialEq, Eq, Hash)]
 pub struct Cid(String);
 
+impl Cid {
+    /// Parses a CID from its string representation, rejecting malformed CIDs
+    pub fn parse(cid: &[u8]) -> anyhow::Result<Self> {
+        let cid = parse_cid(cid)?;
+        Ok(Cid(cid.to_string()))
+    }
+}
+
 impl TryFrom<Vec<u8>> for Cid {
     type Error = anyhow::Error;
 
     fn try_from(cid: Vec<u8>) -> Result<Self, Self::Error> {
-        let cid = std::str::from_utf8(&cid)?;
-        Ok(Cid(cid.to_string()))
+        Cid::parse(&cid)
+    }
+}
+
+// The CIDs read back from the db are validated as well
+impl Decode for Cid {
+    fn decode<I: Input>(input: &mut I) -> Result<Self, codec::Error> {
+        let cid = String::decode(input)?;
+        Cid::parse(cid.as_bytes()).map_err(|_| "Invalid CID".into())
     }
 }
 
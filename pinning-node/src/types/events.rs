@@ -1,6 +1,10 @@
 use std::time::SystemTime;
 
-use super::{batch::Batch, cid::Cid, keytable::TableRow};
+use super::{
+    batch::Batch,
+    cid::Cid,
+    keytable::{RowUpdate, TableRow},
+};
 use anyhow::Result;
 use api::{
     capsules_types::CapsuleKey,
@@ -42,6 +46,17 @@ impl NodeEvent {
         }
     }
 
+    /// The kind of the event, as a label for metrics
+    pub fn kind(&self) -> &'static str {
+        match self {
+            NodeEvent::Pinning(_) => "pinning",
+            NodeEvent::BlockBarrier(_) => "block_barrier",
+            NodeEvent::NodeRegistration(_) => "node_registration",
+            NodeEvent::NodeRemoval(_) => "node_removal",
+            NodeEvent::LatencyTracker(_) => "latency_tracker",
+        }
+    }
+
     pub fn block_barrier_event(self) -> Option<BlockNumber> {
         match self {
             NodeEvent::BlockBarrier(block_num) => Some(block_num),
@@ -104,7 +119,7 @@ pub type PinEventFromLeaveNode = (Cid, Batch<PinningEvent>);
 pub struct CheckpointEvent<'a> {
     pub block_num: BlockNumber,
     /// checkpoint the keytable rows updated at the given block.
-    pub table_rows: Vec<&'a TableRow>,
+    pub table_rows: Vec<RowUpdate<'a>>,
     /// the number of pins to flush for the given IPFS CIDs.
     pub pin_counts: Vec<(Cid, u32)>,
 }
@@ -112,7 +127,7 @@ pub struct CheckpointEvent<'a> {
 impl<'a> CheckpointEvent<'a> {
     pub fn new(
         block_num: BlockNumber,
-        table_rows: Vec<&'a TableRow>,
+        table_rows: Vec<RowUpdate<'a>>,
         pin_counts: Vec<(Cid, u32)>,
     ) -> Self {
         CheckpointEvent {
@@ -133,7 +148,7 @@ pub fn try_event_from_runtime(event: RuntimeEvent) -> Option<NodeEvent> {
             // Upload event
             CapsuleEvent::CapsuleUploaded { id, cid, .. } => {
                 // If the cid is not in a valid format it means the event is not valid, so we return `None`
-                let cid = cid.try_into().ok()?;
+                let cid = cid_from_event(cid)?;
                 node_event = Some(NodeEvent::Pinning(KeyedPinningEvent {
                     key: id,
                     pin: PinningEvent::Pin { cid },
@@ -147,8 +162,8 @@ pub fn try_event_from_runtime(event: RuntimeEvent) -> Option<NodeEvent> {
                 ..
             } => {
                 // Invalid cids bring to an invalid event, so return `None`
-                let old_cid = old_cid.try_into().ok()?;
-                let new_cid = cid.try_into().ok()?;
+                let old_cid = cid_from_event(old_cid)?;
+                let new_cid = cid_from_event(cid)?;
                 node_event = Some(NodeEvent::Pinning(KeyedPinningEvent {
                     key: capsule_id,
                     pin: PinningEvent::UpdatePin { old_cid, new_cid },
@@ -156,7 +171,7 @@ pub fn try_event_from_runtime(event: RuntimeEvent) -> Option<NodeEvent> {
             }
             // Deletion event
             CapsuleEvent::CapsuleStartedDestroying { capsule_id, cid } => {
-                let cid = cid.try_into().ok()?;
+                let cid = cid_from_event(cid)?;
                 node_event = Some(NodeEvent::Pinning(KeyedPinningEvent {
                     key: capsule_id,
                     pin: PinningEvent::RemovePin { cid },
@@ -179,7 +194,7 @@ pub fn try_event_from_runtime(event: RuntimeEvent) -> Option<NodeEvent> {
                 let cids = key_table
                     .cids
                     .into_iter()
-                    .filter_map(|cid| cid.try_into().ok())
+                    .filter_map(cid_from_event)
                     .collect();
 
                 node_event = Some(NodeEvent::NodeRemoval(LeaveNodeEvent {
@@ -194,3 +209,14 @@ pub fn try_event_from_runtime(event: RuntimeEvent) -> Option<NodeEvent> {
 
     node_event
 }
+
+// Parses a cid of a runtime event, logging the malformed ones
+fn cid_from_event(cid: Vec<u8>) -> Option<Cid> {
+    match Cid::try_from(cid) {
+        Ok(cid) => Some(cid),
+        Err(e) => {
+            log::warn!("Ignoring a malformed cid in a runtime event: {}", e);
+            None
+        }
+    }
+}
@@ -2,11 +2,10 @@ use super::cid::Cid;
 use anyhow::Result;
 use api::capsules_types::CapsuleKey;
 use api::common_types::BlockNumber;
-use api::pinning_committee_types::NodeId;
 use codec::{Decode, Encode};
 use std::io::Write;
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     fs::OpenOptions,
     ops::{Deref, DerefMut},
     vec,
@@ -157,10 +156,29 @@ where
     }
 }
 
-type ColumnKey = CapsuleKey;
+pub type ColumnKey = CapsuleKey;
 
 pub type TableRow = Row<ColumnKey, Cid, MAX_COLUMNS>;
 
+/// The changes of a row since the last flush
+#[derive(Encode, Decode, Clone, Default)]
+enum RowChanges {
+    #[default]
+    Clean,
+    /// Some keys of the row have been inserted or removed
+    Keys(BTreeSet<ColumnKey>),
+    /// The whole row has changed, e.g. because rows have been shifted
+    Full,
+}
+
+/// A row of the table that has changed since the last flush
+pub struct RowUpdate<'a> {
+    pub idx: usize,
+    pub row: &'a TableRow,
+    /// The keys of the row that have been inserted or removed, or `None` if the whole row has changed
+    pub keys: Option<BTreeSet<ColumnKey>>,
+}
+
 #[derive(Encode, Decode, Clone)]
 pub struct FaultTolerantKeyTable {
     /// The key table handled by the pinning node.
@@ -168,26 +186,18 @@ pub struct FaultTolerantKeyTable {
     /// i.e. the first row is the closest key range to the node, the second row is the second closest key range, and so on, up to the replication factor.
     key_table: KeyTable<ColumnKey, Cid, MAX_COLUMNS>,
     rep_factor: u32,
-    rows_to_flush: Vec<bool>,
+    row_changes: Vec<RowChanges>,
     /// The optional output file where the key table state is reported
     out_file: Option<String>,
 }
 
 impl FaultTolerantKeyTable {
-    pub fn new(rep_factor: u32, node_id: NodeId, keytable_log: bool) -> Self {
-        let node_id = hex::encode(&node_id.encode()[..=8]);
-
-        let out_file = if keytable_log {
-            let home = std::env::var("HOME").unwrap();
-            let path = format!("{}/node_{}/keytable.log", home, node_id);
-            Some(path)
-        } else {
-            None
-        };
+    /// Creates an empty key table. Its state is reported to `out_file`, if set.
+    pub fn new(rep_factor: u32, out_file: Option<String>) -> Self {
         FaultTolerantKeyTable {
             key_table: KeyTable::new(rep_factor),
             rep_factor,
-            rows_to_flush: vec![false; rep_factor as usize],
+            row_changes: vec![RowChanges::Clean; rep_factor as usize],
             out_file,
         }
     }
@@ -208,7 +218,7 @@ impl FaultTolerantKeyTable {
         // We need to shift existing rows to the right to make space for the new row and remove the last row (if any). Insertion at front is O(n), but since we assume the number of rows is not large, it is acceptable.
         let rm_row = self.key_table.put_at_idx(new_row, row_idx)?.expect("It is always expected to have a row to remove. If there are no elements it's just an empty row");
 
-        self.rows_to_flush.fill(true);
+        self.row_changes.fill(RowChanges::Full);
 
         Ok(rm_row)
     }
@@ -241,7 +251,7 @@ impl FaultTolerantKeyTable {
             self.key_table.insert_row_at(idx, row)?;
         }
 
-        self.rows_to_flush.fill(true);
+        self.row_changes.fill(RowChanges::Full);
         // at this point the last row is surely empty, leaving room for a new row transferred from another node
 
         Ok(())
@@ -254,29 +264,50 @@ impl FaultTolerantKeyTable {
         value: Cid,
     ) -> Result<Option<Cid>> {
         let val = self.key_table.insert(row_idx, column_key, value)?;
-        self.rows_to_flush[row_idx] = true;
+        self.key_changed(row_idx, column_key);
 
         Ok(val)
     }
 
     pub fn remove(&mut self, row_idx: usize, column_key: &ColumnKey) -> Result<Option<Cid>> {
         let val = self.key_table.remove(row_idx, column_key)?;
-        self.rows_to_flush[row_idx] = true;
+        self.key_changed(row_idx, *column_key);
 
         Ok(val)
     }
 
-    pub fn flush(&mut self) -> Vec<&TableRow> {
-        let mut rows = Vec::new();
-        for (idx, should_flush) in self.rows_to_flush.iter().enumerate() {
-            if *should_flush {
-                rows.push(self.key_table.row(idx).unwrap());
+    fn key_changed(&mut self, row_idx: usize, column_key: ColumnKey) {
+        match &mut self.row_changes[row_idx] {
+            RowChanges::Full => {}
+            RowChanges::Keys(keys) => {
+                keys.insert(column_key);
             }
+            changes => *changes = RowChanges::Keys(BTreeSet::from([column_key])),
         }
+    }
 
-        self.rows_to_flush.fill(false);
+    /// Returns the rows changed since the last flush, with their changed keys
+    pub fn flush(&mut self) -> Vec<RowUpdate> {
+        let row_changes = std::mem::replace(
+            &mut self.row_changes,
+            vec![RowChanges::Clean; self.rep_factor as usize],
+        );
+
+        let mut updates = Vec::new();
+        for (idx, changes) in row_changes.into_iter().enumerate() {
+            let keys = match changes {
+                RowChanges::Clean => continue,
+                RowChanges::Keys(keys) => Some(keys),
+                RowChanges::Full => None,
+            };
+            updates.push(RowUpdate {
+                idx,
+                row: self.key_table.row(idx).unwrap(),
+                keys,
+            });
+        }
 
-        rows
+        updates
     }
 
     pub fn extend_last_row(&mut self, row: &mut TableRow) -> Result<()> {
@@ -289,9 +320,12 @@ impl FaultTolerantKeyTable {
             return Err(anyhow::anyhow!("Not enough space to extend last row"));
         }
 
+        let keys: Vec<ColumnKey> = row.keys().copied().collect();
         last_row.append(row);
 
-        self.rows_to_flush[last_idx] = true;
+        for key in keys {
+            self.key_changed(last_idx, key);
+        }
 
         Ok(())
     }
@@ -304,6 +338,16 @@ impl FaultTolerantKeyTable {
         self.key_table.encoded_rows()
     }
 
+    /// Returns the cids referenced by the keys of the table, one for each key
+    pub fn values(&self) -> impl Iterator<Item = &Cid> {
+        self.rows().flat_map(|row| row.values())
+    }
+
+    /// Returns the rows of the table, in order
+    pub fn rows(&self) -> impl Iterator<Item = &TableRow> {
+        self.key_table.0.iter()
+    }
+
     /// Log the key table state to the output file
     pub fn log(&self, block_number: BlockNumber) -> Result<()> {
         if let Some(ref out_file) = self.out_file {
@@ -1,7 +1,10 @@
-use api::pinning_committee_types::NodeId;
+use crate::db::store::DbBackend;
+use anyhow::Result;
+use api::{common_types::BlockNumber, pinning_committee_types::NodeId};
+use codec::Encode;
 use serde::Deserialize;
 use sp_core::{Blake2Hasher, Hasher};
-use std::fs;
+use std::{fs, net::SocketAddr};
 
 #[derive(Deserialize)]
 pub struct PeersConfig {
@@ -17,6 +20,10 @@ impl PeersConfig {
 
         peers_config
     }
+
+    pub fn node_id(&self) -> NodeId {
+        node_id_from_peers(&self.ipfs_peers)
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -41,10 +48,32 @@ pub struct Config {
     pub keytable_log: bool,
     /// Whether to track latency
     pub latency: bool,
+    /// The optional interval, in blocks, at which a signed keytable snapshot is published
+    pub snapshot_interval: Option<BlockNumber>,
+    /// The cids of the keytable snapshots to bootstrap the node from
+    pub bootstrap_snapshots: Vec<String>,
+    /// What the node does once it has been removed from the ring
+    pub retirement: RetirementPolicy,
+    /// The optional address of the HTTP server exposing the metrics and admin endpoints
+    pub http_addr: Option<SocketAddr>,
+    /// The directory under which the node stores its local data
+    pub data_dir: String,
+    /// The storage engine of the checkpointing db
+    pub db_backend: DbBackend,
+    /// The number of checkpoints appended to the delta log before it is compacted
+    pub compaction_interval: u32,
+}
+
+/// What a node does once it has been removed from the ring
+#[derive(Debug, Clone, Copy)]
+pub struct RetirementPolicy {
+    /// The optional number of blocks, after the removal, to wait before unpinning the content of the node. If not set, the content stays pinned
+    pub unpin_after: Option<BlockNumber>,
+    /// Whether to wipe the checkpointing db instead of archiving it
+    pub wipe_db: bool,
 }
 
 impl Config {
-    // Read config from a JSON file
     pub fn new(
         seed_phrase: String,
         chain_node_endpoint: String,
@@ -53,6 +82,13 @@ impl Config {
         rep_factor: u32,
         keytable_log: bool,
         latency: bool,
+        snapshot_interval: Option<BlockNumber>,
+        bootstrap_snapshots: Vec<String>,
+        retirement: RetirementPolicy,
+        http_addr: Option<SocketAddr>,
+        data_dir: String,
+        db_backend: DbBackend,
+        compaction_interval: u32,
     ) -> Self {
         Self {
             seed_phrase,
@@ -62,19 +98,23 @@ impl Config {
             rep_factor,
             keytable_log,
             latency,
+            snapshot_interval,
+            bootstrap_snapshots,
+            retirement,
+            http_addr,
+            data_dir,
+            db_backend,
+            compaction_interval,
         }
     }
 
     pub fn node_id(&self) -> NodeId {
-        // node_id = hash(ipfs_peer1 || ipfs_peer2 || ...)
-        let mut ids = Vec::new();
-
-        for peer in &self.ipfs_peers {
-            let pubkey = hex::decode(&peer.peer_pubkey).expect("Invalid peer pubkey");
-            ids.extend_from_slice(&pubkey);
-        }
+        node_id_from_peers(&self.ipfs_peers)
+    }
 
-        Blake2Hasher::hash(&ids)
+    /// The directory where the node stores its local data
+    pub fn node_dir(&self) -> String {
+        node_dir(&self.data_dir, self.node_id())
     }
 
     pub fn rpc_replicas(&self) -> Vec<&str> {
@@ -84,3 +124,31 @@ impl Config {
             .collect()
     }
 }
+
+pub fn node_id_from_peers(peers: &[IpfsPeer]) -> NodeId {
+    // node_id = hash(ipfs_peer1 || ipfs_peer2 || ...)
+    let mut ids = Vec::new();
+
+    for peer in peers {
+        let pubkey = hex::decode(&peer.peer_pubkey).expect("Invalid peer pubkey");
+        ids.extend_from_slice(&pubkey);
+    }
+
+    Blake2Hasher::hash(&ids)
+}
+
+/// The directory where the node stores its local data (e.g. the checkpointing db), under the `data_dir` storage root.
+/// Every node has its own directory, so several nodes can share the same storage root.
+pub fn node_dir(data_dir: &str, node_id: NodeId) -> String {
+    let node_id = hex::encode(&node_id.encode()[..=8]);
+    format!("{}/node_{}", data_dir.trim_end_matches('/'), node_id)
+}
+
+/// The default storage root of the nodes, i.e. the home directory
+pub fn default_data_dir() -> Result<String> {
+    std::env::var("HOME").map_err(|_| {
+        anyhow::anyhow!(
+            "The HOME environment variable is not set, the data directory must be configured"
+        )
+    })
+}
@@ -0,0 +1,498 @@
+use super::config::{self, node_id_from_peers, Config, IpfsPeer, PeersConfig, RetirementPolicy};
+use crate::db::{checkpointing::DEFAULT_COMPACTION_INTERVAL, store::DbBackend};
+use anyhow::Result;
+use api::{common_types::BlockNumber, pinning_committee_types::NodeId, TitanhApiBuilder};
+use keystore::{KeyType, Keystore};
+use serde::Deserialize;
+use std::{fmt::Display, fs, net::SocketAddr, path::Path, str::FromStr};
+use tokio::runtime::{Builder, Runtime};
+
+/// The prefix of the environment variables that override the values of the config file (e.g. `PINNING_NODE_FAILURE_RETRY`)
+pub const ENV_PREFIX: &str = "PINNING_NODE_";
+
+/// The configuration file of the pinning node, in TOML or JSON format (chosen by the file extension).
+/// Every value can be overridden by an environment variable, named after the field with the `ENV_PREFIX` prefix.
+/// The fields of a section are also prefixed by the section name (e.g. `PINNING_NODE_RETIREMENT_WIPE_DB`).
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    /// The path of the keystore holding the validator key
+    pub keystore: String,
+    /// The name of the validator (sr25519) key in the keystore
+    #[serde(default = "default_validator_key")]
+    pub validator_key: String,
+    /// The path of a file containing the keystore password. If not set, the password is read from the `TITANH_KEYSTORE_PASSWORD` environment variable or from the terminal
+    #[serde(default)]
+    pub keystore_password_file: Option<String>,
+    /// The endpoint of the chain rpc node
+    pub chain_node_endpoint: String,
+    /// The IPFS peers bounded to the pinning node
+    #[serde(default)]
+    pub ipfs_peers: Vec<IpfsPeer>,
+    /// The path of a json file containing the IPFS peers, as an alternative to `ipfs_peers`. A relative path is resolved from the directory of the config file
+    #[serde(default)]
+    pub ipfs_peers_file: Option<String>,
+    /// The number of retries for a failed pinning operation
+    #[serde(default = "default_failure_retry")]
+    pub failure_retry: u8,
+    /// The ring replication factor. It is read from the chain when not set, and must match the chain otherwise
+    #[serde(default)]
+    pub rep_factor: Option<u32>,
+    /// The number of worker threads of the node runtime. Defaults to the number of cores
+    #[serde(default)]
+    pub worker_threads: Option<usize>,
+    /// Whether to log the node keytable to a file
+    #[serde(default)]
+    pub keytable_log: bool,
+    /// Whether to track latency
+    #[serde(default)]
+    pub latency: bool,
+    /// The optional interval, in blocks, at which the node publishes a signed snapshot of its keytable to IPFS
+    #[serde(default)]
+    pub snapshot_interval: Option<BlockNumber>,
+    /// The optional address at which the node serves its health, Prometheus metrics and admin endpoints
+    #[serde(default)]
+    pub http_addr: Option<SocketAddr>,
+    /// The storage root of the node data (checkpointing db, keytable log, control socket). Defaults to the home directory
+    #[serde(default)]
+    pub data_dir: Option<String>,
+    /// The storage engine of the checkpointing db, `sled` or `redb`. A node keeps the backend its db has been created with
+    #[serde(default)]
+    pub db_backend: DbBackend,
+    /// The number of checkpoints appended to the delta log of the checkpointing db before it is compacted into the keytable rows
+    #[serde(default = "default_compaction_interval")]
+    pub compaction_interval: u32,
+    /// What the node does once it has been removed from the ring
+    #[serde(default)]
+    pub retirement: RetirementFile,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RetirementFile {
+    /// The optional number of blocks to wait, once the node has been removed from the ring, before unpinning its content
+    #[serde(default)]
+    pub unpin_after: Option<BlockNumber>,
+    /// Whether to wipe the checkpointing db instead of archiving it
+    #[serde(default)]
+    pub wipe_db: bool,
+}
+
+fn default_failure_retry() -> u8 {
+    3
+}
+
+fn default_compaction_interval() -> u32 {
+    DEFAULT_COMPACTION_INTERVAL
+}
+
+fn default_validator_key() -> String {
+    "validator".to_string()
+}
+
+impl ConfigFile {
+    /// Loads the config file, applies the environment overrides and validates the values that do not depend on the chain
+    pub fn load(path: &str) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read the config file {}: {}", path, e))?;
+
+        let parsed = match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some("json") => serde_json::from_str(&content).map_err(|e| e.to_string()),
+            _ => toml::from_str(&content).map_err(|e| e.to_string()),
+        };
+        let mut config: ConfigFile = parsed
+            .map_err(|e| anyhow::anyhow!("Failed to parse the config file {}: {}", path, e))?;
+        config.apply_env_overrides()?;
+
+        if let Some(peers_file) = config.ipfs_peers_file.as_ref() {
+            if !config.ipfs_peers.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "The IPFS peers must be set either inline or through `ipfs_peers_file`, not both"
+                ));
+            }
+            // A relative path is resolved from the directory of the config file
+            let peers_file = Path::new(path).with_file_name("").join(peers_file);
+            config.ipfs_peers = PeersConfig::from_json(&peers_file.to_string_lossy()).ipfs_peers;
+        }
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn apply_env_overrides(&mut self) -> Result<()> {
+        env_override("keystore", &mut self.keystore)?;
+        env_override("validator_key", &mut self.validator_key)?;
+        env_override_opt("keystore_password_file", &mut self.keystore_password_file)?;
+        env_override("chain_node_endpoint", &mut self.chain_node_endpoint)?;
+        env_override_opt("ipfs_peers_file", &mut self.ipfs_peers_file)?;
+        env_override("failure_retry", &mut self.failure_retry)?;
+        env_override_opt("rep_factor", &mut self.rep_factor)?;
+        env_override_opt("worker_threads", &mut self.worker_threads)?;
+        env_override("keytable_log", &mut self.keytable_log)?;
+        env_override("latency", &mut self.latency)?;
+        env_override_opt("snapshot_interval", &mut self.snapshot_interval)?;
+        env_override_opt("http_addr", &mut self.http_addr)?;
+        env_override_opt("data_dir", &mut self.data_dir)?;
+        env_override("db_backend", &mut self.db_backend)?;
+        env_override("compaction_interval", &mut self.compaction_interval)?;
+        env_override_opt("retirement_unpin_after", &mut self.retirement.unpin_after)?;
+        env_override("retirement_wipe_db", &mut self.retirement.wipe_db)?;
+
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.ipfs_peers.is_empty() {
+            return Err(anyhow::anyhow!("At least one IPFS peer is required"));
+        }
+        for peer in self.ipfs_peers.iter() {
+            url::Url::parse(&peer.rpc_url)
+                .map_err(|e| anyhow::anyhow!("Invalid IPFS rpc url {}: {}", peer.rpc_url, e))?;
+            let pubkey = hex::decode(&peer.peer_pubkey)
+                .map_err(|_| anyhow::anyhow!("Invalid IPFS peer pubkey {}", peer.peer_pubkey))?;
+            if pubkey.len() != 32 {
+                return Err(anyhow::anyhow!(
+                    "Invalid IPFS peer pubkey {}, expected 32 bytes",
+                    peer.peer_pubkey
+                ));
+            }
+        }
+        if self.failure_retry == 0 {
+            return Err(anyhow::anyhow!("`failure_retry` must be at least 1"));
+        }
+        if self.rep_factor == Some(0) {
+            return Err(anyhow::anyhow!("`rep_factor` must be at least 1"));
+        }
+        if self.worker_threads == Some(0) {
+            return Err(anyhow::anyhow!("`worker_threads` must be at least 1"));
+        }
+        if self.snapshot_interval == Some(0) {
+            return Err(anyhow::anyhow!("`snapshot_interval` must be at least 1"));
+        }
+        if self.compaction_interval == 0 {
+            return Err(anyhow::anyhow!("`compaction_interval` must be at least 1"));
+        }
+        if self.data_dir.as_ref().is_some_and(|dir| dir.is_empty()) {
+            return Err(anyhow::anyhow!("`data_dir` cannot be empty"));
+        }
+
+        Ok(())
+    }
+
+    pub fn node_id(&self) -> NodeId {
+        node_id_from_peers(&self.ipfs_peers)
+    }
+
+    /// The storage root of the node data, falling back to the home directory
+    pub fn data_dir(&self) -> Result<String> {
+        match self.data_dir.as_ref() {
+            Some(data_dir) => Ok(data_dir.clone()),
+            None => config::default_data_dir(),
+        }
+    }
+
+    /// The directory where the node stores its local data
+    pub fn node_dir(&self) -> Result<String> {
+        Ok(config::node_dir(&self.data_dir()?, self.node_id()))
+    }
+
+    /// Builds the runtime of the node
+    pub fn runtime(&self) -> Result<Runtime> {
+        let mut builder = Builder::new_multi_thread();
+        if let Some(worker_threads) = self.worker_threads {
+            builder.worker_threads(worker_threads);
+        }
+
+        Ok(builder.enable_all().build()?)
+    }
+
+    /// Decrypts the secret URI of the validator key from the keystore
+    fn validator_suri(&self) -> Result<String> {
+        let keystore = Keystore::open(&self.keystore)?;
+        let password_file = self.keystore_password_file.as_ref().map(Path::new);
+        let password = keystore::read_password(password_file)?;
+
+        let suri = keystore
+            .load(&self.validator_key, KeyType::Sr25519, &password)?
+            .suri()?;
+
+        Ok(suri.to_string())
+    }
+
+    /// Validates the config against the chain state and builds the node config.
+    /// The replication factor is read from the chain when it is not set by the config file.
+    pub async fn into_config(self, bootstrap_snapshots: Vec<String>) -> Result<Config> {
+        let seed_phrase = self.validator_suri()?;
+        let data_dir = self.data_dir()?;
+        let api = TitanhApiBuilder::rpc(&self.chain_node_endpoint)
+            .build()
+            .await
+            .map_err(|e| {
+                anyhow::anyhow!(
+                    "Failed to connect to the chain node at {}: {}",
+                    self.chain_node_endpoint,
+                    e
+                )
+            })?;
+        let block = api.latest_finalized_block().await?;
+        let ring = api.pinning_committee().pinning_ring_at(block).await?;
+
+        // The node id is derived from the IPFS peers, so a node out of the ring usually has misconfigured peers.
+        // A node with local data may have been removed from the ring, and it is started to complete its retirement
+        let node_id = self.node_id();
+        if ring.node(&node_id).is_err() && !Path::new(&self.node_dir()?).exists() {
+            return Err(anyhow::anyhow!(
+                "The pinning node {} is not part of the ring at block {}: its IPFS peers must be registered first",
+                hex::encode(node_id),
+                block.number
+            ));
+        }
+
+        let rep_factor = match self.rep_factor {
+            Some(rep_factor) if rep_factor != ring.replication() => {
+                return Err(anyhow::anyhow!(
+                    "The configured replication factor {} does not match the chain replication factor {}",
+                    rep_factor,
+                    ring.replication()
+                ));
+            }
+            _ => ring.replication(),
+        };
+
+        Ok(Config::new(
+            seed_phrase,
+            self.chain_node_endpoint,
+            self.failure_retry,
+            self.ipfs_peers,
+            rep_factor,
+            self.keytable_log,
+            self.latency,
+            self.snapshot_interval,
+            bootstrap_snapshots,
+            RetirementPolicy {
+                unpin_after: self.retirement.unpin_after,
+                wipe_db: self.retirement.wipe_db,
+            },
+            self.http_addr,
+            data_dir,
+            self.db_backend,
+            self.compaction_interval,
+        ))
+    }
+}
+
+/// Overrides a value with the environment variable named after `field`, if set
+fn env_override<T>(field: &str, value: &mut T) -> Result<()>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    if let Some(overridden) = env_value(field)? {
+        *value = overridden;
+    }
+
+    Ok(())
+}
+
+/// Overrides an optional value with the environment variable named after `field`, if set
+fn env_override_opt<T>(field: &str, value: &mut Option<T>) -> Result<()>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    if let Some(overridden) = env_value(field)? {
+        *value = Some(overridden);
+    }
+
+    Ok(())
+}
+
+/// Parses the environment variable named after `field`, if set
+fn env_value<T>(field: &str) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let name = format!("{}{}", ENV_PREFIX, field.to_uppercase());
+    match std::env::var(&name) {
+        Ok(raw) => raw
+            .parse()
+            .map(Some)
+            .map_err(|e| anyhow::anyhow!("Invalid value of {}: {}", name, e)),
+        Err(_) => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// The environment is shared by the tests, so the ones loading a config file run one at a time
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+    const PEER_PUBKEY: &str = "0101010101010101010101010101010101010101010101010101010101010101";
+
+    /// Writes the files of a test in its own directory, returning the path of the first one
+    fn write_files(test: &str, files: &[(&str, &str)]) -> String {
+        let dir =
+            std::env::temp_dir().join(format!("titanh-config-{}-{}", std::process::id(), test));
+        fs::create_dir_all(&dir).unwrap();
+        for (name, content) in files {
+            fs::write(dir.join(name), content).unwrap();
+        }
+
+        dir.join(files[0].0).to_string_lossy().to_string()
+    }
+
+    fn toml_config(extra: &str) -> String {
+        format!(
+            r#"
+keystore = "/keystore"
+chain_node_endpoint = "ws://127.0.0.1:9944"
+{}
+
+[[ipfs_peers]]
+rpc_url = "http://127.0.0.1:5001"
+peer_pubkey = "{}"
+"#,
+            extra, PEER_PUBKEY
+        )
+    }
+
+    fn load(path: &str, env: &[(&str, &str)]) -> Result<ConfigFile> {
+        let _lock = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        for (name, value) in env {
+            std::env::set_var(name, value);
+        }
+        let config = ConfigFile::load(path);
+        for (name, _) in env {
+            std::env::remove_var(name);
+        }
+
+        config
+    }
+
+    #[test]
+    fn toml_config_is_parsed_with_defaults_test() {
+        let path = write_files("toml", &[("node.toml", &toml_config(""))]);
+        let config = load(&path, &[]).unwrap();
+
+        assert_eq!(config.keystore, "/keystore");
+        assert_eq!(config.validator_key, "validator");
+        assert_eq!(config.ipfs_peers.len(), 1);
+        assert_eq!(config.failure_retry, 3);
+        assert_eq!(config.db_backend, DbBackend::Sled);
+        assert_eq!(config.compaction_interval, DEFAULT_COMPACTION_INTERVAL);
+        assert_eq!(config.retirement.unpin_after, None);
+        assert!(!config.retirement.wipe_db);
+    }
+
+    #[test]
+    fn json_config_is_parsed_test() {
+        let json = format!(
+            r#"{{
+                "keystore": "/keystore",
+                "chain_node_endpoint": "ws://127.0.0.1:9944",
+                "ipfs_peers": [{{ "rpc_url": "http://127.0.0.1:5001", "peer_pubkey": "{}" }}],
+                "db_backend": "redb",
+                "retirement": {{ "unpin_after": 100, "wipe_db": true }}
+            }}"#,
+            PEER_PUBKEY
+        );
+        let path = write_files("json", &[("node.json", &json)]);
+        let config = load(&path, &[]).unwrap();
+
+        assert_eq!(config.db_backend, DbBackend::Redb);
+        assert_eq!(config.retirement.unpin_after, Some(100));
+        assert!(config.retirement.wipe_db);
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected_test() {
+        let path = write_files(
+            "unknown",
+            &[("node.toml", &toml_config("failure_retries = 2"))],
+        );
+
+        assert!(load(&path, &[]).is_err());
+    }
+
+    #[test]
+    fn env_overrides_the_config_file_test() {
+        let path = write_files("env", &[("node.toml", &toml_config("failure_retry = 5"))]);
+        let config = load(
+            &path,
+            &[
+                ("PINNING_NODE_FAILURE_RETRY", "7"),
+                ("PINNING_NODE_SNAPSHOT_INTERVAL", "50"),
+                ("PINNING_NODE_RETIREMENT_UNPIN_AFTER", "10"),
+                ("PINNING_NODE_RETIREMENT_WIPE_DB", "true"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(config.failure_retry, 7);
+        assert_eq!(config.snapshot_interval, Some(50));
+        assert_eq!(config.retirement.unpin_after, Some(10));
+        assert!(config.retirement.wipe_db);
+    }
+
+    #[test]
+    fn invalid_env_value_is_rejected_test() {
+        let path = write_files("invalid-env", &[("node.toml", &toml_config(""))]);
+
+        assert!(load(&path, &[("PINNING_NODE_FAILURE_RETRY", "many")]).is_err());
+        // The overridden values are validated as the values of the file
+        assert!(load(&path, &[("PINNING_NODE_FAILURE_RETRY", "0")]).is_err());
+    }
+
+    #[test]
+    fn peers_file_is_resolved_from_the_config_dir_test() {
+        let peers = format!(
+            r#"{{ "ipfs_peers": [{{ "rpc_url": "http://127.0.0.1:5001", "peer_pubkey": "{}" }}] }}"#,
+            PEER_PUBKEY
+        );
+        let config = r#"
+keystore = "/keystore"
+chain_node_endpoint = "ws://127.0.0.1:9944"
+ipfs_peers_file = "peers.json"
+"#;
+        let path = write_files(
+            "peers-file",
+            &[("node.toml", config), ("peers.json", &peers)],
+        );
+        let config = load(&path, &[]).unwrap();
+
+        assert_eq!(config.ipfs_peers.len(), 1);
+        assert_eq!(config.ipfs_peers[0].peer_pubkey, PEER_PUBKEY);
+    }
+
+    #[test]
+    fn inline_peers_conflict_with_the_peers_file_test() {
+        let path = write_files(
+            "peers-conflict",
+            &[
+                (
+                    "node.toml",
+                    &toml_config(r#"ipfs_peers_file = "peers.json""#),
+                ),
+                ("peers.json", r#"{ "ipfs_peers": [] }"#),
+            ],
+        );
+        let err = load(&path, &[]).unwrap_err();
+
+        assert!(err.to_string().contains("not both"));
+    }
+
+    #[test]
+    fn config_without_peers_is_rejected_test() {
+        let config = r#"
+keystore = "/keystore"
+chain_node_endpoint = "ws://127.0.0.1:9944"
+"#;
+        let path = write_files("no-peers", &[("node.toml", config)]);
+
+        assert!(load(&path, &[]).is_err());
+    }
+}
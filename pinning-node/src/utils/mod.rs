@@ -1,3 +1,4 @@
 pub mod capsules;
 pub mod config;
+pub mod config_file;
 pub mod ref_builder;
@@ -35,6 +35,9 @@ fn capsule<T: Config>() -> CapsuleUploadData<BlockNumberFor<T>> {
     }
 }
 
+/// The longest CID accepted by the benchmarks runtime: a CIDv1 in base32 with a sha2-512 digest, so that its parsing is the worst case
+const LONG_CID: &[u8] = b"bafkrgqaaaebagbafaydqqcikbmga2dqpcaireeyuculbogazdinryhi6d4qccirdeqssmjzifevcwlbnfyxtamjsgm2dknrxha4tuoz4hu7d6";
+
 fn compute_capsule_id<T: Config>(app_id: AppIdFor<T>, metadata: Vec<u8>) -> T::Hash {
     let mut ids = Vec::new();
 
@@ -60,7 +63,8 @@ benchmarks! {
 
         let app_id = app_id::<T>();
         let other_owner: Option<T::AccountId> = None;
-        let capsule = capsule::<T>();
+        let mut capsule = capsule::<T>();
+        capsule.cid = LONG_CID.to_vec();
     }: _(RawOrigin::Signed(caller), app_id.clone(), other_owner, capsule)
     verify {
         // Verify that the capsule was uploaded correctly
@@ -68,6 +72,20 @@ benchmarks! {
         assert!(Capsules::<T>::capsules(capsule_id).is_some());
     }
 
+    update_capsule_content {
+        let caller: T::AccountId = whitelisted_caller();
+        create_app_from_caller::<T>(caller.clone())?;
+
+        let app_id = app_id::<T>();
+        let origin = RawOrigin::Signed(caller.clone());
+        Capsules::<T>::upload_capsule(origin.into(), app_id.clone(), None, capsule::<T>())?;
+        let capsule_id = compute_capsule_id::<T>(app_id, vec![1, 2, 3]);
+    }: _(RawOrigin::Signed(caller), capsule_id.clone(), LONG_CID.to_vec(), 26)
+    verify {
+        let capsule = Capsules::<T>::capsules(capsule_id).unwrap();
+        assert_eq!(capsule.cid.to_vec(), LONG_CID.to_vec());
+    }
+
     impl_benchmark_test_suite!(
         Capsules,
         crate::tests::new_test_ext(),
@@ -1,10 +1,9 @@
-use super::{CapsuleIdFor, CapsuleMetaBuilder, CapsuleUploadData};
+use super::{cid_from_vec, CapsuleIdFor, CapsuleMetaBuilder, CapsuleUploadData};
 use crate::{
     capsule::Status, AppIdFor, Approval, CapsuleContainers, CapsuleFollowers, CapsuleItems,
     Capsules, Config, Container, DeletionCompletion, Error, Event, Follower, FollowersStatus,
     IdComputation, OwnersWaitingApprovals, Ownership, Pallet,
 };
-use common_types::BoundedString;
 use common_types::{BlockNumberFor, ContentSize};
 use frame_support::ensure;
 use pallet_app_registrar::PermissionsApp;
@@ -149,7 +148,7 @@ impl<T: Config> Pallet<T> {
         Self::ensure_capsule_liveness(&capsule)?;
         // change the capsule cid and size
         let old_cid = capsule.cid.clone().to_vec();
-        capsule.cid = BoundedString::from_vec(cid.clone()).map_err(|_| Error::<T>::BadCid)?;
+        capsule.cid = cid_from_vec::<T>(cid.clone())?;
         capsule.size = size;
 
         Capsules::<T>::insert(&capsule_id, capsule);
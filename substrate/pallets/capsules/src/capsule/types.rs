@@ -1,6 +1,7 @@
 use crate::{AppData, AppIdFor, Config, DeletionCompletion, FollowersStatus};
 use codec::{Decode, Encode, MaxEncodedLen};
 use common_types::*;
+use frame_support::ensure;
 use frame_system::Config as SystemConfig;
 use scale_info::TypeInfo;
 use sp_core::{Get, RuntimeDebug};
@@ -102,8 +103,7 @@ impl<T: Config> CapsuleMetaBuilder<T> {
 	pub fn build(self) -> Result<CapsuleMetadataOf<T>, DispatchError> {
 		Ok(CapsuleMetadata {
 			status: Default::default(),
-			cid: BoundedString::from_vec(self.upload_data.cid)
-				.map_err(|_| crate::Error::<T>::BadCid)?,
+			cid: cid_from_vec::<T>(self.upload_data.cid)?,
 			size: self.upload_data.size,
 			ending_retention_block: self.upload_data.ending_retention_block,
 			owners: self.owners.try_into().map_err(|_| crate::Error::<T>::TooManyOwners)?,
@@ -116,3 +116,14 @@ impl<T: Config> CapsuleMetaBuilder<T> {
 		})
 	}
 }
+
+/// Validates an IPFS CID (CIDv0 or CIDv1) in its string representation, bounded by `CidLength`
+pub fn cid_from_vec<T: Config>(cid: Vec<u8>) -> Result<CidFor<T::CidLength>, DispatchError> {
+	// An oversized cid is rejected before it is parsed
+	ensure!(cid.len() <= T::CidLength::get() as usize, crate::Error::<T>::BadCid);
+	let is_valid =
+		core::str::from_utf8(&cid).map(|cid| ::cid::Cid::try_from(cid).is_ok()).unwrap_or(false);
+	ensure!(is_valid, crate::Error::<T>::BadCid);
+
+	BoundedString::from_vec(cid).map_err(|_| crate::Error::<T>::BadCid.into())
+}
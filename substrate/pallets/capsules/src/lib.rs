@@ -78,7 +78,7 @@ pub mod pallet {
         /// Minimum number of blocks for a capsule retention period
         #[pallet::constant]
         type MinimumRetentionPeriod: Get<u32>;
-        /// The IPFS CID length
+        /// The maximum length of an IPFS CID, in its string representation. CIDv1 in base32 are longer than CIDv0
         #[pallet::constant]
         type CidLength: Get<u32> + Clone;
         /// Type representing the weight of this pallet
@@ -373,7 +373,7 @@ pub mod pallet {
         /// Updates the content of a capsule.
         /// By means of changing the IPFS CID and size (see vulnerability in the upload extrinisc).
         #[pallet::call_index(5)]
-        #[pallet::weight(Weight::from_parts(100_000, 0))]
+        #[pallet::weight(T::WeightInfo::update_capsule_content())]
         pub fn update_capsule_content(
             origin: OriginFor<T>,
             capsule_id: CapsuleIdFor<T>,
@@ -11,7 +11,7 @@ use sp_runtime::{
 // Reexport crate as its pallet name for construct_runtime.
 use crate as pallet_capsules;
 use crate::*;
-use frame_support::assert_ok;
+use frame_support::{assert_noop, assert_ok};
 use pallet_app_registrar::{self as app_registrar, CurrentAppId};
 
 type Block = frame_system::mocking::MockBlock<Test>;
@@ -73,7 +73,8 @@ impl pallet_capsules::Config for Test {
     type RemoveItemsLimit = ConstU32<512>;
     // 1 hour, considering one block is 3 seconds
     type MinimumRetentionPeriod = ConstU32<50>;
-    type CidLength = ConstU32<46>;
+    type CidLength = ConstU32<128>;
+    type WeightInfo = ();
 }
 
 // This function basically just builds a genesis storage key/value store according to
@@ -114,6 +115,75 @@ fn upload_capsule_test() {
     });
 }
 
+#[test]
+fn upload_capsule_with_cidv1_test() {
+    new_test_ext().execute_with(|| {
+        let origin = RuntimeOrigin::signed(1);
+        assert_ok!(AppRegistrar::create_app(origin.clone()));
+
+        let capsule = CapsuleUploadData {
+            // CIDv1 in base32
+            cid: "bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi"
+                .as_bytes()
+                .to_vec(),
+            size: 13,
+            ending_retention_block: 100,
+            followers_status: FollowersStatus::All,
+            encoded_metadata: vec![1, 2, 3],
+        };
+
+        assert_ok!(Capsules::upload_capsule(origin, 1, Some(2), capsule));
+
+        let capsule_id = compute_capsule_id(1, vec![1, 2, 3]);
+        assert!(Capsules::capsules(capsule_id).is_some());
+    });
+}
+
+#[test]
+fn upload_capsule_with_malformed_cid_test() {
+    new_test_ext().execute_with(|| {
+        let origin = RuntimeOrigin::signed(1);
+        assert_ok!(AppRegistrar::create_app(origin.clone()));
+
+        let capsule = CapsuleUploadData {
+            cid: "QmNotAValidContentIdentifier".as_bytes().to_vec(),
+            size: 13,
+            ending_retention_block: 100,
+            followers_status: FollowersStatus::All,
+            encoded_metadata: vec![1, 2, 3],
+        };
+
+        assert_noop!(
+            Capsules::upload_capsule(origin, 1, Some(2), capsule),
+            Error::<Test>::BadCid
+        );
+    });
+}
+
+#[test]
+fn upload_capsule_with_oversized_cid_test() {
+    new_test_ext().execute_with(|| {
+        let origin = RuntimeOrigin::signed(1);
+        assert_ok!(AppRegistrar::create_app(origin.clone()));
+
+        // A well-formed base16 CIDv1 of raw content with a 64 bytes identity digest, longer than `CidLength`
+        let cid = format!("f01550040{}", "07".repeat(64));
+        assert!(cid.len() > 128);
+        let capsule = CapsuleUploadData {
+            cid: cid.into_bytes(),
+            size: 13,
+            ending_retention_block: 100,
+            followers_status: FollowersStatus::All,
+            encoded_metadata: vec![1, 2, 3],
+        };
+
+        assert_noop!(
+            Capsules::upload_capsule(origin, 1, Some(2), capsule),
+            Error::<Test>::BadCid
+        );
+    });
+}
+
 fn compute_capsule_id(app_id: u32, metadata: Vec<u8>) -> H256 {
     let mut ids = Vec::new();
 
@@ -29,13 +29,14 @@
 #![allow(unused_imports)]
 #![allow(missing_docs)]
 
-use frame_support::{traits::Get, weights::Weight};
+use frame_support::{traits::Get, weights::{constants::RocksDbWeight, Weight}};
 use core::marker::PhantomData;
 use crate as pallet_capsules;
 
 
 pub trait WeightInfo {
     fn upload_capsule() -> Weight;
+    fn update_capsule_content() -> Weight;
 }
 
 /// Weight functions for `pallet_capsules`.
@@ -44,15 +45,43 @@ impl<T: frame_system::Config> WeightInfo for CapsulesWeight<T> {
 	/// Storage: `AppRegistrar::AppPermissions` (r:1 w:0)
 	/// Proof: `AppRegistrar::AppPermissions` (`max_values`: None, `max_size`: Some(69), added: 2544, mode: `MaxEncodedLen`)
 	/// Storage: `Capsules::Capsules` (r:1 w:1)
-	/// Proof: `Capsules::Capsules` (`max_values`: None, `max_size`: Some(2167), added: 4642, mode: `MaxEncodedLen`)
+	/// Proof: `Capsules::Capsules` (`max_values`: None, `max_size`: Some(2249), added: 4724, mode: `MaxEncodedLen`)
 	fn upload_capsule() -> Weight {
 		// Proof Size summary in bytes:
 		//  Measured:  `234`
-		//  Estimated: `5632`
-		// Minimum execution time: 16_000_000 picoseconds.
-		Weight::from_parts(17_000_000, 0)
-			.saturating_add(Weight::from_parts(0, 5632))
+		//  Estimated: `5714`
+		// Execution time estimated from the last run (16_000_000 picoseconds), plus the parsing of a `CidLength` long CID,
+		// until the benchmark is re-run with the CID validation
+		Weight::from_parts(20_000_000, 0)
+			.saturating_add(Weight::from_parts(0, 5714))
 			.saturating_add(T::DbWeight::get().reads(2))
 			.saturating_add(T::DbWeight::get().writes(1))
 	}
+	/// Storage: `Capsules::Capsules` (r:1 w:1)
+	/// Proof: `Capsules::Capsules` (`max_values`: None, `max_size`: Some(2249), added: 4724, mode: `MaxEncodedLen`)
+	fn update_capsule_content() -> Weight {
+		// Proof Size summary in bytes:
+		//  Estimated: `5714`
+		// Execution time estimated as for `upload_capsule`, until the benchmark is run
+		Weight::from_parts(20_000_000, 0)
+			.saturating_add(Weight::from_parts(0, 5714))
+			.saturating_add(T::DbWeight::get().reads(1))
+			.saturating_add(T::DbWeight::get().writes(1))
+	}
+}
+
+// For backwards compatibility and tests.
+impl WeightInfo for () {
+	fn upload_capsule() -> Weight {
+		Weight::from_parts(20_000_000, 0)
+			.saturating_add(Weight::from_parts(0, 5714))
+			.saturating_add(RocksDbWeight::get().reads(2))
+			.saturating_add(RocksDbWeight::get().writes(1))
+	}
+	fn update_capsule_content() -> Weight {
+		Weight::from_parts(20_000_000, 0)
+			.saturating_add(Weight::from_parts(0, 5714))
+			.saturating_add(RocksDbWeight::get().reads(1))
+			.saturating_add(RocksDbWeight::get().writes(1))
+	}
 }
@@ -2,6 +2,10 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
 mod types;
+pub mod weights;
+
+#[cfg(test)]
+mod tests;
 
 use codec::Encode;
 use common_types::PinningNodeIdOf;
@@ -16,6 +20,7 @@ use sp_application_crypto::RuntimeAppPublic;
 use sp_core::Hasher;
 use sp_runtime::traits::Convert;
 use sp_std::vec::Vec;
+use weights::WeightInfo;
 
 pub use pallet::*;
 pub use types::*;
@@ -61,6 +66,14 @@ pub mod pallet {
             + Ord
             + MaybeSerializeDeserialize
             + MaxEncodedLen;
+        /// The number of blocks the successors of a leaving pinning node have to acknowledge the key transfer
+        #[pallet::constant]
+        type HandoffTimeout: Get<BlockNumberFor<Self>>;
+        /// The maximum number of leaving pinning nodes whose key transfer is pending at the same time
+        #[pallet::constant]
+        type MaxLeavingNodes: Get<u32>;
+        /// Weight information for extrinsics and hooks in this pallet.
+        type WeightInfo: WeightInfo;
     }
 
     /// The number of pinning nodes that will pin the content underneath an IPFS cid
@@ -110,6 +123,17 @@ pub mod pallet {
     pub type IsIpfsKeyAssigned<T: Config> =
         StorageMap<_, Blake2_128Concat, T::IPFSNodeId, bool, ValueQuery>;
 
+    /// Pinning nodes that have left the ring and whose key transfer still has to be acknowledged by the successors
+    #[pallet::storage]
+    #[pallet::getter(fn leaving_nodes)]
+    pub type LeavingNodes<T: Config> =
+        StorageMap<_, Blake2_128Concat, PinningNodeIdOf<T>, HandoffOf<T>, OptionQuery>;
+
+    /// The deadlines of the pending key transfers, so that only the expired ones are visited on each block
+    #[pallet::storage]
+    #[pallet::getter(fn handoff_deadlines)]
+    pub type HandoffDeadlines<T: Config> = StorageValue<_, HandoffQueue<T>, ValueQuery>;
+
     #[pallet::genesis_config]
     #[derive(frame_support::DefaultNoBound)]
     pub struct GenesisConfig<T: Config> {
@@ -165,6 +189,18 @@ pub mod pallet {
         },
         /// Unassigned ipfs nodes have been cleared
         WaitingIpfsNodesCleared { validator: T::ValidatorId },
+        /// A successor has acknowledged the key transfer of a leaving pinning node
+        HandoffAcknowledged {
+            leaving_node: PinningNodeIdOf<T>,
+            pinning_node: PinningNodeIdOf<T>,
+        },
+        /// All the successors have acknowledged the key transfer of a leaving pinning node
+        HandoffCompleted { leaving_node: PinningNodeIdOf<T> },
+        /// The key transfer of a leaving pinning node has not been acknowledged by all the successors in time
+        HandoffExpired {
+            leaving_node: PinningNodeIdOf<T>,
+            pending: PendingSuccessors<T>,
+        },
     }
 
     /// Errors that can be returned by this pallet.
@@ -192,6 +228,40 @@ pub mod pallet {
         IpfsKeyNotFound,
         /// The IPFS key is already assigned
         IpfsKeyAlreadyAssigned,
+        /// There is no pending key transfer for the leaving pinning node
+        NoPendingHandoff,
+        /// The pinning node is not a successor that still has to acknowledge the key transfer
+        NotHandoffSuccessor,
+        /// Too many leaving pinning nodes have a pending key transfer
+        TooManyLeavingNodes,
+    }
+
+    #[pallet::hooks]
+    impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {
+        fn on_initialize(now: BlockNumberFor<T>) -> Weight {
+            let deadlines = HandoffDeadlines::<T>::get();
+            // The deadlines are queued in increasing order, so the expired handoffs are at the front
+            let expired = deadlines
+                .iter()
+                .take_while(|(deadline, _)| *deadline <= now)
+                .count();
+            if expired == 0 {
+                return T::WeightInfo::expire_handoffs(0);
+            }
+
+            let mut deadlines = deadlines.into_inner();
+            for (_, leaving_node) in deadlines.drain(..expired) {
+                if let Some(handoff) = LeavingNodes::<T>::take(leaving_node) {
+                    Self::deposit_event(Event::<T>::HandoffExpired {
+                        leaving_node,
+                        pending: handoff.pending,
+                    });
+                }
+            }
+            HandoffDeadlines::<T>::put(HandoffQueue::<T>::truncate_from(deadlines));
+
+            T::WeightInfo::expire_handoffs(expired as u32)
+        }
     }
 
     #[pallet::call]
@@ -313,6 +383,8 @@ pub mod pallet {
                 PinningNodeIpfsKeys::<T>::insert(pinning_id, waiting_ipfs_keys);
                 // Clear the unassigned ipfs keys
                 WaitingValidatorIpfsKeys::<T>::remove(&validator);
+                // A node that rejoins the ring has no key transfer to complete
+                Self::remove_handoff(&pinning_id);
 
                 Some(pinning_id)
             } else {
@@ -346,7 +418,7 @@ pub mod pallet {
         /// Removes a pinning node associated to a validator and all its ipfs replicas
         /// The pinning node sends all its keys (into an encoded version) because they must be managed by a new pinning node
         #[pallet::call_index(4)]
-        #[pallet::weight(Weight::from_parts(100_000, 0))]
+        #[pallet::weight(T::WeightInfo::rm_pinning_node())]
         pub fn rm_pinning_node(
             origin: OriginFor<T>,
             // The position in the ring of the pinning node key to which the ipfs node is assigned
@@ -373,6 +445,24 @@ pub mod pallet {
 			);
 
             ring.remove(idx);
+
+            // The successors of the leaving node fetch the transferred rows, so they must acknowledge the handoff
+            let successors = Self::ring_successors(&ring, idx);
+            if !successors.is_empty() {
+                // A node that left before with the same ipfs keys must not keep its old deadline, which would expire the new handoff early
+                Self::remove_handoff(&pinning_node);
+                let deadline = frame_system::Pallet::<T>::block_number() + T::HandoffTimeout::get();
+                HandoffDeadlines::<T>::try_append((deadline, pinning_node))
+                    .map_err(|_| Error::<T>::TooManyLeavingNodes)?;
+                LeavingNodes::<T>::insert(
+                    &pinning_node,
+                    Handoff {
+                        pending: successors,
+                        deadline,
+                    },
+                );
+            }
+
             PinningNodesRing::<T>::put(ring);
 
             // Remove the ipfs keys associated to the pinning node
@@ -439,6 +529,47 @@ pub mod pallet {
 
             Ok(())
         }
+
+        /// A successor of a leaving pinning node acknowledges that it has fetched the transferred rows and pinned their content
+        #[pallet::call_index(7)]
+        #[pallet::weight(T::WeightInfo::ack_handoff())]
+        pub fn ack_handoff(
+            origin: OriginFor<T>,
+            // The acknowledging pinning node, controlled by the validator
+            pinning_node: PinningNodeIdOf<T>,
+            leaving_node: PinningNodeIdOf<T>,
+        ) -> DispatchResult {
+            // Check that the extrinsic was signed by a validator.
+            let validator = Self::enure_validator(origin)?;
+
+            ensure!(
+                ValidatorPinningNodes::<T>::get(&validator).contains(&pinning_node),
+                Error::<T>::InvalidPinningNode
+            );
+
+            let mut handoff =
+                LeavingNodes::<T>::get(&leaving_node).ok_or(Error::<T>::NoPendingHandoff)?;
+            let pos = handoff
+                .pending
+                .iter()
+                .position(|node| node == &pinning_node)
+                .ok_or(Error::<T>::NotHandoffSuccessor)?;
+            handoff.pending.remove(pos);
+
+            Self::deposit_event(Event::<T>::HandoffAcknowledged {
+                leaving_node,
+                pinning_node,
+            });
+
+            if handoff.pending.is_empty() {
+                Self::remove_handoff(&leaving_node);
+                Self::deposit_event(Event::<T>::HandoffCompleted { leaving_node });
+            } else {
+                LeavingNodes::<T>::insert(&leaving_node, handoff);
+            }
+
+            Ok(())
+        }
     }
 }
 
@@ -452,6 +583,30 @@ impl<T: Config> Pallet<T> {
         T::Hashing::hash(&ids[..])
     }
 
+    /// Returns the nodes that follow the position `idx` in the ring, up to the replication factor
+    fn ring_successors(ring: &PinningRing<T>, idx: usize) -> PendingSuccessors<T> {
+        let rep_factor = ContentReplicationFactor::<T>::get() as usize;
+        let successors = ring
+            .iter()
+            .cycle()
+            .skip(idx)
+            .take(rep_factor.min(ring.len()))
+            .cloned()
+            .collect();
+
+        // There are at most as many successors as nodes in the ring
+        PendingSuccessors::<T>::truncate_from(successors)
+    }
+
+    /// Drops the pending key transfer of a leaving node, if any
+    fn remove_handoff(leaving_node: &PinningNodeIdOf<T>) {
+        if LeavingNodes::<T>::take(leaving_node).is_some() {
+            HandoffDeadlines::<T>::mutate(|deadlines| {
+                deadlines.retain(|(_, node)| node != leaving_node)
+            });
+        }
+    }
+
     fn enure_validator(origin: OriginFor<T>) -> Result<T::ValidatorId, DispatchError> {
         let who = ensure_signed(origin)?;
         let validator =
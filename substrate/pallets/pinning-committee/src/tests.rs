@@ -0,0 +1,325 @@
+use codec::Encode;
+use frame_support::{derive_impl, traits::Hooks};
+use sp_core::{ConstU32, ConstU64, Hasher, H256};
+use sp_std::vec;
+// The testing primitives are very useful for avoiding having to work with signatures
+// or public keys. `u64` is used as the `AccountId` and the ipfs keys are `UintAuthorityId`s.
+use sp_runtime::{
+    testing::UintAuthorityId,
+    traits::{BlakeTwo256, ConvertInto, IdentityLookup},
+    BuildStorage, DispatchResult, RuntimeAppPublic,
+};
+// Reexport crate as its pallet name for construct_runtime.
+use crate as pallet_pinning_committee;
+use crate::*;
+use frame_support::{assert_noop, assert_ok};
+
+type Block = frame_system::mocking::MockBlock<Test>;
+
+// For testing the pallet, we construct a mock runtime.
+frame_support::construct_runtime!(
+    pub enum Test
+    {
+        System: frame_system,
+        PinningCommittee: pallet_pinning_committee,
+    }
+);
+
+#[derive_impl(frame_system::config_preludes::TestDefaultConfig)]
+impl frame_system::Config for Test {
+    type BaseCallFilter = frame_support::traits::Everything;
+    type BlockWeights = ();
+    type BlockLength = ();
+    type DbWeight = ();
+    type RuntimeOrigin = RuntimeOrigin;
+    type Nonce = u64;
+    type Hash = H256;
+    type RuntimeCall = RuntimeCall;
+    type Hashing = BlakeTwo256;
+    type AccountId = u64;
+    type Lookup = IdentityLookup<Self::AccountId>;
+    type Block = Block;
+    type RuntimeEvent = RuntimeEvent;
+    type Version = ();
+    type PalletInfo = PalletInfo;
+    type AccountData = ();
+    type OnNewAccount = ();
+    type OnKilledAccount = ();
+    type SystemWeightInfo = ();
+    type SS58Prefix = ();
+    type OnSetCode = ();
+    type MaxConsumers = frame_support::traits::ConstU32<16>;
+}
+
+/// Every account up to `MAX_VALIDATOR` is a registered validator
+const MAX_VALIDATOR: u64 = 10;
+const HANDOFF_TIMEOUT: u64 = 10;
+
+pub struct Validators;
+
+impl frame_support::traits::ValidatorRegistration<u64> for Validators {
+    fn is_registered(id: &u64) -> bool {
+        *id <= MAX_VALIDATOR
+    }
+}
+
+impl pallet_pinning_committee::Config for Test {
+    type RuntimeEvent = RuntimeEvent;
+    type MaxPinningNodes = ConstU32<16>;
+    type ValidatorId = u64;
+    type ValidatorRegistrar = Validators;
+    type ValidatorIdOf = ConvertInto;
+    type IPFSNodeId = UintAuthorityId;
+    type HandoffTimeout = ConstU64<HANDOFF_TIMEOUT>;
+    type MaxLeavingNodes = ConstU32<2>;
+    type WeightInfo = ();
+}
+
+// This function basically just builds a genesis storage key/value store according to
+// our desired mockup.
+pub fn new_test_ext() -> sp_io::TestExternalities {
+    let t = RuntimeGenesisConfig {
+        system: Default::default(),
+        pinning_committee: Default::default(),
+    }
+    .build_storage()
+    .unwrap();
+
+    let mut ext: sp_io::TestExternalities = t.into();
+    ext.execute_with(|| {
+        // Events are not deposited on the genesis block
+        System::set_block_number(1);
+        // Each validator has one pinning node with a single ipfs replica, and the content is pinned by two nodes
+        ContentReplicationFactor::<Test>::put(2);
+        NumOfIpfsReplicas::<Test>::put(1);
+        NumOfPinningNodes::<Test>::put(1);
+    });
+    ext
+}
+
+/// Registers the pinning node of a validator, returning its id
+fn register(validator: u64) -> H256 {
+    let key = UintAuthorityId(validator);
+    let signature = key.sign(&validator.encode()).unwrap();
+    assert_ok!(PinningCommittee::register_ipfs_node(
+        RuntimeOrigin::signed(validator),
+        RegistrationMessage { key, signature },
+    ));
+
+    BlakeTwo256::hash(&UintAuthorityId(validator).encode())
+}
+
+/// Removes the pinning node of a validator from the ring
+fn leave(validator: u64, pinning_node: H256) -> DispatchResult {
+    PinningCommittee::rm_pinning_node(
+        RuntimeOrigin::signed(validator),
+        pinning_node,
+        KeyTableAt {
+            block_num: 1,
+            cids: vec![],
+        },
+    )
+}
+
+/// Returns the validator controlling a pinning node
+fn controller(pinning_node: H256) -> u64 {
+    (1..=MAX_VALIDATOR)
+        .find(|validator| ValidatorPinningNodes::<Test>::get(validator).contains(&pinning_node))
+        .unwrap()
+}
+
+/// Registers the nodes of the validators 1 to 4, then removes the node of the validator 1.
+/// Returns the leaving node and its successors
+fn leaving_ring() -> (H256, Vec<H256>) {
+    let leaving_node = register(1);
+    for validator in 2..=4 {
+        register(validator);
+    }
+    assert_ok!(leave(1, leaving_node));
+
+    let successors = LeavingNodes::<Test>::get(leaving_node)
+        .unwrap()
+        .pending
+        .into_inner();
+    (leaving_node, successors)
+}
+
+fn ack(successor: H256, leaving_node: H256) -> DispatchResult {
+    PinningCommittee::ack_handoff(
+        RuntimeOrigin::signed(controller(successor)),
+        successor,
+        leaving_node,
+    )
+}
+
+#[test]
+fn removal_waits_for_the_successors_test() {
+    new_test_ext().execute_with(|| {
+        let (leaving_node, successors) = leaving_ring();
+
+        // The two nodes that follow the leaving node in the ring fetch its rows
+        assert_eq!(successors.len(), 2);
+        assert!(!successors.contains(&leaving_node));
+        assert_eq!(
+            HandoffDeadlines::<Test>::get().into_inner(),
+            vec![(1 + HANDOFF_TIMEOUT, leaving_node)]
+        );
+    });
+}
+
+#[test]
+fn handoff_completes_once_every_successor_acks_test() {
+    new_test_ext().execute_with(|| {
+        let (leaving_node, successors) = leaving_ring();
+
+        assert_ok!(ack(successors[0], leaving_node));
+        System::assert_last_event(
+            Event::<Test>::HandoffAcknowledged {
+                leaving_node,
+                pinning_node: successors[0],
+            }
+            .into(),
+        );
+        assert_eq!(
+            LeavingNodes::<Test>::get(leaving_node)
+                .unwrap()
+                .pending
+                .into_inner(),
+            vec![successors[1]]
+        );
+
+        assert_ok!(ack(successors[1], leaving_node));
+        System::assert_last_event(Event::<Test>::HandoffCompleted { leaving_node }.into());
+        assert!(LeavingNodes::<Test>::get(leaving_node).is_none());
+        assert!(HandoffDeadlines::<Test>::get().is_empty());
+    });
+}
+
+#[test]
+fn double_ack_fails_test() {
+    new_test_ext().execute_with(|| {
+        let (leaving_node, successors) = leaving_ring();
+
+        assert_ok!(ack(successors[0], leaving_node));
+        assert_noop!(
+            ack(successors[0], leaving_node),
+            Error::<Test>::NotHandoffSuccessor
+        );
+
+        // Once the handoff is completed there is nothing left to acknowledge
+        assert_ok!(ack(successors[1], leaving_node));
+        assert_noop!(
+            ack(successors[1], leaving_node),
+            Error::<Test>::NoPendingHandoff
+        );
+    });
+}
+
+#[test]
+fn ack_by_a_non_successor_fails_test() {
+    new_test_ext().execute_with(|| {
+        let (leaving_node, successors) = leaving_ring();
+        let other = PinningNodesRing::<Test>::get()
+            .into_iter()
+            .find(|node| !successors.contains(node))
+            .unwrap();
+
+        assert_noop!(ack(other, leaving_node), Error::<Test>::NotHandoffSuccessor);
+    });
+}
+
+#[test]
+fn ack_for_a_node_of_another_validator_fails_test() {
+    new_test_ext().execute_with(|| {
+        let (leaving_node, successors) = leaving_ring();
+        let validator = controller(successors[1]);
+
+        assert_noop!(
+            PinningCommittee::ack_handoff(
+                RuntimeOrigin::signed(validator),
+                successors[0],
+                leaving_node,
+            ),
+            Error::<Test>::InvalidPinningNode
+        );
+    });
+}
+
+#[test]
+fn handoff_expires_at_the_deadline_test() {
+    new_test_ext().execute_with(|| {
+        let (leaving_node, successors) = leaving_ring();
+        assert_ok!(ack(successors[0], leaving_node));
+
+        // Nothing expires before the deadline
+        PinningCommittee::on_initialize(HANDOFF_TIMEOUT);
+        assert!(LeavingNodes::<Test>::get(leaving_node).is_some());
+
+        PinningCommittee::on_initialize(1 + HANDOFF_TIMEOUT);
+        System::assert_last_event(
+            Event::<Test>::HandoffExpired {
+                leaving_node,
+                pending: PendingSuccessors::<Test>::truncate_from(vec![successors[1]]),
+            }
+            .into(),
+        );
+        assert!(LeavingNodes::<Test>::get(leaving_node).is_none());
+        assert!(HandoffDeadlines::<Test>::get().is_empty());
+        assert_noop!(
+            ack(successors[1], leaving_node),
+            Error::<Test>::NoPendingHandoff
+        );
+    });
+}
+
+#[test]
+fn pending_handoffs_are_bounded_test() {
+    new_test_ext().execute_with(|| {
+        let nodes: Vec<_> = (1..=5).map(register).collect();
+        assert_ok!(leave(1, nodes[0]));
+        assert_ok!(leave(2, nodes[1]));
+
+        assert_noop!(leave(3, nodes[2]), Error::<Test>::TooManyLeavingNodes);
+    });
+}
+
+#[test]
+fn rejoining_node_drops_its_handoff_test() {
+    new_test_ext().execute_with(|| {
+        let (leaving_node, _) = leaving_ring();
+
+        assert_eq!(register(1), leaving_node);
+        assert!(LeavingNodes::<Test>::get(leaving_node).is_none());
+        assert!(HandoffDeadlines::<Test>::get().is_empty());
+    });
+}
+
+#[test]
+fn leaving_again_replaces_the_handoff_test() {
+    new_test_ext().execute_with(|| {
+        let (leaving_node, _) = leaving_ring();
+
+        // The node rejoins with the same ipfs keys and leaves again later
+        System::set_block_number(5);
+        assert_eq!(register(1), leaving_node);
+        assert_ok!(leave(1, leaving_node));
+
+        let deadline = 5 + HANDOFF_TIMEOUT;
+        assert_eq!(
+            HandoffDeadlines::<Test>::get().into_inner(),
+            vec![(deadline, leaving_node)]
+        );
+        assert_eq!(
+            LeavingNodes::<Test>::get(leaving_node).unwrap().deadline,
+            deadline
+        );
+
+        // The deadline of the first handoff does not expire the new one
+        PinningCommittee::on_initialize(1 + HANDOFF_TIMEOUT);
+        assert!(LeavingNodes::<Test>::get(leaving_node).is_some());
+
+        PinningCommittee::on_initialize(deadline);
+        assert!(LeavingNodes::<Test>::get(leaving_node).is_none());
+        assert!(HandoffDeadlines::<Test>::get().is_empty());
+    });
+}
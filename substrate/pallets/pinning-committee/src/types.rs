@@ -1,6 +1,8 @@
 use crate::Config;
 use codec::{Decode, Encode, MaxEncodedLen};
+use frame_support::{CloneNoBound, EqNoBound, PartialEqNoBound, RuntimeDebugNoBound};
 use common_types::{HashOf, PinningNodeIdOf};
+use frame_system::pallet_prelude::BlockNumberFor;
 use scale_info::TypeInfo;
 use sp_application_crypto::RuntimeAppPublic;
 use sp_core::RuntimeDebug;
@@ -38,3 +40,29 @@ pub type RegistrationMessageOf<T> = RegistrationMessage<
 >;
 
 pub type PinningNodeIndex = u32;
+
+/// The key transfer of a leaving pinning node, that must be acknowledged by its successors before the deadline
+#[derive(
+	Encode,
+	Decode,
+	MaxEncodedLen,
+	CloneNoBound,
+	PartialEqNoBound,
+	EqNoBound,
+	RuntimeDebugNoBound,
+	TypeInfo,
+)]
+#[scale_info(skip_type_params(T))]
+#[codec(mel_bound())]
+pub struct Handoff<T: Config> {
+	/// The successors that still have to acknowledge the transfer
+	pub pending: PendingSuccessors<T>,
+	pub deadline: BlockNumberFor<T>,
+}
+
+pub type HandoffOf<T> = Handoff<T>;
+/// The successors of a leaving pinning node, at most one per node of the ring
+pub type PendingSuccessors<T> = BoundedVec<PinningNodeIdOf<T>, <T as Config>::MaxPinningNodes>;
+/// The leaving pinning nodes by handoff deadline, in increasing order
+pub type HandoffQueue<T> =
+	BoundedVec<(BlockNumberFor<T>, PinningNodeIdOf<T>), <T as Config>::MaxLeavingNodes>;
@@ -0,0 +1,96 @@
+//! Weights for `pallet_pinning_committee`
+//!
+//! The weights are not benchmarked: they are conservative estimates of the execution time, with the storage accesses
+//! of the extrinsics and hooks and a worst case proof size for a ring of 512 pinning nodes, 64 leaving nodes and
+//! up to 8 ipfs keys per pinning node.
+
+use core::marker::PhantomData;
+use frame_support::{
+    traits::Get,
+    weights::{constants::RocksDbWeight, Weight},
+};
+
+pub trait WeightInfo {
+    fn rm_pinning_node() -> Weight;
+    fn ack_handoff() -> Weight;
+    fn expire_handoffs(n: u32) -> Weight;
+}
+
+/// The proof size of a `LeavingNodes` entry, holding up to 512 pending successors
+const LEAVING_NODE_PROOF: u64 = 18_914;
+/// The proof size of the `HandoffDeadlines` queue, holding up to 64 deadlines
+const HANDOFF_DEADLINES_PROOF: u64 = 2_801;
+/// The proof size of the `PinningNodesRing`, holding up to 512 pinning nodes
+const RING_PROOF: u64 = 16_881;
+/// The proof size of the unbounded entries read by the extrinsics of a validator: its session keys,
+/// its pinning nodes and the ipfs keys of a pinning node
+const VALIDATOR_ENTRIES_PROOF: u64 = 3_000;
+
+/// Weight functions for `pallet_pinning_committee`.
+pub struct PinningCommitteeWeight<T>(PhantomData<T>);
+impl<T: frame_system::Config> WeightInfo for PinningCommitteeWeight<T> {
+    /// Reads the validator registration and `ContentReplicationFactor`, and reads and writes `ValidatorPinningNodes`,
+    /// `PinningNodesRing`, `LeavingNodes`, `HandoffDeadlines` and `PinningNodeIpfsKeys`. Removes up to 8 `IsIpfsKeyAssigned` entries
+    fn rm_pinning_node() -> Weight {
+        Weight::from_parts(60_000_000, 0)
+            .saturating_add(Weight::from_parts(
+                0,
+                RING_PROOF + LEAVING_NODE_PROOF + HANDOFF_DEADLINES_PROOF + VALIDATOR_ENTRIES_PROOF,
+            ))
+            .saturating_add(T::DbWeight::get().reads(7))
+            .saturating_add(T::DbWeight::get().writes(13))
+    }
+    /// Reads the validator registration and `ValidatorPinningNodes`, and reads and writes `LeavingNodes` and `HandoffDeadlines`
+    fn ack_handoff() -> Weight {
+        Weight::from_parts(26_000_000, 0)
+            .saturating_add(Weight::from_parts(
+                0,
+                LEAVING_NODE_PROOF + HANDOFF_DEADLINES_PROOF + VALIDATOR_ENTRIES_PROOF,
+            ))
+            .saturating_add(T::DbWeight::get().reads(4))
+            .saturating_add(T::DbWeight::get().writes(2))
+    }
+    /// Reads and writes `HandoffDeadlines`, and removes the `LeavingNodes` entries of the `n` expired handoffs
+    fn expire_handoffs(n: u32) -> Weight {
+        Weight::from_parts(3_500_000, 0)
+            .saturating_add(Weight::from_parts(0, HANDOFF_DEADLINES_PROOF))
+            .saturating_add(Weight::from_parts(9_000_000, 0).saturating_mul(n.into()))
+            .saturating_add(T::DbWeight::get().reads(1))
+            .saturating_add(T::DbWeight::get().reads((1_u64).saturating_mul(n.into())))
+            .saturating_add(T::DbWeight::get().writes(1))
+            .saturating_add(T::DbWeight::get().writes((1_u64).saturating_mul(n.into())))
+            .saturating_add(Weight::from_parts(0, LEAVING_NODE_PROOF).saturating_mul(n.into()))
+    }
+}
+
+// For backwards compatibility and tests.
+impl WeightInfo for () {
+    fn rm_pinning_node() -> Weight {
+        Weight::from_parts(60_000_000, 0)
+            .saturating_add(Weight::from_parts(
+                0,
+                RING_PROOF + LEAVING_NODE_PROOF + HANDOFF_DEADLINES_PROOF + VALIDATOR_ENTRIES_PROOF,
+            ))
+            .saturating_add(RocksDbWeight::get().reads(7))
+            .saturating_add(RocksDbWeight::get().writes(13))
+    }
+    fn ack_handoff() -> Weight {
+        Weight::from_parts(26_000_000, 0)
+            .saturating_add(Weight::from_parts(
+                0,
+                LEAVING_NODE_PROOF + HANDOFF_DEADLINES_PROOF + VALIDATOR_ENTRIES_PROOF,
+            ))
+            .saturating_add(RocksDbWeight::get().reads(4))
+            .saturating_add(RocksDbWeight::get().writes(2))
+    }
+    fn expire_handoffs(n: u32) -> Weight {
+        Weight::from_parts(3_500_000, 0)
+            .saturating_add(Weight::from_parts(0, HANDOFF_DEADLINES_PROOF))
+            .saturating_add(Weight::from_parts(9_000_000, 0).saturating_mul(n.into()))
+            .saturating_add(RocksDbWeight::get().reads(1))
+            .saturating_add(RocksDbWeight::get().reads((1_u64).saturating_mul(n.into())))
+            .saturating_add(RocksDbWeight::get().writes(1))
+            .saturating_add(RocksDbWeight::get().writes((1_u64).saturating_mul(n.into())))
+            .saturating_add(Weight::from_parts(0, LEAVING_NODE_PROOF).saturating_mul(n.into()))
+    }
+}
@@ -68,7 +68,7 @@ pub const VERSION: RuntimeVersion = RuntimeVersion {
     //   `spec_version`, and `authoring_version` are the same between Wasm and native.
     // This value is set to 100 to notify Polkadot-JS App (https://polkadot.js.org/apps) to use
     //   the compatible custom types.
-    spec_version: 100,
+    spec_version: 102,
     impl_version: 1,
     apis: RUNTIME_API_VERSIONS,
     transaction_version: 1,
@@ -291,6 +291,9 @@ impl pallet_pinning_committee::Config for Runtime {
     type ValidatorRegistrar = Session;
     type ValidatorIdOf = ValidatorIdOf;
     type IPFSNodeId = primitives::ed25519::IpfsId;
+    type HandoffTimeout = ConstU32<600>;
+    type MaxLeavingNodes = ConstU32<64>;
+    type WeightInfo = pallet_pinning_committee::weights::PinningCommitteeWeight<Runtime>;
 }
 
 impl pallet_app_registrar::Config for Runtime {
@@ -314,7 +317,7 @@ impl pallet_capsules::Config for Runtime {
     type RemoveItemsLimit = ConstU32<512>;
     // 1 hour, considering one block is 3 seconds
     type MinimumRetentionPeriod = ConstU32<1200>;
-    type CidLength = ConstU32<46>;
+    type CidLength = ConstU32<128>;
     type WeightInfo = pallet_capsules::weights::CapsulesWeight<Runtime>;
 }
 